@@ -0,0 +1,176 @@
+use actix_web::{dev, http, web, HttpResponse, Result};
+
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use route_bucket_domain::model::{collection::CollectionId, route::RouteId};
+use route_bucket_usecase::collection::{
+    CollectionCreateRequest, CollectionListRequest, CollectionPermissionDeleteRequest,
+    CollectionPermissionRequest, CollectionReorderRequest, CollectionRouteRequest,
+    CollectionUpdateRequest, CollectionUseCase,
+};
+
+use crate::AddService;
+
+/// ログインしていなくても見られる(公開されている)コレクションがあるので，トークンは任意
+fn token(auth: &Option<BearerAuth>) -> Option<&str> {
+    auth.as_ref().map(BearerAuth::token)
+}
+
+async fn get<U: 'static + CollectionUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<CollectionId>,
+    auth: Option<BearerAuth>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usecase.find(&id, token(&auth)).await?))
+}
+
+async fn get_list<U: 'static + CollectionUseCase>(
+    usecase: web::Data<U>,
+    query: web::Query<CollectionListRequest>,
+    auth: Option<BearerAuth>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usecase.find_by_owner(&query, token(&auth)).await?))
+}
+
+async fn get_gpx<U: 'static + CollectionUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<CollectionId>,
+    auth: Option<BearerAuth>,
+) -> Result<HttpResponse> {
+    let gpx_resp = usecase.find_gpx(&id, token(&auth)).await?;
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            http::header::CONTENT_DISPOSITION,
+            format!("attachment;filename=\"{}.gpx\"", gpx_resp.name()),
+        ))
+        .content_type("application/gpx+xml")
+        .body(dev::Body::from_slice(gpx_resp.as_slice())))
+}
+
+async fn get_zip<U: 'static + CollectionUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<CollectionId>,
+    auth: Option<BearerAuth>,
+) -> Result<HttpResponse> {
+    let archive = usecase.find_archive(&id, token(&auth)).await?;
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            http::header::CONTENT_DISPOSITION,
+            format!("attachment;filename=\"{}.zip\"", archive.name()),
+        ))
+        .content_type("application/zip")
+        .body(dev::Body::from_slice(archive.as_slice())))
+}
+
+async fn post<U: 'static + CollectionUseCase>(
+    usecase: web::Data<U>,
+    auth: BearerAuth,
+    req: web::Json<CollectionCreateRequest>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Created().json(usecase.create(auth.token(), &req).await?))
+}
+
+async fn patch<U: 'static + CollectionUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<CollectionId>,
+    auth: BearerAuth,
+    req: web::Json<CollectionUpdateRequest>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usecase.update(&id, auth.token(), &req).await?))
+}
+
+async fn delete<U: 'static + CollectionUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<CollectionId>,
+    auth: BearerAuth,
+) -> Result<HttpResponse> {
+    usecase.delete(&id, auth.token()).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn put_route<U: 'static + CollectionUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<CollectionId>,
+    auth: BearerAuth,
+    req: web::Json<CollectionRouteRequest>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usecase.add_route(&id, auth.token(), &req).await?))
+}
+
+async fn patch_routes<U: 'static + CollectionUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<CollectionId>,
+    auth: BearerAuth,
+    req: web::Json<CollectionReorderRequest>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usecase.reorder_routes(&id, auth.token(), &req).await?))
+}
+
+async fn delete_route<U: 'static + CollectionUseCase>(
+    usecase: web::Data<U>,
+    path_params: web::Path<(CollectionId, RouteId)>,
+    auth: BearerAuth,
+) -> Result<HttpResponse> {
+    let (collection_id, route_id) = path_params.into_inner();
+    Ok(HttpResponse::Ok().json(
+        usecase
+            .remove_route(&collection_id, auth.token(), &route_id)
+            .await?,
+    ))
+}
+
+async fn put_permission<U: 'static + CollectionUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<CollectionId>,
+    auth: BearerAuth,
+    req: web::Json<CollectionPermissionRequest>,
+) -> Result<HttpResponse> {
+    usecase.update_permission(&id, auth.token(), &req).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+async fn delete_permission<U: 'static + CollectionUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<CollectionId>,
+    auth: BearerAuth,
+    req: web::Json<CollectionPermissionDeleteRequest>,
+) -> Result<HttpResponse> {
+    usecase.delete_permission(&id, auth.token(), &req).await?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+pub trait BuildCollectionService: AddService {
+    fn build_collection_service<U: 'static + CollectionUseCase>(self) -> Self {
+        self.add_service(
+            web::scope("/collections")
+                .service(
+                    web::resource("/")
+                        .route(web::get().to(get_list::<U>))
+                        .route(web::post().to(post::<U>)),
+                )
+                .service(
+                    web::resource("/{id}")
+                        .route(web::get().to(get::<U>))
+                        .route(web::patch().to(patch::<U>))
+                        .route(web::delete().to(delete::<U>)),
+                )
+                .service(web::resource("/{id}/gpx/").route(web::get().to(get_gpx::<U>)))
+                .service(web::resource("/{id}/zip/").route(web::get().to(get_zip::<U>)))
+                .service(
+                    web::resource("/{id}/routes/")
+                        .route(web::put().to(put_route::<U>))
+                        .route(web::patch().to(patch_routes::<U>)),
+                )
+                .service(
+                    web::resource("/{id}/routes/{route_id}")
+                        .route(web::delete().to(delete_route::<U>)),
+                )
+                .service(
+                    web::resource("/{id}/permissions/")
+                        .route(web::put().to(put_permission::<U>))
+                        .route(web::delete().to(delete_permission::<U>)),
+                ),
+        )
+    }
+}
+
+impl<T: AddService> BuildCollectionService for T {}
@@ -0,0 +1,21 @@
+use actix_web::{web, HttpResponse, Result};
+use route_bucket_usecase::elevation::{ElevationLookupRequest, ElevationUseCase};
+
+use crate::AddService;
+
+async fn post<U: 'static + ElevationUseCase>(
+    usecase: web::Data<U>,
+    req: web::Json<ElevationLookupRequest>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usecase.lookup(req.into_inner()).await?))
+}
+
+pub trait BuildElevationService: AddService {
+    fn build_elevation_service<U: 'static + ElevationUseCase>(self) -> Self {
+        self.add_service(
+            web::scope("/elevation").service(web::resource("/").route(web::post().to(post::<U>))),
+        )
+    }
+}
+
+impl<T: AddService> BuildElevationService for T {}
@@ -4,10 +4,16 @@ use actix_web::dev::{HttpServiceFactory, ServiceRequest, ServiceResponse};
 use actix_web::error::Error;
 use actix_web::App;
 
+pub use collection::BuildCollectionService;
+pub use elevation::BuildElevationService;
 pub use route::BuildRouteService;
+pub use tile::BuildTileService;
 pub use user::BuildUserService;
 
+mod collection;
+mod elevation;
 mod route;
+mod tile;
 mod user;
 
 pub trait AddService: Sized {
@@ -1,10 +1,13 @@
 use actix_web::{dev, http, web, HttpResponse, Result};
 
 use actix_web_httpauth::extractors::bearer::BearerAuth;
-use route_bucket_domain::model::route::{RouteId, RouteSearchQuery};
+use route_bucket_domain::model::route::{ProximityQuery, RouteId, RouteSearchQuery};
 use route_bucket_usecase::route::{
-    DeletePermissionRequest, NewPointRequest, RemovePointRequest, RouteCreateRequest,
-    RouteRenameRequest, RouteUseCase, UpdatePermissionRequest,
+    DeletePermissionRequest, ElevationSourceRequest, NewPointRequest, PopularTagsRequest,
+    RemovePointRequest, RouteCreateRequest, RouteDaylightRequest, RouteEnergyRequest,
+    RouteGetGpxResponse, RouteGetRequest, RouteImportRequest, RouteMetadataRequest,
+    RouteRenameRequest, RouteSimilarRequest, RouteStagesRequest, RouteTagRequest, RouteUseCase,
+    SegmentInterpolationRequest, UpdatePermissionRequest, WaypointElevationRequest,
 };
 
 use crate::AddService;
@@ -12,8 +15,9 @@ use crate::AddService;
 async fn get<U: 'static + RouteUseCase>(
     usecase: web::Data<U>,
     id: web::Path<RouteId>,
+    query: web::Query<RouteGetRequest>,
 ) -> Result<HttpResponse> {
-    Ok(HttpResponse::Ok().json(usecase.find(id.as_ref()).await?))
+    Ok(HttpResponse::Ok().json(usecase.find(id.as_ref(), &query).await?))
 }
 
 async fn get_all<U: 'static + RouteUseCase>(usecase: web::Data<U>) -> Result<HttpResponse> {
@@ -27,19 +31,91 @@ async fn get_search<U: 'static + RouteUseCase>(
     Ok(HttpResponse::Ok().json(usecase.search(query.into_inner()).await?))
 }
 
-async fn get_gpx<U: 'static + RouteUseCase>(
+async fn get_nearby<U: 'static + RouteUseCase>(
     usecase: web::Data<U>,
-    id: web::Path<RouteId>,
+    query: web::Query<ProximityQuery>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usecase.search_nearby(query.into_inner()).await?))
+}
+
+async fn get_tags<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    query: web::Query<PopularTagsRequest>,
 ) -> Result<HttpResponse> {
-    let gpx_resp = usecase.find_gpx(id.as_ref()).await?;
+    Ok(HttpResponse::Ok().json(usecase.find_popular_tags(&query).await?))
+}
 
-    Ok(HttpResponse::Ok()
+fn gpx_response(gpx_resp: RouteGetGpxResponse) -> HttpResponse {
+    HttpResponse::Ok()
         .insert_header((
             http::header::CONTENT_DISPOSITION,
             format!("attachment;filename=\"{}.gpx\"", gpx_resp.name()),
         ))
         .content_type("application/gpx+xml")
-        .body(dev::Body::from_slice(gpx_resp.as_slice())))
+        .body(dev::Body::from_slice(gpx_resp.as_slice()))
+}
+
+async fn get_similar<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<RouteId>,
+    query: web::Query<RouteSimilarRequest>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usecase.find_similar(id.as_ref(), &query).await?))
+}
+
+async fn get_gpx<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<RouteId>,
+    query: web::Query<RouteGetRequest>,
+) -> Result<HttpResponse> {
+    Ok(gpx_response(usecase.find_gpx(id.as_ref(), &query).await?))
+}
+
+async fn get_daylight<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<RouteId>,
+    query: web::Query<RouteDaylightRequest>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usecase.check_daylight(&id, &query).await?))
+}
+
+async fn get_stages<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<RouteId>,
+    query: web::Query<RouteStagesRequest>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usecase.split_into_stages(&id, &query).await?))
+}
+
+async fn get_stages_gpx<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<RouteId>,
+    query: web::Query<RouteStagesRequest>,
+) -> Result<HttpResponse> {
+    Ok(gpx_response(
+        usecase.find_stages_gpx(&id, &query, None).await?,
+    ))
+}
+
+async fn get_stage_gpx<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    path_params: web::Path<(RouteId, usize)>,
+    query: web::Query<RouteStagesRequest>,
+) -> Result<HttpResponse> {
+    let (route_id, day) = path_params.into_inner();
+    Ok(gpx_response(
+        usecase
+            .find_stages_gpx(&route_id, &query, Some(day))
+            .await?,
+    ))
+}
+
+async fn get_energy<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<RouteId>,
+    query: web::Query<RouteEnergyRequest>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usecase.estimate_energy(&id, &query).await?))
 }
 
 async fn post<U: 'static + RouteUseCase>(
@@ -50,6 +126,14 @@ async fn post<U: 'static + RouteUseCase>(
     Ok(HttpResponse::Created().json(usecase.create(auth.token(), &req).await?))
 }
 
+async fn post_import<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    auth: BearerAuth,
+    req: web::Json<RouteImportRequest>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Created().json(usecase.import_gpx(auth.token(), &req).await?))
+}
+
 async fn patch_rename<U: 'static + RouteUseCase>(
     usecase: web::Data<U>,
     id: web::Path<RouteId>,
@@ -59,6 +143,15 @@ async fn patch_rename<U: 'static + RouteUseCase>(
     Ok(HttpResponse::Ok().json(usecase.rename(&id, auth.token(), &req).await?))
 }
 
+async fn patch_metadata<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<RouteId>,
+    auth: BearerAuth,
+    req: web::Json<RouteMetadataRequest>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usecase.update_metadata(&id, auth.token(), &req).await?))
+}
+
 async fn patch_add<U: 'static + RouteUseCase>(
     usecase: web::Data<U>,
     path_params: web::Path<(RouteId, usize)>,
@@ -101,6 +194,47 @@ async fn patch_move<U: 'static + RouteUseCase>(
     ))
 }
 
+async fn patch_waypoint_elevation<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    path_params: web::Path<(RouteId, usize)>,
+    auth: BearerAuth,
+    req: web::Json<WaypointElevationRequest>,
+) -> Result<HttpResponse> {
+    let (route_id, pos) = path_params.into_inner();
+    Ok(HttpResponse::Ok().json(
+        usecase
+            .set_waypoint_elevation(&route_id, auth.token(), pos, &req)
+            .await?,
+    ))
+}
+
+async fn patch_segment_interpolation<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    path_params: web::Path<(RouteId, usize)>,
+    auth: BearerAuth,
+    req: web::Json<SegmentInterpolationRequest>,
+) -> Result<HttpResponse> {
+    let (route_id, pos) = path_params.into_inner();
+    Ok(HttpResponse::Ok().json(
+        usecase
+            .set_segment_interpolation(&route_id, auth.token(), pos, &req)
+            .await?,
+    ))
+}
+
+async fn patch_elevation_source<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    route_id: web::Path<RouteId>,
+    auth: BearerAuth,
+    req: web::Json<ElevationSourceRequest>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(
+        usecase
+            .set_elevation_source(&route_id, auth.token(), &req)
+            .await?,
+    ))
+}
+
 async fn patch_clear<U: 'static + RouteUseCase>(
     usecase: web::Data<U>,
     auth: BearerAuth,
@@ -154,6 +288,24 @@ async fn delete_permission<U: 'static + RouteUseCase>(
     Ok(HttpResponse::Ok().finish())
 }
 
+async fn put_tag<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<RouteId>,
+    auth: BearerAuth,
+    req: web::Json<RouteTagRequest>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usecase.add_tag(&id, auth.token(), &req).await?))
+}
+
+async fn delete_tag<U: 'static + RouteUseCase>(
+    usecase: web::Data<U>,
+    id: web::Path<RouteId>,
+    auth: BearerAuth,
+    req: web::Json<RouteTagRequest>,
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(usecase.remove_tag(&id, auth.token(), &req).await?))
+}
+
 pub trait BuildRouteService: AddService {
     fn build_route_service<U: 'static + RouteUseCase>(self) -> Self {
         // TODO: /の過不足は許容する ex) "/{id}/"
@@ -164,19 +316,48 @@ pub trait BuildRouteService: AddService {
                         .route(web::get().to(get_all::<U>))
                         .route(web::post().to(post::<U>)),
                 )
+                .service(web::resource("/import/").route(web::post().to(post_import::<U>)))
                 .service(web::resource("/search").route(web::get().to(get_search::<U>)))
+                .service(web::resource("/nearby").route(web::get().to(get_nearby::<U>)))
+                .service(web::resource("/tags").route(web::get().to(get_tags::<U>)))
                 .service(
                     web::resource("/{id}")
                         .route(web::get().to(get::<U>))
                         .route(web::delete().to(delete::<U>)),
                 )
                 .service(web::resource("/{id}/gpx/").route(web::get().to(get_gpx::<U>)))
+                .service(web::resource("/{id}/similar/").route(web::get().to(get_similar::<U>)))
+                .service(web::resource("/{id}/energy/").route(web::get().to(get_energy::<U>)))
+                .service(web::resource("/{id}/daylight/").route(web::get().to(get_daylight::<U>)))
+                .service(web::resource("/{id}/stages/").route(web::get().to(get_stages::<U>)))
+                .service(
+                    web::resource("/{id}/stages/gpx/").route(web::get().to(get_stages_gpx::<U>)),
+                )
+                .service(
+                    web::resource("/{id}/stages/{day}/gpx/")
+                        .route(web::get().to(get_stage_gpx::<U>)),
+                )
                 .service(web::resource("/{id}/rename/").route(web::patch().to(patch_rename::<U>)))
+                .service(
+                    web::resource("/{id}/metadata/").route(web::patch().to(patch_metadata::<U>)),
+                )
                 .service(web::resource("/{id}/add/{pos}").route(web::patch().to(patch_add::<U>)))
                 .service(
                     web::resource("/{id}/remove/{pos}").route(web::patch().to(patch_remove::<U>)),
                 )
                 .service(web::resource("/{id}/move/{pos}").route(web::patch().to(patch_move::<U>)))
+                .service(
+                    web::resource("/{id}/elevation/{pos}")
+                        .route(web::patch().to(patch_waypoint_elevation::<U>)),
+                )
+                .service(
+                    web::resource("/{id}/interpolation/{pos}")
+                        .route(web::patch().to(patch_segment_interpolation::<U>)),
+                )
+                .service(
+                    web::resource("/{id}/elevation_source/")
+                        .route(web::patch().to(patch_elevation_source::<U>)),
+                )
                 .service(web::resource("/{id}/clear/").route(web::patch().to(patch_clear::<U>)))
                 .service(web::resource("/{id}/undo/").route(web::patch().to(patch_undo::<U>)))
                 .service(web::resource("/{id}/redo/").route(web::patch().to(patch_redo::<U>)))
@@ -186,6 +367,11 @@ pub trait BuildRouteService: AddService {
                 .service(
                     web::resource("/{id}/permissions/")
                         .route(web::delete().to(delete_permission::<U>)),
+                )
+                .service(
+                    web::resource("/{id}/tags/")
+                        .route(web::put().to(put_tag::<U>))
+                        .route(web::delete().to(delete_tag::<U>)),
                 ),
         )
     }
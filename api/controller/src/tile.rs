@@ -0,0 +1,41 @@
+use actix_web::{dev, http, web, HttpResponse, Result};
+
+use actix_web_httpauth::extractors::bearer::BearerAuth;
+use route_bucket_usecase::tile::{TileRequest, TileUseCase};
+
+use crate::AddService;
+
+/// タイルに掛かるルートの一部だけを描いたときに付けるヘッダー
+const TRUNCATED_HEADER: &str = "X-Tile-Truncated";
+
+async fn get_route_tile<U: 'static + TileUseCase>(
+    usecase: web::Data<U>,
+    path: web::Path<(u8, u32, u32)>,
+    query: web::Query<TileRequest>,
+    auth: Option<BearerAuth>,
+) -> Result<HttpResponse> {
+    let (z, x, y) = path.into_inner();
+    let token = auth.as_ref().map(BearerAuth::token);
+    let tile = usecase.find_route_tile(z, x, y, &query, token).await?;
+    let mut resp = HttpResponse::Ok();
+    resp.insert_header((http::header::CACHE_CONTROL, "max-age=300"));
+    // 描き切れなかったルートがあれば，ズームインを促せるように知らせる
+    if tile.is_truncated() {
+        resp.insert_header((TRUNCATED_HEADER, "true"));
+    }
+    Ok(resp
+        .content_type("application/vnd.mapbox-vector-tile")
+        .body(dev::Body::from_slice(tile.as_slice())))
+}
+
+pub trait BuildTileService: AddService {
+    fn build_tile_service<U: 'static + TileUseCase>(self) -> Self {
+        self.add_service(
+            web::scope("/tiles").service(
+                web::resource("/{z}/{x}/{y}.mvt").route(web::get().to(get_route_tile::<U>)),
+            ),
+        )
+    }
+}
+
+impl<T: AddService> BuildTileService for T {}
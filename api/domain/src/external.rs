@@ -4,7 +4,11 @@ use itertools::Itertools;
 use route_bucket_utils::{ApplicationError, ApplicationResult};
 
 use crate::model::{
-    route::{Coordinate, DrawingMode, Elevation, Route, Segment},
+    route::{
+        Coordinate, DemVersion, DrawingMode, Elevation, ElevationSource, Route, Segment,
+        SegmentList,
+    },
+    tile::{TileKey, VectorTile},
     types::Email,
     user::{User, UserId},
 };
@@ -40,15 +44,72 @@ pub trait CallRouteInterpolationApi {
 }
 
 #[cfg_attr(feature = "mocking", mockall::automock)]
+#[async_trait]
 pub trait ElevationApi: Send + Sync {
-    fn get_elevation(&self, coord: &Coordinate) -> ApplicationResult<Option<Elevation>>;
+    /// `coords`の標高をまとめて求める (返り値は`coords`と同じ順)
+    async fn get_elevations(
+        &self,
+        coords: &[Coordinate],
+    ) -> ApplicationResult<Vec<Option<Elevation>>>;
 
-    fn attach_elevations(&self, route: &mut Route) -> ApplicationResult<()> {
-        route.iter_seg_mut().try_for_each(|seg| {
-            seg.iter_mut()
-                .filter(|coord| coord.elevation().is_none())
-                .try_for_each(|coord| coord.set_elevation(self.get_elevation(coord)?))
-        })
+    /// 現在のDEMの版
+    fn dem_version(&self) -> DemVersion;
+
+    /// `coord`を含むDEMのタイルがあるか
+    ///
+    /// 含むタイルが無い点の標高はNoneになる (タイル内の欠測値と区別するのに使う)
+    fn is_covered(&self, coord: &Coordinate) -> bool;
+
+    /// DEMのタイルに含まれない点があるセグメントの番号
+    ///
+    /// 番号はレスポンスのセグメント(`into_segments_in_between`)と揃えるため，ゴールだけの最後のセグメントは数えない
+    /// ゴールが範囲外なら，ゴールで終わる直前のセグメントが含まれる
+    fn find_uncovered_segments(&self, seg_list: &SegmentList) -> Vec<usize> {
+        seg_list
+            .iter()
+            .take(seg_list.len().saturating_sub(1))
+            .enumerate()
+            .filter(|(_, seg)| seg.iter().any(|coord| !self.is_covered(coord)))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// 標高が現在のDEMで求められていないセグメントにだけ，標高を付け直す
+    ///
+    /// セグメントごとにまとめて問い合わせ，各セグメントは並行に処理する
+    /// ルートが元の標高を使う設定なら，取り込んだファイルの標高はそのまま残し，
+    /// 全ての点に元の標高があるセグメントはDEMに問い合わせない
+    /// 手動で補正された標高は，それらの上から反映する
+    async fn attach_elevations(&self, route: &mut Route) -> ApplicationResult<()> {
+        let dem_version = self.dem_version();
+        let use_source = *route.info().elevation_source() == ElevationSource::Source;
+        let seg_future_iter = route
+            .iter_seg_mut()
+            .filter(|seg| {
+                seg.dem_version().as_ref() != Some(&dem_version)
+                    || (use_source && seg.has_source_elevations())
+            })
+            .map(|seg| {
+                let dem_version = dem_version.clone();
+                async move {
+                    let fully_sourced =
+                        use_source && seg.iter().all(|coord| coord.source_elevation().is_some());
+                    if !fully_sourced && seg.dem_version().as_ref() != Some(&dem_version) {
+                        let elevations = self.get_elevations(seg.points()).await?;
+                        seg.set_elevations(elevations, dem_version)?;
+                    }
+                    if use_source {
+                        seg.apply_source_elevations();
+                    }
+                    seg.apply_elevation_override();
+                    Ok(())
+                }
+            });
+
+        futures::future::join_all(seg_future_iter)
+            .await
+            .into_iter()
+            .try_collect()
     }
 }
 
@@ -102,3 +163,181 @@ pub trait CallReservedUserIdCheckerApi {
 
     fn reserved_user_id_checker_api(&self) -> &Self::ReservedUserIdCheckerApi;
 }
+
+/// 作ったベクタータイルを一定の時間だけ保持しておく
+///
+/// ルートを編集しても古いタイルは消さないので，有効期限までは編集前の線が見えうる
+#[cfg_attr(feature = "mocking", mockall::automock)]
+pub trait TileCacheApi: Send + Sync {
+    /// 無いか有効期限が切れていればNone
+    fn get(&self, key: &TileKey) -> Option<VectorTile>;
+
+    fn put(&self, key: TileKey, tile: VectorTile);
+}
+
+pub trait CallTileCacheApi {
+    type TileCacheApi: TileCacheApi;
+
+    fn tile_cache_api(&self) -> &Self::TileCacheApi;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures::executor::block_on;
+    use rstest::rstest;
+
+    use crate::model::route::tests::RouteFixtures;
+
+    use super::*;
+
+    /// 全ての点を0mとし，問い合わせの回数を数える
+    #[derive(Default)]
+    struct CountingElevationApi {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ElevationApi for CountingElevationApi {
+        async fn get_elevations(
+            &self,
+            coords: &[Coordinate],
+        ) -> ApplicationResult<Vec<Option<Elevation>>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(coords.iter().map(|_| Some(Elevation::zero())).collect())
+        }
+
+        fn dem_version(&self) -> DemVersion {
+            DemVersion::from(String::from("current"))
+        }
+
+        /// 千葉より北(東京など)はDEMの範囲外とする
+        fn is_covered(&self, coord: &Coordinate) -> bool {
+            coord.latitude().value() < 35.65
+        }
+    }
+
+    #[rstest]
+    fn can_find_uncovered_segments() {
+        let api = CountingElevationApi::default();
+        let route = Route::yokohama_to_chiba_via_tokyo_filled(false, false);
+        // 横浜→東京と東京→千葉が東京を含む
+        assert_eq!(api.find_uncovered_segments(route.seg_list()), vec![0, 1]);
+
+        let route = Route::yokohama_to_chiba_filled(false, false);
+        assert!(api.find_uncovered_segments(route.seg_list()).is_empty());
+    }
+
+    #[rstest]
+    fn uncovered_goal_is_reported_in_last_segment_in_between() {
+        let api = CountingElevationApi::default();
+        let route = Route::yokohama_to_tokyo_filled(false, false);
+        let uncovered = api.find_uncovered_segments(route.seg_list());
+
+        // 東京はゴールだけのセグメントではなく，横浜→東京のセグメントとして返す
+        assert_eq!(uncovered, vec![0]);
+        let segments = route.seg_list().clone().into_segments_in_between();
+        assert!(uncovered.iter().all(|&i| i < segments.len()));
+    }
+
+    #[rstest]
+    fn attach_elevations_looks_up_each_segment_at_once() {
+        let api = CountingElevationApi::default();
+        let mut route = Route::yokohama_to_chiba_via_tokyo_filled(false, false);
+
+        block_on(api.attach_elevations(&mut route)).unwrap();
+
+        assert_eq!(api.calls.load(Ordering::SeqCst), 3);
+        route.iter_seg_mut().for_each(|seg| {
+            assert_eq!(seg.dem_version(), &Some(api.dem_version()));
+            assert!(seg.iter().all(|coord| coord.elevation().is_some()));
+        });
+    }
+
+    #[rstest]
+    fn attach_elevations_skips_segments_of_current_dem_version() {
+        let api = CountingElevationApi::default();
+        let mut route = Route::yokohama_to_chiba_via_tokyo_filled(false, false);
+        let mut seg_iter = route.iter_seg_mut();
+        seg_iter.next().unwrap().set_dem_version(api.dem_version());
+        seg_iter
+            .next()
+            .unwrap()
+            .set_dem_version(DemVersion::from(String::from("outdated")));
+
+        block_on(api.attach_elevations(&mut route)).unwrap();
+
+        // 残りの2セグメントだけ問い合わせる
+        assert_eq!(api.calls.load(Ordering::SeqCst), 2);
+        let mut seg_iter = route.iter_seg_mut();
+        assert!(seg_iter
+            .next()
+            .unwrap()
+            .iter()
+            .all(|coord| coord.elevation().is_none()));
+        assert!(seg_iter
+            .next()
+            .unwrap()
+            .iter()
+            .all(|coord| coord.elevation().is_some()));
+    }
+
+    #[rstest]
+    #[case::source(ElevationSource::Source, 2, 100)]
+    #[case::dem(ElevationSource::Dem, 3, 0)]
+    fn attach_elevations_follows_elevation_source(
+        #[case] elevation_source: ElevationSource,
+        #[case] expected_calls: usize,
+        #[case] expected_elevation: i32,
+    ) {
+        let api = CountingElevationApi::default();
+        let mut route = Route::yokohama_to_chiba_via_tokyo_filled(false, false);
+        route.set_elevation_source(elevation_source);
+        let seg = route.iter_seg_mut().next().unwrap();
+        let source_elevations = vec![Some(Elevation::try_from(100).unwrap()); seg.points().len()];
+        seg.set_source_elevations(source_elevations).unwrap();
+
+        block_on(api.attach_elevations(&mut route)).unwrap();
+
+        // 全ての点に元の標高があるセグメントはDEMに問い合わせない
+        assert_eq!(api.calls.load(Ordering::SeqCst), expected_calls);
+        assert!(route
+            .iter_seg_mut()
+            .next()
+            .unwrap()
+            .iter()
+            .all(|coord| *coord.elevation()
+                == Some(Elevation::try_from(expected_elevation).unwrap())));
+    }
+
+    #[rstest]
+    fn attach_elevations_keeps_dem_version_of_partly_sourced_segment() {
+        let api = CountingElevationApi::default();
+        let mut route = Route::yokohama_to_chiba_via_tokyo_filled(false, false);
+        route.set_elevation_source(ElevationSource::Source);
+        let seg = route.iter_seg_mut().next().unwrap();
+        let mut source_elevations = vec![None; seg.points().len()];
+        source_elevations[0] = Some(Elevation::try_from(100).unwrap());
+        seg.set_source_elevations(source_elevations).unwrap();
+
+        block_on(api.attach_elevations(&mut route)).unwrap();
+        assert_eq!(api.calls.load(Ordering::SeqCst), 3);
+
+        // 保存した後に読み込み直しても，DEMには問い合わせ直さない
+        block_on(api.attach_elevations(&mut route)).unwrap();
+        assert_eq!(api.calls.load(Ordering::SeqCst), 3);
+
+        // DEMの標高に戻すと，元の標高を反映していたセグメントだけ求め直す
+        route.set_elevation_source(ElevationSource::Dem);
+        block_on(api.attach_elevations(&mut route)).unwrap();
+        assert_eq!(api.calls.load(Ordering::SeqCst), 4);
+        assert!(route
+            .iter_seg_mut()
+            .next()
+            .unwrap()
+            .iter()
+            .all(|coord| *coord.elevation() == Some(Elevation::zero())));
+    }
+}
@@ -1,20 +1,30 @@
+pub mod collection;
 pub mod permission;
 pub mod route;
+pub mod tile;
 pub mod types;
 pub mod user;
 
 #[cfg(feature = "fixtures")]
 pub mod fixtures {
+    pub mod collection {
+        pub use crate::model::collection::tests::CollectionFixtures;
+    }
+
     pub mod route {
         pub use crate::model::permission::tests::PermissionFixtures;
         pub use crate::model::route::bounding_box::tests::BoundingBoxFixture;
         pub use crate::model::route::coordinate::tests::CoordinateFixtures;
+        pub use crate::model::route::energy::tests::EnergyModelFixtures;
+        pub use crate::model::route::metadata::tests::RouteMetadataFixtures;
         pub use crate::model::route::route_gpx::tests::RouteGpxFixtures;
         pub use crate::model::route::route_info::tests::RouteInfoFixtures;
         pub use crate::model::route::search_query::tests::RouteSearchQueryFixtures;
         pub use crate::model::route::segment_list::tests::{
             OperationFixtures, SegmentFixtures, SegmentListFixture,
         };
+        pub use crate::model::route::stage::tests::StagePlanFixtures;
+        pub use crate::model::route::tag::tests::TagFixtures;
         pub use crate::model::route::tests::RouteFixtures;
     }
 
@@ -0,0 +1,342 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use derivative::Derivative;
+use derive_more::From;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+pub use self::collection_archive::CollectionArchive;
+pub use self::collection_stats::CollectionStats;
+
+use super::permission::PermissionType;
+use super::route::RouteId;
+use super::types::NanoId;
+use super::user::UserId;
+
+pub(crate) mod collection_archive;
+pub(crate) mod collection_stats;
+
+/// 1つのコレクションに入れられるルートの数の上限
+const MAX_ROUTES: usize = 500;
+
+pub type CollectionId = NanoId<Collection, 11>;
+
+/// コレクションを見られる範囲
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum CollectionVisibility {
+    /// 所有者だけ
+    Private,
+    /// 所有者と，権限を与えられたユーザー
+    Shared,
+    /// 全員が見られる (編集は所有者と，Editorの権限を与えられたユーザーだけ)
+    Public,
+}
+
+impl Default for CollectionVisibility {
+    fn default() -> Self {
+        Self::Private
+    }
+}
+
+/// 名前付きの，順番のあるルートの一覧
+#[derive(Clone, Debug, From, Getters, Derivative, Serialize, Deserialize)]
+#[get = "pub"]
+#[derivative(Default)]
+#[cfg_attr(any(test, feature = "fixtures"), derivative(PartialEq))]
+pub struct Collection {
+    #[derivative(Default(value = "CollectionId::new()"))]
+    #[cfg_attr(any(test, feature = "fixtures"), derivative(PartialEq = "ignore"))]
+    id: CollectionId,
+    name: String,
+    #[derivative(Default(value = "UserId::from(\"\".to_string())"))]
+    owner_id: UserId,
+    visibility: CollectionVisibility,
+    /// 並び順どおり
+    route_ids: Vec<RouteId>,
+    #[derivative(Default(value = "chrono::MIN_DATETIME"))]
+    created_at: DateTime<Utc>,
+    #[derivative(Default(value = "chrono::MIN_DATETIME"))]
+    updated_at: DateTime<Utc>,
+}
+
+impl Collection {
+    pub fn new(name: &str, owner_id: UserId, visibility: CollectionVisibility) -> Self {
+        Self {
+            name: name.to_string(),
+            owner_id,
+            visibility,
+            ..Default::default()
+        }
+    }
+
+    pub fn rename(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    pub fn set_visibility(&mut self, visibility: CollectionVisibility) {
+        self.visibility = visibility;
+    }
+
+    /// `pos`の位置(Noneなら末尾)にルートを入れる
+    pub fn add_route(&mut self, route_id: RouteId, pos: Option<usize>) -> ApplicationResult<()> {
+        if self.route_ids.contains(&route_id) {
+            return Err(ApplicationError::InvalidOperation(
+                "The route is already in the collection",
+            ));
+        }
+        if self.route_ids.len() >= MAX_ROUTES {
+            return Err(ApplicationError::InvalidOperation(
+                "A collection cannot have more than 500 routes",
+            ));
+        }
+        let pos = pos.unwrap_or(self.route_ids.len());
+        if pos > self.route_ids.len() {
+            return Err(ApplicationError::ValidationError(format!(
+                "pos must be in 0..={}, but got {}",
+                self.route_ids.len(),
+                pos
+            )));
+        }
+        self.route_ids.insert(pos, route_id);
+        Ok(())
+    }
+
+    pub fn remove_route(&mut self, route_id: &RouteId) -> ApplicationResult<()> {
+        let pos = self
+            .route_ids
+            .iter()
+            .position(|id| id == route_id)
+            .ok_or_else(|| {
+                ApplicationError::ResourceNotFound(format!(
+                    "Route {} is not in Collection {}",
+                    route_id, self.id
+                ))
+            })?;
+        self.route_ids.remove(pos);
+        Ok(())
+    }
+
+    /// `route_ids`の順に並べ替える (今入っているルートをちょうど1回ずつ含まなければならない)
+    pub fn reorder(&mut self, route_ids: Vec<RouteId>) -> ApplicationResult<()> {
+        let current = self
+            .route_ids
+            .iter()
+            .map(ToString::to_string)
+            .collect::<HashSet<_>>();
+        let requested = route_ids
+            .iter()
+            .map(ToString::to_string)
+            .collect::<HashSet<_>>();
+        if route_ids.len() != self.route_ids.len() || current != requested {
+            return Err(ApplicationError::ValidationError(
+                "route_ids must contain every route in the collection exactly once".into(),
+            ));
+        }
+        self.route_ids = route_ids;
+        Ok(())
+    }
+
+    /// 公開範囲を踏まえた，ユーザーの権限
+    ///
+    /// `granted`はユーザーに与えられている権限で，`user_id`がNoneならログインしていない
+    pub fn permission_type(
+        &self,
+        user_id: Option<&UserId>,
+        granted: PermissionType,
+    ) -> PermissionType {
+        if user_id == Some(&self.owner_id) {
+            return PermissionType::Owner;
+        }
+        let granted = if user_id.is_some() {
+            granted
+        } else {
+            PermissionType::None
+        };
+        match self.visibility {
+            CollectionVisibility::Private => PermissionType::None,
+            CollectionVisibility::Shared => granted,
+            CollectionVisibility::Public if granted < PermissionType::Viewer => {
+                PermissionType::Viewer
+            }
+            CollectionVisibility::Public => granted,
+        }
+    }
+
+    pub fn authorize(
+        &self,
+        user_id: Option<&UserId>,
+        granted: PermissionType,
+        target_type: PermissionType,
+    ) -> ApplicationResult<()> {
+        let permission_type = self.permission_type(user_id, granted);
+        (target_type <= permission_type).then(|| ()).ok_or_else(|| {
+            ApplicationError::AuthorizationError(format!(
+                "User {} doesn't have {} permission on Collection {} (actual permission: {}).",
+                user_id.map_or_else(|| "(anonymous)".to_string(), ToString::to_string),
+                target_type,
+                self.id,
+                permission_type
+            ))
+        })
+    }
+}
+
+#[cfg(any(test, feature = "fixtures"))]
+pub(crate) mod tests {
+    use rstest::rstest;
+
+    use crate::model::user::tests::UserIdFixtures;
+
+    use super::*;
+
+    fn route_ids(ids: &[&str]) -> Vec<RouteId> {
+        ids.iter()
+            .map(|id| RouteId::from_string(id.to_string()))
+            .collect()
+    }
+
+    #[rstest]
+    #[case::append(None, &["a", "b", "c", "new"])]
+    #[case::prepend(Some(0), &["new", "a", "b", "c"])]
+    #[case::middle(Some(2), &["a", "b", "new", "c"])]
+    fn can_add_route(#[case] pos: Option<usize>, #[case] expected: &[&str]) {
+        let mut collection = Collection::rides_in_kanto();
+        collection
+            .add_route(RouteId::from_string("new".into()), pos)
+            .unwrap();
+        assert_eq!(collection.route_ids, route_ids(expected));
+    }
+
+    #[rstest]
+    fn cannot_add_route_twice_or_out_of_range() {
+        let mut collection = Collection::rides_in_kanto();
+        assert!(matches!(
+            collection.add_route(RouteId::from_string("a".into()), None),
+            Err(ApplicationError::InvalidOperation(_))
+        ));
+        assert!(matches!(
+            collection.add_route(RouteId::from_string("new".into()), Some(4)),
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
+    #[rstest]
+    fn can_remove_route() {
+        let mut collection = Collection::rides_in_kanto();
+        collection
+            .remove_route(&RouteId::from_string("b".into()))
+            .unwrap();
+        assert_eq!(collection.route_ids, route_ids(&["a", "c"]));
+        assert!(matches!(
+            collection.remove_route(&RouteId::from_string("b".into())),
+            Err(ApplicationError::ResourceNotFound(_))
+        ));
+    }
+
+    #[rstest]
+    #[case::reversed(&["c", "b", "a"], true)]
+    #[case::missing(&["c", "b"], false)]
+    #[case::duplicated(&["c", "b", "b"], false)]
+    #[case::unknown(&["c", "b", "x"], false)]
+    fn can_reorder_only_with_permutation(#[case] ids: &[&str], #[case] is_ok: bool) {
+        let mut collection = Collection::rides_in_kanto();
+        let result = collection.reorder(route_ids(ids));
+        if is_ok {
+            assert_eq!(result, Ok(()));
+            assert_eq!(collection.route_ids, route_ids(ids));
+        } else {
+            assert!(matches!(result, Err(ApplicationError::ValidationError(_))));
+            assert_eq!(collection.route_ids, route_ids(&["a", "b", "c"]));
+        }
+    }
+
+    #[rstest]
+    #[case::owner(
+        CollectionVisibility::Private,
+        Some(UserId::doncic()),
+        PermissionType::None,
+        PermissionType::Owner
+    )]
+    #[case::private(
+        CollectionVisibility::Private,
+        Some(UserId::porzingis()),
+        PermissionType::Editor,
+        PermissionType::None
+    )]
+    #[case::shared(
+        CollectionVisibility::Shared,
+        Some(UserId::porzingis()),
+        PermissionType::Editor,
+        PermissionType::Editor
+    )]
+    #[case::shared_anonymous(
+        CollectionVisibility::Shared,
+        None,
+        PermissionType::Editor,
+        PermissionType::None
+    )]
+    #[case::public(
+        CollectionVisibility::Public,
+        None,
+        PermissionType::None,
+        PermissionType::Viewer
+    )]
+    #[case::public_editor(
+        CollectionVisibility::Public,
+        Some(UserId::porzingis()),
+        PermissionType::Editor,
+        PermissionType::Editor
+    )]
+    fn can_find_permission_type(
+        #[case] visibility: CollectionVisibility,
+        #[case] user_id: Option<UserId>,
+        #[case] granted: PermissionType,
+        #[case] expected: PermissionType,
+    ) {
+        let mut collection = Collection::rides_in_kanto();
+        collection.set_visibility(visibility);
+        assert_eq!(
+            collection.permission_type(user_id.as_ref(), granted),
+            expected
+        );
+    }
+
+    #[rstest]
+    fn cannot_edit_public_collection_as_viewer() {
+        let mut collection = Collection::rides_in_kanto();
+        collection.set_visibility(CollectionVisibility::Public);
+        assert_eq!(
+            collection.authorize(None, PermissionType::None, PermissionType::Viewer),
+            Ok(())
+        );
+        assert!(matches!(
+            collection.authorize(
+                Some(&UserId::porzingis()),
+                PermissionType::Viewer,
+                PermissionType::Editor
+            ),
+            Err(ApplicationError::AuthorizationError(_))
+        ));
+    }
+
+    pub trait CollectionFixtures {
+        fn rides_in_kanto() -> Collection {
+            Collection {
+                id: CollectionId::from_string("collection0".into()),
+                name: "Rides in Kanto".into(),
+                owner_id: UserId::doncic(),
+                route_ids: route_ids(&["a", "b", "c"]),
+                ..Default::default()
+            }
+        }
+    }
+
+    impl CollectionFixtures for Collection {}
+}
@@ -0,0 +1,240 @@
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+use crate::model::route::RouteGpx;
+
+/// コレクションの各ルートのGPXファイルをまとめたzipファイル
+#[derive(Clone, Debug)]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct CollectionArchive {
+    name: String,
+    data: Vec<u8>,
+}
+
+impl CollectionArchive {
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+
+    /// 各GPXを"{ルート名}.gpx"としてまとめる (同名のファイルには" (2)"などを付ける)
+    pub fn from_gpxs(
+        name: &str,
+        gpxs: Vec<RouteGpx>,
+        modified_at: DateTime<Utc>,
+    ) -> ApplicationResult<Self> {
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .last_modified_time(Self::zip_date_time(modified_at));
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let mut used_names = HashSet::new();
+        for gpx in gpxs {
+            let file_name = Self::unique_file_name(gpx.name(), &mut used_names);
+            writer
+                .start_file(file_name, options)
+                .map_err(to_domain_error)?;
+            writer.write_all(gpx.as_slice()).map_err(to_domain_error)?;
+        }
+        let data = writer.finish().map_err(to_domain_error)?.into_inner();
+
+        Ok(Self {
+            name: name.to_string(),
+            data,
+        })
+    }
+
+    /// zipに書く更新日時 (zipで表せない1980年より前などは1980/01/01 00:00:00にする)
+    fn zip_date_time(datetime: DateTime<Utc>) -> zip::DateTime {
+        zip::DateTime::from_date_and_time(
+            datetime.year() as u16,
+            datetime.month() as u8,
+            datetime.day() as u8,
+            datetime.hour() as u8,
+            datetime.minute() as u8,
+            datetime.second() as u8,
+        )
+        .unwrap_or_default()
+    }
+
+    /// パス区切りなど，ファイル名に使えない文字を'_'に置き換え，重複しない名前にする
+    ///
+    /// 大文字と小文字を区別しないファイルシステムに展開しても上書きされないよう，
+    /// 大文字と小文字の違いだけの名前も重複とみなす (`used_names`は小文字にして持つ)
+    fn unique_file_name(name: &str, used_names: &mut HashSet<String>) -> String {
+        let stem = name
+            .trim()
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                c if c.is_control() => '_',
+                c => c,
+            })
+            .collect::<String>();
+        let stem = match stem.as_str() {
+            "" | "." | ".." => "untitled".to_string(),
+            _ => stem,
+        };
+
+        let mut file_name = format!("{}.gpx", stem);
+        let mut count = 1;
+        while used_names.contains(&file_name.to_lowercase()) {
+            count += 1;
+            file_name = format!("{} ({}).gpx", stem, count);
+        }
+        used_names.insert(file_name.to_lowercase());
+        file_name
+    }
+}
+
+fn to_domain_error<E: std::fmt::Debug>(err: E) -> ApplicationError {
+    ApplicationError::DomainError(format!("Failed to write zip archive ({:?})", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use chrono::TimeZone;
+    use rstest::rstest;
+    use zip::ZipArchive;
+
+    use crate::model::route::route_gpx::tests::RouteGpxFixtures;
+
+    use super::*;
+
+    /// zipクレートで展開して，(ファイル名, 中身)の一覧を返す (CRCも確かめられる)
+    fn unzip(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut archive = ZipArchive::new(Cursor::new(data)).unwrap();
+        (0..archive.len())
+            .map(|i| {
+                let mut file = archive.by_index(i).unwrap();
+                assert_eq!(file.compression(), CompressionMethod::Deflated);
+                let mut content = Vec::new();
+                file.read_to_end(&mut content).unwrap();
+                (file.name().to_string(), content)
+            })
+            .collect()
+    }
+
+    #[rstest]
+    fn can_archive_gpxs() {
+        let archive = CollectionArchive::from_gpxs(
+            "Rides in Kanto",
+            vec![RouteGpx::route0(), RouteGpx::route0_stages()],
+            Utc.ymd(2021, 9, 15).and_hms(12, 30, 10),
+        )
+        .unwrap();
+
+        assert_eq!(archive.name(), "Rides in Kanto");
+        let modified = ZipArchive::new(Cursor::new(archive.as_slice()))
+            .unwrap()
+            .by_index(0)
+            .unwrap()
+            .last_modified();
+        assert_eq!(
+            (
+                modified.year(),
+                modified.month(),
+                modified.day(),
+                modified.hour(),
+                modified.minute(),
+                modified.second()
+            ),
+            (2021, 9, 15, 12, 30, 10)
+        );
+        assert_eq!(
+            unzip(archive.as_slice()),
+            vec![
+                (
+                    "route0.gpx".to_string(),
+                    RouteGpx::route0().as_slice().to_vec()
+                ),
+                (
+                    "route0 (2).gpx".to_string(),
+                    RouteGpx::route0_stages().as_slice().to_vec()
+                ),
+            ]
+        );
+    }
+
+    #[rstest]
+    fn can_archive_empty_collection() {
+        let archive =
+            CollectionArchive::from_gpxs("empty", Vec::new(), chrono::MIN_DATETIME).unwrap();
+        assert_eq!(archive.as_slice().len(), 22);
+        assert!(unzip(archive.as_slice()).is_empty());
+    }
+
+    #[rstest]
+    fn can_archive_non_ascii_names() {
+        let gpx = RouteGpx::from_routes("皇居ラン", Vec::new()).unwrap();
+        let archive =
+            CollectionArchive::from_gpxs("runs", vec![gpx.clone()], chrono::MIN_DATETIME).unwrap();
+        assert_eq!(
+            unzip(archive.as_slice()),
+            vec![("皇居ラン.gpx".to_string(), gpx.as_slice().to_vec())]
+        );
+    }
+
+    #[rstest]
+    #[case::plain("route0", "route0.gpx")]
+    #[case::separators("Tokyo/Chiba: day 1?", "Tokyo_Chiba_ day 1_.gpx")]
+    #[case::empty("  ", "untitled.gpx")]
+    #[case::parent("..", "untitled.gpx")]
+    fn can_sanitize_file_name(#[case] name: &str, #[case] expected: &str) {
+        assert_eq!(
+            CollectionArchive::unique_file_name(name, &mut HashSet::new()),
+            expected
+        );
+    }
+
+    #[rstest]
+    fn file_names_differing_only_in_case_are_unique() {
+        let mut used_names = HashSet::new();
+        let names = ["Route0", "route0", "ROUTE0", "route0 (2)"]
+            .iter()
+            .map(|name| CollectionArchive::unique_file_name(name, &mut used_names))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "Route0.gpx",
+                "route0 (2).gpx",
+                "ROUTE0 (3).gpx",
+                "route0 (2) (2).gpx"
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case::start_of_dos_era(Utc.ymd(1980, 1, 1).and_hms(0, 0, 0), (1980, 1, 1, 0, 0, 0))]
+    #[case::before_dos_era(Utc.ymd(1970, 1, 1).and_hms(0, 0, 0), (1980, 1, 1, 0, 0, 0))]
+    #[case::afternoon(Utc.ymd(2021, 9, 15).and_hms(12, 30, 11), (2021, 9, 15, 12, 30, 11))]
+    fn can_convert_into_zip_date_time(
+        #[case] datetime: DateTime<Utc>,
+        #[case] expected: (u16, u8, u8, u8, u8, u8),
+    ) {
+        let converted = CollectionArchive::zip_date_time(datetime);
+        assert_eq!(
+            (
+                converted.year(),
+                converted.month(),
+                converted.day(),
+                converted.hour(),
+                converted.minute(),
+                converted.second()
+            ),
+            expected
+        );
+    }
+}
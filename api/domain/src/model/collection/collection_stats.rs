@@ -0,0 +1,63 @@
+use derive_more::From;
+use getset::Getters;
+use serde::Serialize;
+
+use crate::model::route::{Distance, Elevation, RouteInfo};
+
+/// コレクションに含まれるルートの合計値
+#[derive(Clone, Debug, Default, From, Getters, Serialize)]
+#[get = "pub"]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct CollectionStats {
+    route_count: usize,
+    total_distance: Distance,
+    ascent_elevation_gain: Elevation,
+    descent_elevation_gain: Elevation,
+}
+
+impl From<&[RouteInfo]> for CollectionStats {
+    fn from(infos: &[RouteInfo]) -> Self {
+        Self {
+            route_count: infos.len(),
+            total_distance: infos.iter().map(|info| *info.total_distance()).sum(),
+            ascent_elevation_gain: infos.iter().map(|info| *info.ascent_elevation_gain()).sum(),
+            descent_elevation_gain: infos
+                .iter()
+                .map(|info| *info.descent_elevation_gain())
+                .sum(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use rstest::rstest;
+
+    use crate::model::route::route_info::tests::RouteInfoFixtures;
+
+    use super::*;
+
+    #[rstest]
+    fn can_sum_up_routes() {
+        let infos = vec![
+            RouteInfo::yokohama_to_chiba(),
+            RouteInfo::filled_route0(100, 120, 1000., 0., 0),
+        ];
+        assert_eq!(
+            CollectionStats::from(infos.as_slice()),
+            CollectionStats {
+                route_count: 2,
+                total_distance: Distance::try_from(47779.709825324135).unwrap(),
+                ascent_elevation_gain: Elevation::try_from(110).unwrap(),
+                descent_elevation_gain: Elevation::try_from(120).unwrap(),
+            }
+        );
+    }
+
+    #[rstest]
+    fn empty_collection_has_zero_stats() {
+        assert_eq!(CollectionStats::from(&[][..]), CollectionStats::default());
+    }
+}
@@ -7,22 +7,49 @@ use route_bucket_utils::{ApplicationError, ApplicationResult};
 
 pub use self::bounding_box::BoundingBox;
 pub use self::coordinate::Coordinate;
+pub use self::daylight::{DarkSection, DaylightChecker, DaylightReport, WaypointDaylight};
+pub use self::difficulty::{Difficulty, DifficultyRating};
+pub use self::elevation_interpolation::ElevationInterpolation;
+pub use self::elevation_override::ElevationOverride;
+pub use self::elevation_profile::ElevationProfile;
+pub use self::elevation_source::ElevationSource;
+pub use self::energy::{EnergyExpenditure, EnergyModel};
+pub use self::metadata::{BikeType, RouteMetadata, SurfaceType};
+pub use self::proximity::{ProximityQuery, ProximityTarget, MAX_NEARBY_CANDIDATES};
 pub use self::route_gpx::RouteGpx;
 pub use self::route_info::RouteInfo;
-pub use self::search_query::RouteSearchQuery;
+pub use self::search_cursor::{RouteSearchCursor, RouteSortValue};
+pub use self::search_query::{RouteSearchQuery, RouteSortKey, SortOrder};
 pub use self::segment_list::{
     DrawingMode, Operation, OperationId, OperationType, Segment, SegmentList, SegmentTemplate,
+    MAX_TRACK_POINTS,
 };
-pub use self::types::{Distance, Elevation, Latitude, Longitude, Polyline};
+pub use self::similarity::{RouteSimilarity, SimilarExtentQuery, MAX_SIMILAR_CANDIDATES};
+pub use self::stage::{Stage, StagePlan};
+pub use self::tag::{Tag, TagCount};
+pub use self::types::{DemVersion, Distance, Elevation, Latitude, Longitude, Polyline};
 
 use super::types::NanoId;
 
 pub(crate) mod bounding_box;
 pub(crate) mod coordinate;
+pub(crate) mod daylight;
+pub(crate) mod difficulty;
+pub(crate) mod elevation_interpolation;
+pub(crate) mod elevation_override;
+pub(crate) mod elevation_profile;
+pub(crate) mod elevation_source;
+pub(crate) mod energy;
+pub(crate) mod metadata;
+pub(crate) mod proximity;
 pub(crate) mod route_gpx;
 pub(crate) mod route_info;
+pub(crate) mod search_cursor;
 pub(crate) mod search_query;
 pub(crate) mod segment_list;
+pub(crate) mod similarity;
+pub(crate) mod stage;
+pub(crate) mod tag;
 pub(crate) mod types;
 
 pub type RouteId = NanoId<Route, 11>;
@@ -106,10 +133,27 @@ impl Route {
         self.info.ascent_elevation_gain = asc_gain;
         self.info.descent_elevation_gain = desc_gain;
         self.info.total_distance = self.seg_list.get_total_distance()?;
+        self.info.bounding_box = self.seg_list.calc_bounding_box().ok();
+        self.info.difficulty = Difficulty::calc(
+            &self.seg_list,
+            self.info.total_distance,
+            self.info.ascent_elevation_gain,
+        );
 
         Ok(())
     }
 
+    /// 標高の取り方を変え，元の標高を反映していたセグメントの標高は求め直させる
+    pub fn set_elevation_source(&mut self, elevation_source: ElevationSource) {
+        if self.info.elevation_source != elevation_source {
+            self.seg_list
+                .iter_mut()
+                .filter(|seg| seg.has_source_elevations())
+                .for_each(Segment::clear_elevations);
+        }
+        self.info.set_elevation_source(elevation_source);
+    }
+
     pub fn iter_seg_mut(&mut self) -> IterMut<Segment> {
         self.seg_list.iter_mut()
     }
@@ -246,6 +290,10 @@ pub(crate) mod tests {
         #[from(full_route_filled)] expected: Route,
     ) {
         route.calc_route_features_from_seg_list().unwrap();
+        assert_eq!(
+            route.info().bounding_box(),
+            &Some(route.seg_list().calc_bounding_box().unwrap())
+        );
         assert_eq!(route, expected)
     }
 
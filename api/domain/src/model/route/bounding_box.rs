@@ -1,23 +1,105 @@
 use derive_more::From;
+use getset::Getters;
 use serde::Serialize;
 
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
 use super::Coordinate;
 
-#[derive(Clone, Debug, Serialize, From)]
+#[derive(Clone, Debug, Serialize, From, Getters)]
+#[get = "pub"]
 #[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
 pub struct BoundingBox {
     min_coord: Coordinate,
     max_coord: Coordinate,
 }
 
+impl BoundingBox {
+    pub fn new(
+        min_latitude: f64,
+        min_longitude: f64,
+        max_latitude: f64,
+        max_longitude: f64,
+    ) -> ApplicationResult<Self> {
+        Ok(Self {
+            min_coord: Coordinate::new(min_latitude, min_longitude)?,
+            max_coord: Coordinate::new(max_latitude, max_longitude)?,
+        })
+    }
+
+    /// 地図の表示範囲のクエリ(`minLon,minLat,maxLon,maxLat`)を読む
+    ///
+    /// 日付変更線をまたぐ範囲は，`minLon`が`maxLon`より大きくなる
+    pub fn parse_viewport(viewport: &str) -> ApplicationResult<Self> {
+        let invalid = || {
+            ApplicationError::ValidationError(format!(
+                "Invalid bbox {:?} (expected minLon,minLat,maxLon,maxLat)",
+                viewport
+            ))
+        };
+        let values = viewport
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid())?;
+        match values.as_slice() {
+            [min_lon, min_lat, max_lon, max_lat] if min_lat <= max_lat => {
+                Self::new(*min_lat, *min_lon, *max_lat, *max_lon)
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    /// `parse_viewport`で読める形式にする
+    pub fn to_viewport(&self) -> String {
+        format!(
+            "{},{},{},{}",
+            self.min_coord.longitude.value(),
+            self.min_coord.latitude.value(),
+            self.max_coord.longitude.value(),
+            self.max_coord.latitude.value()
+        )
+    }
+
+    /// 日付変更線をまたいでいるか
+    pub fn crosses_antimeridian(&self) -> bool {
+        self.min_coord.longitude > self.max_coord.longitude
+    }
+}
+
 #[cfg(any(test, feature = "fixtures"))]
 pub(crate) mod tests {
     use std::convert::TryInto;
 
+    use rstest::rstest;
+
     use crate::model::route::coordinate::tests::CoordinateFixtures;
 
     use super::*;
 
+    #[rstest]
+    #[case::normal("139.62607,35.46798,140.11135,35.68048", false)]
+    #[case::with_spaces(" 139.62607, 35.46798 ,140.11135,35.68048", false)]
+    #[case::antimeridian("179.5,35.46798,-179.5,35.68048", true)]
+    fn can_parse_viewport(#[case] viewport: &str, #[case] crosses_antimeridian: bool) {
+        let bbox = BoundingBox::parse_viewport(viewport).unwrap();
+        assert_eq!(bbox.crosses_antimeridian(), crosses_antimeridian);
+        assert_eq!(bbox.min_coord().latitude().value(), 35.46798);
+        assert_eq!(
+            BoundingBox::parse_viewport(&bbox.to_viewport()).unwrap(),
+            bbox
+        );
+    }
+
+    #[rstest]
+    #[case::too_few("139.6,35.4,140.1")]
+    #[case::not_a_number("139.6,35.4,140.1,north")]
+    #[case::lat_reversed("139.6,35.6,140.1,35.4")]
+    #[case::out_of_range("139.6,35.4,140.1,95")]
+    fn cannot_parse_invalid_viewport(#[case] viewport: &str) {
+        assert!(BoundingBox::parse_viewport(viewport).is_err());
+    }
+
     pub trait BoundingBoxFixture {
         fn yokohama() -> BoundingBox {
             BoundingBox {
@@ -32,12 +114,14 @@ pub(crate) mod tests {
                     latitude: 35.46798.try_into().unwrap(),
                     longitude: 139.62607.try_into().unwrap(),
                     elevation: None,
+                    source_elevation: None,
                     distance_from_start: None,
                 },
                 max_coord: Coordinate {
                     latitude: 35.68048.try_into().unwrap(),
                     longitude: 140.11135.try_into().unwrap(),
                     elevation: None,
+                    source_elevation: None,
                     distance_from_start: None,
                 },
             }
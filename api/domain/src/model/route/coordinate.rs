@@ -11,6 +11,9 @@ use route_bucket_utils::{ApplicationError, ApplicationResult};
 
 use super::types::{Distance, Elevation, Latitude, Longitude, Polyline};
 
+/// 局所的に平面とみなして距離を測るときの地球の半径 [m]
+pub(super) const EARTH_RADIUS: f64 = 6_371_008.8;
+
 /// Value Object for Coordinates
 #[derive(Clone, Debug, PartialEq, Getters, Deserialize, Serialize)]
 #[get = "pub"]
@@ -19,6 +22,9 @@ pub struct Coordinate {
     pub(super) longitude: Longitude,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(super) elevation: Option<Elevation>,
+    /// 取り込んだファイルに書かれていた元の標高
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) source_elevation: Option<Elevation>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(super) distance_from_start: Option<Distance>,
 }
@@ -29,6 +35,7 @@ impl Coordinate {
             latitude: Latitude::try_from(lat)?,
             longitude: Longitude::try_from(lon)?,
             elevation: None,
+            source_elevation: None,
             distance_from_start: None,
         };
         Ok(coord)
@@ -44,9 +51,22 @@ impl Coordinate {
             })
     }
 
+    pub fn set_source_elevation(&mut self, source_elevation: Option<Elevation>) {
+        self.source_elevation = source_elevation;
+    }
+
     pub fn set_distance_from_start(&mut self, distance: Distance) {
         self.distance_from_start = Some(distance);
     }
+
+    /// `origin`の周りを平面とみなしたときの位置 (xが東向き，yが北向き，単位はm)
+    pub fn project_locally(&self, origin: &Coordinate) -> geo::Coordinate<f64> {
+        let (lat0, lon0) = (origin.latitude.value(), origin.longitude.value());
+        geo::Coordinate::from((
+            (self.longitude.value() - lon0).to_radians() * lat0.to_radians().cos() * EARTH_RADIUS,
+            (self.latitude.value() - lat0).to_radians() * EARTH_RADIUS,
+        ))
+    }
 }
 
 impl From<Coordinate> for geo::Coordinate<f64> {
@@ -66,6 +86,7 @@ impl TryFrom<geo::Coordinate<f64>> for Coordinate {
             latitude: Latitude::try_from(geo_coord.y)?,
             longitude: Longitude::try_from(geo_coord.x)?,
             elevation: None,
+            source_elevation: None,
             distance_from_start: None,
         })
     }
@@ -89,7 +110,15 @@ impl TryFrom<Polyline> for Vec<Coordinate> {
     type Error = ApplicationError;
 
     fn try_from(value: Polyline) -> Result<Self, Self::Error> {
-        let line_str = decode_polyline(&String::from(value), 5).map_err(|err| {
+        let line = String::from(value);
+        // NOTE: decode_polyline は範囲外の文字でpanicするので，先に弾いておく
+        if let Some(invalid_char) = line.chars().find(|c| !('?'..='~').contains(c)) {
+            return Err(ApplicationError::DomainError(format!(
+                "failed to decode polyline: invalid character {:?}",
+                invalid_char
+            )));
+        }
+        let line_str = decode_polyline(&line, 5).map_err(|err| {
             ApplicationError::DomainError(format!("failed to encode polyline: {}", err))
         })?;
         line_str.into_iter().map(Coordinate::try_from).try_collect()
@@ -238,11 +267,22 @@ pub(crate) mod tests {
         assert_eq!(Vec::try_from(polyline), Ok(coords))
     }
 
+    #[rstest]
+    #[case::invalid_character("{inwE}uesY coh@")]
+    #[case::truncated("{inwE}ues")]
+    fn cannot_convert_invalid_polyline_into_coords(#[case] line: &str) {
+        assert!(matches!(
+            Vec::<Coordinate>::try_from(Polyline::from(String::from(line))),
+            Err(ApplicationError::DomainError(_))
+        ))
+    }
+
     fn init_coord(lat: f64, lon: f64, ele: Option<i32>, dist: Option<f64>) -> Coordinate {
         Coordinate {
             latitude: lat.try_into().unwrap(),
             longitude: lon.try_into().unwrap(),
             elevation: ele.map(Elevation::try_from).transpose().unwrap(),
+            source_elevation: None,
             distance_from_start: dist.map(Distance::try_from).transpose().unwrap(),
         }
     }
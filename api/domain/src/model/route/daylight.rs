@@ -0,0 +1,367 @@
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use getset::Getters;
+use serde::Serialize;
+
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+use super::coordinate::Coordinate;
+use super::segment_list::SegmentList;
+use super::types::Distance;
+
+/// 日の出・日の入りとみなす太陽の高度 (大気差と視半径を考慮) [deg]
+const SUNRISE_SUN_ALTITUDE: f64 = -0.833;
+/// 日の出・日の入りを求めるときに，太陽の位置を計算し直す回数
+const SOLAR_ITERATIONS: usize = 4;
+const UNIX_EPOCH_JULIAN_DAY: f64 = 2440587.5;
+const J2000_JULIAN_DAY: f64 = 2451545.;
+const SECONDS_PER_DAY: f64 = 86400.;
+
+/// 出発日時と平均速度から，ルート上の各地点を明るいうちに走れるかを調べる
+#[derive(Clone, Debug, Getters)]
+#[get = "pub"]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct DaylightChecker {
+    departure: DateTime<Utc>,
+    /// [m/s]
+    speed: f64,
+}
+
+/// ウェイポイント(スタート・ゴールを含む)での日の出・日の入り
+#[derive(Clone, Debug, Getters, Serialize)]
+#[get = "pub"]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct WaypointDaylight {
+    coord: Coordinate,
+    estimated_time: DateTime<Utc>,
+    /// 白夜・極夜の場合はNone
+    sunrise: Option<DateTime<Utc>>,
+    sunset: Option<DateTime<Utc>>,
+    is_dark: bool,
+}
+
+/// 暗い中を走る区間
+#[derive(Clone, Debug, Getters, Serialize)]
+#[get = "pub"]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct DarkSection {
+    start_distance: Distance,
+    end_distance: Distance,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Getters, Serialize)]
+#[get = "pub"]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct DaylightReport {
+    waypoints: Vec<WaypointDaylight>,
+    dark_sections: Vec<DarkSection>,
+    estimated_arrival: DateTime<Utc>,
+    finishes_before_dark: bool,
+}
+
+impl DaylightChecker {
+    /// `speed` is in km/h
+    pub fn new(departure: DateTime<Utc>, speed: f64) -> ApplicationResult<Self> {
+        if speed > 0. {
+            Ok(Self {
+                departure,
+                speed: speed / 3.6,
+            })
+        } else {
+            Err(ApplicationError::InvalidOperation(
+                "Speed must be positive to estimate the time of arrival.",
+            ))
+        }
+    }
+
+    /// `seg_list`には`distance_from_start`が付与されている必要がある
+    pub fn check(&self, seg_list: &SegmentList) -> ApplicationResult<DaylightReport> {
+        let waypoints = seg_list
+            .iter()
+            .filter_map(|seg| seg.iter().next())
+            .map(|coord| {
+                let estimated_time = self.estimate_time(coord)?;
+                let (sunrise, sunset) = calc_sunrise_and_sunset(coord, estimated_time);
+                Ok(WaypointDaylight {
+                    coord: coord.clone(),
+                    estimated_time,
+                    sunrise,
+                    sunset,
+                    is_dark: is_dark(coord, estimated_time),
+                })
+            })
+            .collect::<ApplicationResult<Vec<_>>>()?;
+
+        let mut dark_sections: Vec<DarkSection> = Vec::new();
+        let mut is_prev_dark = false;
+        let mut estimated_arrival = self.departure;
+        for coord in seg_list.iter().flat_map(|seg| seg.iter()) {
+            let estimated_time = self.estimate_time(coord)?;
+            let distance = coord.distance_from_start().unwrap_or_else(Distance::zero);
+            let is_curr_dark = is_dark(coord, estimated_time);
+            match dark_sections.last_mut() {
+                Some(section) if is_prev_dark && is_curr_dark => {
+                    section.end_distance = distance;
+                    section.end_time = estimated_time;
+                }
+                _ if is_curr_dark => dark_sections.push(DarkSection {
+                    start_distance: distance,
+                    end_distance: distance,
+                    start_time: estimated_time,
+                    end_time: estimated_time,
+                }),
+                _ => (),
+            }
+            is_prev_dark = is_curr_dark;
+            estimated_arrival = estimated_time;
+        }
+
+        Ok(DaylightReport {
+            finishes_before_dark: !is_prev_dark,
+            waypoints,
+            dark_sections,
+            estimated_arrival,
+        })
+    }
+
+    fn estimate_time(&self, coord: &Coordinate) -> ApplicationResult<DateTime<Utc>> {
+        let distance = coord.distance_from_start().ok_or_else(|| {
+            ApplicationError::DomainError(format!(
+                "Cannot estimate the time without distance_from_start! ({:?})",
+                coord
+            ))
+        })?;
+        Ok(self.departure + Duration::milliseconds((distance.value() / self.speed * 1000.) as i64))
+    }
+}
+
+fn to_julian_day(time: DateTime<Utc>) -> f64 {
+    time.timestamp_millis() as f64 / 1000. / SECONDS_PER_DAY + UNIX_EPOCH_JULIAN_DAY
+}
+
+fn from_julian_day(julian_day: f64) -> DateTime<Utc> {
+    Utc.timestamp_millis(((julian_day - UNIX_EPOCH_JULIAN_DAY) * SECONDS_PER_DAY * 1000.) as i64)
+}
+
+/// 太陽の(赤経 [deg], 赤緯 [rad])
+///
+/// `days`はJ2000からの日数．日の出・日の入りと暗さの判定は，どちらもこの式を使う
+///
+/// 参考: https://aa.usno.navy.mil/faq/sun_approx
+fn calc_sun_position(days: f64) -> (f64, f64) {
+    let mean_longitude = (280.459 + 0.98564736 * days).rem_euclid(360.);
+    let mean_anomaly = (357.529 + 0.98560028 * days).rem_euclid(360.).to_radians();
+    let ecliptic_longitude =
+        (mean_longitude + 1.915 * mean_anomaly.sin() + 0.020 * (2. * mean_anomaly).sin())
+            .to_radians();
+    let obliquity = (23.439 - 0.00000036 * days).to_radians();
+
+    let right_ascension = (obliquity.cos() * ecliptic_longitude.sin())
+        .atan2(ecliptic_longitude.cos())
+        .to_degrees();
+    let declination = (obliquity.sin() * ecliptic_longitude.sin()).asin();
+    (right_ascension, declination)
+}
+
+/// 太陽の時角 [deg] (-180 ~ 180，南中で0)
+fn calc_hour_angle(coord: &Coordinate, days: f64, right_ascension: f64) -> f64 {
+    let sidereal_time = 280.46061837 + 360.98564736629 * days;
+    (sidereal_time + coord.longitude().value() - right_ascension + 180.).rem_euclid(360.) - 180.
+}
+
+/// 太陽の高度 [deg]
+fn calc_sun_altitude(coord: &Coordinate, time: DateTime<Utc>) -> f64 {
+    let days = to_julian_day(time) - J2000_JULIAN_DAY;
+    let (right_ascension, declination) = calc_sun_position(days);
+    let hour_angle = calc_hour_angle(coord, days, right_ascension).to_radians();
+
+    let latitude = coord.latitude().value().to_radians();
+    (latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos())
+        .asin()
+        .to_degrees()
+}
+
+fn is_dark(coord: &Coordinate, time: DateTime<Utc>) -> bool {
+    calc_sun_altitude(coord, time) < SUNRISE_SUN_ALTITUDE
+}
+
+/// `time`の地方時での日付における日の出・日の入り
+///
+/// 南中を求めてから，太陽の高度が`SUNRISE_SUN_ALTITUDE`になる時角の時刻を求める
+/// 太陽の位置は時刻によって変わるので，何度か計算し直して近づける
+fn calc_sunrise_and_sunset(
+    coord: &Coordinate,
+    time: DateTime<Utc>,
+) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+    let longitude = coord.longitude().value();
+    let local_date = (time + Duration::seconds((longitude / 15. * 3600.) as i64)).date();
+    // 地方平均時の正午
+    let local_noon = local_date
+        .naive_utc()
+        .signed_duration_since(NaiveDate::from_ymd(2000, 1, 1))
+        .num_days() as f64
+        - longitude / 360.;
+
+    let latitude = coord.latitude().value().to_radians();
+    let sunrise_hour_angle = |declination: f64| {
+        let cos_hour_angle = (SUNRISE_SUN_ALTITUDE.to_radians().sin()
+            - latitude.sin() * declination.sin())
+            / (latitude.cos() * declination.cos());
+        // 範囲外なら白夜・極夜
+        (-1. ..=1.)
+            .contains(&cos_hour_angle)
+            .then(|| cos_hour_angle.acos().to_degrees())
+    };
+
+    let transit = calc_time_of_hour_angle(coord, local_noon, |_| Some(0.));
+    let at = |sign: f64| {
+        transit
+            .and_then(|transit| {
+                calc_time_of_hour_angle(coord, transit, |declination| {
+                    sunrise_hour_angle(declination).map(|hour_angle| sign * hour_angle)
+                })
+            })
+            .map(|days| from_julian_day(days + J2000_JULIAN_DAY))
+    };
+    match (at(-1.), at(1.)) {
+        (Some(sunrise), Some(sunset)) => (Some(sunrise), Some(sunset)),
+        _ => (None, None),
+    }
+}
+
+/// `days`の近くで，太陽の時角が`target(赤緯)`になる時刻 (J2000からの日数)
+fn calc_time_of_hour_angle<F>(coord: &Coordinate, mut days: f64, target: F) -> Option<f64>
+where
+    F: Fn(f64) -> Option<f64>,
+{
+    for _ in 0..SOLAR_ITERATIONS {
+        let (right_ascension, declination) = calc_sun_position(days);
+        let diff = calc_hour_angle(coord, days, right_ascension) - target(declination)?;
+        // 時角は1日でおよそ360度進む
+        days -= ((diff + 180.).rem_euclid(360.) - 180.) / 360.;
+    }
+    Some(days)
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::model::route::coordinate::tests::CoordinateFixtures;
+    use crate::model::route::segment_list::tests::SegmentListFixture;
+
+    use super::*;
+
+    fn assert_close(actual: DateTime<Utc>, expected: DateTime<Utc>) {
+        assert!(
+            (actual - expected).num_minutes().abs() <= 3,
+            "{} is too far from {}",
+            actual,
+            expected
+        );
+    }
+
+    #[rstest]
+    // 国立天文台の暦計算室による東京の値
+    #[case::summer_solstice(
+        Utc.ymd(2021, 6, 21).and_hms(3, 0, 0),
+        Utc.ymd(2021, 6, 20).and_hms(19, 25, 0),
+        Utc.ymd(2021, 6, 21).and_hms(10, 0, 0)
+    )]
+    #[case::winter_solstice(
+        Utc.ymd(2021, 12, 22).and_hms(3, 0, 0),
+        Utc.ymd(2021, 12, 21).and_hms(21, 47, 0),
+        Utc.ymd(2021, 12, 22).and_hms(7, 32, 0)
+    )]
+    fn can_calc_sunrise_and_sunset(
+        #[case] time: DateTime<Utc>,
+        #[case] expected_sunrise: DateTime<Utc>,
+        #[case] expected_sunset: DateTime<Utc>,
+    ) {
+        let (sunrise, sunset) = calc_sunrise_and_sunset(&Coordinate::tokyo(false, None), time);
+        assert_close(sunrise.unwrap(), expected_sunrise);
+        assert_close(sunset.unwrap(), expected_sunset);
+    }
+
+    #[rstest]
+    #[case::midnight_sun(Utc.ymd(2021, 6, 21).and_hms(0, 0, 0), false)]
+    #[case::polar_night(Utc.ymd(2021, 12, 22).and_hms(12, 0, 0), true)]
+    fn can_handle_polar_regions(#[case] time: DateTime<Utc>, #[case] expected_is_dark: bool) {
+        let svalbard = Coordinate::new(78.22, 15.65).unwrap();
+        assert_eq!(calc_sunrise_and_sunset(&svalbard, time), (None, None));
+        assert_eq!(is_dark(&svalbard, time), expected_is_dark);
+    }
+
+    #[rstest]
+    #[case::summer_solstice(Utc.ymd(2021, 6, 21).and_hms(3, 0, 0))]
+    #[case::equinox(Utc.ymd(2021, 9, 23).and_hms(3, 0, 0))]
+    #[case::winter_solstice(Utc.ymd(2021, 12, 22).and_hms(3, 0, 0))]
+    fn darkness_agrees_with_sunrise_and_sunset(#[case] time: DateTime<Utc>) {
+        let tokyo = Coordinate::tokyo(false, None);
+        let (sunrise, sunset) = calc_sunrise_and_sunset(&tokyo, time);
+        let (sunrise, sunset) = (sunrise.unwrap(), sunset.unwrap());
+        let margin = Duration::seconds(5);
+
+        assert!(is_dark(&tokyo, sunrise - margin));
+        assert!(!is_dark(&tokyo, sunrise + margin));
+        assert!(!is_dark(&tokyo, sunset - margin));
+        assert!(is_dark(&tokyo, sunset + margin));
+    }
+
+    #[rstest]
+    #[case::noon(Utc.ymd(2021, 6, 21).and_hms(3, 0, 0), false)]
+    #[case::midnight(Utc.ymd(2021, 6, 21).and_hms(15, 0, 0), true)]
+    fn can_check_darkness(#[case] time: DateTime<Utc>, #[case] expected: bool) {
+        assert_eq!(is_dark(&Coordinate::tokyo(false, None), time), expected)
+    }
+
+    #[rstest]
+    fn can_check_route_in_daylight() {
+        let checker = DaylightChecker::new(Utc.ymd(2021, 6, 21).and_hms(0, 0, 0), 20.).unwrap();
+        let report = checker
+            .check(&SegmentList::yokohama_to_chiba_via_tokyo(true, true, false))
+            .unwrap();
+
+        assert_eq!(report.waypoints.len(), 3);
+        assert!(report.waypoints.iter().all(|waypoint| !waypoint.is_dark));
+        assert_eq!(report.dark_sections, vec![]);
+        assert!(report.finishes_before_dark);
+        assert_eq!(
+            report.estimated_arrival,
+            Utc.ymd(2021, 6, 21).and_hms_milli(2, 56, 16, 795)
+        );
+    }
+
+    #[rstest]
+    fn can_find_dark_sections() {
+        // 17:00 JSTに出発し，東京を過ぎたあたりで日が沈む
+        let checker = DaylightChecker::new(Utc.ymd(2021, 6, 21).and_hms(8, 0, 0), 20.).unwrap();
+        let report = checker
+            .check(&SegmentList::yokohama_to_chiba_via_tokyo(true, true, false))
+            .unwrap();
+
+        assert_eq!(
+            report
+                .waypoints
+                .iter()
+                .map(|waypoint| waypoint.is_dark)
+                .collect::<Vec<_>>(),
+            vec![false, false, true]
+        );
+        assert_eq!(report.dark_sections.len(), 1);
+        assert_eq!(
+            report.dark_sections[0].end_distance.value(),
+            58759.973932514884
+        );
+        assert!(!report.finishes_before_dark);
+    }
+
+    #[rstest]
+    fn cannot_check_with_non_positive_speed() {
+        assert!(matches!(
+            DaylightChecker::new(Utc.ymd(2021, 6, 21).and_hms(0, 0, 0), 0.),
+            Err(ApplicationError::InvalidOperation(_))
+        ))
+    }
+}
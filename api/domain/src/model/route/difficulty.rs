@@ -0,0 +1,160 @@
+use geo::algorithm::haversine_distance::HaversineDistance;
+use getset::Getters;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use super::segment_list::SegmentList;
+use super::types::{Distance, Elevation};
+
+/// 平坦な道1kmあたりのスコア
+const SCORE_PER_KILOMETER: f64 = 0.5;
+/// 獲得標高1mあたりのスコア
+const SCORE_PER_ASCENT_METER: f64 = 0.04;
+/// これを超える勾配[%]の上りは，超過分に応じて追加でスコアを加算する
+const STEEP_GRADIENT_THRESHOLD: f64 = 3.;
+/// DEMのノイズで極端な勾配が出ないように抑える上限[%]
+const MAX_GRADIENT: f64 = 25.;
+
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    strum::Display,
+    strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum DifficultyRating {
+    Easy,
+    Moderate,
+    Hard,
+    Extreme,
+}
+
+impl DifficultyRating {
+    fn from_score(score: f64) -> Self {
+        if score < 25. {
+            Self::Easy
+        } else if score < 50. {
+            Self::Moderate
+        } else if score < 100. {
+            Self::Hard
+        } else {
+            Self::Extreme
+        }
+    }
+}
+
+impl Default for DifficultyRating {
+    fn default() -> Self {
+        Self::Easy
+    }
+}
+
+/// ルートの難易度
+///
+/// scoreは距離，獲得標高，急勾配の上りの長さから計算され，
+/// 目安として50kmの平坦路が25，100kmで獲得標高1000mのルートが90程度になる
+#[derive(Clone, Copy, Debug, Default, Getters, Serialize, Deserialize)]
+#[get = "pub"]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct Difficulty {
+    score: f64,
+    rating: DifficultyRating,
+}
+
+impl Difficulty {
+    pub fn from_score(score: f64) -> Self {
+        Self {
+            score,
+            rating: DifficultyRating::from_score(score),
+        }
+    }
+
+    pub fn calc(
+        seg_list: &SegmentList,
+        total_distance: Distance,
+        ascent_elevation_gain: Elevation,
+    ) -> Self {
+        Self::from_score(
+            total_distance.value() / 1000. * SCORE_PER_KILOMETER
+                + f64::from(ascent_elevation_gain.value()) * SCORE_PER_ASCENT_METER
+                + Self::calc_steepness(seg_list),
+        )
+    }
+
+    /// 急勾配の上りについて，距離[km]と閾値を超えた勾配[%]の積を合計したもの
+    fn calc_steepness(seg_list: &SegmentList) -> f64 {
+        seg_list
+            .iter()
+            .flat_map(|seg| seg.iter().tuple_windows())
+            .filter_map(|(from, to)| {
+                let climb = to.elevation().as_ref()?.value() - from.elevation().as_ref()?.value();
+                let distance = from.haversine_distance(to).value();
+                (distance > 0.).then(|| {
+                    let gradient = (f64::from(climb) / distance * 100.).min(MAX_GRADIENT);
+                    distance / 1000. * (gradient - STEEP_GRADIENT_THRESHOLD).max(0.)
+                })
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+    use std::convert::TryFrom;
+
+    use crate::model::route::coordinate::Coordinate;
+    use crate::model::route::segment_list::{tests::SegmentListFixture, DrawingMode, Segment};
+
+    use super::*;
+
+    #[rstest]
+    #[case::flat_short(0., DifficultyRating::Easy)]
+    #[case::moderate_lower_bound(25., DifficultyRating::Moderate)]
+    #[case::hard(99.9, DifficultyRating::Hard)]
+    #[case::extreme_lower_bound(100., DifficultyRating::Extreme)]
+    fn can_rate_score(#[case] score: f64, #[case] expected: DifficultyRating) {
+        assert_eq!(Difficulty::from_score(score).rating, expected)
+    }
+
+    #[rstest]
+    fn can_calc_without_steep_climb() {
+        let seg_list = SegmentList::yokohama_to_chiba_via_tokyo(true, true, false);
+        assert_eq!(
+            Difficulty::calc(
+                &seg_list,
+                Distance::try_from(100000.).unwrap(),
+                Elevation::try_from(1000).unwrap()
+            ),
+            Difficulty::from_score(90.)
+        )
+    }
+
+    #[rstest]
+    fn steep_climb_makes_route_harder() {
+        // 約1.1kmで100m上る (約9%)
+        let mut bottom = Coordinate::new(35., 135.).unwrap();
+        bottom.set_elevation(Some(Elevation::zero())).unwrap();
+        let mut top = Coordinate::new(35.01, 135.).unwrap();
+        top.set_elevation(Some(Elevation::try_from(100).unwrap()))
+            .unwrap();
+        let mut seg = Segment::new_empty(bottom.clone(), top.clone(), DrawingMode::Freehand);
+        seg.set_points(vec![bottom, top]).unwrap();
+        let seg_list = SegmentList::from(vec![seg]);
+
+        let steepness = Difficulty::calc_steepness(&seg_list);
+        assert!(6. < steepness && steepness < 7.);
+        assert_eq!(
+            Difficulty::calc(&seg_list, Distance::zero(), Elevation::zero()),
+            Difficulty::from_score(steepness)
+        )
+    }
+}
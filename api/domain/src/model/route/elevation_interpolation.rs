@@ -0,0 +1,204 @@
+use std::convert::TryFrom;
+
+use serde::{Deserialize, Serialize};
+
+use route_bucket_utils::ApplicationResult;
+
+use super::types::Elevation;
+
+/// DEMのピクセル間の標高の補間方法
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ElevationInterpolation {
+    /// 最も近いピクセルの値をそのまま使う
+    Nearest,
+    /// 周囲2x2ピクセルの双線形補間
+    Bilinear,
+    /// 周囲4x4ピクセルの双三次補間(Catmull-Rom)
+    ///
+    /// no_dataのピクセルが含まれる場合は双線形補間にフォールバックする
+    Bicubic,
+}
+
+impl Default for ElevationInterpolation {
+    fn default() -> Self {
+        Self::Bilinear
+    }
+}
+
+impl ElevationInterpolation {
+    /// ピクセル単位の位置(`x`, `y`)の標高を補間する
+    ///
+    /// 整数の位置にピクセルの中心があるものとし，
+    /// `sample(col, row)`はそのピクセルの標高(no_dataならNone)を返す
+    /// 補間した値を四捨五入してElevationにする
+    pub fn interpolate<F>(
+        &self,
+        x: f64,
+        y: f64,
+        mut sample: F,
+    ) -> ApplicationResult<Option<Elevation>>
+    where
+        F: FnMut(i64, i64) -> ApplicationResult<Option<f64>>,
+    {
+        let value = match self {
+            Self::Nearest => sample(x.round() as i64, y.round() as i64)?,
+            Self::Bilinear => Self::bilinear(x, y, &mut sample)?,
+            Self::Bicubic => match Self::bicubic(x, y, &mut sample)? {
+                Some(value) => Some(value),
+                None => Self::bilinear(x, y, &mut sample)?,
+            },
+        };
+        value
+            .map(|value| Elevation::try_from(value.round() as i32))
+            .transpose()
+    }
+
+    /// no_dataのピクセルを除き，残りの重みで正規化する
+    fn bilinear<F>(x: f64, y: f64, sample: &mut F) -> ApplicationResult<Option<f64>>
+    where
+        F: FnMut(i64, i64) -> ApplicationResult<Option<f64>>,
+    {
+        let (col, row) = (x.floor(), y.floor());
+        let (tx, ty) = (x - col, y - row);
+        let (col, row) = (col as i64, row as i64);
+
+        let mut weighted_sum = 0.;
+        let mut weight_sum = 0.;
+        for (dx, dy, weight) in [
+            (0, 0, (1. - tx) * (1. - ty)),
+            (1, 0, tx * (1. - ty)),
+            (0, 1, (1. - tx) * ty),
+            (1, 1, tx * ty),
+        ] {
+            if weight <= 0. {
+                continue;
+            }
+            if let Some(value) = sample(col + dx, row + dy)? {
+                weighted_sum += weight * value;
+                weight_sum += weight;
+            }
+        }
+        Ok((weight_sum > 0.).then(|| weighted_sum / weight_sum))
+    }
+
+    /// 周囲16ピクセルのうち一つでもno_dataならNoneを返す
+    fn bicubic<F>(x: f64, y: f64, sample: &mut F) -> ApplicationResult<Option<f64>>
+    where
+        F: FnMut(i64, i64) -> ApplicationResult<Option<f64>>,
+    {
+        let (col, row) = (x.floor(), y.floor());
+        let x_weights = Self::catmull_rom_weights(x - col);
+        let y_weights = Self::catmull_rom_weights(y - row);
+        let (col, row) = (col as i64, row as i64);
+
+        let mut value = 0.;
+        for (dy, y_weight) in (-1..=2).zip(y_weights.iter()) {
+            for (dx, x_weight) in (-1..=2).zip(x_weights.iter()) {
+                match sample(col + dx, row + dy)? {
+                    Some(sampled) => value += x_weight * y_weight * sampled,
+                    None => return Ok(None),
+                }
+            }
+        }
+        Ok(Some(value))
+    }
+
+    fn catmull_rom_weights(t: f64) -> [f64; 4] {
+        let (t2, t3) = (t * t, t * t * t);
+        [
+            (-t3 + 2. * t2 - t) / 2.,
+            (3. * t3 - 5. * t2 + 2.) / 2.,
+            (-3. * t3 + 4. * t2 + t) / 2.,
+            (t3 - t2) / 2.,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use route_bucket_utils::ApplicationError;
+
+    use super::*;
+
+    /// 東に1ピクセル進むごとに10m，南に1ピクセル進むごとに100m高くなる斜面
+    fn slope(col: i64, row: i64) -> ApplicationResult<Option<f64>> {
+        Ok(Some((col * 10 + row * 100) as f64))
+    }
+
+    /// (1, 1)のピクセルだけno_dataな斜面
+    fn slope_with_hole(col: i64, row: i64) -> ApplicationResult<Option<f64>> {
+        Ok(((col, row) != (1, 1)).then(|| (col * 10 + row * 100) as f64))
+    }
+
+    fn elevation(value: i32) -> Option<Elevation> {
+        Some(Elevation::try_from(value).unwrap())
+    }
+
+    #[rstest]
+    #[case::nearest(ElevationInterpolation::Nearest, 1.4, 0.6, elevation(110))]
+    #[case::bilinear(ElevationInterpolation::Bilinear, 1.4, 0.6, elevation(74))]
+    #[case::bicubic(ElevationInterpolation::Bicubic, 1.4, 0.6, elevation(74))]
+    #[case::bilinear_on_pixel_center(ElevationInterpolation::Bilinear, 2., 3., elevation(320))]
+    fn can_interpolate(
+        #[case] mode: ElevationInterpolation,
+        #[case] x: f64,
+        #[case] y: f64,
+        #[case] expected: Option<Elevation>,
+    ) {
+        assert_eq!(mode.interpolate(x, y, slope).unwrap(), expected)
+    }
+
+    #[rstest]
+    #[case::nearest_on_hole(ElevationInterpolation::Nearest, 1.2, 0.8, None)]
+    // (1, 1)を除いた3ピクセルの重み(0.64, 0.16, 0.16)で正規化される
+    #[case::bilinear_skips_hole(ElevationInterpolation::Bilinear, 0.2, 0.2, elevation(18))]
+    #[case::bilinear_on_hole_center(ElevationInterpolation::Bilinear, 1., 1., None)]
+    #[case::bicubic_falls_back_to_bilinear(
+        ElevationInterpolation::Bicubic,
+        0.2,
+        0.2,
+        elevation(18)
+    )]
+    fn can_handle_no_data(
+        #[case] mode: ElevationInterpolation,
+        #[case] x: f64,
+        #[case] y: f64,
+        #[case] expected: Option<Elevation>,
+    ) {
+        assert_eq!(mode.interpolate(x, y, slope_with_hole).unwrap(), expected)
+    }
+
+    #[rstest]
+    fn bicubic_is_smoother_than_bilinear_on_curve() {
+        // 高さがcolの2乗に比例する断面では，双線形補間は弦の上を通るので高く出る
+        let curve = |col: i64, _row: i64| Ok(Some((col * col * 100) as f64));
+        let bilinear = ElevationInterpolation::Bilinear.interpolate(1.5, 0., curve);
+        let bicubic = ElevationInterpolation::Bicubic.interpolate(1.5, 0., curve);
+        assert_eq!(bilinear.unwrap(), elevation(250));
+        assert_eq!(bicubic.unwrap(), elevation(225));
+    }
+
+    #[rstest]
+    fn sampling_error_is_propagated() {
+        let broken = |_col: i64, _row: i64| -> ApplicationResult<Option<f64>> {
+            Err(ApplicationError::ExternalError("broken tile".into()))
+        };
+        assert!(matches!(
+            ElevationInterpolation::Bilinear.interpolate(0.5, 0.5, broken),
+            Err(ApplicationError::ExternalError(_))
+        ))
+    }
+
+    #[rstest]
+    #[case::nearest("nearest", ElevationInterpolation::Nearest)]
+    #[case::bicubic("bicubic", ElevationInterpolation::Bicubic)]
+    fn can_parse_from_str(#[case] name: &str, #[case] expected: ElevationInterpolation) {
+        assert_eq!(name.parse::<ElevationInterpolation>().unwrap(), expected)
+    }
+}
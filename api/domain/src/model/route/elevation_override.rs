@@ -0,0 +1,177 @@
+use std::convert::TryFrom;
+use std::ops::Not;
+
+use geo::algorithm::haversine_distance::HaversineDistance;
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+use super::coordinate::Coordinate;
+use super::types::Elevation;
+
+/// セグメントの標高の手動補正
+///
+/// SRTMは橋やトンネルで地表(谷底や山の上)の標高を返すので，
+/// 始点・終点の標高を指定したり，その間を線形補間させたりできるようにする
+#[derive(Clone, Debug, Default, PartialEq, Getters, Serialize, Deserialize)]
+#[get = "pub"]
+pub struct ElevationOverride {
+    /// 始点(ウェイポイント)の標高
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    start: Option<Elevation>,
+    /// 終点(次のウェイポイント)の標高
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    goal: Option<Elevation>,
+    /// 橋・トンネルとして，始点と終点の間を線形補間する
+    #[serde(default, skip_serializing_if = "Not::not")]
+    interpolate: bool,
+}
+
+impl ElevationOverride {
+    pub fn new(start: Option<Elevation>, goal: Option<Elevation>, interpolate: bool) -> Self {
+        Self {
+            start,
+            goal,
+            interpolate,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn set_start(&mut self, start: Option<Elevation>) {
+        self.start = start;
+    }
+
+    pub fn set_goal(&mut self, goal: Option<Elevation>) {
+        self.goal = goal;
+    }
+
+    pub fn set_interpolate(&mut self, interpolate: bool) {
+        self.interpolate = interpolate;
+    }
+
+    /// 始点側の補正だけを残す (終点が動いたときなど)
+    pub fn start_only(&self) -> Self {
+        Self::new(self.start, None, false)
+    }
+
+    /// 終点側の補正だけを残す (始点が動いたときなど)
+    pub fn goal_only(&self) -> Self {
+        Self::new(None, self.goal, false)
+    }
+
+    /// セグメントの点列の標高を補正する
+    pub fn apply(&self, points: &mut [Coordinate]) {
+        if let (Some(start), Some(first)) = (self.start, points.first_mut()) {
+            first.elevation = Some(start);
+        }
+        if let (Some(goal), Some(last)) = (self.goal, points.last_mut()) {
+            last.elevation = Some(goal);
+        }
+        if self.interpolate {
+            Self::interpolate_linearly(points);
+        }
+    }
+
+    /// 始点と終点の標高を，点列に沿った距離の比で線形補間する
+    ///
+    /// どちらかの標高が無い場合は何もしない
+    fn interpolate_linearly(points: &mut [Coordinate]) {
+        let (start, goal) = match (points.first(), points.last()) {
+            (Some(first), Some(last)) => match (first.elevation, last.elevation) {
+                (Some(start), Some(goal)) => (f64::from(start.value()), f64::from(goal.value())),
+                _ => return,
+            },
+            _ => return,
+        };
+
+        let distances = points
+            .iter()
+            .scan((0., None), |(distance, prev), coord: &Coordinate| {
+                if let Some(prev) = prev {
+                    *distance += coord.haversine_distance(*prev).value();
+                }
+                *prev = Some(coord);
+                Some(*distance)
+            })
+            .collect::<Vec<f64>>();
+        let total = distances.last().copied().unwrap_or(0.);
+        let len = points.len();
+
+        points.iter_mut().enumerate().for_each(|(i, coord)| {
+            let ratio = if total > 0. {
+                distances[i] / total
+            } else if len > 1 {
+                i as f64 / (len - 1) as f64
+            } else {
+                0.
+            };
+            let elevation = (start + (goal - start) * ratio).round() as i32;
+            // 始点と終点の間の値なので，範囲外にはならない
+            coord.elevation = Some(Elevation::try_from(elevation).unwrap());
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::model::route::coordinate::tests::CoordinateFixtures;
+
+    use super::*;
+
+    fn elevation(value: i32) -> Option<Elevation> {
+        Some(Elevation::try_from(value).unwrap())
+    }
+
+    fn elevations(points: &[Coordinate]) -> Vec<Option<Elevation>> {
+        points.iter().map(|coord| coord.elevation).collect()
+    }
+
+    #[rstest]
+    #[case::empty(ElevationOverride::default(), vec![elevation(1), elevation(4), elevation(11)])]
+    #[case::start_and_goal(
+        ElevationOverride::new(elevation(100), elevation(200), false),
+        vec![elevation(100), elevation(4), elevation(200)]
+    )]
+    // yokohama-tokyo間(26.9km)とtokyo-chiba間(31.8km)の距離の比で補間される
+    #[case::interpolate(
+        ElevationOverride::new(None, None, true),
+        vec![elevation(1), elevation(6), elevation(11)]
+    )]
+    #[case::interpolate_overridden(
+        ElevationOverride::new(elevation(0), elevation(100), true),
+        vec![elevation(0), elevation(46), elevation(100)]
+    )]
+    fn can_apply(
+        #[case] elevation_override: ElevationOverride,
+        #[case] expected: Vec<Option<Elevation>>,
+    ) {
+        let mut points = Coordinate::yokohama_to_chiba_via_tokyo_coords(true, None);
+        elevation_override.apply(&mut points);
+        assert_eq!(elevations(&points), expected)
+    }
+
+    #[rstest]
+    fn interpolation_needs_both_ends() {
+        let mut points = Coordinate::yokohama_to_chiba_via_tokyo_coords(true, None);
+        points.last_mut().unwrap().elevation = None;
+        ElevationOverride::new(None, None, true).apply(&mut points);
+        assert_eq!(elevations(&points), vec![elevation(1), elevation(4), None])
+    }
+
+    #[rstest]
+    fn can_keep_only_one_side() {
+        let elevation_override = ElevationOverride::new(elevation(10), elevation(20), true);
+        assert_eq!(
+            elevation_override.start_only(),
+            ElevationOverride::new(elevation(10), None, false)
+        );
+        assert_eq!(
+            elevation_override.goal_only(),
+            ElevationOverride::new(None, elevation(20), false)
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use std::cmp::max;
+
+use getset::Getters;
+use serde::Serialize;
+
+use super::types::Elevation;
+
+/// 点列の標高と，その獲得標高などの集計
+///
+/// 標高が取れなかった点(None)は集計で読み飛ばす
+#[derive(Clone, Debug, PartialEq, Getters, Serialize)]
+#[get = "pub"]
+pub struct ElevationProfile {
+    elevations: Vec<Option<Elevation>>,
+    ascent_elevation_gain: Elevation,
+    descent_elevation_gain: Elevation,
+    max_elevation: Option<Elevation>,
+    min_elevation: Option<Elevation>,
+}
+
+impl ElevationProfile {
+    pub fn new(elevations: Vec<Option<Elevation>>) -> Self {
+        let (ascent_elevation_gain, descent_elevation_gain) =
+            calc_elevation_gain(elevations.iter().copied());
+        let known_elevations = || elevations.iter().flatten().copied();
+
+        Self {
+            ascent_elevation_gain,
+            descent_elevation_gain,
+            max_elevation: known_elevations().max(),
+            min_elevation: known_elevations().min(),
+            elevations,
+        }
+    }
+}
+
+/// (獲得標高, 獲得標高(下り))を計算する
+pub(super) fn calc_elevation_gain<I>(elevations: I) -> (Elevation, Elevation)
+where
+    I: IntoIterator<Item = Option<Elevation>>,
+{
+    let mut ascent_gain = Elevation::zero();
+    let mut descent_gain = Elevation::zero();
+    let mut prev_elev = None;
+    elevations.into_iter().flatten().for_each(|elev| {
+        if let Some(prev_elev_value) = prev_elev {
+            ascent_gain += max(elev - prev_elev_value, Elevation::zero());
+            descent_gain += max(prev_elev_value - elev, Elevation::zero());
+        }
+        prev_elev = Some(elev);
+    });
+    (ascent_gain, descent_gain)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use rstest::rstest;
+
+    use super::*;
+
+    fn elevations(values: &[Option<i32>]) -> Vec<Option<Elevation>> {
+        values
+            .iter()
+            .map(|value| value.map(|value| Elevation::try_from(value).unwrap()))
+            .collect()
+    }
+
+    fn elevation(value: i32) -> Elevation {
+        Elevation::try_from(value).unwrap()
+    }
+
+    #[rstest]
+    #[case::empty(&[], 0, 0, None, None)]
+    #[case::single(&[Some(10)], 0, 0, Some(10), Some(10))]
+    #[case::up_and_down(&[Some(10), Some(30), Some(25), Some(40)], 35, 5, Some(40), Some(10))]
+    #[case::skips_missing(&[Some(10), None, Some(5), None], 0, 5, Some(10), Some(5))]
+    #[case::all_missing(&[None, None], 0, 0, None, None)]
+    fn can_summarize(
+        #[case] values: &[Option<i32>],
+        #[case] expected_ascent: i32,
+        #[case] expected_descent: i32,
+        #[case] expected_max: Option<i32>,
+        #[case] expected_min: Option<i32>,
+    ) {
+        let profile = ElevationProfile::new(elevations(values));
+        assert_eq!(profile.elevations(), &elevations(values));
+        assert_eq!(profile.ascent_elevation_gain(), &elevation(expected_ascent));
+        assert_eq!(
+            profile.descent_elevation_gain(),
+            &elevation(expected_descent)
+        );
+        assert_eq!(profile.max_elevation(), &expected_max.map(elevation));
+        assert_eq!(profile.min_elevation(), &expected_min.map(elevation));
+    }
+}
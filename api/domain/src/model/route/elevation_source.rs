@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// ルートの標高にどのデータを使うか
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ElevationSource {
+    /// DEMから求めた標高
+    Dem,
+    /// 取り込んだファイルの標高 (ファイルに標高が無い点はDEMで補う)
+    Source,
+}
+
+impl Default for ElevationSource {
+    fn default() -> Self {
+        Self::Dem
+    }
+}
@@ -0,0 +1,182 @@
+use derive_more::{Add, Sum};
+use geo::algorithm::haversine_distance::HaversineDistance;
+use getset::Getters;
+use itertools::Itertools;
+use serde::Serialize;
+
+use super::coordinate::Coordinate;
+use super::segment_list::{Segment, SegmentList};
+
+const GRAVITATIONAL_ACCELERATION: f64 = 9.80665;
+const AIR_DENSITY: f64 = 1.225;
+/// ratio of mechanical work to the food energy burnt by a rider
+const GROSS_EFFICIENCY: f64 = 0.24;
+const JOULES_PER_KILOCALORIE: f64 = 4184.;
+
+/// Energy spent by a rider on (a part of) a route
+#[derive(Clone, Copy, Debug, Default, Add, Sum, Getters, Serialize)]
+#[get = "pub"]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct EnergyExpenditure {
+    /// mechanical work done on the pedals
+    kilojoules: f64,
+    /// food energy burnt to do the work
+    kilocalories: f64,
+}
+
+impl EnergyExpenditure {
+    fn from_joules(joules: f64) -> Self {
+        Self {
+            kilojoules: joules / 1000.,
+            kilocalories: joules / GROSS_EFFICIENCY / JOULES_PER_KILOCALORIE,
+        }
+    }
+}
+
+/// Physics-based model of a rider riding at a constant speed
+///
+/// The power is the sum of rolling resistance, aerodynamic drag (no wind)
+/// and gravity from the elevation deltas between the route's points.
+/// Descents don't give energy back, so the power never goes below zero.
+#[derive(Clone, Debug, Getters)]
+#[get = "pub"]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct EnergyModel {
+    /// total mass of the rider and the bike [kg]
+    mass: f64,
+    /// drag coefficient times frontal area [m^2]
+    cda: f64,
+    /// coefficient of rolling resistance
+    crr: f64,
+    /// [m/s]
+    speed: f64,
+}
+
+impl EnergyModel {
+    /// `speed` is in km/h
+    pub fn new(rider_mass: f64, bike_mass: f64, cda: f64, crr: f64, speed: f64) -> Self {
+        Self {
+            mass: rider_mass + bike_mass,
+            cda,
+            crr,
+            speed: speed / 3.6,
+        }
+    }
+
+    pub fn estimate(&self, seg_list: &SegmentList) -> EnergyExpenditure {
+        seg_list.iter().map(|seg| self.estimate_segment(seg)).sum()
+    }
+
+    /// Returns the estimation for each segment, aligned with `SegmentList::into_segments_in_between`
+    pub fn estimate_each_segment(&self, seg_list: &SegmentList) -> Vec<EnergyExpenditure> {
+        let mut expenditures = seg_list
+            .iter()
+            .map(|seg| self.estimate_segment(seg))
+            .collect_vec();
+        expenditures.pop();
+        expenditures
+    }
+
+    pub fn estimate_segment(&self, seg: &Segment) -> EnergyExpenditure {
+        EnergyExpenditure::from_joules(
+            seg.iter()
+                .tuple_windows()
+                .map(|(from, to)| self.calc_work(from, to))
+                .sum(),
+        )
+    }
+
+    /// Work [J] to ride from `from` to `to`
+    fn calc_work(&self, from: &Coordinate, to: &Coordinate) -> f64 {
+        let distance = from.haversine_distance(to).value();
+        let climb = match (from.elevation(), to.elevation()) {
+            (Some(from_elev), Some(to_elev)) => f64::from(to_elev.value() - from_elev.value()),
+            _ => 0.,
+        };
+        let slope_length = distance.hypot(climb);
+        if slope_length == 0. {
+            return 0.;
+        }
+
+        let weight = self.mass * GRAVITATIONAL_ACCELERATION;
+        let rolling_resistance = self.crr * weight * distance / slope_length;
+        let aerodynamic_drag = 0.5 * AIR_DENSITY * self.cda * self.speed.powi(2);
+        let gravity = weight * climb / slope_length;
+
+        (rolling_resistance + aerodynamic_drag + gravity).max(0.) * slope_length
+    }
+}
+
+#[cfg(any(test, feature = "fixtures"))]
+pub(crate) mod tests {
+    use rstest::{fixture, rstest};
+
+    #[cfg(test)]
+    use std::convert::TryInto;
+
+    #[cfg(test)]
+    use crate::model::route::coordinate::tests::CoordinateFixtures;
+    #[cfg(test)]
+    use crate::model::route::segment_list::tests::{SegmentFixtures, SegmentListFixture};
+    #[cfg(test)]
+    use crate::model::route::segment_list::DrawingMode;
+
+    use super::*;
+
+    #[fixture]
+    fn model() -> EnergyModel {
+        EnergyModel::road_bike()
+    }
+
+    #[rstest]
+    #[case::flat(
+        Segment::yokohama_to_tokyo(false, None, false, DrawingMode::Freehand),
+        351.0244033079364
+    )]
+    #[case::uphill(
+        Segment::yokohama_to_tokyo(true, None, false, DrawingMode::Freehand),
+        353.17206123701453
+    )]
+    #[case::single_point(Segment::chiba(true, None, false, DrawingMode::Freehand), 0.)]
+    fn can_estimate_segment(
+        model: EnergyModel,
+        #[case] seg: Segment,
+        #[case] expected_kilojoules: f64,
+    ) {
+        assert_eq!(model.estimate_segment(&seg).kilojoules, expected_kilojoules)
+    }
+
+    #[rstest]
+    fn descent_does_not_give_energy_back(model: EnergyModel) {
+        let mut from = Coordinate::yokohama(false, None);
+        from.set_elevation(Some(1000.try_into().unwrap())).unwrap();
+        let to = Coordinate::tokyo(true, None);
+        assert_eq!(model.calc_work(&from, &to), 0.)
+    }
+
+    #[rstest]
+    fn can_estimate_each_segment(model: EnergyModel) {
+        let seg_list = SegmentList::yokohama_to_chiba_via_tokyo(true, false, false);
+        let expenditures = model.estimate_each_segment(&seg_list);
+        assert_eq!(expenditures.len(), 2);
+        assert_eq!(
+            expenditures.into_iter().sum::<EnergyExpenditure>(),
+            model.estimate(&seg_list)
+        )
+    }
+
+    #[rstest]
+    fn kilocalories_include_gross_efficiency() {
+        let expenditure = EnergyExpenditure::from_joules(1004160.);
+        assert_eq!(expenditure.kilojoules, 1004.16);
+        assert_eq!(expenditure.kilocalories, 1000.)
+    }
+
+    pub trait EnergyModelFixtures {
+        fn road_bike() -> EnergyModel {
+            EnergyModel::new(65., 8., 0.32, 0.005, 25.)
+        }
+    }
+
+    impl EnergyModelFixtures for EnergyModel {}
+}
@@ -0,0 +1,74 @@
+use derive_more::From;
+use getset::{Getters, Setters};
+use serde::{Deserialize, Serialize};
+
+use crate::model::types::Url;
+
+/// 路面の種類
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum SurfaceType {
+    Paved,
+    Gravel,
+    Mixed,
+    Unpaved,
+}
+
+/// おすすめの自転車の種類
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum BikeType {
+    Road,
+    Gravel,
+    Mountain,
+    Touring,
+    City,
+}
+
+/// ルートの説明などの付加情報 (名前以外)
+///
+/// 値の検証はリクエストを受け取るところで行う
+#[derive(Clone, Debug, Default, From, Getters, Setters, Serialize, Deserialize)]
+#[get = "pub"]
+#[set = "pub"]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct RouteMetadata {
+    /// markdownで書かれた説明
+    description: Option<String>,
+    surface_type: Option<SurfaceType>,
+    bike_type: Option<BikeType>,
+    /// 出発地点の名前 ("横浜駅"など)
+    start_label: Option<String>,
+    /// 到着地点の名前
+    finish_label: Option<String>,
+    /// 外部のページ(イベントの案内など)へのリンク
+    external_link: Option<Url>,
+}
+
+#[cfg(any(test, feature = "fixtures"))]
+pub(crate) mod tests {
+    use std::convert::TryFrom;
+
+    use super::*;
+
+    pub trait RouteMetadataFixtures {
+        fn gravel_ride() -> RouteMetadata {
+            RouteMetadata {
+                description: Some("Mostly **gravel** along the river.".into()),
+                surface_type: Some(SurfaceType::Gravel),
+                bike_type: Some(BikeType::Gravel),
+                start_label: Some("Yokohama".into()),
+                finish_label: Some("Chiba".into()),
+                external_link: Url::try_from("https://example.com/gravel".to_string()).ok(),
+            }
+        }
+    }
+
+    impl RouteMetadataFixtures for RouteMetadata {}
+}
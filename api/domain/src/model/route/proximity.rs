@@ -0,0 +1,230 @@
+use std::convert::TryFrom;
+
+use geo::algorithm::haversine_distance::HaversineDistance;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+use super::coordinate::Coordinate;
+use super::segment_list::SegmentList;
+use super::types::Distance;
+
+/// 近くを探せる範囲の上限 [m]
+const MAX_RADIUS: f64 = 100_000.;
+
+/// 距離を確かめるルートの数の上限
+///
+/// 格子の上で中心に近いルートから選ぶので，候補が多すぎると遠い方のルートは確かめない
+pub const MAX_NEARBY_CANDIDATES: usize = 200;
+
+/// 局所的に平面とみなして距離を測るときの地球の半径 [m]
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// 地点の近くにあるかを調べるルートの部分
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ProximityTarget {
+    /// 出発地点
+    Start,
+    /// ルート上のどこか
+    Path,
+}
+
+impl Default for ProximityTarget {
+    fn default() -> Self {
+        Self::Path
+    }
+}
+
+/// 地点の近くを通る(または出発する)ルートの検索条件
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct ProximityQuery {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// [m]
+    pub radius: f64,
+    #[serde(default)]
+    pub target: ProximityTarget,
+    pub limit: Option<usize>,
+}
+
+impl ProximityQuery {
+    /// 検索の中心
+    pub fn center(&self) -> ApplicationResult<Coordinate> {
+        Coordinate::new(self.latitude, self.longitude)
+    }
+
+    pub fn radius(&self) -> ApplicationResult<Distance> {
+        if self.radius > 0. && self.radius <= MAX_RADIUS {
+            Distance::try_from(self.radius)
+        } else {
+            Err(ApplicationError::ValidationError(format!(
+                "radius must be in (0, {}] m, but got {}",
+                MAX_RADIUS, self.radius
+            )))
+        }
+    }
+
+    /// `seg_list`が条件に合えば，中心からの距離を返す
+    pub fn match_distance(&self, seg_list: &SegmentList) -> ApplicationResult<Option<Distance>> {
+        let center = self.center()?;
+        let radius = self.radius()?;
+        let distance = match self.target {
+            ProximityTarget::Start => seg_list
+                .iter()
+                .next()
+                .map(|seg| center.haversine_distance(seg.start())),
+            ProximityTarget::Path => Self::distance_to_path(&center, seg_list)?,
+        };
+        Ok(distance.filter(|distance| *distance <= radius))
+    }
+
+    /// ルートの線上で`center`に最も近い点までの距離
+    ///
+    /// 中心の周りを平面とみなして，各区間(点と点の間の線分)への距離を測る
+    fn distance_to_path(
+        center: &Coordinate,
+        seg_list: &SegmentList,
+    ) -> ApplicationResult<Option<Distance>> {
+        let lat0 = center.latitude().value();
+        let lon0 = center.longitude().value();
+        let project = |coord: &Coordinate| {
+            (
+                (coord.longitude().value() - lon0).to_radians()
+                    * lat0.to_radians().cos()
+                    * EARTH_RADIUS,
+                (coord.latitude().value() - lat0).to_radians() * EARTH_RADIUS,
+            )
+        };
+
+        let points = seg_list
+            .iter()
+            .flat_map(|seg| seg.iter())
+            .map(project)
+            .collect_vec();
+        let min_distance = match points.as_slice() {
+            [] => return Ok(None),
+            [(x, y)] => x.hypot(*y),
+            _ => points
+                .iter()
+                .tuple_windows()
+                .map(|(from, to)| Self::distance_to_line(*from, *to))
+                .fold(f64::INFINITY, f64::min),
+        };
+        Distance::try_from(min_distance).map(Some)
+    }
+
+    /// 原点から線分`from`-`to`までの距離
+    fn distance_to_line((x0, y0): (f64, f64), (x1, y1): (f64, f64)) -> f64 {
+        let (dx, dy) = (x1 - x0, y1 - y0);
+        let length_sq = dx * dx + dy * dy;
+        let ratio = if length_sq > 0. {
+            (-(x0 * dx + y0 * dy) / length_sq).clamp(0., 1.)
+        } else {
+            0.
+        };
+        (x0 + dx * ratio).hypot(y0 + dy * ratio)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::model::route::coordinate::tests::CoordinateFixtures;
+    use crate::model::route::segment_list::tests::SegmentListFixture;
+
+    use super::*;
+
+    fn query(coord: Coordinate, radius: f64, target: ProximityTarget) -> ProximityQuery {
+        ProximityQuery {
+            latitude: coord.latitude().value(),
+            longitude: coord.longitude().value(),
+            radius,
+            target,
+            limit: None,
+        }
+    }
+
+    #[rstest]
+    #[case::start_inside(
+        Coordinate::tokyo(false, None),
+        30_000.,
+        ProximityTarget::Start,
+        Some(26936.)
+    )]
+    #[case::start_outside(Coordinate::tokyo(false, None), 20_000., ProximityTarget::Start, None)]
+    #[case::passing_waypoint(Coordinate::tokyo(false, None), 100., ProximityTarget::Path, Some(0.))]
+    #[case::passing_between_points(
+        Coordinate::new(35.57423, 139.69757).unwrap(),
+        100.,
+        ProximityTarget::Path,
+        Some(0.)
+    )]
+    #[case::far_from_path(Coordinate::new(34.69, 135.50).unwrap(), 1000., ProximityTarget::Path, None)]
+    fn can_match_distance(
+        #[case] center: Coordinate,
+        #[case] radius: f64,
+        #[case] target: ProximityTarget,
+        #[case] expected: Option<f64>,
+    ) {
+        let seg_list = SegmentList::yokohama_to_chiba_via_tokyo(false, false, false);
+        let distance = query(center, radius, target)
+            .match_distance(&seg_list)
+            .unwrap();
+        match (distance, expected) {
+            (Some(distance), Some(expected)) => {
+                assert!((distance.value() - expected).abs() < 1., "{:?}", distance)
+            }
+            (distance, expected) => assert_eq!(distance.map(|d| d.value()), expected),
+        }
+    }
+
+    #[rstest]
+    #[case::north_of_line(35.57423 + 0.001, 139.69757, 53.)]
+    #[case::beyond_end_point(35.61311, 140.11135 + 0.01, 905.)]
+    fn measures_distance_to_nearest_line(
+        #[case] lat: f64,
+        #[case] lon: f64,
+        #[case] expected: f64,
+    ) {
+        let seg_list = SegmentList::yokohama_to_chiba_via_tokyo(false, false, false);
+        let distance = query(
+            Coordinate::new(lat, lon).unwrap(),
+            5000.,
+            ProximityTarget::Path,
+        )
+        .match_distance(&seg_list)
+        .unwrap()
+        .unwrap();
+        assert!(
+            (distance.value() - expected).abs() < expected * 0.1,
+            "{:?}",
+            distance
+        );
+    }
+
+    #[rstest]
+    #[case::zero_radius(35., 139., 0.)]
+    #[case::too_large_radius(35., 139., MAX_RADIUS + 1.)]
+    #[case::invalid_center(95., 139., 100.)]
+    fn cannot_match_with_invalid_query(#[case] lat: f64, #[case] lon: f64, #[case] radius: f64) {
+        let seg_list = SegmentList::yokohama_to_chiba_via_tokyo(false, false, false);
+        let query = ProximityQuery {
+            latitude: lat,
+            longitude: lon,
+            radius,
+            target: ProximityTarget::Path,
+            limit: None,
+        };
+        assert!(matches!(
+            query.match_distance(&seg_list),
+            Err(ApplicationError::ValidationError(_)) | Err(ApplicationError::ValueObjectError(_))
+        ));
+    }
+}
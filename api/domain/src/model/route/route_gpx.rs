@@ -4,13 +4,14 @@ use std::str::from_utf8;
 
 use itertools::Itertools;
 use num_traits::FromPrimitive;
-use quick_xml::events::Event;
+use quick_xml::events::{BytesEnd, BytesStart, Event};
 use quick_xml::{Reader, Writer};
 
 use route_bucket_utils::{ApplicationError, ApplicationResult};
 
 use crate::model::route::{
-    coordinate::Coordinate, route_info::RouteInfo, segment_list::SegmentList, Route,
+    coordinate::Coordinate, route_info::RouteInfo, segment_list::SegmentList, stage::Stage,
+    types::Elevation, Route,
 };
 
 #[cfg(any(test, feature = "fixtures"))]
@@ -31,16 +32,58 @@ impl From<Coordinate> for gpx::Waypoint {
     }
 }
 
+/// 距離標を名前付きのwptにする
+fn distance_marker_into_waypoint(marker: Coordinate) -> gpx::Waypoint {
+    let name = marker.distance_from_start.map(|dist| {
+        // 小数第2位までにし，末尾の0は省く ("0.3 km", "20 km")
+        let km = format!("{:.2}", dist.value() / 1000.);
+        format!("{} km", km.trim_end_matches('0').trim_end_matches('.'))
+    });
+    let mut waypoint = gpx::Waypoint::from(marker);
+    waypoint.name = name;
+    waypoint
+}
+
 impl From<RouteInfo> for gpx::Metadata {
     fn from(route_info: RouteInfo) -> Self {
+        let RouteInfo {
+            name,
+            elevation_source,
+            metadata,
+            ..
+        } = route_info;
+        // GPXに対応する要素の無い情報は，keywordsに入れる
+        let keywords = std::iter::once(format!("elevation_source:{}", elevation_source))
+            .chain(
+                metadata
+                    .surface_type()
+                    .map(|surface_type| format!("surface_type:{}", surface_type)),
+            )
+            .chain(
+                metadata
+                    .bike_type()
+                    .map(|bike_type| format!("bike_type:{}", bike_type)),
+            )
+            .join(",");
+        let links = metadata
+            .external_link()
+            .iter()
+            .map(|url| gpx::Link {
+                href: url.to_string(),
+                text: Some(name.clone()),
+                _type: None,
+            })
+            .collect_vec();
+
         Self {
-            name: Some(route_info.name),
-            description: None,
+            name: Some(name),
+            // NOTE: gpxクレートはGPX1.1でも<description>と書くので，RouteGpx::from_gpxで<desc>に直す
+            description: metadata.description().clone(),
+            keywords: Some(keywords),
             // TODO: ここにRouteBucketのリンクを入れられると良さそう
             author: None,
-            links: vec![],
+            links,
             time: None,
-            keywords: None,
             bounds: None,
         }
     }
@@ -61,6 +104,21 @@ impl From<SegmentList> for gpx::Track {
     }
 }
 
+impl From<Stage> for gpx::Track {
+    fn from(stage: Stage) -> Self {
+        let mut trk = Self::new();
+        trk.name = Some(format!("Day {}", stage.day()));
+        trk.segments.push(gpx::TrackSegment::new());
+        trk.segments[0].points = stage
+            .points()
+            .iter()
+            .cloned()
+            .map(gpx::Waypoint::from)
+            .collect_vec();
+        trk
+    }
+}
+
 impl From<Route> for gpx::Gpx {
     fn from(route: Route) -> Self {
         gpx::Gpx {
@@ -92,15 +150,91 @@ impl RouteGpx {
     pub fn as_slice(&self) -> &[u8] {
         self.data.as_slice()
     }
-}
 
-impl TryFrom<Route> for RouteGpx {
-    type Error = ApplicationError;
+    /// 各ステージを1つのtrkとして持つGPXを作る
+    pub fn from_stages(info: RouteInfo, stages: Vec<Stage>) -> ApplicationResult<Self> {
+        let file_name = info.name.clone();
+        Self::from_gpx(
+            file_name,
+            gpx::Gpx {
+                version: gpx::GpxVersion::Gpx11,
+                metadata: Some(info.into()),
+                tracks: stages.into_iter().map(gpx::Track::from).collect_vec(),
+                ..Default::default()
+            },
+        )
+    }
 
-    fn try_from(route: Route) -> ApplicationResult<Self> {
+    /// 各ルートを1つのtrkとして持つGPXを作る (コレクションの書き出し用)
+    pub fn from_routes(name: &str, routes: Vec<Route>) -> ApplicationResult<Self> {
+        let tracks = routes
+            .into_iter()
+            .map(|route| {
+                let mut trk = gpx::Track::from(route.seg_list);
+                trk.name = Some(route.info.name);
+                trk.description = route.info.metadata.description().clone();
+                trk
+            })
+            .collect_vec();
+        Self::from_gpx(
+            name.to_string(),
+            gpx::Gpx {
+                version: gpx::GpxVersion::Gpx11,
+                metadata: Some(gpx::Metadata {
+                    name: Some(name.to_string()),
+                    ..Default::default()
+                }),
+                tracks,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// 取り込むGPXを読み，名前とトラック(無ければルート)の点を返す
+    ///
+    /// `<ele>`は各点の元の標高として持たせる
+    pub fn read_track(data: &[u8]) -> ApplicationResult<(Option<String>, Vec<Coordinate>)> {
+        let gpx = gpx::read(data)
+            .map_err(|err| ApplicationError::ValidationError(format!("Invalid GPX ({})", err)))?;
+
+        let name = gpx
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.name.clone())
+            .or_else(|| gpx.tracks.iter().find_map(|trk| trk.name.clone()))
+            .or_else(|| gpx.route.name.clone());
+        let track_points = gpx
+            .tracks
+            .into_iter()
+            .flat_map(|trk| trk.segments)
+            .flat_map(|trkseg| trkseg.points)
+            .collect_vec();
+        let waypoints = if track_points.is_empty() {
+            gpx.route.points
+        } else {
+            track_points
+        };
+
+        let points = waypoints
+            .into_iter()
+            .map(|waypoint| {
+                let point = waypoint.point();
+                let mut coord = Coordinate::new(point.y(), point.x())?;
+                coord.set_source_elevation(
+                    waypoint
+                        .elevation
+                        .map(|elevation| Elevation::try_from(elevation.round() as i32))
+                        .transpose()?,
+                );
+                Ok(coord)
+            })
+            .collect::<ApplicationResult<Vec<_>>>()?;
+        Ok((name, points))
+    }
+
+    fn from_gpx(file_name: String, org_gpx: gpx::Gpx) -> ApplicationResult<Self> {
         let mut org_gpx_buf = Vec::new();
-        let file_name = route.info.name.clone();
-        gpx::write(&route.into(), &mut org_gpx_buf).unwrap();
+        gpx::write(&org_gpx, &mut org_gpx_buf).unwrap();
 
         let mut reader = Reader::from_str(from_utf8(&org_gpx_buf).unwrap());
         let mut writer = Writer::new(Cursor::new(Vec::new()));
@@ -112,9 +246,36 @@ impl TryFrom<Route> for RouteGpx {
         };
 
         let mut found_gpx_element = false;
+        // NOTE: gpxクレートは<metadata>の<keywords>を<link>より前に書くが，
+        //     : GPX1.1では<link>の後なので，<keywords>から</metadata>までを並べ替えて書く
+        let mut deferred: Option<Vec<Event<'static>>> = None;
 
         loop {
             match reader.read_event(&mut read_buf) {
+                Ok(Event::Start(elem)) if elem.name() == b"description" => {
+                    writer
+                        .write_event(Event::Start(BytesStart::borrowed_name(b"desc")))
+                        .map_err(to_write_err)?;
+                }
+                Ok(Event::End(elem)) if elem.name() == b"description" => {
+                    writer
+                        .write_event(Event::End(BytesEnd::borrowed(b"desc")))
+                        .map_err(to_write_err)?;
+                }
+                Ok(Event::Start(elem)) if elem.name() == b"keywords" && deferred.is_none() => {
+                    deferred = Some(vec![Event::Start(elem.into_owned())]);
+                }
+                Ok(Event::End(elem)) if elem.name() == b"metadata" => {
+                    for event in Self::move_keywords_to_end(deferred.take().unwrap_or_default()) {
+                        writer.write_event(event).map_err(to_write_err)?;
+                    }
+                    writer.write_event(Event::End(elem)).map_err(to_write_err)?;
+                }
+                Ok(event) if deferred.is_some() => {
+                    if let Some(deferred) = deferred.as_mut() {
+                        deferred.push(event.into_owned());
+                    }
+                }
                 Ok(Event::Start(mut elem)) if elem.name() == b"gpx" => {
                     elem.extend_attributes([
                         ("xsi:schemaLocation", "http://www.topografix.com/GPX/11.xsd"),
@@ -156,11 +317,77 @@ impl TryFrom<Route> for RouteGpx {
     }
 }
 
+impl RouteGpx {
+    /// `<keywords>...</keywords>`で始まる`events`を，keywordsが最後になるように並べ替える
+    ///
+    /// 要素の間の空白(インデント)はそのままの位置に残す
+    fn move_keywords_to_end(events: Vec<Event<'static>>) -> Vec<Event<'static>> {
+        let is_space = |event: &Event| matches!(event, Event::Text(text) if text.iter().all(u8::is_ascii_whitespace));
+        let keywords_len = events
+            .iter()
+            .position(|event| matches!(event, Event::End(elem) if elem.name() == b"keywords"))
+            .map_or(events.len(), |pos| pos + 1);
+        let mut rest = events;
+        let keywords = rest.drain(..keywords_len).collect_vec();
+
+        let trail = rest.last().filter(|event| is_space(event)).cloned();
+        if trail.is_some() {
+            rest.pop();
+        }
+        let lead = rest.first().filter(|event| is_space(event)).cloned();
+        if lead.is_some() {
+            rest.remove(0);
+        }
+        let lead = lead.filter(|_| !rest.is_empty());
+
+        rest.into_iter()
+            .chain(lead)
+            .chain(keywords)
+            .chain(trail)
+            .collect()
+    }
+}
+
+impl TryFrom<Route> for RouteGpx {
+    type Error = ApplicationError;
+
+    fn try_from(route: Route) -> ApplicationResult<Self> {
+        Self::try_from((route, Vec::new()))
+    }
+}
+
+/// 距離標をwptとして書き出す
+impl TryFrom<(Route, Vec<Coordinate>)> for RouteGpx {
+    type Error = ApplicationError;
+
+    fn try_from((route, distance_markers): (Route, Vec<Coordinate>)) -> ApplicationResult<Self> {
+        let file_name = route.info.name.clone();
+        let mut org_gpx: gpx::Gpx = route.into();
+        org_gpx.waypoints = distance_markers
+            .into_iter()
+            .map(distance_marker_into_waypoint)
+            .collect_vec();
+        Self::from_gpx(file_name, org_gpx)
+    }
+}
+
 #[cfg(any(test, feature = "fixtures"))]
 pub(crate) mod tests {
     use rstest::{fixture, rstest};
 
+    #[cfg(test)]
+    use crate::model::route::coordinate::tests::CoordinateFixtures;
+    #[cfg(test)]
+    use crate::model::route::metadata::{tests::RouteMetadataFixtures, RouteMetadata};
+    #[cfg(test)]
+    use crate::model::route::route_info::tests::RouteInfoFixtures;
+    #[cfg(test)]
+    use crate::model::route::stage::{tests::StagePlanFixtures, StagePlan};
     use crate::model::route::tests::RouteFixtures;
+    #[cfg(test)]
+    use crate::model::route::types::Distance;
+    #[cfg(test)]
+    use crate::model::route::{ElevationSource, Segment};
 
     use super::*;
 
@@ -182,6 +409,127 @@ pub(crate) mod tests {
         assert_eq!(RouteGpx::try_from(route), Ok(expected_gpx))
     }
 
+    #[rstest]
+    #[case::whole_km(20000., "20 km")]
+    #[case::float_error(0.1 * 3. * 1000., "0.3 km")]
+    #[case::hundredths(12345., "12.35 km")]
+    fn can_name_distance_marker(#[case] distance: f64, #[case] expected: &str) {
+        let mut marker = Coordinate::yokohama(false, None);
+        marker.set_distance_from_start(Distance::try_from(distance).unwrap());
+        assert_eq!(
+            distance_marker_into_waypoint(marker).name,
+            Some(expected.to_string())
+        );
+    }
+
+    #[rstest]
+    fn can_convert_route_with_distance_markers_into_gpx(#[from(route0)] route: Route) {
+        let markers = route
+            .seg_list()
+            .calc_distance_markers(Distance::try_from(20000.).unwrap())
+            .unwrap();
+        assert_eq!(
+            RouteGpx::try_from((route, markers)),
+            Ok(RouteGpx::route0_with_markers())
+        )
+    }
+
+    #[rstest]
+    fn can_convert_stages_into_gpx(#[from(route0)] route: Route) {
+        let stages = StagePlan::thirty_kilometers()
+            .split(route.seg_list())
+            .unwrap();
+        assert_eq!(
+            RouteGpx::from_stages(route.info.clone(), stages),
+            Ok(RouteGpx::route0_stages())
+        )
+    }
+
+    #[rstest]
+    fn can_export_metadata_into_gpx(#[from(route0)] mut route: Route) {
+        route.info.set_metadata(RouteMetadata::gravel_ride());
+        let gpx = RouteGpx::try_from(route).unwrap();
+
+        let expected_metadata = r#"
+            <metadata>
+              <name>route0</name>
+              <desc>Mostly **gravel** along the river.</desc>
+              <link href="https://example.com/gravel">
+                <text>route0</text>
+              </link>
+              <keywords>elevation_source:dem,surface_type:gravel,bike_type:gravel</keywords>
+            </metadata>
+            "#;
+        let without_white_spaces = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+        assert!(
+            without_white_spaces(from_utf8(gpx.as_slice()).unwrap())
+                .contains(&without_white_spaces(expected_metadata)),
+            "{}",
+            from_utf8(gpx.as_slice()).unwrap()
+        );
+    }
+
+    #[rstest]
+    fn can_convert_routes_into_multi_track_gpx() {
+        let mut first = Route::yokohama_to_chiba();
+        first.info.set_metadata(RouteMetadata::gravel_ride());
+        let gpx = RouteGpx::from_routes("Rides in Kanto", vec![first, Route::yokohama_to_tokyo()])
+            .unwrap();
+        let gpx_str = from_utf8(gpx.as_slice()).unwrap();
+
+        assert_eq!(gpx.name(), "Rides in Kanto");
+        assert!(gpx_str.contains("<name>Rides in Kanto</name>"));
+        assert_eq!(gpx_str.matches("<trk>").count(), 2);
+        assert!(gpx_str.contains("<desc>Mostly **gravel** along the river.</desc>"));
+    }
+
+    #[rstest]
+    fn can_read_track(#[from(route0_gpx)] gpx: RouteGpx) {
+        let (name, points) = RouteGpx::read_track(gpx.as_slice()).unwrap();
+        assert_eq!(name, Some("route0".into()));
+        assert_eq!(
+            <(f64, f64)>::from(points[0].clone()),
+            <(f64, f64)>::from(Coordinate::yokohama(false, None))
+        );
+        assert_eq!(
+            points
+                .iter()
+                .map(|coord| coord.source_elevation().map(|elevation| elevation.value()))
+                .collect_vec(),
+            vec![Some(1), Some(4), Some(4), Some(11), Some(11)]
+        );
+    }
+
+    #[rstest]
+    fn cannot_read_invalid_gpx() {
+        assert!(matches!(
+            RouteGpx::read_track(b"<gpx><trk>"),
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
+    #[rstest]
+    fn imported_elevations_survive_export(#[from(route0_gpx)] gpx: RouteGpx) {
+        let (_, points) = RouteGpx::read_track(gpx.as_slice()).unwrap();
+        let mut seg_list = SegmentList::from_track(points.clone()).unwrap();
+        seg_list
+            .iter_mut()
+            .for_each(Segment::apply_source_elevations);
+        let mut info = RouteInfo::empty_route0(0);
+        info.set_elevation_source(ElevationSource::Source);
+
+        let exported = RouteGpx::try_from(Route::new(info, Vec::new(), seg_list)).unwrap();
+        assert!(from_utf8(exported.as_slice())
+            .unwrap()
+            .contains("elevation_source:source"));
+
+        let (_, reimported) = RouteGpx::read_track(exported.as_slice()).unwrap();
+        assert_eq!(
+            SegmentList::from_track(reimported).unwrap(),
+            SegmentList::from_track(points).unwrap()
+        );
+    }
+
     pub(super) fn cmp_utf8_without_white_spaces(left: &[u8], right: &[u8]) -> bool {
         std::str::from_utf8(left)
             .unwrap()
@@ -196,7 +544,119 @@ pub(crate) mod tests {
                 <gpx version="1.1" creator="https://github.com/georust/gpx" xsi:schemaLocation="http://www.topografix.com/GPX/11.xsd" xmlns="http://www.topografix.com/GPX/1/1" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
                   <metadata>
                     <name>route0</name>
+                    <keywords>elevation_source:dem</keywords>
+                  </metadata>
+                  <trk>
+                    <trkseg>
+                      <trkpt lat="35.46798" lon="139.62607">
+                        <ele>1</ele>
+                      </trkpt>
+                      <trkpt lat="35.68048" lon="139.76906">
+                        <ele>4</ele>
+                      </trkpt>
+                      <trkpt lat="35.68048" lon="139.76906">
+                        <ele>4</ele>
+                      </trkpt>
+                      <trkpt lat="35.61311" lon="140.11135">
+                        <ele>11</ele>
+                      </trkpt>
+                      <trkpt lat="35.61311" lon="140.11135">
+                        <ele>11</ele>
+                      </trkpt>
+                    </trkseg>
+                  </trk>
+                  <rte />
+                </gpx>
+                "#;
+            RouteGpx {
+                name: "route0".into(),
+                data: gpx_str.into(),
+            }
+        }
+
+        fn route0_stages() -> RouteGpx {
+            let gpx_str = r#"
+                <?xml version="1.0" encoding="utf-8"?>
+                <gpx version="1.1" creator="https://github.com/georust/gpx" xsi:schemaLocation="http://www.topografix.com/GPX/11.xsd" xmlns="http://www.topografix.com/GPX/1/1" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+                  <metadata>
+                    <name>route0</name>
+                    <keywords>elevation_source:dem</keywords>
+                  </metadata>
+                  <trk>
+                    <name>Day 1</name>
+                    <trkseg>
+                      <trkpt lat="35.46798" lon="139.62607">
+                        <ele>1</ele>
+                      </trkpt>
+                      <trkpt lat="35.68048" lon="139.76906">
+                        <ele>4</ele>
+                      </trkpt>
+                    </trkseg>
+                  </trk>
+                  <trk>
+                    <name>Day 2</name>
+                    <trkseg>
+                      <trkpt lat="35.68048" lon="139.76906">
+                        <ele>4</ele>
+                      </trkpt>
+                      <trkpt lat="35.61311" lon="140.11135">
+                        <ele>11</ele>
+                      </trkpt>
+                    </trkseg>
+                  </trk>
+                  <rte />
+                </gpx>
+                "#;
+            RouteGpx {
+                name: "route0".into(),
+                data: gpx_str.into(),
+            }
+        }
+
+        fn route0_day2() -> RouteGpx {
+            let gpx_str = r#"
+                <?xml version="1.0" encoding="utf-8"?>
+                <gpx version="1.1" creator="https://github.com/georust/gpx" xsi:schemaLocation="http://www.topografix.com/GPX/11.xsd" xmlns="http://www.topografix.com/GPX/1/1" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+                  <metadata>
+                    <name>route0_day2</name>
+                    <keywords>elevation_source:dem</keywords>
+                  </metadata>
+                  <trk>
+                    <name>Day 2</name>
+                    <trkseg>
+                      <trkpt lat="35.68048" lon="139.76906">
+                        <ele>4</ele>
+                      </trkpt>
+                      <trkpt lat="35.61311" lon="140.11135">
+                        <ele>11</ele>
+                      </trkpt>
+                    </trkseg>
+                  </trk>
+                  <rte />
+                </gpx>
+                "#;
+            RouteGpx {
+                name: "route0_day2".into(),
+                data: gpx_str.into(),
+            }
+        }
+
+        fn route0_with_markers() -> RouteGpx {
+            let gpx_str = r#"
+                <?xml version="1.0" encoding="utf-8"?>
+                <gpx version="1.1" creator="https://github.com/georust/gpx" xsi:schemaLocation="http://www.topografix.com/GPX/11.xsd" xmlns="http://www.topografix.com/GPX/1/1" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">
+                  <metadata>
+                    <name>route0</name>
+                    <keywords>elevation_source:dem</keywords>
                   </metadata>
+                  <wpt lat="35.62575891049552" lon="139.73223850076118">
+                    <ele>3</ele>
+                    <name>20 km</name>
+                  </wpt>
+                  <wpt lat="35.65282459938639" lon="139.90957012432878">
+                    <ele>7</ele>
+                    <name>40 km</name>
+                  </wpt>
                   <trk>
                     <trkseg>
                       <trkpt lat="35.46798" lon="139.62607">
@@ -4,9 +4,16 @@ use derive_more::From;
 use getset::Getters;
 use serde::{Deserialize, Serialize};
 
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
 use crate::model::user::UserId;
 
-use super::{Distance, Elevation, RouteId};
+use super::{
+    BoundingBox, Difficulty, Distance, Elevation, ElevationSource, RouteId, RouteMetadata, Tag,
+};
+
+/// 1つのルートに付けられるタグの数の上限
+const MAX_TAGS: usize = 10;
 
 #[derive(Clone, Debug, From, Getters, Derivative, Deserialize, Serialize)]
 #[get = "pub"]
@@ -24,6 +31,17 @@ pub struct RouteInfo {
     pub(super) ascent_elevation_gain: Elevation,
     pub(super) descent_elevation_gain: Elevation,
     pub(super) total_distance: Distance,
+    pub(super) difficulty: Difficulty,
+    pub(super) elevation_source: ElevationSource,
+    /// 検索用に保存する範囲 (点が無ければNone)
+    #[serde(skip)]
+    #[cfg_attr(any(test, feature = "fixtures"), derivative(PartialEq = "ignore"))]
+    pub(super) bounding_box: Option<BoundingBox>,
+    /// 名前順に並べて持つ
+    #[serde(default)]
+    pub(super) tags: Vec<Tag>,
+    #[serde(flatten)]
+    pub(super) metadata: RouteMetadata,
     #[derivative(Default(value = "chrono::MIN_DATETIME"))]
     pub(super) created_at: DateTime<Utc>,
     #[derivative(Default(value = "chrono::MIN_DATETIME"))]
@@ -43,8 +61,35 @@ impl RouteInfo {
         self.name = name.to_string();
     }
 
+    pub fn set_metadata(&mut self, metadata: RouteMetadata) {
+        self.metadata = metadata;
+    }
+
+    pub fn set_elevation_source(&mut self, elevation_source: ElevationSource) {
+        self.elevation_source = elevation_source;
+    }
+
     pub fn clear_route(&mut self) {
         self.op_num = 0;
+        self.bounding_box = None;
+    }
+
+    /// 既に付いているタグなら何もしない
+    pub fn add_tag(&mut self, tag: Tag) -> ApplicationResult<()> {
+        if let Err(pos) = self.tags.binary_search(&tag) {
+            if self.tags.len() >= MAX_TAGS {
+                return Err(ApplicationError::InvalidOperation(
+                    "A route cannot have more than 10 tags",
+                ));
+            }
+            self.tags.insert(pos, tag);
+        }
+        Ok(())
+    }
+
+    /// 付いていないタグなら何もしない
+    pub fn remove_tag(&mut self, tag: &Tag) {
+        self.tags.retain(|t| t != tag);
     }
 }
 
@@ -53,6 +98,8 @@ pub(crate) mod tests {
     use rstest::{fixture, rstest};
     use std::convert::TryFrom;
 
+    #[cfg(test)]
+    use crate::model::route::tag::tests::TagFixtures;
     use crate::model::user::tests::UserIdFixtures;
 
     use super::*;
@@ -79,6 +126,36 @@ pub(crate) mod tests {
         assert_eq!(info.op_num, 0)
     }
 
+    #[rstest]
+    fn can_add_tags_in_order(#[from(route0_without_op)] mut info: RouteInfo) {
+        info.add_tag(Tag::gravel()).unwrap();
+        info.add_tag(Tag::commute()).unwrap();
+        info.add_tag(Tag::gravel()).unwrap();
+        assert_eq!(info.tags, vec![Tag::commute(), Tag::gravel()])
+    }
+
+    #[rstest]
+    fn cannot_add_too_many_tags(#[from(route0_without_op)] mut info: RouteInfo) {
+        for i in 0..MAX_TAGS {
+            info.add_tag(Tag::try_from(format!("tag{}", i)).unwrap())
+                .unwrap();
+        }
+        assert!(matches!(
+            info.add_tag(Tag::gravel()),
+            Err(ApplicationError::InvalidOperation(_))
+        ));
+        assert_eq!(info.tags.len(), MAX_TAGS);
+    }
+
+    #[rstest]
+    fn can_remove_tag(#[from(route0_without_op)] mut info: RouteInfo) {
+        info.add_tag(Tag::gravel()).unwrap();
+        info.add_tag(Tag::commute()).unwrap();
+        info.remove_tag(&Tag::gravel());
+        info.remove_tag(&Tag::gravel());
+        assert_eq!(info.tags, vec![Tag::commute()])
+    }
+
     pub trait RouteInfoFixtures {
         fn empty_route0(op_num: usize) -> RouteInfo {
             RouteInfo {
@@ -94,12 +171,14 @@ pub(crate) mod tests {
             asc_gain: i32,
             desc_gain: i32,
             total_dist: f64,
+            difficulty_score: f64,
             op_num: usize,
         ) -> RouteInfo {
             RouteInfo {
                 ascent_elevation_gain: Elevation::try_from(asc_gain).unwrap(),
                 descent_elevation_gain: Elevation::try_from(desc_gain).unwrap(),
                 total_distance: Distance::try_from(total_dist).unwrap(),
+                difficulty: Difficulty::from_score(difficulty_score),
                 ..Self::empty_route0(op_num)
             }
         }
@@ -112,15 +191,15 @@ pub(crate) mod tests {
         }
 
         fn yokohama_to_chiba() -> RouteInfo {
-            RouteInfo::filled_route0(10, 0, 46779.709825324135, 2)
+            RouteInfo::filled_route0(10, 0, 46779.709825324135, 23.789854912662065, 2)
         }
 
         fn yokohama_to_chiba_via_tokyo() -> RouteInfo {
-            RouteInfo::filled_route0(10, 0, 58759.973932514884, 3)
+            RouteInfo::filled_route0(10, 0, 58759.973932514884, 29.77998696625744, 3)
         }
 
         fn yokohama_to_tokyo() -> RouteInfo {
-            RouteInfo::filled_route0(3, 0, 26936.42633640023, 3)
+            RouteInfo::filled_route0(3, 0, 26936.42633640023, 13.588213168200113, 3)
         }
     }
 
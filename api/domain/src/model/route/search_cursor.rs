@@ -0,0 +1,187 @@
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+use super::search_query::{RouteSortKey, SortOrder};
+use super::types::{Distance, Elevation};
+use super::{RouteId, RouteInfo};
+
+/// 並び順に使う項目の値
+#[derive(Clone, Debug, PartialEq)]
+pub enum RouteSortValue {
+    Name(String),
+    TotalDistance(Distance),
+    AscentElevationGain(Elevation),
+    CreatedAt(DateTime<Utc>),
+    UpdatedAt(DateTime<Utc>),
+}
+
+impl RouteSortValue {
+    pub fn of(key: RouteSortKey, info: &RouteInfo) -> Self {
+        match key {
+            RouteSortKey::Name => Self::Name(info.name().clone()),
+            RouteSortKey::TotalDistance => Self::TotalDistance(*info.total_distance()),
+            RouteSortKey::AscentElevationGain => {
+                Self::AscentElevationGain(*info.ascent_elevation_gain())
+            }
+            RouteSortKey::CreatedAt => Self::CreatedAt(*info.created_at()),
+            RouteSortKey::UpdatedAt => Self::UpdatedAt(*info.updated_at()),
+        }
+    }
+
+    pub fn key(&self) -> RouteSortKey {
+        match self {
+            Self::Name(_) => RouteSortKey::Name,
+            Self::TotalDistance(_) => RouteSortKey::TotalDistance,
+            Self::AscentElevationGain(_) => RouteSortKey::AscentElevationGain,
+            Self::CreatedAt(_) => RouteSortKey::CreatedAt,
+            Self::UpdatedAt(_) => RouteSortKey::UpdatedAt,
+        }
+    }
+
+    fn to_text(&self) -> String {
+        match self {
+            Self::Name(name) => name.clone(),
+            Self::TotalDistance(distance) => distance.value().to_string(),
+            Self::AscentElevationGain(elevation) => elevation.value().to_string(),
+            Self::CreatedAt(datetime) | Self::UpdatedAt(datetime) => datetime.to_rfc3339(),
+        }
+    }
+
+    fn from_text(key: RouteSortKey, text: &str) -> Option<Self> {
+        let datetime = || {
+            DateTime::parse_from_rfc3339(text)
+                .ok()
+                .map(|datetime| datetime.with_timezone(&Utc))
+        };
+        match key {
+            RouteSortKey::Name => Some(Self::Name(text.to_string())),
+            RouteSortKey::TotalDistance => text
+                .parse::<f64>()
+                .ok()
+                .and_then(|value| Distance::try_from(value).ok())
+                .map(Self::TotalDistance),
+            RouteSortKey::AscentElevationGain => text
+                .parse::<i32>()
+                .ok()
+                .and_then(|value| Elevation::try_from(value).ok())
+                .map(Self::AscentElevationGain),
+            RouteSortKey::CreatedAt => datetime().map(Self::CreatedAt),
+            RouteSortKey::UpdatedAt => datetime().map(Self::UpdatedAt),
+        }
+    }
+}
+
+/// 前のページの最後のルートの位置
+///
+/// 並び順の項目の値とidの組で，このルートより後ろから続きを返す (keyset pagination)
+/// クライアントには中身を見せない文字列として渡す
+#[derive(Clone, Debug, PartialEq, Getters, Serialize, Deserialize)]
+#[get = "pub"]
+#[serde(try_from = "String", into = "String")]
+pub struct RouteSearchCursor {
+    value: RouteSortValue,
+    order: SortOrder,
+    id: RouteId,
+}
+
+impl RouteSearchCursor {
+    /// `info`の次から続けるカーソル
+    pub fn after(info: &RouteInfo, sort_by: RouteSortKey, order: SortOrder) -> Self {
+        Self {
+            value: RouteSortValue::of(sort_by, info),
+            order,
+            id: info.id().clone(),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        // 名前に`\n`が含まれていても，最後に置けばそのまま読める
+        let text = format!(
+            "{}\n{}\n{}\n{}",
+            self.value.key(),
+            self.order,
+            self.id,
+            self.value.to_text()
+        );
+        base64::encode_config(text, base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn decode(cursor: &str) -> ApplicationResult<Self> {
+        let invalid = || ApplicationError::ValidationError(format!("Invalid cursor {:?}", cursor));
+        let text = base64::decode_config(cursor, base64::URL_SAFE_NO_PAD)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(invalid)?;
+
+        let mut fields = text.splitn(4, '\n');
+        let mut next = || fields.next().ok_or_else(invalid);
+        let key = RouteSortKey::from_str(next()?).map_err(|_| invalid())?;
+        let order = SortOrder::from_str(next()?).map_err(|_| invalid())?;
+        let id = RouteId::from_string(next()?.to_string());
+        let value = RouteSortValue::from_text(key, next()?).ok_or_else(invalid)?;
+        Ok(Self { value, order, id })
+    }
+}
+
+impl TryFrom<String> for RouteSearchCursor {
+    type Error = ApplicationError;
+
+    fn try_from(cursor: String) -> Result<Self, Self::Error> {
+        Self::decode(&cursor)
+    }
+}
+
+impl From<RouteSearchCursor> for String {
+    fn from(cursor: RouteSearchCursor) -> Self {
+        cursor.encode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use rstest::rstest;
+
+    use super::*;
+
+    fn cursor(value: RouteSortValue, order: SortOrder) -> RouteSearchCursor {
+        RouteSearchCursor {
+            value,
+            order,
+            id: RouteId::from_string("5D3yRzxZhaQ".into()),
+        }
+    }
+
+    #[rstest]
+    #[case::name(RouteSortValue::Name("Tokyo\n東京 :,".into()), SortOrder::Asc)]
+    #[case::distance(RouteSortValue::TotalDistance(Distance::try_from(12345.678).unwrap()), SortOrder::Desc)]
+    #[case::ascent(RouteSortValue::AscentElevationGain(Elevation::try_from(1200).unwrap()), SortOrder::Asc)]
+    #[case::created_at(RouteSortValue::CreatedAt(Utc.ymd(2021, 7, 1).and_hms(9, 30, 0)), SortOrder::Desc)]
+    #[case::updated_at(RouteSortValue::UpdatedAt(Utc.ymd(2021, 7, 1).and_hms(9, 30, 0)), SortOrder::Desc)]
+    fn can_decode_encoded_cursor(#[case] value: RouteSortValue, #[case] order: SortOrder) {
+        let cursor = cursor(value, order);
+        let encoded = cursor.encode();
+        assert!(encoded
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(RouteSearchCursor::decode(&encoded), Ok(cursor));
+    }
+
+    #[rstest]
+    #[case::not_base64("not base64!")]
+    #[case::too_few_fields(&base64::encode_config("name\nasc", base64::URL_SAFE_NO_PAD))]
+    #[case::unknown_key(&base64::encode_config("speed\nasc\nid\n1", base64::URL_SAFE_NO_PAD))]
+    #[case::invalid_value(&base64::encode_config("total_distance\nasc\nid\nfar", base64::URL_SAFE_NO_PAD))]
+    fn cannot_decode_invalid_cursor(#[case] encoded: &str) {
+        assert!(matches!(
+            RouteSearchCursor::decode(encoded),
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+}
@@ -1,21 +1,166 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
 use crate::model::user::UserId;
 
+use super::{BoundingBox, DifficultyRating, Distance, Elevation, RouteSearchCursor, Tag};
+
+/// 検索結果の並び順に使う項目
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum RouteSortKey {
+    Name,
+    TotalDistance,
+    AscentElevationGain,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl Default for RouteSortKey {
+    fn default() -> Self {
+        Self::UpdatedAt
+    }
+}
+
+#[derive(
+    Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl Default for SortOrder {
+    fn default() -> Self {
+        Self::Desc
+    }
+}
+
+/// ルートの検索条件
+///
+/// 範囲の条件(min_*, max_*, *_after, *_before)は両端を含む
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 #[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
 pub struct RouteSearchQuery {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub owner_id: Option<UserId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<DifficultyRating>,
+    /// ルート名に含まれる文字列
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_distance: Option<Distance>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_distance: Option<Distance>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_ascent: Option<Elevation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_ascent: Option<Elevation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_after: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_before: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_after: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_before: Option<DateTime<Utc>>,
+    /// 地図の表示範囲(`minLon,minLat,maxLon,maxLat`)．範囲が重なるルートを探す
+    #[serde(default, with = "viewport", skip_serializing_if = "Option::is_none")]
+    pub bbox: Option<BoundingBox>,
+    /// カンマ区切りのタグ(`gravel,commute`)．全てのタグが付いたルートを探す
+    #[serde(default, with = "tag_list", skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<Tag>,
+    #[serde(default)]
+    pub sort_by: RouteSortKey,
+    #[serde(default)]
+    pub order: SortOrder,
     #[serde(default)]
     pub page_offset: usize,
     pub page_size: Option<usize>,
+    /// 前のレスポンスの`next_cursor`．指定すると`page_offset`は使わない
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<RouteSearchCursor>,
 }
 
 impl RouteSearchQuery {
     pub fn empty() -> Self {
         Default::default()
     }
+
+    /// カーソルが同じ並び順の検索で作られたものか確かめる
+    pub fn validate_cursor(&self) -> ApplicationResult<()> {
+        match &self.cursor {
+            Some(cursor)
+                if cursor.value().key() != self.sort_by || *cursor.order() != self.order =>
+            {
+                Err(ApplicationError::ValidationError(format!(
+                    "The cursor is for sort_by={}&order={}, but got sort_by={}&order={}",
+                    cursor.value().key(),
+                    cursor.order(),
+                    self.sort_by,
+                    self.order
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// bboxをクエリ文字列の形式で読み書きする
+mod viewport {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    use super::BoundingBox;
+
+    pub fn serialize<S: Serializer>(
+        bbox: &Option<BoundingBox>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match bbox {
+            Some(bbox) => serializer.serialize_str(&bbox.to_viewport()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<BoundingBox>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|viewport| BoundingBox::parse_viewport(&viewport).map_err(de::Error::custom))
+            .transpose()
+    }
+}
+
+/// タグの一覧をクエリ文字列の形式で読み書きする
+mod tag_list {
+    use std::convert::TryFrom;
+
+    use itertools::Itertools;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    use super::Tag;
+
+    pub fn serialize<S: Serializer>(tags: &[Tag], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&tags.iter().join(","))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Tag>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .iter()
+            .flat_map(|tags| tags.split(','))
+            .filter(|tag| !tag.trim().is_empty())
+            .map(|tag| Tag::try_from(tag.to_string()).map_err(de::Error::custom))
+            .collect::<Result<Vec<_>, _>>()
+            .map(|tags| tags.into_iter().unique().collect())
+    }
 }
 
 #[cfg(any(test, feature = "fixtures"))]
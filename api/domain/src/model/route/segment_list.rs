@@ -1,7 +1,9 @@
-use std::cmp::max;
+use std::convert::TryFrom;
 use std::slice::{Iter, IterMut};
 
+use geo::algorithm::simplify::SimplifyIdx;
 use getset::Getters;
+use itertools::Itertools;
 use rayon::iter::{ParallelBridge, ParallelIterator};
 use serde::Serialize;
 
@@ -9,6 +11,7 @@ use route_bucket_utils::{ApplicationError, ApplicationResult};
 
 use super::bounding_box::BoundingBox;
 use super::coordinate::Coordinate;
+use super::elevation_profile::calc_elevation_gain;
 use super::types::{Distance, Elevation};
 
 pub use self::operation::{Operation, OperationId, OperationType, SegmentTemplate};
@@ -17,6 +20,20 @@ pub use self::segment::{DrawingMode, Segment};
 mod operation;
 mod segment;
 
+/// 1つのルートに付ける距離標の数の上限
+const MAX_DISTANCE_MARKERS: usize = 1000;
+
+/// 取り込めるトラックの点の数の上限 (1秒ごとの記録でおよそ14時間分)
+pub const MAX_TRACK_POINTS: usize = 50_000;
+
+/// 取り込んだトラックからウェイポイントを選ぶときの許容誤差 [m]
+const TRACK_WAYPOINT_TOLERANCE: f64 = 20.;
+
+/// 取り込んだトラックの1つのセグメントに入れる点の数の上限
+///
+/// 超える区間は途中にウェイポイントを置いて分け，ポリラインが列に収まり編集もしやすいようにする
+const MAX_TRACK_POINTS_PER_SEGMENT: usize = 500;
+
 #[derive(Clone, Debug, Serialize, Getters)]
 #[get = "pub"]
 #[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
@@ -26,6 +43,22 @@ pub struct SegmentList {
 
 impl SegmentList {
     pub fn apply_operation(&mut self, op: Operation) -> ApplicationResult<()> {
+        // 標高だけを変える操作では点列を残す (作り直すと経路を引き直すことになり，形が変わりうる)
+        if op.op_type == OperationType::SetElevation {
+            let (end, len) = (op.pos + op.new_seg_templates.len(), self.segments.len());
+            let segments = self.segments.get_mut(op.pos..end).ok_or_else(|| {
+                ApplicationError::DomainError(format!(
+                    "Operation range ({}..{}) is out of SegmentList (len: {}) at apply_operation",
+                    op.pos, end, len
+                ))
+            })?;
+            for (seg, template) in segments.iter_mut().zip(op.new_seg_templates) {
+                seg.set_elevation_override(template.elevation_override);
+                seg.clear_elevations();
+            }
+            return Ok(());
+        }
+
         self.segments.splice(
             op.pos..op.pos + op.org_seg_templates.len(),
             op.new_seg_templates
@@ -60,20 +93,9 @@ impl SegmentList {
         let gain_tuple_add = |(asc0, desc0), (asc1, desc1)| (asc0 + asc1, desc0 + desc1);
         self.iter()
             .par_bridge()
-            .fold(gain_tuple_identity, |(ascent_total, descent_total), seg| {
-                let mut ascent_gain = Elevation::zero();
-                let mut descent_gain = Elevation::zero();
-                let mut prev_elev = None;
-                seg.iter().for_each(|coord| {
-                    if let Some(elev) = coord.elevation() {
-                        if let Some(prev_elev_value) = prev_elev {
-                            ascent_gain += max(*elev - prev_elev_value, Elevation::zero());
-                            descent_gain += max(prev_elev_value - *elev, Elevation::zero());
-                        }
-                        prev_elev = Some(*elev);
-                    }
-                });
-                gain_tuple_add((ascent_total, descent_total), (ascent_gain, descent_gain))
+            .fold(gain_tuple_identity, |gain_total, seg| {
+                let seg_gain = calc_elevation_gain(seg.iter().map(|coord| *coord.elevation()));
+                gain_tuple_add(gain_total, seg_gain)
             })
             .reduce(gain_tuple_identity, gain_tuple_add)
     }
@@ -123,6 +145,121 @@ impl SegmentList {
         }
     }
 
+    /// ルートに沿って`interval`ごとの距離標を作る
+    ///
+    /// 各点に`distance_from_start`が付与されている必要がある
+    pub fn calc_distance_markers(&self, interval: Distance) -> ApplicationResult<Vec<Coordinate>> {
+        if interval <= Distance::zero() {
+            return Err(ApplicationError::InvalidOperation(
+                "Interval of distance markers must be positive.",
+            ));
+        }
+
+        let last_distance = self
+            .iter()
+            .last()
+            .and_then(|seg| seg.iter().last())
+            .and_then(|coord| coord.distance_from_start)
+            .unwrap_or_else(Distance::zero);
+        if last_distance.value() / interval.value() > MAX_DISTANCE_MARKERS as f64 {
+            return Err(ApplicationError::ValidationError(format!(
+                "Interval of distance markers is too short for the route (at most {} markers)",
+                MAX_DISTANCE_MARKERS
+            )));
+        }
+
+        let mut markers = Vec::new();
+        // 足し合わせると誤差がたまるので，何番目の距離標かから距離を求める
+        let mut count = 1;
+        let mut next_distance = interval;
+        for (from, to) in self.iter().flat_map(|seg| seg.iter()).tuple_windows() {
+            let (from_dist, to_dist) = match (from.distance_from_start, to.distance_from_start) {
+                (Some(from_dist), Some(to_dist)) => (from_dist, to_dist),
+                _ => {
+                    return Err(ApplicationError::DomainError(format!(
+                        "Cannot calc distance markers without distance_from_start! ({:?})",
+                        self
+                    )))
+                }
+            };
+
+            while next_distance <= to_dist {
+                let ratio = (next_distance - from_dist).value() / (to_dist - from_dist).value();
+                let lerp = |from_val: f64, to_val: f64| from_val + (to_val - from_val) * ratio;
+
+                let mut marker = Coordinate::new(
+                    lerp(from.latitude.value(), to.latitude.value()),
+                    lerp(from.longitude.value(), to.longitude.value()),
+                )?;
+                if let (Some(from_elev), Some(to_elev)) = (from.elevation, to.elevation) {
+                    marker.elevation = Some(Elevation::try_from(
+                        lerp(from_elev.value().into(), to_elev.value().into()).round() as i32,
+                    )?);
+                }
+                marker.distance_from_start = Some(next_distance);
+                markers.push(marker);
+
+                count += 1;
+                next_distance = Distance::try_from(interval.value() * count as f64)?;
+            }
+        }
+
+        Ok(markers)
+    }
+
+    /// 取り込んだトラックの点を順につなぐ
+    ///
+    /// 線を単純化して残った点をウェイポイントとし，間をフリーハンドのセグメントにする
+    /// セグメントには元の点をそのまま入れる (元の標高も残す)
+    /// 点が`MAX_TRACK_POINTS`より多いトラックは取り込まない
+    pub fn from_track(mut points: Vec<Coordinate>) -> ApplicationResult<Self> {
+        points.dedup_by(|coord, prev| {
+            coord.latitude() == prev.latitude() && coord.longitude() == prev.longitude()
+        });
+        let last = points
+            .last()
+            .cloned()
+            .ok_or_else(|| ApplicationError::ValidationError("The track has no points.".into()))?;
+        if points.len() > MAX_TRACK_POINTS {
+            return Err(ApplicationError::ValidationError(format!(
+                "The track has {} points, but at most {} points can be imported.",
+                points.len(),
+                MAX_TRACK_POINTS
+            )));
+        }
+
+        let line: geo::LineString<f64> = points
+            .iter()
+            .map(|coord| coord.project_locally(&points[0]))
+            .collect::<Vec<_>>()
+            .into();
+        let mut waypoint_indices = line
+            .simplify_idx(&TRACK_WAYPOINT_TOLERANCE)
+            .into_iter()
+            .tuple_windows()
+            .flat_map(|(from, to)| (from..to).step_by(MAX_TRACK_POINTS_PER_SEGMENT))
+            .collect::<Vec<_>>();
+        waypoint_indices.push(points.len() - 1);
+
+        let mut segments = waypoint_indices
+            .iter()
+            .tuple_windows()
+            .map(|(&from, &to)| {
+                let mut seg = Segment::new_empty(
+                    points[from].clone(),
+                    points[to].clone(),
+                    DrawingMode::Freehand,
+                );
+                seg.set_points(points[from..=to].to_vec())?;
+                Ok(seg)
+            })
+            .collect::<ApplicationResult<Vec<_>>>()?;
+        let mut goal = Segment::new_empty(last.clone(), last.clone(), DrawingMode::Freehand);
+        goal.set_points(vec![last])?;
+        segments.push(goal);
+        Ok(Self::from(segments))
+    }
+
     pub fn gather_waypoints(&self) -> Vec<Coordinate> {
         self.segments.iter().map(|seg| seg.start.clone()).collect()
     }
@@ -303,6 +440,63 @@ pub(crate) mod tests {
         ))
     }
 
+    #[rstest]
+    #[case::empty(SegmentList::empty(), 10000., vec![])]
+    #[case::every_20km(
+        SegmentList::yokohama_to_chiba_via_tokyo(true, true, false),
+        20000.,
+        vec![(20000., 3), (40000., 7)]
+    )]
+    #[case::at_waypoint(
+        SegmentList::yokohama_to_chiba_via_tokyo(true, true, false),
+        26936.42633640023,
+        vec![(26936.42633640023, 4), (26936.42633640023 * 2., 10)]
+    )]
+
+    fn can_calc_distance_markers(
+        #[case] seg_list: SegmentList,
+        #[case] interval: f64,
+        #[case] expected: Vec<(f64, i32)>,
+    ) {
+        let markers = seg_list
+            .calc_distance_markers(Distance::try_from(interval).unwrap())
+            .unwrap();
+        assert_eq!(
+            markers
+                .iter()
+                .map(|marker| (
+                    marker.distance_from_start.unwrap().value(),
+                    marker.elevation.unwrap().value()
+                ))
+                .collect_vec(),
+            expected
+        );
+    }
+
+    #[rstest]
+    fn distance_markers_do_not_accumulate_error() {
+        let markers = SegmentList::yokohama_to_chiba_via_tokyo(true, true, false)
+            .calc_distance_markers(Distance::try_from(100.).unwrap())
+            .unwrap();
+        assert_eq!(
+            markers
+                .iter()
+                .map(|marker| marker.distance_from_start.unwrap().value())
+                .collect_vec(),
+            (1..=587).map(|count| 100. * count as f64).collect_vec()
+        );
+    }
+
+    #[rstest]
+    #[case::zero_interval(SegmentList::yokohama_to_chiba_via_tokyo(true, true, false), 0.)]
+    #[case::without_distance(SegmentList::yokohama_to_chiba_via_tokyo(true, false, false), 10000.)]
+    #[case::too_many_markers(SegmentList::yokohama_to_chiba_via_tokyo(true, true, false), 50.)]
+    fn cannot_calc_distance_markers(#[case] seg_list: SegmentList, #[case] interval: f64) {
+        assert!(seg_list
+            .calc_distance_markers(Distance::try_from(interval).unwrap())
+            .is_err())
+    }
+
     #[rstest]
     #[case::empty(SegmentList::empty(), vec![])]
     #[case::single_point(
@@ -340,11 +534,103 @@ pub(crate) mod tests {
         assert_eq!(seg_list.into_segments_in_between(), expected_segments)
     }
 
+    #[rstest]
+    fn can_create_from_track() {
+        let mut tokyo = Coordinate::tokyo(false, None);
+        tokyo.set_source_elevation(Some(Elevation::try_from(40).unwrap()));
+        let seg_list = SegmentList::from_track(vec![
+            Coordinate::yokohama(false, None),
+            tokyo.clone(),
+            tokyo.clone(),
+            Coordinate::chiba(false, None),
+        ])
+        .unwrap();
+
+        // 続けて同じ点は1つにまとめる
+        assert_eq!(
+            seg_list.gather_waypoints(),
+            vec![
+                Coordinate::yokohama(false, None),
+                tokyo.clone(),
+                Coordinate::chiba(false, None)
+            ]
+        );
+        assert!(seg_list
+            .iter()
+            .all(|seg| *seg.mode() == DrawingMode::Freehand));
+        assert_eq!(
+            seg_list.iter().map(|seg| seg.points().len()).collect_vec(),
+            vec![2, 2, 1]
+        );
+        assert_eq!(seg_list.segments[0].points()[1], tokyo);
+    }
+
+    /// 東に`spacing`度ずつ並んだ`len`個の点
+    #[cfg(test)]
+    fn straight_track(len: usize, spacing: f64) -> Vec<Coordinate> {
+        (0..len)
+            .map(|i| Coordinate::new(35., 139. + i as f64 * spacing).unwrap())
+            .collect()
+    }
+
+    #[rstest]
+    fn from_track_keeps_points_between_waypoints() {
+        let points = straight_track(11, 0.001);
+        let seg_list = SegmentList::from_track(points.clone()).unwrap();
+
+        // 直線の途中の点はウェイポイントにしないが，セグメントの点としては残す
+        assert_eq!(
+            seg_list.gather_waypoints(),
+            vec![points[0].clone(), points[10].clone()]
+        );
+        assert_eq!(seg_list.segments[0].points(), &points);
+    }
+
+    #[rstest]
+    fn from_track_splits_long_segments() {
+        let seg_list = SegmentList::from_track(straight_track(1201, 0.0001)).unwrap();
+        assert_eq!(
+            seg_list.iter().map(|seg| seg.points().len()).collect_vec(),
+            vec![501, 501, 201, 1]
+        );
+    }
+
+    #[rstest]
+    fn cannot_create_from_too_long_track() {
+        assert!(matches!(
+            SegmentList::from_track(straight_track(MAX_TRACK_POINTS + 1, 0.00001)),
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
+    #[rstest]
+    fn cannot_create_from_empty_track() {
+        assert!(matches!(
+            SegmentList::from_track(Vec::new()),
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
     pub trait SegmentListFixture {
         fn empty() -> SegmentList {
             SegmentList { segments: vec![] }
         }
 
+        /// (緯度, 経度)の列をそのまま点に持つ，手描きのセグメント1つのルート
+        fn freehand(coords: &[(f64, f64)]) -> SegmentList {
+            let coords = coords
+                .iter()
+                .map(|(lat, lon)| Coordinate::new(*lat, *lon).unwrap())
+                .collect::<Vec<_>>();
+            let mut seg = Segment::new_empty(
+                coords[0].clone(),
+                coords[coords.len() - 1].clone(),
+                DrawingMode::Freehand,
+            );
+            seg.set_points(coords).unwrap();
+            SegmentList::from(vec![seg])
+        }
+
         fn yokohama(set_ele: bool, set_dist: bool, empty: bool) -> SegmentList {
             SegmentList {
                 segments: vec![Segment::yokohama(
@@ -9,6 +9,8 @@ use route_bucket_utils::{ApplicationError, ApplicationResult};
 use crate::model::types::NanoId;
 
 use super::super::coordinate::Coordinate;
+use super::super::elevation_override::ElevationOverride;
+use super::super::types::Elevation;
 use super::{DrawingMode, Segment, SegmentList};
 
 #[cfg(any(test, feature = "fixtures"))]
@@ -24,6 +26,8 @@ pub enum OperationType {
     Remove,
     #[strum(serialize = "mv")]
     Move,
+    #[strum(serialize = "el")]
+    SetElevation,
 }
 
 impl OperationType {
@@ -32,6 +36,7 @@ impl OperationType {
             OperationType::Add => OperationType::Remove,
             OperationType::Remove => OperationType::Add,
             OperationType::Move => OperationType::Move,
+            OperationType::SetElevation => OperationType::SetElevation,
         }
     }
 }
@@ -41,11 +46,18 @@ pub struct SegmentTemplate {
     start: Coordinate,
     goal: Coordinate,
     mode: DrawingMode,
+    #[serde(default)]
+    pub(super) elevation_override: ElevationOverride,
 }
 
 impl SegmentTemplate {
     pub fn new(start: Coordinate, goal: Coordinate, mode: DrawingMode) -> Self {
-        Self { start, goal, mode }
+        Self {
+            start,
+            goal,
+            mode,
+            elevation_override: ElevationOverride::default(),
+        }
     }
 
     pub fn from_segment(segment: &Segment) -> Self {
@@ -54,15 +66,31 @@ impl SegmentTemplate {
             segment.goal().clone(),
             *segment.mode(),
         )
+        .with_elevation_override(segment.elevation_override().clone())
+    }
+
+    pub fn with_elevation_override(mut self, elevation_override: ElevationOverride) -> Self {
+        self.elevation_override = elevation_override;
+        self
     }
 }
 
 impl From<SegmentTemplate> for Segment {
     fn from(template: SegmentTemplate) -> Self {
-        Segment::new_empty(template.start, template.goal, template.mode)
+        let mut segment = Segment::new_empty(template.start, template.goal, template.mode);
+        segment.set_elevation_override(template.elevation_override);
+        segment
     }
 }
 
+/// `pos`番目のウェイポイントに指定された標高
+fn waypoint_elevation(seg_list: &SegmentList, pos: usize) -> Option<Elevation> {
+    seg_list
+        .segments
+        .get(pos)
+        .and_then(|seg| *seg.elevation_override().start())
+}
+
 #[derive(Clone, Debug, Getters, From)]
 #[get = "pub"]
 #[cfg_attr(any(test, feature = "fixtures"), derive(Derivative))]
@@ -107,7 +135,8 @@ impl Operation {
                 let org_seg = &org_seg_list.segments[pos - 1];
                 let start = org_seg.start();
                 org_seg_templates = vec![SegmentTemplate::from_segment(org_seg)];
-                new_seg_templates = vec![SegmentTemplate::new(start.clone(), coord.clone(), mode)];
+                new_seg_templates = vec![SegmentTemplate::new(start.clone(), coord.clone(), mode)
+                    .with_elevation_override(org_seg.elevation_override().start_only())];
             }
 
             let goal = org_seg_list
@@ -116,7 +145,11 @@ impl Operation {
                 .map(Segment::start)
                 .unwrap_or_else(|| &coord);
 
-            new_seg_templates.push(SegmentTemplate::new(coord.clone(), goal.clone(), mode));
+            new_seg_templates.push(
+                SegmentTemplate::new(coord.clone(), goal.clone(), mode).with_elevation_override(
+                    ElevationOverride::new(None, waypoint_elevation(org_seg_list, pos), false),
+                ),
+            );
         } else {
             return Err(ApplicationError::DomainError(format!(
                 "pos({}) cannot be greater than org_seg_list.len()({}) at Operation::new_add",
@@ -143,12 +176,19 @@ impl Operation {
 
         if pos < org_seg_list.len() {
             if pos > 0 {
-                let start = org_seg_list.segments[pos - 1].start();
-                let goal = org_seg_list.segments[pos].goal();
-                org_seg_templates = vec![SegmentTemplate::from_segment(
-                    &org_seg_list.segments[pos - 1],
-                )];
-                new_seg_templates = vec![SegmentTemplate::new(start.clone(), goal.clone(), mode)];
+                let prev_seg = &org_seg_list.segments[pos - 1];
+                let removed_seg = &org_seg_list.segments[pos];
+                org_seg_templates = vec![SegmentTemplate::from_segment(prev_seg)];
+                new_seg_templates = vec![SegmentTemplate::new(
+                    prev_seg.start().clone(),
+                    removed_seg.goal().clone(),
+                    mode,
+                )
+                .with_elevation_override(ElevationOverride::new(
+                    *prev_seg.elevation_override().start(),
+                    *removed_seg.elevation_override().goal(),
+                    false,
+                ))];
             }
 
             org_seg_templates.push(SegmentTemplate::from_segment(&org_seg_list.segments[pos]));
@@ -179,11 +219,13 @@ impl Operation {
 
         if pos < org_seg_list.len() {
             if pos > 0 {
-                let start = org_seg_list.segments[pos - 1].start();
-                org_seg_templates = vec![SegmentTemplate::from_segment(
-                    &org_seg_list.segments[pos - 1],
-                )];
-                new_seg_templates = vec![SegmentTemplate::new(start.clone(), coord.clone(), mode)];
+                let prev_seg = &org_seg_list.segments[pos - 1];
+                org_seg_templates = vec![SegmentTemplate::from_segment(prev_seg)];
+                new_seg_templates =
+                    vec![
+                        SegmentTemplate::new(prev_seg.start().clone(), coord.clone(), mode)
+                            .with_elevation_override(prev_seg.elevation_override().start_only()),
+                    ];
             }
 
             let goal = org_seg_list
@@ -193,7 +235,11 @@ impl Operation {
                 .unwrap_or_else(|| &coord);
 
             org_seg_templates.push(SegmentTemplate::from_segment(&org_seg_list.segments[pos]));
-            new_seg_templates.push(SegmentTemplate::new(coord.clone(), goal.clone(), mode));
+            new_seg_templates.push(
+                SegmentTemplate::new(coord.clone(), goal.clone(), mode).with_elevation_override(
+                    ElevationOverride::new(None, waypoint_elevation(org_seg_list, pos + 1), false),
+                ),
+            );
         } else {
             return Err(ApplicationError::DomainError(format!(
                 "pos({}) cannot be greater than or equal to org_seg_list.len()({}) at Operation::new_move",
@@ -210,6 +256,76 @@ impl Operation {
         ))
     }
 
+    /// `pos`番目のウェイポイントの標高を指定する (Noneなら指定を解除する)
+    ///
+    /// ウェイポイントを終点とするセグメントと始点とするセグメントの両方の指定を変える
+    /// 適用しても点列はそのままで，標高だけを求め直す
+    pub fn new_set_waypoint_elevation(
+        pos: usize,
+        elevation: Option<Elevation>,
+        org_seg_list: &SegmentList,
+    ) -> ApplicationResult<Self> {
+        let mut org_seg_templates = Vec::new();
+        let mut new_seg_templates = Vec::new();
+
+        if pos < org_seg_list.len() {
+            if pos > 0 {
+                let prev_template = SegmentTemplate::from_segment(&org_seg_list.segments[pos - 1]);
+                let mut new_template = prev_template.clone();
+                new_template.elevation_override.set_goal(elevation);
+                org_seg_templates.push(prev_template);
+                new_seg_templates.push(new_template);
+            }
+
+            let template = SegmentTemplate::from_segment(&org_seg_list.segments[pos]);
+            let mut new_template = template.clone();
+            new_template.elevation_override.set_start(elevation);
+            org_seg_templates.push(template);
+            new_seg_templates.push(new_template);
+        } else {
+            return Err(ApplicationError::DomainError(format!(
+                "pos({}) cannot be greater than or equal to org_seg_list.len()({}) at Operation::new_set_waypoint_elevation",
+                pos,
+                org_seg_list.len()
+            )));
+        }
+
+        Ok(Self::new(
+            OperationType::SetElevation,
+            pos.saturating_sub(1),
+            org_seg_templates,
+            new_seg_templates,
+        ))
+    }
+
+    /// `pos`番目のセグメントを橋・トンネルとして，始点と終点の間の標高を線形補間するかを切り替える
+    pub fn new_set_interpolation(
+        pos: usize,
+        interpolate: bool,
+        org_seg_list: &SegmentList,
+    ) -> ApplicationResult<Self> {
+        let template = org_seg_list
+            .segments
+            .get(pos)
+            .map(SegmentTemplate::from_segment)
+            .ok_or_else(|| {
+                ApplicationError::DomainError(format!(
+                    "pos({}) cannot be greater than or equal to org_seg_list.len()({}) at Operation::new_set_interpolation",
+                    pos,
+                    org_seg_list.len()
+                ))
+            })?;
+        let mut new_template = template.clone();
+        new_template.elevation_override.set_interpolate(interpolate);
+
+        Ok(Self::new(
+            OperationType::SetElevation,
+            pos,
+            vec![template],
+            vec![new_template],
+        ))
+    }
+
     pub fn reverse(&mut self) {
         self.op_type = self.op_type.reverse();
         swap(&mut self.org_seg_templates, &mut self.new_seg_templates);
@@ -224,6 +340,8 @@ pub(crate) mod tests {
 
     #[cfg(test)]
     use crate::model::route::segment_list::tests::SegmentListFixture;
+    #[cfg(test)]
+    use std::convert::TryFrom;
 
     use super::*;
 
@@ -316,6 +434,127 @@ pub(crate) mod tests {
         assert_eq!(op, op_inv)
     }
 
+    #[cfg(test)]
+    fn elevation(value: i32) -> Option<Elevation> {
+        Some(Elevation::try_from(value).unwrap())
+    }
+
+    #[cfg(test)]
+    fn set_waypoint_elevation(seg_list: &mut SegmentList, pos: usize, value: i32) {
+        let op = Operation::new_set_waypoint_elevation(pos, elevation(value), seg_list).unwrap();
+        seg_list.apply_operation(op).unwrap();
+    }
+
+    #[rstest]
+    fn can_new_set_waypoint_elevation() {
+        assert_eq!(
+            Operation::new_set_waypoint_elevation(
+                1,
+                elevation(30),
+                &SegmentList::yokohama_to_chiba(false, false, true),
+            ),
+            Ok(Operation {
+                id: OperationId::new(),
+                op_type: OperationType::SetElevation,
+                pos: 0,
+                org_seg_templates: vec![
+                    init_template!(yokohama, chiba, FollowRoad),
+                    init_template!(chiba, chiba, FollowRoad),
+                ],
+                new_seg_templates: vec![
+                    init_template!(yokohama, chiba, FollowRoad).with_elevation_override(
+                        ElevationOverride::new(None, elevation(30), false)
+                    ),
+                    init_template!(chiba, chiba, FollowRoad).with_elevation_override(
+                        ElevationOverride::new(elevation(30), None, false)
+                    ),
+                ],
+            })
+        )
+    }
+
+    #[rstest]
+    fn can_new_set_interpolation() {
+        assert_eq!(
+            Operation::new_set_interpolation(
+                0,
+                true,
+                &SegmentList::yokohama_to_chiba(false, false, true),
+            ),
+            Ok(Operation {
+                id: OperationId::new(),
+                op_type: OperationType::SetElevation,
+                pos: 0,
+                org_seg_templates: vec![init_template!(yokohama, chiba, FollowRoad)],
+                new_seg_templates: vec![init_template!(yokohama, chiba, FollowRoad)
+                    .with_elevation_override(ElevationOverride::new(None, None, true))],
+            })
+        )
+    }
+
+    #[rstest]
+    fn elevation_override_survives_undo_and_redo() {
+        let mut seg_list = SegmentList::yokohama_to_chiba(false, false, true);
+        let op = Operation::new_set_interpolation(0, true, &seg_list).unwrap();
+        let mut inverse_op = op.clone();
+        inverse_op.reverse();
+
+        seg_list.apply_operation(op.clone()).unwrap();
+        assert!(seg_list.segments[0].elevation_override().interpolate());
+        seg_list.apply_operation(inverse_op).unwrap();
+        assert!(!seg_list.segments[0].elevation_override().interpolate());
+        seg_list.apply_operation(op).unwrap();
+        assert!(seg_list.segments[0].elevation_override().interpolate());
+    }
+
+    #[rstest]
+    fn set_elevation_keeps_points() {
+        let mut seg_list = SegmentList::yokohama_to_chiba(true, false, false);
+        let org_seg_list = seg_list.clone();
+        set_waypoint_elevation(&mut seg_list, 1, 30);
+
+        for (seg, org_seg) in seg_list.segments.iter().zip(&org_seg_list.segments) {
+            assert_eq!(seg.id(), org_seg.id());
+            assert_eq!(seg.points().len(), org_seg.points().len());
+            assert!(seg.iter().all(|coord| coord.elevation().is_none()));
+            assert_eq!(seg.dem_version(), &None);
+        }
+        assert_eq!(
+            seg_list.segments[1].elevation_override(),
+            &ElevationOverride::new(elevation(30), None, false)
+        );
+
+        let op = Operation::new_set_interpolation(0, true, &seg_list).unwrap();
+        seg_list.apply_operation(op).unwrap();
+        assert!(!seg_list.segments[0].is_empty());
+        assert!(seg_list.segments[0].elevation_override().interpolate());
+    }
+
+    #[rstest]
+    fn new_move_keeps_elevations_of_other_waypoints() {
+        let mut seg_list = SegmentList::yokohama_to_chiba_via_tokyo(false, false, true);
+        set_waypoint_elevation(&mut seg_list, 0, 10);
+        set_waypoint_elevation(&mut seg_list, 1, 99);
+        set_waypoint_elevation(&mut seg_list, 2, 20);
+
+        let op = Operation::new_move(
+            1,
+            Coordinate::yokohama(false, None),
+            &seg_list,
+            DrawingMode::FollowRoad,
+        )
+        .unwrap();
+        assert_eq!(
+            op.new_seg_templates,
+            vec![
+                init_template!(yokohama, yokohama, FollowRoad)
+                    .with_elevation_override(ElevationOverride::new(elevation(10), None, false)),
+                init_template!(yokohama, chiba, FollowRoad)
+                    .with_elevation_override(ElevationOverride::new(None, elevation(20), false)),
+            ]
+        )
+    }
+
     macro_rules! concat_op_list {
         ($op_list_name:ident, $op_name:ident) => {
             vec![Operation::$op_list_name(), vec![Operation::$op_name()]].concat()
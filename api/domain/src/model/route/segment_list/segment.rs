@@ -11,7 +11,8 @@ use serde::{Deserialize, Serialize};
 use route_bucket_utils::{ApplicationError, ApplicationResult};
 
 use super::super::coordinate::Coordinate;
-use super::super::types::{Distance, Polyline};
+use super::super::elevation_override::ElevationOverride;
+use super::super::types::{DemVersion, Distance, Elevation, Polyline};
 use crate::model::types::NanoId;
 
 pub(crate) type SegmentId = NanoId<Segment, 21>;
@@ -45,6 +46,11 @@ pub struct Segment {
     pub(super) mode: DrawingMode,
     #[into_iterator(owned)]
     pub(super) points: Vec<Coordinate>,
+    /// `points`の標高を求めたDEMの版 (標高が無ければNone)
+    #[serde(skip_serializing)]
+    pub(super) dem_version: Option<DemVersion>,
+    #[serde(skip_serializing_if = "ElevationOverride::is_empty")]
+    pub(super) elevation_override: ElevationOverride,
 }
 
 impl Segment {
@@ -55,6 +61,8 @@ impl Segment {
             goal,
             mode,
             points: Vec::new(),
+            dem_version: None,
+            elevation_override: ElevationOverride::default(),
         }
     }
 
@@ -130,6 +138,83 @@ impl Segment {
         }
     }
 
+    /// 各点の標高を`dem_version`で求めたものにまとめて置き換える
+    pub fn set_elevations(
+        &mut self,
+        elevations: Vec<Option<Elevation>>,
+        dem_version: DemVersion,
+    ) -> ApplicationResult<()> {
+        self.check_elevations_len(&elevations)?;
+        self.points
+            .iter_mut()
+            .zip(elevations)
+            .for_each(|(coord, elevation)| coord.elevation = elevation);
+        self.dem_version = Some(dem_version);
+        Ok(())
+    }
+
+    /// 各点に，取り込んだファイルの元の標高を持たせる
+    pub fn set_source_elevations(
+        &mut self,
+        source_elevations: Vec<Option<Elevation>>,
+    ) -> ApplicationResult<()> {
+        self.check_elevations_len(&source_elevations)?;
+        self.points
+            .iter_mut()
+            .zip(source_elevations)
+            .for_each(|(coord, elevation)| coord.set_source_elevation(elevation));
+        Ok(())
+    }
+
+    pub fn has_source_elevations(&self) -> bool {
+        self.iter().any(|coord| coord.source_elevation.is_some())
+    }
+
+    /// 元の標高がある点は，その標高を使う
+    ///
+    /// `dem_version`は元の標高が無い点の標高を求めたDEMの版としてそのまま残す
+    /// (DEMの標高に戻すときは`clear_elevations`で求め直す)
+    pub fn apply_source_elevations(&mut self) {
+        self.points.iter_mut().for_each(|coord| {
+            if let Some(source_elevation) = coord.source_elevation {
+                coord.elevation = Some(source_elevation);
+            }
+        });
+    }
+
+    fn check_elevations_len(&self, elevations: &[Option<Elevation>]) -> ApplicationResult<()> {
+        if elevations.len() != self.points.len() {
+            return Err(ApplicationError::DomainError(format!(
+                "Number of elevations ({}) doesn't match that of points ({}) in Segment {}!",
+                elevations.len(),
+                self.points.len(),
+                self.id
+            )));
+        }
+        Ok(())
+    }
+
+    /// 標高を全て消して，求め直せるようにする
+    pub fn clear_elevations(&mut self) {
+        self.points
+            .iter_mut()
+            .for_each(|coord| coord.elevation = None);
+        self.dem_version = None;
+    }
+
+    pub fn set_dem_version(&mut self, dem_version: DemVersion) {
+        self.dem_version = Some(dem_version);
+    }
+
+    pub fn set_elevation_override(&mut self, elevation_override: ElevationOverride) {
+        self.elevation_override = elevation_override;
+    }
+
+    /// 手動で補正された標高を点列に反映する
+    pub fn apply_elevation_override(&mut self) {
+        self.elevation_override.apply(&mut self.points);
+    }
+
     pub fn is_empty(&self) -> bool {
         self.points.is_empty()
     }
@@ -161,6 +246,8 @@ impl TryFrom<(String, String, String)> for Segment {
                 ApplicationError::DomainError(format!("Invalid mode: {}", mode_str))
             })?,
             points,
+            dem_version: None,
+            elevation_override: ElevationOverride::default(),
         })
     }
 }
@@ -274,6 +361,82 @@ pub(crate) mod tests {
         ))
     }
 
+    #[fixture]
+    fn dem_version() -> DemVersion {
+        DemVersion::from(String::from("bilinear-test"))
+    }
+
+    #[rstest]
+    fn can_set_elevations(
+        #[from(yokohama_to_tokyo)] mut seg: Segment,
+        #[from(yokohama_to_tokyo_coords)] coords: Vec<Coordinate>,
+        dem_version: DemVersion,
+    ) {
+        let elevations = coords.iter().map(|coord| *coord.elevation()).collect();
+        seg.set_elevations(elevations, dem_version.clone()).unwrap();
+
+        let mut expected_seg =
+            Segment::yokohama_to_tokyo(true, None, false, DrawingMode::FollowRoad);
+        expected_seg.set_dem_version(dem_version);
+        assert_eq!(seg, expected_seg)
+    }
+
+    #[rstest]
+    fn cannot_set_elevations_of_wrong_length(
+        #[from(yokohama_to_tokyo)] mut seg: Segment,
+        dem_version: DemVersion,
+    ) {
+        assert!(matches!(
+            seg.set_elevations(vec![None], dem_version),
+            Err(ApplicationError::DomainError(_))
+        ))
+    }
+
+    #[rstest]
+    fn can_clear_elevations(
+        #[from(yokohama_to_tokyo_verbose)] mut seg: Segment,
+        #[from(yokohama_to_tokyo_with_distance)] expected_seg: Segment,
+        dem_version: DemVersion,
+    ) {
+        seg.set_dem_version(dem_version);
+        seg.clear_elevations();
+        assert_eq!(seg, expected_seg)
+    }
+
+    #[rstest]
+    fn can_apply_source_elevations(
+        #[from(yokohama_to_tokyo_verbose)] mut seg: Segment,
+        dem_version: DemVersion,
+    ) {
+        seg.set_dem_version(dem_version.clone());
+        let source_elevation = Some(Elevation::try_from(100).unwrap());
+        seg.set_source_elevations(vec![None, source_elevation])
+            .unwrap();
+        assert!(seg.has_source_elevations());
+
+        seg.apply_source_elevations();
+
+        // 元の標高が無い点はDEMの標高のまま
+        let elevations = seg
+            .iter()
+            .map(|coord| *coord.elevation())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            elevations,
+            vec![Some(Elevation::try_from(1).unwrap()), source_elevation]
+        );
+        // 1点目の標高を求めたDEMの版
+        assert_eq!(seg.dem_version(), &Some(dem_version));
+    }
+
+    #[rstest]
+    fn cannot_set_source_elevations_of_wrong_length(#[from(yokohama_to_tokyo)] mut seg: Segment) {
+        assert!(matches!(
+            seg.set_source_elevations(vec![None]),
+            Err(ApplicationError::DomainError(_))
+        ))
+    }
+
     #[rstest]
     #[case::follow_road("follow_road", DrawingMode::FollowRoad)]
     #[case::freehand("freehand", DrawingMode::Freehand)]
@@ -318,6 +481,8 @@ pub(crate) mod tests {
                     vec![Coordinate::$fix_name($set_ele, $dist_offset)]
                 },
                 mode: $mode,
+                dem_version: None,
+                elevation_override: ElevationOverride::default(),
             }
         };
     }
@@ -339,6 +504,8 @@ pub(crate) mod tests {
                     Coordinate::yokohama_to_tokyo_coords(set_ele, dist_offset)
                 },
                 mode,
+                dem_version: None,
+                elevation_override: ElevationOverride::default(),
             }
         }
 
@@ -358,6 +525,8 @@ pub(crate) mod tests {
                     Coordinate::tokyo_to_chiba_coords(set_ele, dist_offset)
                 },
                 mode,
+                dem_version: None,
+                elevation_override: ElevationOverride::default(),
             }
         }
 
@@ -377,6 +546,8 @@ pub(crate) mod tests {
                     Coordinate::yokohama_to_chiba_coords(set_ele, dist_offset)
                 },
                 mode,
+                dem_version: None,
+                elevation_override: ElevationOverride::default(),
             }
         }
 
@@ -0,0 +1,254 @@
+use std::convert::TryFrom;
+use std::f64::consts::PI;
+
+use geo::algorithm::frechet_distance::FrechetDistance;
+use getset::Getters;
+use serde::Serialize;
+
+use route_bucket_utils::ApplicationResult;
+
+use crate::model::user::UserId;
+
+use super::bounding_box::BoundingBox;
+use super::coordinate::{Coordinate, EARTH_RADIUS};
+use super::segment_list::SegmentList;
+use super::types::Distance;
+
+/// 比べる前に線上の点を取り直す間隔 [m]
+///
+/// 離散フレシェ距離は，頂点の置き方の違いで間隔の半分ほど大きくなりうる
+const RESAMPLE_SPACING: f64 = 25.;
+
+/// 取り直した後に比べる点の数の上限．長いルートでは間隔を広げる
+const MAX_POINTS: usize = 1_000;
+
+/// フレシェ距離がこれ以下なら，ほぼ同じルートとみなす [m]
+const NEAR_DUPLICATE_THRESHOLD: f64 = 200.;
+
+/// 似たルートを並べるときに，形を比べる候補の範囲の各辺のずれの上限 [m]
+const SIMILAR_EXTENT_MARGIN: f64 = 2_000.;
+
+/// 形を比べるルートの数の上限．範囲の近いルートから選ぶ
+pub const MAX_SIMILAR_CANDIDATES: usize = 200;
+
+/// 2つのルートの形の近さ
+///
+/// 線上に等間隔に取り直した点どうしの(離散)フレシェ距離で測る．向きが逆のルートは別のものとみなす
+#[derive(Clone, Debug, Serialize, Getters)]
+#[get = "pub"]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct RouteSimilarity {
+    frechet_distance: Distance,
+    near_duplicate: bool,
+}
+
+impl RouteSimilarity {
+    /// どちらかに点が無ければNone
+    pub fn between(lhs: &SegmentList, rhs: &SegmentList) -> ApplicationResult<Option<Self>> {
+        let origin = match lhs.iter().next() {
+            Some(seg) => seg.start().clone(),
+            None => return Ok(None),
+        };
+        let (lhs, rhs) = (Self::to_line(lhs, &origin), Self::to_line(rhs, &origin));
+        if lhs.0.is_empty() || rhs.0.is_empty() {
+            return Ok(None);
+        }
+
+        let frechet_distance = Distance::try_from(lhs.frechet_distance(&rhs))?;
+        Ok(Some(Self {
+            frechet_distance,
+            near_duplicate: frechet_distance.value() <= NEAR_DUPLICATE_THRESHOLD,
+        }))
+    }
+
+    /// `origin`の周りを平面とみなしてメートル単位の線にし，線上に等間隔に点を取り直す
+    fn to_line(seg_list: &SegmentList, origin: &Coordinate) -> geo::LineString<f64> {
+        let points = seg_list
+            .iter()
+            .flat_map(|seg| seg.iter())
+            .map(|coord| coord.project_locally(origin))
+            .collect::<Vec<_>>();
+        let first = match points.first() {
+            Some(first) => *first,
+            None => return geo::LineString(Vec::new()),
+        };
+
+        let length = points
+            .windows(2)
+            .map(|pair| (pair[1].x - pair[0].x).hypot(pair[1].y - pair[0].y))
+            .sum::<f64>();
+        let count = ((length / RESAMPLE_SPACING).ceil() as usize).min(MAX_POINTS - 1);
+        if count == 0 {
+            return geo::LineString(vec![first]);
+        }
+        let spacing = length / count as f64;
+
+        let mut resampled = Vec::with_capacity(count + 1);
+        resampled.push(first);
+        let mut walked = 0.;
+        for pair in points.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            let dist = (to.x - from.x).hypot(to.y - from.y);
+            while resampled.len() < count && resampled.len() as f64 * spacing <= walked + dist {
+                let ratio = (resampled.len() as f64 * spacing - walked) / dist;
+                resampled.push(from + (to - from) * ratio);
+            }
+            walked += dist;
+        }
+        // 終点は丸め誤差によらず元の線の終点にする
+        resampled.push(points[points.len() - 1]);
+        geo::LineString(resampled)
+    }
+}
+
+/// 範囲の各辺が`bbox`の辺から`margin`以内にあるルートの検索条件
+///
+/// フレシェ距離は範囲の辺のずれより小さくならないので，
+/// 範囲が`margin`より大きくずれたルートはフレシェ距離も`margin`より大きい
+#[derive(Clone, Debug, Getters)]
+#[get = "pub"]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct SimilarExtentQuery {
+    bbox: BoundingBox,
+    margin: Distance,
+    /// 指定すればこのユーザーのルートだけを探す
+    owner_id: Option<UserId>,
+}
+
+impl SimilarExtentQuery {
+    /// 似たルートを並べるための条件
+    pub fn similar_to(bbox: BoundingBox, owner_id: Option<UserId>) -> ApplicationResult<Self> {
+        Ok(Self {
+            bbox,
+            margin: Distance::try_from(SIMILAR_EXTENT_MARGIN)?,
+            owner_id,
+        })
+    }
+
+    /// 同じ所有者のほぼ同じルートを探すための条件
+    ///
+    /// 取り直した点は範囲の端から間隔の半分ずつ内側に寄りうるので，その分だけ広く取る
+    pub fn near_duplicates_of(bbox: BoundingBox, owner_id: UserId) -> ApplicationResult<Self> {
+        Ok(Self {
+            bbox,
+            margin: Distance::try_from(NEAR_DUPLICATE_THRESHOLD + RESAMPLE_SPACING)?,
+            owner_id: Some(owner_id),
+        })
+    }
+
+    /// `margin`に当たる(緯度, 経度)の幅 [度]
+    ///
+    /// 経度の幅は，範囲の中で最も極に近い緯度で測る
+    pub fn margin_degrees(&self) -> (f64, f64) {
+        let meters_per_degree = EARTH_RADIUS * PI / 180.;
+        let max_abs_latitude = self
+            .bbox
+            .min_coord()
+            .latitude()
+            .value()
+            .abs()
+            .max(self.bbox.max_coord().latitude().value().abs());
+        let lat_margin = self.margin.value() / meters_per_degree;
+        let lon_margin = lat_margin / (max_abs_latitude + lat_margin).min(89.).to_radians().cos();
+        (lat_margin, lon_margin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter::once;
+
+    use rstest::rstest;
+
+    use crate::model::route::segment_list::tests::SegmentListFixture;
+
+    use super::*;
+
+    /// 横浜の周りの半径5kmの四分円を，`offset`だけずらした角度から`steps`等分した点で描く
+    fn quarter_arc(steps: usize, offset: f64) -> SegmentList {
+        let radius = 5_000. / (EARTH_RADIUS * PI / 180.);
+        let coords = once(0.)
+            .chain((1..steps).map(|i| (i as f64 - offset) / steps as f64 * PI / 2.))
+            .chain(once(PI / 2.))
+            .map(|angle| {
+                (
+                    35.46798 + radius * angle.sin(),
+                    139.62607 + radius * angle.cos() / 35.46798_f64.to_radians().cos(),
+                )
+            })
+            .collect::<Vec<_>>();
+        SegmentList::freehand(&coords)
+    }
+
+    #[rstest]
+    #[case::same_route(
+        SegmentList::yokohama_to_chiba_via_tokyo(false, false, false),
+        0.,
+        true
+    )]
+    #[case::slightly_shifted(
+        SegmentList::freehand(&[(35.46898, 139.62607), (35.68148, 139.76906), (35.61411, 140.11135)]),
+        111.,
+        true
+    )]
+    #[case::not_via_tokyo(SegmentList::yokohama_to_chiba(false, false, false), 17_701., false)]
+    #[case::reversed(
+        SegmentList::freehand(&[(35.61311, 140.11135), (35.68048, 139.76906), (35.46798, 139.62607)]),
+        46_780.,
+        false
+    )]
+    fn can_compare_routes(
+        #[case] other: SegmentList,
+        #[case] expected_distance: f64,
+        #[case] near_duplicate: bool,
+    ) {
+        let base = SegmentList::yokohama_to_chiba_via_tokyo(false, false, false);
+        let similarity = RouteSimilarity::between(&base, &other).unwrap().unwrap();
+        assert!(
+            (similarity.frechet_distance().value() - expected_distance).abs()
+                <= expected_distance * 0.1 + 1.,
+            "{:?}",
+            similarity
+        );
+        assert_eq!(*similarity.near_duplicate(), near_duplicate);
+    }
+
+    #[rstest]
+    fn same_path_with_other_vertices_is_near_duplicate() {
+        let similarity = RouteSimilarity::between(&quarter_arc(15, 0.), &quarter_arc(20, 0.5))
+            .unwrap()
+            .unwrap();
+        assert!(
+            similarity.frechet_distance().value() < RESAMPLE_SPACING,
+            "{:?}",
+            similarity
+        );
+        assert!(*similarity.near_duplicate());
+    }
+
+    #[rstest]
+    fn margin_widens_toward_poles() {
+        let bbox = SegmentList::yokohama_to_chiba_via_tokyo(false, false, false)
+            .calc_bounding_box()
+            .unwrap();
+        let query = SimilarExtentQuery::similar_to(bbox, None).unwrap();
+        let (lat_margin, lon_margin) = query.margin_degrees();
+
+        // 2km ≒ 緯度0.018度, 北緯35.7度で経度0.022度
+        assert!((lat_margin - 0.01799).abs() < 1e-4, "{}", lat_margin);
+        assert!((lon_margin - 0.02216).abs() < 1e-4, "{}", lon_margin);
+    }
+
+    #[rstest]
+    fn cannot_compare_with_empty_route() {
+        let base = SegmentList::yokohama_to_chiba_via_tokyo(false, false, false);
+        assert_eq!(
+            RouteSimilarity::between(&base, &SegmentList::empty()).unwrap(),
+            None
+        );
+        assert_eq!(
+            RouteSimilarity::between(&SegmentList::empty(), &base).unwrap(),
+            None
+        );
+    }
+}
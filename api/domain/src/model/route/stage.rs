@@ -0,0 +1,322 @@
+use std::cmp::max;
+
+use getset::Getters;
+use serde::Serialize;
+
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+use super::coordinate::Coordinate;
+use super::segment_list::SegmentList;
+use super::types::{Distance, Elevation};
+
+/// 目標に対してこの割合までの前後であれば，ウェイポイントで区切ることを優先する
+const WAYPOINT_TOLERANCE: f64 = 0.2;
+
+/// 1日あたりの目標距離・獲得標高
+///
+/// どちらか一方だけでもよく，両方指定された場合は先に達した方で区切る
+#[derive(Clone, Debug)]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct StagePlan {
+    distance: Option<Distance>,
+    ascent: Option<Elevation>,
+}
+
+impl StagePlan {
+    pub fn new(distance: Option<Distance>, ascent: Option<Elevation>) -> ApplicationResult<Self> {
+        let is_positive_distance = matches!(distance, Some(dist) if dist.value() > 0.);
+        let is_positive_ascent = matches!(ascent, Some(asc) if asc.value() > 0);
+        if is_positive_distance || is_positive_ascent {
+            Ok(Self {
+                distance: distance.filter(|_| is_positive_distance),
+                ascent: ascent.filter(|_| is_positive_ascent),
+            })
+        } else {
+            Err(ApplicationError::InvalidOperation(
+                "Either a positive distance or ascent per day is required to split a route.",
+            ))
+        }
+    }
+
+    /// `seg_list`を日毎のステージに分割する
+    ///
+    /// `seg_list`には`distance_from_start`が付与されている必要がある
+    pub fn split(&self, seg_list: &SegmentList) -> ApplicationResult<Vec<Stage>> {
+        let track = Track::try_from_seg_list(seg_list)?;
+        let last = match track.points.len().checked_sub(1) {
+            Some(last) if last > 0 => last,
+            _ => return Ok(Vec::new()),
+        };
+
+        let mut stages = Vec::new();
+        let mut start = 0;
+        while start < last {
+            let goal = self.find_split_pos(&track, start, last);
+            stages.push(track.to_stage(stages.len() + 1, start, goal));
+            start = goal;
+        }
+        Ok(stages)
+    }
+
+    fn find_split_pos(&self, track: &Track, start: usize, last: usize) -> usize {
+        if self.calc_load(track, start, last) <= 1. + WAYPOINT_TOLERANCE {
+            return last;
+        }
+
+        let mut first_overload_pos = None;
+        let mut best_waypoint: Option<(usize, f64)> = None;
+        for pos in start + 1..=last {
+            let load = self.calc_load(track, start, pos);
+            if load >= 1. && first_overload_pos.is_none() {
+                first_overload_pos = Some(pos);
+            }
+            if load > 1. + WAYPOINT_TOLERANCE {
+                break;
+            }
+            let gap = (load - 1.).abs();
+            if track.is_waypoint[pos]
+                && gap <= WAYPOINT_TOLERANCE
+                && !matches!(best_waypoint, Some((_, best_gap)) if best_gap <= gap)
+            {
+                best_waypoint = Some((pos, gap));
+            }
+        }
+
+        best_waypoint
+            .map(|(pos, _)| pos)
+            .or(first_overload_pos)
+            .unwrap_or(last)
+    }
+
+    /// 1日分の目標に対する`start`から`goal`までの割合
+    fn calc_load(&self, track: &Track, start: usize, goal: usize) -> f64 {
+        let distance_load = self.distance.map_or(0., |target| {
+            (track.distances[goal] - track.distances[start]).value() / target.value()
+        });
+        let ascent_load = self.ascent.map_or(0., |target| {
+            f64::from((track.ascents[goal] - track.ascents[start]).value())
+                / f64::from(target.value())
+        });
+        distance_load.max(ascent_load)
+    }
+}
+
+/// 分割された1日分のルート
+#[derive(Clone, Debug, Getters, Serialize)]
+#[get = "pub"]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct Stage {
+    /// 1始まりの日数
+    day: usize,
+    start: Coordinate,
+    goal: Coordinate,
+    start_distance: Distance,
+    distance: Distance,
+    ascent_elevation_gain: Elevation,
+    descent_elevation_gain: Elevation,
+    /// 区切りがウェイポイント（またはルートのゴール）かどうか
+    ends_at_waypoint: bool,
+    #[serde(skip_serializing)]
+    points: Vec<Coordinate>,
+}
+
+/// 分割用にSegmentListの点を平坦にし，累積値を持たせたもの
+struct Track {
+    points: Vec<Coordinate>,
+    is_waypoint: Vec<bool>,
+    distances: Vec<Distance>,
+    ascents: Vec<Elevation>,
+    descents: Vec<Elevation>,
+}
+
+impl Track {
+    fn try_from_seg_list(seg_list: &SegmentList) -> ApplicationResult<Self> {
+        let mut track = Self {
+            points: Vec::new(),
+            is_waypoint: Vec::new(),
+            distances: Vec::new(),
+            ascents: Vec::new(),
+            descents: Vec::new(),
+        };
+
+        let mut ascent = Elevation::zero();
+        let mut descent = Elevation::zero();
+        let mut prev_elev = None;
+        for (i, seg) in seg_list.iter().enumerate() {
+            for (j, coord) in seg.iter().enumerate() {
+                let distance = coord.distance_from_start().ok_or_else(|| {
+                    ApplicationError::DomainError(format!(
+                        "Cannot split a route without distance_from_start! ({:?})",
+                        coord
+                    ))
+                })?;
+                if let Some(elev) = coord.elevation() {
+                    if let Some(prev_elev_value) = prev_elev {
+                        ascent += max(*elev - prev_elev_value, Elevation::zero());
+                        descent += max(prev_elev_value - *elev, Elevation::zero());
+                    }
+                    prev_elev = Some(*elev);
+                }
+
+                // セグメントの境目の重複した点はまとめる
+                if j == 0 && track.points.last() == Some(coord) {
+                    if let Some(is_waypoint) = track.is_waypoint.last_mut() {
+                        *is_waypoint = true;
+                    }
+                    continue;
+                }
+
+                track.points.push(coord.clone());
+                track.is_waypoint.push(i > 0 && j == 0);
+                track.distances.push(distance);
+                track.ascents.push(ascent);
+                track.descents.push(descent);
+            }
+        }
+        // ゴールもウェイポイントとして扱う
+        if let Some(is_goal_waypoint) = track.is_waypoint.last_mut() {
+            *is_goal_waypoint = true;
+        }
+
+        Ok(track)
+    }
+
+    fn to_stage(&self, day: usize, start: usize, goal: usize) -> Stage {
+        Stage {
+            day,
+            start: self.points[start].clone(),
+            goal: self.points[goal].clone(),
+            start_distance: self.distances[start],
+            distance: self.distances[goal] - self.distances[start],
+            ascent_elevation_gain: self.ascents[goal] - self.ascents[start],
+            descent_elevation_gain: self.descents[goal] - self.descents[start],
+            ends_at_waypoint: self.is_waypoint[goal],
+            points: self.points[start..=goal].to_vec(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "fixtures"))]
+pub(crate) mod tests {
+    use rstest::rstest;
+    use std::convert::TryFrom;
+
+    #[cfg(test)]
+    use crate::model::route::coordinate::tests::CoordinateFixtures;
+    #[cfg(test)]
+    use crate::model::route::segment_list::{tests::SegmentListFixture, DrawingMode, Segment};
+
+    use super::*;
+
+    fn plan(distance: Option<f64>, ascent: Option<i32>) -> StagePlan {
+        StagePlan::new(
+            distance.map(|dist| Distance::try_from(dist).unwrap()),
+            ascent.map(|asc| Elevation::try_from(asc).unwrap()),
+        )
+        .unwrap()
+    }
+
+    #[rstest]
+    #[case::nothing(None, None)]
+    #[case::zero_distance(Some(0.), None)]
+    #[case::zero_ascent(None, Some(0))]
+    fn cannot_make_plan_without_target(#[case] distance: Option<f64>, #[case] ascent: Option<i32>) {
+        assert!(matches!(
+            StagePlan::new(
+                distance.map(|dist| Distance::try_from(dist).unwrap()),
+                ascent.map(|asc| Elevation::try_from(asc).unwrap()),
+            ),
+            Err(ApplicationError::InvalidOperation(_))
+        ))
+    }
+
+    #[rstest]
+    fn can_split_at_waypoint() {
+        // 東京(約26.9km地点)が目標の30kmに近いので，そこで区切られる
+        let stages = plan(Some(30000.), None)
+            .split(&SegmentList::yokohama_to_chiba_via_tokyo(true, true, false))
+            .unwrap();
+        assert_eq!(
+            stages
+                .iter()
+                .map(|stage| (stage.day, stage.distance.value(), stage.ends_at_waypoint))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, 26936.42633640023, true),
+                (2, 58759.973932514884 - 26936.42633640023, true)
+            ]
+        );
+        assert_eq!(stages[0].goal, stages[1].start);
+    }
+
+    #[rstest]
+    fn can_split_by_ascent() {
+        let stages = plan(None, Some(3))
+            .split(&SegmentList::yokohama_to_chiba_via_tokyo(true, true, false))
+            .unwrap();
+        assert_eq!(
+            stages
+                .iter()
+                .map(|stage| stage.ascent_elevation_gain.value())
+                .collect::<Vec<_>>(),
+            vec![3, 7]
+        );
+    }
+
+    #[rstest]
+    fn can_split_between_waypoints() {
+        // 東京はウェイポイントではないので，目標を超えた最初の点として区切られる
+        let mut seg = Segment::new_empty(
+            Coordinate::yokohama(false, None),
+            Coordinate::chiba(false, None),
+            DrawingMode::FollowRoad,
+        );
+        seg.set_points(vec![
+            Coordinate::yokohama(false, None),
+            Coordinate::tokyo(false, None),
+            Coordinate::chiba(false, None),
+        ])
+        .unwrap();
+        let mut seg_list = SegmentList::from(vec![seg]);
+        seg_list.attach_distance_from_start();
+
+        let stages = plan(Some(20000.), None).split(&seg_list).unwrap();
+        assert_eq!(
+            stages
+                .iter()
+                .map(|stage| (stage.goal.clone(), stage.ends_at_waypoint))
+                .collect::<Vec<_>>(),
+            vec![
+                (Coordinate::tokyo(false, Some(26936.42633640023)), false),
+                (Coordinate::chiba(false, Some(58759.973932514884)), true)
+            ]
+        );
+    }
+
+    #[rstest]
+    fn whole_route_fits_in_a_day() {
+        let stages = plan(Some(100000.), Some(1000))
+            .split(&SegmentList::yokohama_to_chiba_via_tokyo(true, true, false))
+            .unwrap();
+        assert_eq!(stages.len(), 1);
+        assert_eq!(stages[0].distance.value(), 58759.973932514884);
+    }
+
+    #[rstest]
+    fn cannot_split_without_distance() {
+        assert!(matches!(
+            plan(Some(30000.), None).split(&SegmentList::yokohama_to_chiba_via_tokyo(
+                true, false, false
+            )),
+            Err(ApplicationError::DomainError(_))
+        ))
+    }
+
+    pub trait StagePlanFixtures {
+        fn thirty_kilometers() -> StagePlan {
+            plan(Some(30000.), None)
+        }
+    }
+
+    impl StagePlanFixtures for StagePlan {}
+}
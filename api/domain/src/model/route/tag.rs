@@ -0,0 +1,93 @@
+use std::convert::TryFrom;
+
+use derive_more::{Display, From, Into};
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+/// タグの最大文字数
+const MAX_TAG_LENGTH: usize = 30;
+
+/// ルートに付けるラベル ("gravel", "commute", "race recon"など)
+///
+/// 前後の空白を除き，小文字にそろえて持つ
+#[derive(
+    Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Display, Into, Serialize, Deserialize,
+)]
+#[serde(try_from = "String", into = "String")]
+pub struct Tag(String);
+
+impl TryFrom<String> for Tag {
+    type Error = ApplicationError;
+
+    fn try_from(value: String) -> ApplicationResult<Self> {
+        let tag = value.trim().to_lowercase();
+        let length = tag.chars().count();
+        if length == 0 || length > MAX_TAG_LENGTH {
+            return Err(ApplicationError::ValidationError(format!(
+                "A tag must have 1 to {} characters, but got {:?}",
+                MAX_TAG_LENGTH, value
+            )));
+        }
+        // 検索条件ではカンマ区切りで渡すので，カンマは使えない
+        if tag.chars().any(|c| c == ',' || c.is_control()) {
+            return Err(ApplicationError::ValidationError(format!(
+                "A tag cannot contain commas or control characters, but got {:?}",
+                value
+            )));
+        }
+        Ok(Self(tag))
+    }
+}
+
+/// よく使われているタグと，そのタグの付いたルートの数
+#[derive(Clone, Debug, From, Getters, Serialize)]
+#[get = "pub"]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct TagCount {
+    tag: Tag,
+    count: usize,
+}
+
+#[cfg(any(test, feature = "fixtures"))]
+pub(crate) mod tests {
+    use rstest::rstest;
+
+    use super::*;
+
+    #[rstest]
+    #[case::plain("gravel", "gravel")]
+    #[case::normalized("  Race Recon ", "race recon")]
+    #[case::multibyte("峠", "峠")]
+    fn can_init_tag(#[case] value: &str, #[case] expected: &str) {
+        assert_eq!(
+            Tag::try_from(value.to_string()).unwrap().to_string(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case::empty("   ")]
+    #[case::too_long(&"a".repeat(MAX_TAG_LENGTH + 1))]
+    #[case::comma("gravel,commute")]
+    #[case::newline("gravel\nroad")]
+    fn cannot_init_invalid_tag(#[case] value: &str) {
+        assert!(matches!(
+            Tag::try_from(value.to_string()),
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
+    pub trait TagFixtures {
+        fn gravel() -> Tag {
+            Tag("gravel".into())
+        }
+
+        fn commute() -> Tag {
+            Tag("commute".into())
+        }
+    }
+
+    impl TagFixtures for Tag {}
+}
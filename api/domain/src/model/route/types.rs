@@ -16,6 +16,12 @@ impl Polyline {
     }
 }
 
+/// 標高を求めたDEM(とその補間方法)の版
+///
+/// 保存された標高の版が現在のDEMと異なる場合は，標高を求め直す
+#[derive(Display, From, Into, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DemVersion(String);
+
 pub type Latitude = NumericValueObject<OrderedFloat<f64>, 90>;
 pub type Longitude = NumericValueObject<OrderedFloat<f64>, 180>;
 // NOTE: genericsの特殊化が実装されたら、この0は消せる
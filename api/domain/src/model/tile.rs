@@ -0,0 +1,204 @@
+use std::f64::consts::PI;
+
+use getset::Getters;
+use serde::{Deserialize, Serialize};
+
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+pub use self::vector_tile::VectorTile;
+
+use super::route::{BoundingBox, Coordinate};
+use super::user::UserId;
+
+pub(crate) mod vector_tile;
+
+/// 受け付ける最大のズームレベル
+pub const MAX_ZOOM: u8 = 22;
+
+/// タイル内の座標の1辺の大きさ
+pub const EXTENT: u32 = 4096;
+
+/// タイルの外側に含める幅 (タイル内の座標の単位)
+///
+/// 線の太さの分だけ隣のタイルの線も描かないと，タイルの境目で線が欠ける
+pub const BUFFER: f64 = 64.;
+
+/// ウェブメルカトルで表せる緯度の上限
+const MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+
+/// XYZ形式のタイルの番号
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Getters)]
+#[get = "pub"]
+pub struct TileCoord {
+    z: u8,
+    x: u32,
+    y: u32,
+}
+
+impl TileCoord {
+    pub fn new(z: u8, x: u32, y: u32) -> ApplicationResult<Self> {
+        if z > MAX_ZOOM {
+            return Err(ApplicationError::ValidationError(format!(
+                "z must be in 0..={}, but got {}",
+                MAX_ZOOM, z
+            )));
+        }
+        let size = 1u32 << z;
+        if x >= size || y >= size {
+            return Err(ApplicationError::ValidationError(format!(
+                "x and y must be in 0..{} at zoom {}, but got ({}, {})",
+                size, z, x, y
+            )));
+        }
+        Ok(Self { z, x, y })
+    }
+
+    /// このズームレベルでの，1辺のタイルの数
+    fn size(&self) -> f64 {
+        (1u32 << self.z) as f64
+    }
+
+    /// 緯度経度を，このタイル内の座標(左上が原点，右下が(EXTENT, EXTENT))にする
+    pub fn project(&self, coord: &Coordinate) -> (f64, f64) {
+        let lon = coord.longitude().value();
+        let lat = coord
+            .latitude()
+            .value()
+            .clamp(-MAX_LATITUDE, MAX_LATITUDE)
+            .to_radians();
+        let x = (lon + 180.) / 360. * self.size();
+        let y = (1. - lat.tan().asinh() / PI) / 2. * self.size();
+        (
+            (x - self.x as f64) * EXTENT as f64,
+            (y - self.y as f64) * EXTENT as f64,
+        )
+    }
+
+    /// `BUFFER`を含めた，このタイルの範囲
+    pub fn bounding_box(&self) -> ApplicationResult<BoundingBox> {
+        let buffer = BUFFER / EXTENT as f64;
+        let clamp = |value: f64| value.max(0.).min(self.size());
+        let (min_x, max_x) = (
+            clamp(self.x as f64 - buffer),
+            clamp(self.x as f64 + 1. + buffer),
+        );
+        let (min_y, max_y) = (
+            clamp(self.y as f64 - buffer),
+            clamp(self.y as f64 + 1. + buffer),
+        );
+
+        let longitude = |x: f64| x / self.size() * 360. - 180.;
+        let latitude = |y: f64| {
+            (PI * (1. - 2. * y / self.size()))
+                .sinh()
+                .atan()
+                .to_degrees()
+        };
+        // yは南に向かって大きくなる
+        BoundingBox::new(
+            latitude(max_y),
+            longitude(min_x),
+            latitude(min_y),
+            longitude(max_x),
+        )
+    }
+}
+
+/// タイルのキャッシュのキー
+///
+/// 自分のルートだけを描いたタイルは，ユーザーごとに別のものになる
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Getters)]
+#[get = "pub"]
+pub struct TileKey {
+    coord: TileCoord,
+    owner_id: Option<UserId>,
+}
+
+impl TileKey {
+    pub fn new(coord: TileCoord, owner_id: Option<UserId>) -> Self {
+        Self { coord, owner_id }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::model::route::coordinate::tests::CoordinateFixtures;
+
+    use super::*;
+
+    fn assert_close((actual_x, actual_y): (f64, f64), (expected_x, expected_y): (f64, f64)) {
+        assert!(
+            (actual_x - expected_x).abs() < 1e-6 && (actual_y - expected_y).abs() < 1e-6,
+            "expected {:?}, but got {:?}",
+            (expected_x, expected_y),
+            (actual_x, actual_y)
+        );
+    }
+
+    #[rstest]
+    #[case::world(0, 0, 0)]
+    #[case::max_zoom(MAX_ZOOM, (1 << MAX_ZOOM) - 1, 0)]
+    #[case::yokohama(8, 227, 100)]
+    fn can_create_tile_coord(#[case] z: u8, #[case] x: u32, #[case] y: u32) {
+        assert_eq!(TileCoord::new(z, x, y), Ok(TileCoord { z, x, y }));
+    }
+
+    #[rstest]
+    #[case::too_deep(MAX_ZOOM + 1, 0, 0)]
+    #[case::x_out_of_range(1, 2, 0)]
+    #[case::y_out_of_range(8, 0, 256)]
+    fn cannot_create_invalid_tile_coord(#[case] z: u8, #[case] x: u32, #[case] y: u32) {
+        assert!(matches!(
+            TileCoord::new(z, x, y),
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
+    #[rstest]
+    #[case::center(0, 0, 0, 0., 0., (2048., 2048.))]
+    #[case::north_west(0, 0, 0, MAX_LATITUDE, -180., (0., 0.))]
+    #[case::pole_is_clamped(0, 0, 0, 90., 180., (4096., 0.))]
+    #[case::other_tile(1, 1, 1, 0., 0., (0., 0.))]
+    fn can_project(
+        #[case] z: u8,
+        #[case] x: u32,
+        #[case] y: u32,
+        #[case] lat: f64,
+        #[case] lon: f64,
+        #[case] expected: (f64, f64),
+    ) {
+        let tile = TileCoord::new(z, x, y).unwrap();
+        assert_close(tile.project(&Coordinate::new(lat, lon).unwrap()), expected);
+    }
+
+    #[rstest]
+    fn can_project_yokohama() {
+        let tile = TileCoord::new(8, 227, 100).unwrap();
+        let (x, y) = tile.project(&Coordinate::yokohama(false, None));
+        assert_eq!((x.round(), y.round()), (1186., 4070.));
+    }
+
+    #[rstest]
+    fn bounding_box_includes_buffer() {
+        let tile = TileCoord::new(1, 1, 0).unwrap();
+        let bbox = tile.bounding_box().unwrap();
+
+        let buffer = 360. / 2. * BUFFER / EXTENT as f64;
+        assert_close(
+            (
+                bbox.min_coord().longitude().value(),
+                bbox.max_coord().longitude().value(),
+            ),
+            (-buffer, 180.),
+        );
+        assert_close(
+            (
+                bbox.max_coord().latitude().value(),
+                tile.project(bbox.min_coord()).1,
+            ),
+            (MAX_LATITUDE, EXTENT as f64 + BUFFER),
+        );
+    }
+}
@@ -0,0 +1,441 @@
+use geo::algorithm::simplify::Simplify;
+
+use crate::model::route::Route;
+
+use super::{TileCoord, BUFFER, EXTENT};
+
+/// 線を単純化するときの許容誤差 (タイル内の座標の単位)
+///
+/// タイル内の座標で単純化するので，地図上の許容誤差はズームレベルが1つ上がるごとに半分になる
+/// 256pxで表示するとき，16で1pxになる
+const SIMPLIFY_TOLERANCE: f64 = 8.;
+
+const LAYER_NAME: &str = "routes";
+/// 各ルートの属性の名前 (`values`には，ルートごとにこの順で並べる)
+const KEYS: [&str; 3] = ["id", "name", "distance"];
+/// Mapbox Vector Tileの仕様の版
+const VERSION: u64 = 2;
+/// Feature.GeomType.LINESTRING
+const LINESTRING: u64 = 2;
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+
+const WIRE_VARINT: u32 = 0;
+const WIRE_FIXED64: u32 = 1;
+const WIRE_LENGTH_DELIMITED: u32 = 2;
+
+/// ルートの線を"routes"レイヤーに持つMapbox Vector Tile
+///
+/// 各ルートは，id(文字列)，name(文字列)，distance(m, 浮動小数点数)の属性を持つ
+#[derive(Clone, Debug)]
+#[cfg_attr(any(test, feature = "fixtures"), derive(PartialEq))]
+pub struct VectorTile {
+    data: Vec<u8>,
+    truncated: bool,
+}
+
+impl VectorTile {
+    pub fn as_slice(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+
+    /// 上限を超えたため，タイルに掛かるのに描かなかったルートがあるか
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// 描かなかったルートがあることを記録する
+    pub fn mark_truncated(self) -> Self {
+        Self {
+            truncated: true,
+            ..self
+        }
+    }
+
+    /// タイルに掛からないルートは書かない (1つも掛からなければ空のタイルになる)
+    pub fn from_routes(coord: &TileCoord, routes: &[Route]) -> Self {
+        let mut features = Vec::new();
+        let mut values = Vec::new();
+        for route in routes {
+            let geometry = Self::encode_geometry(&Self::to_lines(coord, route));
+            if geometry.is_empty() {
+                continue;
+            }
+
+            let first_value = values.len() as u32;
+            let info = route.info();
+            values.push(Value::String(info.id().to_string()));
+            values.push(Value::String(info.name().clone()));
+            values.push(Value::Double(info.total_distance().value()));
+            let tags = (0..KEYS.len() as u32)
+                .flat_map(|key| vec![key, first_value + key])
+                .collect::<Vec<_>>();
+
+            let mut feature = Vec::new();
+            push_packed(&mut feature, 2, &tags);
+            push_key(&mut feature, 3, WIRE_VARINT);
+            push_varint(&mut feature, LINESTRING);
+            push_packed(&mut feature, 4, &geometry);
+            features.push(feature);
+        }
+        if features.is_empty() {
+            return Self {
+                data: Vec::new(),
+                truncated: false,
+            };
+        }
+
+        let mut layer = Vec::new();
+        push_key(&mut layer, 15, WIRE_VARINT);
+        push_varint(&mut layer, VERSION);
+        push_bytes(&mut layer, 1, LAYER_NAME.as_bytes());
+        features
+            .iter()
+            .for_each(|feature| push_bytes(&mut layer, 2, feature));
+        KEYS.iter()
+            .for_each(|key| push_bytes(&mut layer, 3, key.as_bytes()));
+        values
+            .iter()
+            .for_each(|value| push_bytes(&mut layer, 4, &value.encode()));
+        push_key(&mut layer, 5, WIRE_VARINT);
+        push_varint(&mut layer, EXTENT as u64);
+
+        let mut data = Vec::new();
+        push_bytes(&mut data, 3, &layer);
+        Self {
+            data,
+            truncated: false,
+        }
+    }
+
+    /// タイル内の座標にして範囲外を切り取り，単純化した線の一覧
+    fn to_lines(coord: &TileCoord, route: &Route) -> Vec<Vec<(i32, i32)>> {
+        let points = route
+            .seg_list()
+            .iter()
+            .flat_map(|seg| seg.iter())
+            .map(|point| coord.project(point))
+            .collect::<Vec<_>>();
+
+        clip(&points)
+            .into_iter()
+            .map(|line| {
+                let line: geo::LineString<f64> = line.into();
+                let mut points = line
+                    .simplify(&SIMPLIFY_TOLERANCE)
+                    .0
+                    .into_iter()
+                    .map(|point| (point.x.round() as i32, point.y.round() as i32))
+                    .collect::<Vec<_>>();
+                points.dedup();
+                points
+            })
+            .filter(|line| line.len() >= 2)
+            .collect()
+    }
+
+    /// 各線をMoveTo 1回とLineToで表す (位置は直前の位置からの差分で書く)
+    fn encode_geometry(lines: &[Vec<(i32, i32)>]) -> Vec<u32> {
+        let mut geometry = Vec::new();
+        let mut cursor = (0, 0);
+        for line in lines {
+            geometry.push(command(MOVE_TO, 1));
+            for (i, point) in line.iter().enumerate() {
+                if i == 1 {
+                    geometry.push(command(LINE_TO, line.len() as u32 - 1));
+                }
+                geometry.push(zigzag(point.0 - cursor.0));
+                geometry.push(zigzag(point.1 - cursor.1));
+                cursor = *point;
+            }
+        }
+        geometry
+    }
+}
+
+enum Value {
+    String(String),
+    Double(f64),
+}
+
+impl Value {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Self::String(value) => push_bytes(&mut buf, 1, value.as_bytes()),
+            Self::Double(value) => {
+                push_key(&mut buf, 3, WIRE_FIXED64);
+                buf.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        buf
+    }
+}
+
+/// `BUFFER`を含めたタイルの範囲に入る部分ごとに，線を分ける
+fn clip(points: &[(f64, f64)]) -> Vec<Vec<(f64, f64)>> {
+    let mut lines = Vec::new();
+    let mut line: Vec<(f64, f64)> = Vec::new();
+    for pair in points.windows(2) {
+        match clip_segment(pair[0], pair[1]) {
+            Some((start, end)) => {
+                if line.last() != Some(&start) {
+                    lines.push(std::mem::take(&mut line));
+                    line.push(start);
+                }
+                line.push(end);
+            }
+            None => lines.push(std::mem::take(&mut line)),
+        }
+    }
+    lines.push(line);
+    lines.retain(|line| line.len() >= 2);
+    lines
+}
+
+/// 線分のうち，範囲に入る部分 (Liang-Barsky)
+///
+/// 切り取らなかった端点は，元の値をそのまま返す
+fn clip_segment(start: (f64, f64), end: (f64, f64)) -> Option<((f64, f64), (f64, f64))> {
+    let (min, max) = (-BUFFER, EXTENT as f64 + BUFFER);
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let (mut t0, mut t1) = (0f64, 1f64);
+    for (p, q) in [
+        (-dx, start.0 - min),
+        (dx, max - start.0),
+        (-dy, start.1 - min),
+        (dy, max - start.1),
+    ]
+    .iter()
+    {
+        if *p == 0. {
+            if *q < 0. {
+                return None;
+            }
+        } else if *p < 0. {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+
+    let at = |t: f64| (start.0 + t * dx, start.1 + t * dy);
+    Some((
+        if t0 > 0. { at(t0) } else { start },
+        if t1 < 1. { at(t1) } else { end },
+    ))
+}
+
+fn command(id: u32, count: u32) -> u32 {
+    (id & 0x7) | (count << 3)
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn push_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn push_key(buf: &mut Vec<u8>, field: u32, wire_type: u32) {
+    push_varint(buf, ((field << 3) | wire_type) as u64);
+}
+
+fn push_bytes(buf: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    push_key(buf, field, WIRE_LENGTH_DELIMITED);
+    push_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn push_packed(buf: &mut Vec<u8>, field: u32, values: &[u32]) {
+    let mut packed = Vec::new();
+    values
+        .iter()
+        .for_each(|value| push_varint(&mut packed, *value as u64));
+    push_bytes(buf, field, &packed);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use rstest::rstest;
+
+    use crate::model::route::tests::RouteFixtures;
+
+    use super::*;
+
+    /// protobufのメッセージの(フィールド番号, 値)の一覧
+    ///
+    /// varintは`Varint`，長さ付きのものは`Bytes`，64bitの値は`Fixed64`にする
+    #[derive(Debug, PartialEq)]
+    enum Field {
+        Varint(u64),
+        Fixed64([u8; 8]),
+        Bytes(Vec<u8>),
+    }
+
+    fn read_varint(data: &[u8], pos: &mut usize) -> u64 {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = data[*pos];
+            *pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return value;
+            }
+            shift += 7;
+        }
+    }
+
+    fn decode(data: &[u8]) -> Vec<(u32, Field)> {
+        let mut pos = 0;
+        let mut fields = Vec::new();
+        while pos < data.len() {
+            let key = read_varint(data, &mut pos);
+            let field = match key & 0x7 {
+                0 => Field::Varint(read_varint(data, &mut pos)),
+                1 => {
+                    pos += 8;
+                    Field::Fixed64(data[pos - 8..pos].try_into().unwrap())
+                }
+                2 => {
+                    let len = read_varint(data, &mut pos) as usize;
+                    pos += len;
+                    Field::Bytes(data[pos - len..pos].to_vec())
+                }
+                wire_type => panic!("unexpected wire type {}", wire_type),
+            };
+            fields.push(((key >> 3) as u32, field));
+        }
+        fields
+    }
+
+    fn bytes_of(fields: &[(u32, Field)], number: u32) -> Vec<Vec<u8>> {
+        fields
+            .iter()
+            .filter_map(|(n, field)| match field {
+                Field::Bytes(bytes) if *n == number => Some(bytes.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn unpack(packed: &[u8]) -> Vec<u32> {
+        let mut pos = 0;
+        let mut values = Vec::new();
+        while pos < packed.len() {
+            values.push(read_varint(packed, &mut pos) as u32);
+        }
+        values
+    }
+
+    #[rstest]
+    fn can_encode_routes() {
+        let coord = TileCoord::new(8, 227, 100).unwrap();
+        let route = Route::yokohama_to_chiba_filled(false, true);
+        let tile = VectorTile::from_routes(&coord, std::slice::from_ref(&route));
+
+        let layers = bytes_of(&decode(tile.as_slice()), 3);
+        assert_eq!(layers.len(), 1);
+        let layer = decode(&layers[0]);
+        assert_eq!(layer[0], (15, Field::Varint(2)));
+        assert_eq!(bytes_of(&layer, 1), vec![b"routes".to_vec()]);
+        assert_eq!(
+            bytes_of(&layer, 3),
+            vec![b"id".to_vec(), b"name".to_vec(), b"distance".to_vec()]
+        );
+        assert_eq!(layer.last(), Some(&(5, Field::Varint(4096))));
+
+        let values = bytes_of(&layer, 4)
+            .iter()
+            .map(|value| decode(value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![
+                vec![(1, Field::Bytes(route.info().id().to_string().into_bytes()))],
+                vec![(1, Field::Bytes(b"route0".to_vec()))],
+                vec![(3, Field::Fixed64(46779.709825324135f64.to_le_bytes()))],
+            ]
+        );
+
+        let features = bytes_of(&layer, 2);
+        assert_eq!(features.len(), 1);
+        let feature = decode(&features[0]);
+        assert_eq!(unpack(&bytes_of(&feature, 2)[0]), vec![0, 0, 1, 1, 2, 2]);
+        assert!(feature.contains(&(3, Field::Varint(2))));
+        // 横浜(1186, 4070)から千葉(2600, 3550)まで
+        assert_eq!(
+            unpack(&bytes_of(&feature, 4)[0]),
+            vec![
+                command(MOVE_TO, 1),
+                zigzag(1186),
+                zigzag(4070),
+                command(LINE_TO, 1),
+                zigzag(1414),
+                zigzag(-520)
+            ]
+        );
+    }
+
+    #[rstest]
+    fn routes_outside_tile_are_skipped() {
+        let coord = TileCoord::new(8, 0, 0).unwrap();
+        let tile =
+            VectorTile::from_routes(&coord, &[Route::yokohama_to_chiba_filled(false, false)]);
+        assert!(tile.as_slice().is_empty());
+    }
+
+    #[rstest]
+    fn can_clip_at_tile_edge() {
+        // 横浜のタイルの右隣に千葉がある
+        let coord = TileCoord::new(10, 909, 403).unwrap();
+        let lines = VectorTile::to_lines(&coord, &Route::yokohama_to_chiba_filled(false, false));
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].first(), Some(&(650, 3991)));
+        assert_eq!(lines[0].last().unwrap().0, EXTENT as i32 + BUFFER as i32);
+    }
+
+    #[rstest]
+    #[case::inside(vec![(0., 0.), (10., 10.), (20., 0.)], vec![vec![(0., 0.), (10., 10.), (20., 0.)]])]
+    #[case::leaves_and_returns(
+        vec![(0., 0.), (0., 5000.), (10., 5000.), (10., 0.)],
+        vec![vec![(0., 0.), (0., 4160.)], vec![(10., 4160.), (10., 0.)]]
+    )]
+    #[case::passes_through(vec![(-100., 0.), (5000., 0.)], vec![vec![(-64., 0.), (4160., 0.)]])]
+    #[case::outside(vec![(-100., -100.), (-100., 5000.)], vec![])]
+    #[case::single_point(vec![(0., 0.)], vec![])]
+    fn can_clip(#[case] points: Vec<(f64, f64)>, #[case] expected: Vec<Vec<(f64, f64)>>) {
+        assert_eq!(clip(&points), expected);
+    }
+
+    #[rstest]
+    fn can_simplify_lines() {
+        let coord = TileCoord::new(0, 0, 0).unwrap();
+        // 全ての点が数単位の範囲に収まるので，始点と終点だけになる
+        let lines = VectorTile::to_lines(
+            &coord,
+            &Route::yokohama_to_chiba_via_tokyo_filled(false, false),
+        );
+        assert_eq!(lines, vec![vec![(3637, 1616), (3642, 1614)]]);
+    }
+
+    #[rstest]
+    #[case::zero(0, 0)]
+    #[case::minus_one(-1, 1)]
+    #[case::one(1, 2)]
+    #[case::min(i32::MIN, u32::MAX)]
+    fn can_zigzag(#[case] value: i32, #[case] expected: u32) {
+        assert_eq!(zigzag(value), expected);
+    }
+}
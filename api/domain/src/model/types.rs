@@ -1,4 +1,8 @@
-use std::{convert::TryFrom, marker::PhantomData};
+use std::{
+    convert::TryFrom,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+};
 
 use derive_more::{Display, Into};
 use nanoid::nanoid;
@@ -6,7 +10,10 @@ use route_bucket_utils::ApplicationError;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
-#[derive(Default, Debug, Clone, Display, Serialize, Deserialize, PartialEq, Eq)]
+/// `T`はどのモデルのIDかを区別するためだけに使う
+///
+/// 比較やハッシュは`id`だけで決まるので，`T`に境界を求めないよう手で実装している
+#[derive(Default, Debug, Clone, Display, Serialize, Deserialize)]
 #[display(fmt = "{}", id)]
 #[serde(transparent)]
 pub struct NanoId<T, const LEN: usize> {
@@ -27,6 +34,20 @@ impl<T, const LEN: usize> NanoId<T, LEN> {
     }
 }
 
+impl<T, const LEN: usize> PartialEq for NanoId<T, LEN> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T, const LEN: usize> Eq for NanoId<T, LEN> {}
+
+impl<T, const LEN: usize> Hash for NanoId<T, LEN> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Validate, Display, Into, Serialize, Deserialize)]
 #[display(fmt = "{}", value)]
 #[serde(transparent)]
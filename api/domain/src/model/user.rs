@@ -11,7 +11,9 @@ use super::types::Url;
 static USER_ID_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^[a-zA-Z0-9]([a-zA-Z0-9]?|[\-]?([a-zA-Z0-9])){0,38}$").unwrap());
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Display, From, Into, Validate)]
+#[derive(
+    Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Display, From, Into, Validate,
+)]
 #[display(fmt = "{}", id)]
 #[serde(transparent)]
 pub struct UserId {
@@ -2,15 +2,18 @@ use async_trait::async_trait;
 use futures::future::BoxFuture;
 use route_bucket_utils::ApplicationResult;
 
+pub use collection::{CallCollectionRepository, CollectionRepository};
 pub use permission::{CallPermissionRepository, PermissionRepository};
 pub use route::{CallRouteRepository, RouteRepository};
 pub use user::{CallUserRepository, UserRepository};
 
 #[cfg(feature = "mocking")]
 pub use self::{
-    permission::MockPermissionRepository, route::MockRouteRepository, user::MockUserRepository,
+    collection::MockCollectionRepository, permission::MockPermissionRepository,
+    route::MockRouteRepository, user::MockUserRepository,
 };
 
+pub(crate) mod collection;
 pub(crate) mod permission;
 pub(crate) mod route;
 pub(crate) mod user;
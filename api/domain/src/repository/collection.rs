@@ -0,0 +1,108 @@
+use async_trait::async_trait;
+
+use route_bucket_utils::ApplicationResult;
+
+use crate::model::{
+    collection::{Collection, CollectionId},
+    permission::PermissionType,
+    user::UserId,
+};
+
+use super::Repository;
+
+#[async_trait]
+pub trait CollectionRepository: Repository {
+    async fn find(
+        &self,
+        id: &CollectionId,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<Collection>;
+
+    /// 更新の新しい順に返す
+    async fn find_by_owner(
+        &self,
+        owner_id: &UserId,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<Vec<Collection>>;
+
+    /// `user_id`に与えられている権限を返す
+    ///
+    /// 所有者かどうかや公開範囲は見ないので，`Collection::permission_type`に渡して使う
+    async fn find_permission_type(
+        &self,
+        id: &CollectionId,
+        user_id: &UserId,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<PermissionType>;
+
+    async fn insert(
+        &self,
+        collection: &Collection,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<()>;
+
+    /// ルートの一覧(並び順を含む)も`collection`のもので置き換える
+    async fn update(
+        &self,
+        collection: &Collection,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<()>;
+
+    async fn insert_or_update_permission(
+        &self,
+        id: &CollectionId,
+        user_id: &UserId,
+        permission_type: PermissionType,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<()>;
+
+    async fn delete_permission(
+        &self,
+        id: &CollectionId,
+        user_id: &UserId,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<()>;
+
+    async fn delete(
+        &self,
+        id: &CollectionId,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<()>;
+}
+
+pub trait CallCollectionRepository {
+    type CollectionRepository: CollectionRepository;
+
+    fn collection_repository(&self) -> &Self::CollectionRepository;
+}
+
+#[cfg(feature = "mocking")]
+mockall::mock! {
+    pub CollectionRepository {}
+
+    #[async_trait]
+    impl Repository for CollectionRepository {
+        type Connection = super::MockConnection;
+
+        async fn get_connection(&self) -> ApplicationResult<super::MockConnection>;
+    }
+
+    #[async_trait]
+    impl CollectionRepository for CollectionRepository {
+        async fn find(&self, id: &CollectionId, conn: &super::MockConnection) -> ApplicationResult<Collection>;
+
+        async fn find_by_owner(&self, owner_id: &UserId, conn: &super::MockConnection) -> ApplicationResult<Vec<Collection>>;
+
+        async fn find_permission_type(&self, id: &CollectionId, user_id: &UserId, conn: &super::MockConnection) -> ApplicationResult<PermissionType>;
+
+        async fn insert(&self, collection: &Collection, conn: &super::MockConnection) -> ApplicationResult<()>;
+
+        async fn update(&self, collection: &Collection, conn: &super::MockConnection) -> ApplicationResult<()>;
+
+        async fn insert_or_update_permission(&self, id: &CollectionId, user_id: &UserId, permission_type: PermissionType, conn: &super::MockConnection) -> ApplicationResult<()>;
+
+        async fn delete_permission(&self, id: &CollectionId, user_id: &UserId, conn: &super::MockConnection) -> ApplicationResult<()>;
+
+        async fn delete(&self, id: &CollectionId, conn: &super::MockConnection) -> ApplicationResult<()>;
+    }
+}
@@ -3,7 +3,9 @@ use async_trait::async_trait;
 use route_bucket_utils::ApplicationResult;
 
 use crate::model::route::search_query::RouteSearchQuery;
-use crate::model::route::{Route, RouteId, RouteInfo};
+use crate::model::route::{
+    ProximityQuery, Route, RouteId, RouteInfo, SegmentList, SimilarExtentQuery, TagCount,
+};
 use crate::repository::Repository;
 
 #[async_trait]
@@ -36,6 +38,34 @@ pub trait RouteRepository: Repository {
         conn: &<Self as Repository>::Connection,
     ) -> ApplicationResult<usize>;
 
+    /// `query`の範囲にかかるかもしれないルートを，格子の上で中心に近い順に
+    /// `MAX_NEARBY_CANDIDATES`個まで探す
+    ///
+    /// 範囲外のルートも含まれうるので，距離は`ProximityQuery::match_distance`で確かめる
+    async fn find_ids_near(
+        &self,
+        query: &ProximityQuery,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<Vec<RouteId>>;
+
+    /// `query`の範囲に近い範囲を持つルートを，範囲の近い順に`MAX_SIMILAR_CANDIDATES`個まで探す
+    ///
+    /// 形の近さは`RouteSimilarity::between`で確かめる
+    async fn find_ids_with_similar_extent(
+        &self,
+        query: &SimilarExtentQuery,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<Vec<RouteId>>;
+
+    /// `ids`のルートの形だけをまとめて読む
+    ///
+    /// 標高や操作の履歴は読まない．セグメントの無いルートは含まれない
+    async fn find_seg_lists(
+        &self,
+        ids: &[RouteId],
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<Vec<(RouteId, SegmentList)>>;
+
     async fn insert_info(
         &self,
         info: &RouteInfo,
@@ -50,6 +80,20 @@ pub trait RouteRepository: Repository {
 
     async fn update(&self, route: &Route, conn: &Self::Connection) -> ApplicationResult<()>;
 
+    /// `info`に付いているタグで置き換える
+    async fn update_tags(
+        &self,
+        info: &RouteInfo,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<()>;
+
+    /// 付いているルートの多い順に，タグを`limit`個まで返す
+    async fn count_tags(
+        &self,
+        limit: usize,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<Vec<TagCount>>;
+
     async fn delete(
         &self,
         id: &RouteId,
@@ -87,12 +131,22 @@ mockall::mock! {
 
         async fn count_infos(&self, query: RouteSearchQuery, conn: &super::MockConnection) -> ApplicationResult<usize>;
 
+        async fn find_ids_near(&self, query: &ProximityQuery, conn: &super::MockConnection) -> ApplicationResult<Vec<RouteId>>;
+
+        async fn find_ids_with_similar_extent(&self, query: &SimilarExtentQuery, conn: &super::MockConnection) -> ApplicationResult<Vec<RouteId>>;
+
+        async fn find_seg_lists(&self, ids: &[RouteId], conn: &super::MockConnection) -> ApplicationResult<Vec<(RouteId, SegmentList)>>;
+
         async fn insert_info(&self, info: &RouteInfo, conn: &super::MockConnection) -> ApplicationResult<()>;
 
         async fn update_info(&self, info: &RouteInfo, conn: &super::MockConnection) -> ApplicationResult<()>;
 
         async fn update(&self, route: &Route, conn: &super::MockConnection) -> ApplicationResult<()>;
 
+        async fn update_tags(&self, info: &RouteInfo, conn: &super::MockConnection) -> ApplicationResult<()>;
+
+        async fn count_tags(&self, limit: usize, conn: &super::MockConnection) -> ApplicationResult<Vec<TagCount>>;
+
         async fn delete(&self, id: &RouteId, conn: &super::MockConnection) -> ApplicationResult<()>;
     }
 }
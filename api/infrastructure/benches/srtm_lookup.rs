@@ -0,0 +1,152 @@
+//! SrtmReaderの標高取得の速度を測るベンチマーク
+//!
+//! `cargo bench -p route-bucket-infrastructure --bench srtm_lookup`
+//!
+//! 合成したタイルに対して，以前の実装と同じく毎回ファイルを開いてseekする読み方(before)と，
+//! メモリマップしたSrtmReader(after, 補間方法ごと)の1秒あたりの取得数を比較する
+
+use std::fs::{self, File};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use route_bucket_domain::model::route::{Coordinate, ElevationInterpolation};
+use route_bucket_infrastructure::SrtmReader;
+
+/// 3秒角(約90m)で1度四方のタイル
+const TILE_SIZE: u32 = 1200;
+const TILE_LAT: f64 = 35.;
+const TILE_LON: f64 = 139.;
+const NO_DATA_VALUE: &str = "-32768";
+const IFD_OFFSET: u32 = 8;
+const IFD_ENTRY_COUNT: u16 = 6;
+const LOOKUP_COUNT: usize = 100_000;
+const REPEAT: usize = 5;
+
+/// ヘッダとIFDの後ろに付加データを置き，最後に画素を行ごとに並べたGeoTIFFを書き出す
+fn write_tile(path: &Path) -> std::io::Result<Vec<u32>> {
+    let mut file = File::create(path)?;
+    let data_offset = IFD_OFFSET + 2 + 12 * IFD_ENTRY_COUNT as u32 + 4;
+    let pixel_scale_offset = data_offset;
+    let tiepoint_offset = pixel_scale_offset + 8 * 3;
+    let no_data_offset = tiepoint_offset + 8 * 6;
+    let strip_offsets_offset = no_data_offset + NO_DATA_VALUE.len() as u32 + 1;
+    let strips_offset = strip_offsets_offset + 4 * TILE_SIZE;
+    let strip_offsets = (0..TILE_SIZE)
+        .map(|row| strips_offset + row * TILE_SIZE * 2)
+        .collect::<Vec<_>>();
+
+    file.write_all(b"II")?;
+    file.write_u16::<LittleEndian>(0x2A)?;
+    file.write_u32::<LittleEndian>(IFD_OFFSET)?;
+
+    file.write_u16::<LittleEndian>(IFD_ENTRY_COUNT)?;
+    for (tag, datatype, count, data) in [
+        (0x0100, 4, 1, TILE_SIZE),
+        (0x0101, 4, 1, TILE_SIZE),
+        (0x0111, 4, TILE_SIZE, strip_offsets_offset),
+        (0x830E, 12, 3, pixel_scale_offset),
+        (0x8482, 12, 6, tiepoint_offset),
+        (0xA481, 2, NO_DATA_VALUE.len() as u32 + 1, no_data_offset),
+    ] {
+        file.write_u16::<LittleEndian>(tag)?;
+        file.write_u16::<LittleEndian>(datatype)?;
+        file.write_u32::<LittleEndian>(count)?;
+        file.write_u32::<LittleEndian>(data)?;
+    }
+    file.write_u32::<LittleEndian>(0)?;
+
+    let scale = 1. / TILE_SIZE as f64;
+    for value in [scale, scale, 0., 0., 0., 0., TILE_LON, TILE_LAT + 1., 0.] {
+        file.write_f64::<LittleEndian>(value)?;
+    }
+    file.write_all(NO_DATA_VALUE.as_bytes())?;
+    file.write_u8(0)?;
+    for offset in strip_offsets.iter() {
+        file.write_u32::<LittleEndian>(*offset)?;
+    }
+    for row in 0..TILE_SIZE {
+        for col in 0..TILE_SIZE {
+            file.write_i16::<LittleEndian>(((row + col) % 3000) as i16)?;
+        }
+    }
+    Ok(strip_offsets)
+}
+
+/// タイル内をランダムウォークする，ルートの点列に見立てた座標
+fn route_like_coords() -> Vec<Coordinate> {
+    let mut seed = 0x2545_F491_u64;
+    let mut next = || {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+        (seed >> 33) as f64 / (1u64 << 31) as f64 - 0.5
+    };
+    let (mut lat, mut lon) = (TILE_LAT + 0.5, TILE_LON + 0.5);
+    (0..LOOKUP_COUNT)
+        .map(|_| {
+            lat = (lat + next() * 0.002).clamp(TILE_LAT, TILE_LAT + 0.999);
+            lon = (lon + next() * 0.002).clamp(TILE_LON, TILE_LON + 0.999);
+            Coordinate::new(lat, lon).unwrap()
+        })
+        .collect()
+}
+
+/// 以前の`SrtmFile::get`と同じく，1点ごとにファイルを開いてseekする
+fn reopen_and_seek(path: &Path, strip_offsets: &[u32], coord: &Coordinate) -> i16 {
+    let mut file = File::open(path).unwrap();
+    let lon_idx = ((coord.longitude().value() - TILE_LON) * TILE_SIZE as f64) as u32;
+    let lat_idx = ((TILE_LAT + 1. - coord.latitude().value()) * TILE_SIZE as f64) as usize;
+    file.seek(SeekFrom::Start(
+        (strip_offsets[lat_idx] + lon_idx * 2) as u64,
+    ))
+    .unwrap();
+    file.read_i16::<LittleEndian>().unwrap()
+}
+
+fn measure(name: &str, mut lookup_all: impl FnMut()) {
+    let best = (0..REPEAT)
+        .map(|_| {
+            let start = Instant::now();
+            lookup_all();
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or_else(|| Duration::from_secs(0));
+    println!(
+        "{:<16} {:>12.0} lookups/s ({} lookups in {:?})",
+        name,
+        LOOKUP_COUNT as f64 / best.as_secs_f64(),
+        LOOKUP_COUNT,
+        best
+    );
+}
+
+fn main() {
+    let dir: PathBuf = std::env::temp_dir().join("route-bucket-srtm-bench");
+    fs::create_dir_all(&dir).unwrap();
+    let tile_path = dir.join("N35E139.tif");
+    let strip_offsets = write_tile(&tile_path).unwrap();
+    let coords = route_like_coords();
+
+    measure("before (seek)", || {
+        coords.iter().for_each(|coord| {
+            reopen_and_seek(&tile_path, &strip_offsets, coord);
+        })
+    });
+
+    for interpolation in [
+        ElevationInterpolation::Nearest,
+        ElevationInterpolation::Bilinear,
+        ElevationInterpolation::Bicubic,
+    ] {
+        let reader = SrtmReader::open_dir(&dir, interpolation).unwrap();
+        measure(&format!("after ({})", interpolation), || {
+            coords.iter().for_each(|coord| {
+                reader.get_elevation(coord).unwrap().unwrap();
+            })
+        });
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,6 +1,8 @@
+pub mod collection;
 pub mod operation;
 pub mod permission;
 pub mod route;
+pub mod route_cell;
 pub mod search_query;
 pub mod segment;
 pub mod user;
@@ -0,0 +1,62 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use getset::Getters;
+
+use route_bucket_domain::model::{
+    collection::{Collection, CollectionId, CollectionVisibility},
+    route::RouteId,
+    user::UserId,
+};
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+/// コレクションのdto構造体 (ルートの一覧は`collection_routes`に持つ)
+#[derive(sqlx::FromRow, Getters)]
+#[get = "pub"]
+pub(crate) struct CollectionDto {
+    id: String,
+    name: String,
+    owner_id: String,
+    visibility: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl CollectionDto {
+    /// `route_ids`は`collection_routes`から並び順どおりに読み出したもの
+    pub fn into_model(self, route_ids: Vec<String>) -> ApplicationResult<Collection> {
+        let Self {
+            id,
+            name,
+            owner_id,
+            visibility,
+            created_at,
+            updated_at,
+        } = self;
+        Ok(Collection::from((
+            CollectionId::from_string(id),
+            name,
+            UserId::from(owner_id),
+            CollectionVisibility::from_str(&visibility).map_err(|_| {
+                ApplicationError::DataBaseError(format!(
+                    "Failed to parse collections.visibility ({})",
+                    visibility
+                ))
+            })?,
+            route_ids.into_iter().map(RouteId::from_string).collect(),
+            created_at,
+            updated_at,
+        )))
+    }
+
+    pub fn from_model(collection: &Collection) -> Self {
+        Self {
+            id: collection.id().to_string(),
+            name: collection.name().clone(),
+            owner_id: collection.owner_id().to_string(),
+            visibility: collection.visibility().to_string(),
+            created_at: *collection.created_at(),
+            updated_at: *collection.updated_at(),
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use getset::Getters;
 use itertools::Itertools;
 use route_bucket_domain::model::route::{
-    DrawingMode, Operation, OperationId, OperationType, Polyline, RouteId, SegmentTemplate,
+    DrawingMode, ElevationOverride, Operation, OperationId, OperationType, Polyline, RouteId,
+    SegmentTemplate,
 };
 use route_bucket_utils::{ApplicationError, ApplicationResult};
 use serde::{Deserialize, Serialize};
@@ -13,15 +14,18 @@ pub struct SegmentTemplateDto {
     start: String,
     goal: String,
     mode: String,
+    #[serde(default, skip_serializing_if = "ElevationOverride::is_empty")]
+    elevation_override: ElevationOverride,
 }
 
 impl From<SegmentTemplate> for SegmentTemplateDto {
     fn from(template: SegmentTemplate) -> Self {
-        let (start, goal, mode) = template.into();
+        let (start, goal, mode, elevation_override) = template.into();
         Self {
             start: Polyline::from(start).into(),
             goal: Polyline::from(goal).into(),
             mode: mode.to_string(),
+            elevation_override,
         }
     }
 }
@@ -36,7 +40,8 @@ impl TryFrom<SegmentTemplateDto> for SegmentTemplate {
             DrawingMode::from_str(&dto.mode).map_err(|err| {
                 ApplicationError::DataBaseError(format!("Invalid mode found in DB: {:?}", err))
             })?,
-        ))
+        )
+        .with_elevation_override(dto.elevation_override))
     }
 }
 
@@ -1,12 +1,25 @@
 use std::convert::TryFrom;
+use std::str::FromStr;
 
 use chrono::{DateTime, Utc};
 use getset::Getters;
+use itertools::Itertools;
+use sqlx::types::Json;
+
 use route_bucket_domain::model::{
-    route::{Distance, Elevation, RouteId, RouteInfo},
+    route::{
+        BikeType, BoundingBox, Difficulty, Distance, Elevation, ElevationSource, RouteId,
+        RouteInfo, RouteMetadata, SurfaceType, Tag,
+    },
+    types::Url,
     user::UserId,
 };
-use route_bucket_utils::ApplicationResult;
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+/// `RouteDto`として読み出す列 (タグは`route_tags`からJSONの配列にまとめる)
+pub(crate) const ROUTE_COLUMNS: &str = "`routes`.*, \
+    (SELECT JSON_ARRAYAGG(`tag`) FROM `route_tags` WHERE `route_tags`.`route_id` = `routes`.`id`) \
+    AS `tags`";
 
 /// ルートのdto構造体
 #[derive(sqlx::FromRow, Getters)]
@@ -19,8 +32,23 @@ pub struct RouteDto {
     ascent_elevation_gain: u32,
     descent_elevation_gain: u32,
     total_distance: f64,
+    difficulty_score: f64,
+    difficulty_rating: String,
+    elevation_source: String,
+    min_latitude: Option<f64>,
+    min_longitude: Option<f64>,
+    max_latitude: Option<f64>,
+    max_longitude: Option<f64>,
+    description: Option<String>,
+    surface_type: Option<String>,
+    bike_type: Option<String>,
+    start_label: Option<String>,
+    finish_label: Option<String>,
+    external_link: Option<String>,
     created_at: DateTime<Utc>,
     updated_at: DateTime<Utc>,
+    /// タグが無ければNULL (`route_tags`に書き込むので，routesには保存しない)
+    tags: Option<Json<Vec<String>>>,
 }
 
 impl RouteDto {
@@ -33,9 +61,51 @@ impl RouteDto {
             ascent_elevation_gain,
             descent_elevation_gain,
             total_distance,
+            difficulty_score,
+            // ratingはscoreから決まるので，検索用にDBに持たせているだけ
+            difficulty_rating: _,
+            elevation_source,
+            min_latitude,
+            min_longitude,
+            max_latitude,
+            max_longitude,
+            description,
+            surface_type,
+            bike_type,
+            start_label,
+            finish_label,
+            external_link,
             created_at,
             updated_at,
+            tags,
         } = self;
+        let bounding_box = match (min_latitude, min_longitude, max_latitude, max_longitude) {
+            (Some(min_lat), Some(min_lon), Some(max_lat), Some(max_lon)) => {
+                Some(BoundingBox::new(min_lat, min_lon, max_lat, max_lon)?)
+            }
+            _ => None,
+        };
+        let metadata = RouteMetadata::from((
+            description,
+            surface_type
+                .map(|surface_type| Self::parse_enum::<SurfaceType>("surface_type", &surface_type))
+                .transpose()?,
+            bike_type
+                .map(|bike_type| Self::parse_enum::<BikeType>("bike_type", &bike_type))
+                .transpose()?,
+            start_label,
+            finish_label,
+            external_link.map(Url::try_from).transpose()?,
+        ));
+        let tags = tags
+            .map(|Json(tags)| tags)
+            .unwrap_or_default()
+            .into_iter()
+            .map(Tag::try_from)
+            .collect::<ApplicationResult<Vec<_>>>()?
+            .into_iter()
+            .sorted()
+            .collect_vec();
         Ok(RouteInfo::from((
             RouteId::from_string(id),
             name,
@@ -44,12 +114,29 @@ impl RouteDto {
             Elevation::try_from(ascent_elevation_gain as i32)?,
             Elevation::try_from(descent_elevation_gain as i32)?,
             Distance::try_from(total_distance)?,
+            Difficulty::from_score(difficulty_score),
+            ElevationSource::from_str(&elevation_source).map_err(|_| {
+                ApplicationError::DomainError(format!(
+                    "Invalid elevation_source: {}",
+                    elevation_source
+                ))
+            })?,
+            bounding_box,
+            tags,
+            metadata,
             created_at,
             updated_at,
         )))
     }
 
+    fn parse_enum<T: FromStr>(column: &str, value: &str) -> ApplicationResult<T> {
+        T::from_str(value)
+            .map_err(|_| ApplicationError::DomainError(format!("Invalid {}: {}", column, value)))
+    }
+
     pub fn from_model(route_info: &RouteInfo) -> ApplicationResult<RouteDto> {
+        let bbox = route_info.bounding_box().as_ref();
+        let metadata = route_info.metadata();
         Ok(RouteDto {
             id: route_info.id().to_string(),
             name: route_info.name().clone(),
@@ -58,8 +145,24 @@ impl RouteDto {
             ascent_elevation_gain: route_info.ascent_elevation_gain().value() as u32,
             descent_elevation_gain: route_info.descent_elevation_gain().value() as u32,
             total_distance: route_info.total_distance().value(),
+            difficulty_score: *route_info.difficulty().score(),
+            difficulty_rating: route_info.difficulty().rating().to_string(),
+            elevation_source: route_info.elevation_source().to_string(),
+            min_latitude: bbox.map(|bbox| bbox.min_coord().latitude().value()),
+            min_longitude: bbox.map(|bbox| bbox.min_coord().longitude().value()),
+            max_latitude: bbox.map(|bbox| bbox.max_coord().latitude().value()),
+            max_longitude: bbox.map(|bbox| bbox.max_coord().longitude().value()),
+            description: metadata.description().clone(),
+            surface_type: metadata
+                .surface_type()
+                .map(|surface_type| surface_type.to_string()),
+            bike_type: metadata.bike_type().map(|bike_type| bike_type.to_string()),
+            start_label: metadata.start_label().clone(),
+            finish_label: metadata.finish_label().clone(),
+            external_link: metadata.external_link().as_ref().map(Url::to_string),
             created_at: *route_info.created_at(),
             updated_at: *route_info.updated_at(),
+            tags: Some(Json(route_info.tags().iter().map(Tag::to_string).collect())),
         })
     }
 }
@@ -0,0 +1,195 @@
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+use getset::Getters;
+use itertools::Itertools;
+
+use route_bucket_domain::model::route::{
+    Coordinate, ProximityQuery, ProximityTarget, RouteId, SegmentList,
+};
+use route_bucket_utils::ApplicationResult;
+
+/// 格子の1辺 [度]
+const CELL_SIZE: f64 = 0.01;
+
+/// 緯度1度あたりの距離 [m]
+const METERS_PER_DEGREE: f64 = 111_195.;
+
+/// 近くのルートを探すための格子のdto構造体
+///
+/// ルートの出発地点(target = start)と，ルートが通る全てのセル(target = path)を持つ
+/// セルの番号は緯度・経度を`CELL_SIZE`で割って切り捨てたもの
+// NOTE: 日付変更線をまたぐ範囲は考えていない
+#[derive(Clone, Debug, PartialEq, Eq, Hash, sqlx::FromRow, Getters)]
+#[get = "pub"]
+pub struct RouteCellDto {
+    route_id: String,
+    target: String,
+    latitude_index: i32,
+    longitude_index: i32,
+}
+
+impl RouteCellDto {
+    pub fn from_model(route_id: &RouteId, seg_list: &SegmentList) -> Vec<Self> {
+        let start = seg_list.iter().next().map(|seg| seg.start().clone());
+        let coords = seg_list.iter().flat_map(|seg| seg.iter()).collect_vec();
+        let path = coords
+            .iter()
+            .tuple_windows()
+            .flat_map(|(from, to)| Self::sample_line(from, to))
+            .chain(coords.last().map(|coord| Self::to_degrees(coord)));
+
+        start
+            .iter()
+            .map(|coord| (ProximityTarget::Start, Self::to_degrees(coord)))
+            .chain(path.map(|point| (ProximityTarget::Path, point)))
+            .map(|(target, (lat, lon))| Self {
+                route_id: route_id.to_string(),
+                target: target.to_string(),
+                latitude_index: Self::index_of(lat),
+                longitude_index: Self::index_of(lon),
+            })
+            .unique()
+            .collect()
+    }
+
+    /// `query`の範囲にかかるかもしれないセル (緯度, 経度の番号の範囲)
+    ///
+    /// セルの間を斜めに横切る線は拾えていないことがあるので，1つ外側のセルまで含める
+    pub fn search_range(
+        query: &ProximityQuery,
+    ) -> ApplicationResult<(RangeInclusive<i32>, RangeInclusive<i32>)> {
+        let center = query.center()?;
+        let radius = query.radius()?.value();
+        let (lat, lon) = Self::to_degrees(&center);
+        let lat_margin = radius / METERS_PER_DEGREE;
+        let lon_margin = radius / (METERS_PER_DEGREE * lat.to_radians().cos().max(CELL_SIZE));
+        let range = |value: f64, margin: f64| {
+            Self::index_of(value - margin) - 1..=Self::index_of(value + margin) + 1
+        };
+        Ok((range(lat, lat_margin), range(lon, lon_margin)))
+    }
+
+    /// `query`の中心のセル上の位置と，経度方向のセルの長さの(緯度方向に対する)比
+    ///
+    /// セルの番号との差から，中心までのおおよその距離を比べるのに使う
+    pub fn search_center(query: &ProximityQuery) -> ApplicationResult<(f64, f64, f64)> {
+        let (lat, lon) = Self::to_degrees(&query.center()?);
+        Ok((
+            lat / CELL_SIZE,
+            lon / CELL_SIZE,
+            lat.to_radians().cos().max(CELL_SIZE),
+        ))
+    }
+
+    /// 保存してある`old`を`new`にするために，消すセルと加えるセル
+    pub fn diff<'a>(old: &'a [Self], new: &'a [Self]) -> (Vec<&'a Self>, Vec<&'a Self>) {
+        let (old_set, new_set) = (
+            old.iter().collect::<HashSet<_>>(),
+            new.iter().collect::<HashSet<_>>(),
+        );
+        (
+            old.iter().filter(|dto| !new_set.contains(dto)).collect(),
+            new.iter().filter(|dto| !old_set.contains(dto)).collect(),
+        )
+    }
+
+    /// 線分上の点を，セルの半分以下の間隔で取る (`to`は含まない)
+    fn sample_line(from: &Coordinate, to: &Coordinate) -> Vec<(f64, f64)> {
+        let (from, to) = (Self::to_degrees(from), Self::to_degrees(to));
+        let span = (to.0 - from.0).abs().max((to.1 - from.1).abs());
+        let steps = (span / (CELL_SIZE / 2.)).ceil().max(1.) as usize;
+        (0..steps)
+            .map(|i| {
+                let ratio = i as f64 / steps as f64;
+                (
+                    from.0 + (to.0 - from.0) * ratio,
+                    from.1 + (to.1 - from.1) * ratio,
+                )
+            })
+            .collect()
+    }
+
+    fn to_degrees(coord: &Coordinate) -> (f64, f64) {
+        (coord.latitude().value(), coord.longitude().value())
+    }
+
+    fn index_of(degrees: f64) -> i32 {
+        (degrees / CELL_SIZE).floor() as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use route_bucket_domain::model::fixtures::route::SegmentListFixture;
+
+    use super::*;
+
+    fn cells(dtos: &[RouteCellDto], target: ProximityTarget) -> Vec<(i32, i32)> {
+        dtos.iter()
+            .filter(|dto| dto.target == target.to_string())
+            .map(|dto| (dto.latitude_index, dto.longitude_index))
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn covers_every_cell_along_the_path() {
+        let dtos = RouteCellDto::from_model(
+            &RouteId::new(),
+            &SegmentList::freehand(&[(35.001, 139.001), (35.001, 139.045), (35.025, 139.045)]),
+        );
+
+        assert_eq!(cells(&dtos, ProximityTarget::Start), vec![(3500, 13900)]);
+        let path = cells(&dtos, ProximityTarget::Path);
+        assert_eq!(path.len(), 5 + 2);
+        assert!((13900..=13904).all(|lon| path.contains(&(3500, lon))));
+        assert!((3501..=3502).all(|lat| path.contains(&(lat, 13904))));
+    }
+
+    #[test]
+    fn diff_keeps_unchanged_cells() {
+        let id = RouteId::new();
+        let old = RouteCellDto::from_model(
+            &id,
+            &SegmentList::freehand(&[(35.001, 139.001), (35.001, 139.025)]),
+        );
+        let new = RouteCellDto::from_model(
+            &id,
+            &SegmentList::freehand(&[(35.001, 139.011), (35.001, 139.035)]),
+        );
+
+        let (removed, added) = RouteCellDto::diff(&old, &new);
+        let indices = |dtos: Vec<&RouteCellDto>| {
+            dtos.iter()
+                .map(|dto| (dto.target.clone(), dto.longitude_index))
+                .sorted()
+                .collect_vec()
+        };
+        assert_eq!(
+            indices(removed),
+            vec![("path".into(), 13900), ("start".into(), 13900)]
+        );
+        assert_eq!(
+            indices(added),
+            vec![("path".into(), 13903), ("start".into(), 13901)]
+        );
+        assert_eq!(RouteCellDto::diff(&old, &old), (vec![], vec![]));
+    }
+
+    #[test]
+    fn search_range_covers_radius() {
+        let query = ProximityQuery {
+            latitude: 35.005,
+            longitude: 139.005,
+            radius: 2000.,
+            target: ProximityTarget::Path,
+            limit: None,
+        };
+        let (lat_range, lon_range) = RouteCellDto::search_range(&query).unwrap();
+
+        // 2km ≒ 緯度0.018度, 北緯35度で経度0.022度
+        assert_eq!(lat_range, 3497..=3503);
+        assert_eq!(lon_range, 13897..=13903);
+    }
+}
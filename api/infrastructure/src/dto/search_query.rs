@@ -1,21 +1,172 @@
-use std::collections::HashMap;
+use std::cmp::Ordering;
 
+use chrono::{DateTime, Utc};
 use itertools::Itertools;
-use route_bucket_domain::model::route::RouteSearchQuery;
+use sqlx::mysql::{MySql, MySqlArguments};
+use sqlx::query::QueryAs;
 
+use route_bucket_domain::model::route::{
+    BoundingBox, Coordinate, RouteSearchCursor, RouteSearchQuery, RouteSortKey, RouteSortValue,
+    SortOrder,
+};
+
+use super::route::ROUTE_COLUMNS;
+
+/// SQLにbindする値
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum SqlValue {
+    String(String),
+    Int(i64),
+    Float(f64),
+    DateTime(DateTime<Utc>),
+}
+
+impl From<String> for SqlValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<i64> for SqlValue {
+    fn from(value: i64) -> Self {
+        Self::Int(value)
+    }
+}
+
+impl From<f64> for SqlValue {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<DateTime<Utc>> for SqlValue {
+    fn from(value: DateTime<Utc>) -> Self {
+        Self::DateTime(value)
+    }
+}
+
+/// WHERE句の条件
+///
+/// 列名はコード中の固定の文字列だけを使い，値は全てプレースホルダにしてbindする
 #[derive(Clone, Debug)]
-enum WhereCondition {
-    Eq(String),
+pub(crate) enum Condition {
+    Eq(&'static str, SqlValue),
+    /// 列の値と比べて小さい(Less)・大きい(Greater)
+    Compare(&'static str, Ordering, SqlValue),
+    /// 両端を含む範囲 (Noneの側は制限しない)
+    Range(&'static str, Option<SqlValue>, Option<SqlValue>),
+    /// 部分一致 (`%`, `_`もただの文字として探す)
+    Like(&'static str, String),
+    // NOTE: Inはまだルートの検索条件からは使っていない
+    #[allow(dead_code)]
+    In(&'static str, Vec<SqlValue>),
+    /// 副問い合わせの結果に含まれる (副問い合わせもコード中の固定の文字列だけを使う)
+    InSelect(&'static str, &'static str, Vec<SqlValue>),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
 }
 
-impl WhereCondition {
-    fn to_query(&self, field_name: &'static str) -> String {
+impl Condition {
+    /// 範囲の両端がNoneなら条件を付けない
+    pub fn range<T: Into<SqlValue>>(
+        column: &'static str,
+        min: Option<T>,
+        max: Option<T>,
+    ) -> Option<Self> {
+        if min.is_none() && max.is_none() {
+            None
+        } else {
+            Some(Self::Range(column, min.map(T::into), max.map(T::into)))
+        }
+    }
+
+    fn write_sql(&self, sql: &mut String, values: &mut Vec<SqlValue>) {
         match self {
-            Self::Eq(value) => {
-                format!("{} = \"{}\"", field_name, value)
+            Self::Eq(column, value) => {
+                *sql += &format!("`{}` = ?", column);
+                values.push(value.clone());
+            }
+            Self::Compare(column, ordering, value) => {
+                let op = match ordering {
+                    Ordering::Less => "<",
+                    Ordering::Equal => "=",
+                    Ordering::Greater => ">",
+                };
+                *sql += &format!("`{}` {} ?", column, op);
+                values.push(value.clone());
+            }
+            Self::Range(column, min, max) => {
+                let bounds = min
+                    .iter()
+                    .map(|min| (">=", min))
+                    .chain(max.iter().map(|max| ("<=", max)))
+                    .collect_vec();
+                if bounds.is_empty() {
+                    *sql += "TRUE";
+                    return;
+                }
+                *sql += &format!(
+                    "({})",
+                    bounds
+                        .iter()
+                        .map(|(op, _)| format!("`{}` {} ?", column, op))
+                        .join(" AND ")
+                );
+                values.extend(bounds.into_iter().map(|(_, value)| value.clone()));
+            }
+            Self::Like(column, substring) => {
+                *sql += &format!("`{}` LIKE ?", column);
+                values.push(SqlValue::String(format!(
+                    "%{}%",
+                    Self::escape_like(substring)
+                )));
+            }
+            Self::In(column, list) => {
+                if list.is_empty() {
+                    // `IN ()`は構文エラーになるので，何にも一致しない条件にする
+                    *sql += "FALSE";
+                    return;
+                }
+                *sql += &format!("`{}` IN ({})", column, list.iter().map(|_| "?").join(", "));
+                values.extend(list.iter().cloned());
+            }
+            Self::InSelect(column, subquery, list) => {
+                *sql += &format!("`{}` IN ({})", column, subquery);
+                values.extend(list.iter().cloned());
             }
+            Self::And(conditions) => Self::write_joined(conditions, "AND", sql, values),
+            Self::Or(conditions) => Self::write_joined(conditions, "OR", sql, values),
         }
     }
+
+    /// 条件を括弧でくくってつなぐ．空ならANDは常に真，ORは常に偽
+    fn write_joined(
+        conditions: &[Condition],
+        operator: &str,
+        sql: &mut String,
+        values: &mut Vec<SqlValue>,
+    ) {
+        if conditions.is_empty() {
+            *sql += if operator == "AND" { "TRUE" } else { "FALSE" };
+            return;
+        }
+        *sql += "(";
+        for (i, condition) in conditions.iter().enumerate() {
+            if i > 0 {
+                *sql += &format!(" {} ", operator);
+            }
+            condition.write_sql(sql, values);
+        }
+        *sql += ")";
+    }
+
+    /// LIKEのワイルドカードとエスケープ文字を，ただの文字として扱わせる
+    fn escape_like(value: &str) -> String {
+        value
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -25,40 +176,51 @@ struct OrderBy {
 }
 
 impl OrderBy {
+    /// 値が同じ行の順番も決まるように，idでも並べる
     fn to_query(&self) -> String {
-        format!(
-            "`{}` {}",
-            self.field_name,
-            if self.descending { "DESC" } else { "" }
-        )
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        format!("`{}` {}, `id` {}", self.field_name, direction, direction)
     }
 }
 
 #[derive(Clone, Debug, Default)]
 pub(crate) struct SearchQuery {
     table_name: &'static str,
-    where_conditions: HashMap<&'static str, WhereCondition>,
+    /// 取り出す列 (空なら`*`)
+    columns: &'static str,
+    /// 全てANDでつなぐ
+    conditions: Vec<Condition>,
+    /// カーソルより後ろの行だけにする条件 (件数を数えるときは使わない)
+    after: Option<Condition>,
     order_by: Option<OrderBy>,
     limit: Option<usize>,
     offset: Option<usize>,
 }
 
 impl SearchQuery {
-    pub fn to_sql(&self, is_for_counting: bool) -> String {
+    /// プレースホルダ付きのSQLと，そこにbindする値を返す
+    pub fn to_sql(&self, is_for_counting: bool) -> (String, Vec<SqlValue>) {
         let mut query = format!(
-            "SELECT {} FROM {} ",
-            if is_for_counting { "COUNT(*)" } else { "*" },
+            "SELECT {} FROM `{}` ",
+            match (is_for_counting, self.columns) {
+                (true, _) => "COUNT(*)",
+                (false, "") => "*",
+                (false, columns) => columns,
+            },
             self.table_name
         );
+        let mut values = Vec::new();
 
-        if !self.where_conditions.is_empty() {
-            query += &format!(
-                "WHERE {} ",
-                self.where_conditions
-                    .iter()
-                    .map(|(field, cond)| cond.to_query(field))
-                    .join(", "),
-            );
+        let conditions = self
+            .conditions
+            .iter()
+            .chain(self.after.iter().filter(|_| !is_for_counting))
+            .cloned()
+            .collect_vec();
+        if !conditions.is_empty() {
+            query += "WHERE ";
+            Condition::write_joined(&conditions, "AND", &mut query, &mut values);
+            query += " ";
         }
 
         if !is_for_counting {
@@ -67,42 +229,403 @@ impl SearchQuery {
             }
 
             if let Some(limit) = self.limit {
-                query += &format!("LIMIT {} ", limit);
+                query += "LIMIT ? ";
+                values.push(SqlValue::Int(limit as i64));
             }
 
             if let Some(offset) = self.offset {
-                query += &format!("OFFSET {} ", offset);
+                query += "OFFSET ? ";
+                values.push(SqlValue::Int(offset as i64));
             }
         }
 
-        query
+        (query, values)
+    }
+}
+
+impl SearchQuery {
+    /// 保存してあるルートの範囲が`bbox`と重なる条件
+    ///
+    /// 範囲を持たない(点の無い)ルートはNULLとの比較になるので一致しない
+    ///
+    /// NOTE: 条件はどれも片側だけの範囲なので，`bbox_idx`で絞り込めるのは先頭の`min_latitude`
+    /// (`bbox`の北端より南から始まるルート)だけで，残りの列はその中を索引で読みながら確かめる．
+    /// 南の方にルートが多いと読む行が増えるが，ルートの数が少ないうちはこれで足りる．
+    /// 足りなくなったら範囲をPOLYGONの列にしてSPATIAL INDEXと`MBRIntersects`で引く
+    fn intersects(bbox: &BoundingBox) -> Vec<Condition> {
+        let (min, max) = (bbox.min_coord(), bbox.max_coord());
+        let lat = |value: &Coordinate| value.latitude().value();
+        let lon = |value: &Coordinate| value.longitude().value();
+        let (east_of_min, west_of_max) = (
+            Condition::Range("max_longitude", Some(lon(min).into()), None),
+            Condition::Range("min_longitude", None, Some(lon(max).into())),
+        );
+        vec![
+            Condition::Range("min_latitude", None, Some(lat(max).into())),
+            Condition::Range("max_latitude", Some(lat(min).into()), None),
+        ]
+        .into_iter()
+        .chain(if bbox.crosses_antimeridian() {
+            // 日付変更線の東西どちらかの側に重なればよい
+            vec![Condition::Or(vec![east_of_min, west_of_max])]
+        } else {
+            vec![east_of_min, west_of_max]
+        })
+        .collect()
     }
 }
 
+impl SearchQuery {
+    /// 並び順で`cursor`より後ろにある行の条件
+    ///
+    /// `(列, id)`の組を比べる．`ORDER BY`と同じくidは値が同じ行の順番を決めるためだけに使う
+    fn after(cursor: &RouteSearchCursor) -> Condition {
+        let (column, value): (_, SqlValue) = match cursor.value().clone() {
+            RouteSortValue::Name(name) => ("name", name.into()),
+            RouteSortValue::TotalDistance(distance) => ("total_distance", distance.value().into()),
+            RouteSortValue::AscentElevationGain(elevation) => {
+                ("ascent_elevation_gain", (elevation.value() as i64).into())
+            }
+            RouteSortValue::CreatedAt(datetime) => ("created_at", datetime.into()),
+            RouteSortValue::UpdatedAt(datetime) => ("updated_at", datetime.into()),
+        };
+        let ordering = match cursor.order() {
+            SortOrder::Asc => Ordering::Greater,
+            SortOrder::Desc => Ordering::Less,
+        };
+        Condition::Or(vec![
+            Condition::Compare(column, ordering, value.clone()),
+            Condition::And(vec![
+                Condition::Eq(column, value),
+                Condition::Compare("id", ordering, cursor.id().to_string().into()),
+            ]),
+        ])
+    }
+}
+
+/// `to_sql`で作った値を順にbindする
+pub(crate) fn bind_values<'q, O>(
+    query: QueryAs<'q, MySql, O, MySqlArguments>,
+    values: Vec<SqlValue>,
+) -> QueryAs<'q, MySql, O, MySqlArguments> {
+    values.into_iter().fold(query, |query, value| match value {
+        SqlValue::String(value) => query.bind(value),
+        SqlValue::Int(value) => query.bind(value),
+        SqlValue::Float(value) => query.bind(value),
+        SqlValue::DateTime(value) => query.bind(value),
+    })
+}
+
 impl From<RouteSearchQuery> for SearchQuery {
     fn from(route_search_query: RouteSearchQuery) -> Self {
         let mut search_query = SearchQuery {
             table_name: "routes",
+            columns: ROUTE_COLUMNS,
             ..Default::default()
         };
 
         if let Some(owner_id) = route_search_query.owner_id {
             search_query
-                .where_conditions
-                .insert("owner_id", WhereCondition::Eq(owner_id.to_string()));
+                .conditions
+                .push(Condition::Eq("owner_id", owner_id.to_string().into()));
+        }
+
+        if let Some(difficulty) = route_search_query.difficulty {
+            search_query.conditions.push(Condition::Eq(
+                "difficulty_rating",
+                difficulty.to_string().into(),
+            ));
+        }
+
+        if let Some(name) = route_search_query.name.filter(|name| !name.is_empty()) {
+            search_query.conditions.push(Condition::Like("name", name));
+        }
+
+        let ranges = vec![
+            Condition::range(
+                "total_distance",
+                route_search_query.min_distance.map(|d| d.value()),
+                route_search_query.max_distance.map(|d| d.value()),
+            ),
+            Condition::range(
+                "ascent_elevation_gain",
+                route_search_query.min_ascent.map(|e| e.value() as i64),
+                route_search_query.max_ascent.map(|e| e.value() as i64),
+            ),
+            Condition::range(
+                "created_at",
+                route_search_query.created_after,
+                route_search_query.created_before,
+            ),
+            Condition::range(
+                "updated_at",
+                route_search_query.updated_after,
+                route_search_query.updated_before,
+            ),
+        ];
+        search_query.conditions.extend(ranges.into_iter().flatten());
+
+        if let Some(bbox) = route_search_query.bbox {
+            search_query.conditions.extend(Self::intersects(&bbox));
         }
 
-        // TODO: ここを指定できるようにする
+        // 全てのタグが付いているルートだけにする
+        search_query
+            .conditions
+            .extend(route_search_query.tags.into_iter().map(|tag| {
+                Condition::InSelect(
+                    "id",
+                    "SELECT `route_id` FROM `route_tags` WHERE `tag` = ?",
+                    vec![tag.to_string().into()],
+                )
+            }));
+
         search_query.order_by = Some(OrderBy {
-            field_name: "updated_at",
-            descending: true,
+            field_name: match route_search_query.sort_by {
+                RouteSortKey::Name => "name",
+                RouteSortKey::TotalDistance => "total_distance",
+                RouteSortKey::AscentElevationGain => "ascent_elevation_gain",
+                RouteSortKey::CreatedAt => "created_at",
+                RouteSortKey::UpdatedAt => "updated_at",
+            },
+            descending: route_search_query.order == SortOrder::Desc,
         });
 
+        if let Some(cursor) = &route_search_query.cursor {
+            search_query.after = Some(Self::after(cursor));
+        }
+
         if let Some(page_size) = route_search_query.page_size {
             search_query.limit = Some(page_size);
-            search_query.offset = Some(page_size * route_search_query.page_offset);
+            if route_search_query.cursor.is_none() {
+                search_query.offset = Some(page_size * route_search_query.page_offset);
+            }
         }
 
         search_query
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use route_bucket_domain::model::route::{RouteInfo, Tag};
+    use route_bucket_domain::model::user::UserId;
+
+    use super::*;
+
+    const HOSTILE_INPUTS: [&str; 5] = [
+        "\"; DROP TABLE routes; --",
+        "' OR '1'='1",
+        "\\\" OR 1=1 #",
+        "`name`",
+        "?",
+    ];
+
+    fn where_clause(conditions: Vec<Condition>) -> (String, Vec<SqlValue>) {
+        let mut sql = String::new();
+        let mut values = Vec::new();
+        Condition::write_joined(&conditions, "AND", &mut sql, &mut values);
+        (sql, values)
+    }
+
+    #[test]
+    fn hostile_values_are_only_bound() {
+        for input in HOSTILE_INPUTS.iter() {
+            let query = SearchQuery::from(RouteSearchQuery {
+                owner_id: Some(UserId::from(input.to_string())),
+                name: Some(input.to_string()),
+                ..Default::default()
+            });
+            let (sql, values) = query.to_sql(false);
+
+            assert_eq!(
+                sql,
+                format!(
+                    "SELECT {} FROM `routes` WHERE (`owner_id` = ? AND `name` LIKE ?) \
+                     ORDER BY `updated_at` DESC, `id` DESC ",
+                    ROUTE_COLUMNS
+                )
+            );
+            assert_eq!(values[0], SqlValue::String(input.to_string()));
+        }
+    }
+
+    #[test]
+    fn like_escapes_wildcards() {
+        let (sql, values) = where_clause(vec![Condition::Like("name", "100%_\\".into())]);
+        assert_eq!(sql, "(`name` LIKE ?)");
+        assert_eq!(values, vec![SqlValue::String("%100\\%\\_\\\\%".into())]);
+    }
+
+    #[test]
+    fn can_combine_conditions_with_and_or() {
+        let (sql, values) = where_clause(vec![
+            Condition::Or(vec![
+                Condition::Eq("owner_id", SqlValue::from(String::from("a"))),
+                Condition::In(
+                    "difficulty_rating",
+                    vec![String::from("easy").into(), String::from("hard").into()],
+                ),
+            ]),
+            Condition::range("total_distance", Some(1000.), None).unwrap(),
+            Condition::range("ascent_elevation_gain", Some(10_i64), Some(20)).unwrap(),
+        ]);
+        assert_eq!(
+            sql,
+            "((`owner_id` = ? OR `difficulty_rating` IN (?, ?)) AND (`total_distance` >= ?) \
+             AND (`ascent_elevation_gain` >= ? AND `ascent_elevation_gain` <= ?))"
+        );
+        assert_eq!(
+            values,
+            vec![
+                SqlValue::String("a".into()),
+                SqlValue::String("easy".into()),
+                SqlValue::String("hard".into()),
+                SqlValue::Float(1000.),
+                SqlValue::Int(10),
+                SqlValue::Int(20),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_conditions_are_valid_sql() {
+        assert!(Condition::range::<i64>("id", None, None).is_none());
+        let (sql, values) = where_clause(vec![
+            Condition::In("id", Vec::new()),
+            Condition::Or(Vec::new()),
+            Condition::And(Vec::new()),
+        ]);
+        assert_eq!(sql, "(FALSE AND FALSE AND TRUE)");
+        assert!(values.is_empty());
+    }
+
+    #[test]
+    fn can_search_by_bbox() {
+        let query = SearchQuery::from(RouteSearchQuery {
+            bbox: Some(BoundingBox::parse_viewport("139.5,35.4,140.1,35.7").unwrap()),
+            ..Default::default()
+        });
+        let (sql, values) = query.to_sql(true);
+        assert_eq!(
+            sql,
+            "SELECT COUNT(*) FROM `routes` WHERE ((`min_latitude` <= ?) AND (`max_latitude` >= ?) \
+             AND (`max_longitude` >= ?) AND (`min_longitude` <= ?)) "
+        );
+        assert_eq!(
+            values,
+            vec![
+                SqlValue::Float(35.7),
+                SqlValue::Float(35.4),
+                SqlValue::Float(139.5),
+                SqlValue::Float(140.1),
+            ]
+        );
+    }
+
+    #[test]
+    fn bbox_across_antimeridian_matches_either_side() {
+        let query = SearchQuery::from(RouteSearchQuery {
+            bbox: Some(BoundingBox::parse_viewport("179.5,-17.,-179.5,-16.").unwrap()),
+            ..Default::default()
+        });
+        let (sql, values) = query.to_sql(true);
+        assert_eq!(
+            sql,
+            "SELECT COUNT(*) FROM `routes` WHERE ((`min_latitude` <= ?) AND (`max_latitude` >= ?) \
+             AND ((`max_longitude` >= ?) OR (`min_longitude` <= ?))) "
+        );
+        assert_eq!(
+            values[2..],
+            [SqlValue::Float(179.5), SqlValue::Float(-179.5)]
+        );
+    }
+
+    #[test]
+    fn can_search_by_all_tags() {
+        let query = SearchQuery::from(RouteSearchQuery {
+            tags: vec![
+                Tag::try_from(String::from("gravel")).unwrap(),
+                Tag::try_from(String::from("commute")).unwrap(),
+            ],
+            ..Default::default()
+        });
+        assert_eq!(
+            query.to_sql(true),
+            (
+                "SELECT COUNT(*) FROM `routes` WHERE \
+                 (`id` IN (SELECT `route_id` FROM `route_tags` WHERE `tag` = ?) \
+                 AND `id` IN (SELECT `route_id` FROM `route_tags` WHERE `tag` = ?)) "
+                    .into(),
+                vec![
+                    SqlValue::String("gravel".into()),
+                    SqlValue::String("commute".into()),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn cursor_replaces_offset_and_is_skipped_for_counting() {
+        let info = RouteInfo::new("route", UserId::from(String::from("owner")));
+        let query = SearchQuery::from(RouteSearchQuery {
+            page_offset: 2,
+            page_size: Some(10),
+            sort_by: RouteSortKey::Name,
+            order: SortOrder::Asc,
+            cursor: Some(RouteSearchCursor::after(
+                &info,
+                RouteSortKey::Name,
+                SortOrder::Asc,
+            )),
+            ..Default::default()
+        });
+        assert_eq!(
+            query.to_sql(false),
+            (
+                format!(
+                    "SELECT {} FROM `routes` WHERE ((`name` > ? OR (`name` = ? AND `id` > ?))) \
+                     ORDER BY `name` ASC, `id` ASC LIMIT ? ",
+                    ROUTE_COLUMNS
+                ),
+                vec![
+                    SqlValue::String("route".into()),
+                    SqlValue::String("route".into()),
+                    SqlValue::String(info.id().to_string()),
+                    SqlValue::Int(10),
+                ]
+            )
+        );
+        assert_eq!(
+            query.to_sql(true),
+            ("SELECT COUNT(*) FROM `routes` ".into(), Vec::new())
+        );
+    }
+
+    #[test]
+    fn paging_is_bound_and_skipped_for_counting() {
+        let query = SearchQuery::from(RouteSearchQuery {
+            page_offset: 2,
+            page_size: Some(10),
+            sort_by: RouteSortKey::Name,
+            order: SortOrder::Asc,
+            ..Default::default()
+        });
+        assert_eq!(
+            query.to_sql(false),
+            (
+                format!(
+                    "SELECT {} FROM `routes` ORDER BY `name` ASC, `id` ASC LIMIT ? OFFSET ? ",
+                    ROUTE_COLUMNS
+                ),
+                vec![SqlValue::Int(10), SqlValue::Int(20)]
+            )
+        );
+        assert_eq!(
+            query.to_sql(true),
+            ("SELECT COUNT(*) FROM `routes` ".into(), Vec::new())
+        );
+    }
+}
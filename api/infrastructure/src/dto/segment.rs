@@ -1,11 +1,21 @@
 use std::convert::TryFrom;
 
 use getset::Getters;
+use sqlx::types::Json;
 
-use route_bucket_domain::model::route::{Polyline, RouteId, Segment};
+use route_bucket_domain::model::route::{
+    DemVersion, Elevation, ElevationOverride, Polyline, RouteId, Segment,
+};
 use route_bucket_utils::ApplicationResult;
 
 /// 座標のdto構造体
+///
+/// 標高は`polyline`の点と同じ順に`elevations`に保存し，
+/// 求めたDEMの版を`dem_version`に残す(どちらもNULLなら未計算)
+/// 手動の標高の補正は`elevation_override`に保存する(NULLなら補正なし)
+/// 取り込んだファイルの元の標高は`source_elevations`に保存する(NULLなら無し)
+/// 元の標高を反映した`elevations`も，残りの点を求めたDEMの版と一緒に保存するので，
+/// 読み込むたびにDEMへ問い合わせ直すことはない
 #[derive(sqlx::FromRow, Getters)]
 #[get = "pub"]
 pub struct SegmentDto {
@@ -14,11 +24,35 @@ pub struct SegmentDto {
     index: u32,
     mode: String,
     polyline: String,
+    elevations: Option<Json<Vec<Option<i32>>>>,
+    dem_version: Option<String>,
+    elevation_override: Option<Json<ElevationOverride>>,
+    source_elevations: Option<Json<Vec<Option<i32>>>>,
 }
 
 impl SegmentDto {
     pub fn into_model(self) -> ApplicationResult<Segment> {
-        Segment::try_from((self.id, self.mode, self.polyline))
+        let mut segment = Segment::try_from((self.id, self.mode, self.polyline))?;
+        if let Some(Json(elevation_override)) = self.elevation_override {
+            segment.set_elevation_override(elevation_override);
+        }
+        if let (Some(Json(elevations)), Some(dem_version)) = (self.elevations, self.dem_version) {
+            segment.set_elevations(
+                Self::into_elevations(elevations)?,
+                DemVersion::from(dem_version),
+            )?;
+        }
+        if let Some(Json(source_elevations)) = self.source_elevations {
+            segment.set_source_elevations(Self::into_elevations(source_elevations)?)?;
+        }
+        Ok(segment)
+    }
+
+    fn into_elevations(values: Vec<Option<i32>>) -> ApplicationResult<Vec<Option<Elevation>>> {
+        values
+            .into_iter()
+            .map(|value| value.map(Elevation::try_from).transpose())
+            .collect()
     }
 
     pub fn from_model(
@@ -26,12 +60,37 @@ impl SegmentDto {
         route_id: &RouteId,
         index: u32,
     ) -> ApplicationResult<SegmentDto> {
+        let elevations = segment.dem_version().as_ref().map(|_| {
+            Json(
+                segment
+                    .iter()
+                    .map(|coord| coord.elevation().map(|elevation| elevation.value()))
+                    .collect(),
+            )
+        });
+        let source_elevations = Some(segment)
+            .filter(|segment| segment.has_source_elevations())
+            .map(|segment| {
+                Json(
+                    segment
+                        .iter()
+                        .map(|coord| coord.source_elevation().map(|elevation| elevation.value()))
+                        .collect(),
+                )
+            });
         Ok(SegmentDto {
             id: segment.id().to_string(),
             route_id: route_id.to_string(),
             index,
             mode: segment.mode().to_string(),
             polyline: Polyline::from(segment.points().clone()).into(),
+            elevations,
+            dem_version: segment.dem_version().clone().map(String::from),
+            elevation_override: Some(segment.elevation_override())
+                .filter(|elevation_override| !elevation_override.is_empty())
+                .cloned()
+                .map(Json),
+            source_elevations,
         })
     }
 }
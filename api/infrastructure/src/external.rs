@@ -2,3 +2,4 @@ pub mod firebase;
 pub mod osrm;
 pub mod reserved_uids_reader;
 pub mod srtm;
+pub mod tile_cache;
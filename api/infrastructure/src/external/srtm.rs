@@ -1,311 +1,268 @@
-use std::collections::HashMap;
-use std::convert::{TryFrom, TryInto};
-use std::fs::File;
-use std::io::{Read, Seek, SeekFrom};
-use std::ops::Range;
-use std::path::{Path, PathBuf};
+mod ascii_grid_file;
+mod dem_preparation;
+mod dem_tile;
+mod geotiff_file;
+mod hgt_file;
+mod tile_index;
 
-use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
-use num_traits::FromPrimitive;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use itertools::Itertools;
 
 use route_bucket_domain::external::ElevationApi;
-use route_bucket_domain::model::route::{Coordinate, Elevation, Latitude, Longitude};
+use route_bucket_domain::model::route::{
+    Coordinate, DemVersion, Elevation, ElevationInterpolation,
+};
 use route_bucket_utils::{ApplicationError, ApplicationResult};
 
-#[derive(num_derive::FromPrimitive)]
-enum SrtmByteOrder {
-    LittleEndian = 0x4949,
-    BigEndian = 0x4D4D,
-}
-
-trait GetSrtmByteOrder {
-    fn get_srtm_byte_order() -> SrtmByteOrder;
-}
-
-impl GetSrtmByteOrder for BigEndian {
-    fn get_srtm_byte_order() -> SrtmByteOrder {
-        SrtmByteOrder::BigEndian
-    }
-}
-impl GetSrtmByteOrder for LittleEndian {
-    fn get_srtm_byte_order() -> SrtmByteOrder {
-        SrtmByteOrder::LittleEndian
-    }
-}
-
-#[derive(Clone, Debug, num_derive::FromPrimitive, Eq, PartialEq, Hash)]
-enum IfdTag {
-    ImageWidth = 0x0100,
-    ImageHeight = 0x0101,
-    StripOffsets = 0x0111,
-    // RowsPerStrip = 0x0116,
-    // StripByteCounts = 0x0117,
-    ModelPixelScale = 0x830E,
-    ModelTiepoint = 0x8482,
-    NoDataValue = 0xA481,
-}
-
-#[derive(Debug)]
-#[allow(dead_code)]
-struct IfdEntry {
-    datatype: u16,
-    count: u32,
-    data: u32,
-}
-
-impl IfdEntry {
-    pub fn read<Endian: ByteOrder>(f: &mut File) -> std::io::Result<Self> {
-        let datatype = f.read_u16::<Endian>()?;
-        let count = f.read_u32::<Endian>()?;
-        let data = f.read_u32::<Endian>()?;
-        Ok(Self {
-            datatype,
-            count,
-            data,
-        })
-    }
-}
-
-/// struct to process srtm 30x30 GeoTIFF files from https://srtm.csi.cgiar.org/
-struct SrtmFile {
-    path: PathBuf,
-    byte_order: SrtmByteOrder,
-    lat_range: Range<Latitude>,
-    lon_range: Range<Longitude>,
-    pixel_scale: Coordinate,
-    strip_offsets: Vec<u32>,
-    no_data_value: Elevation,
+use self::ascii_grid_file::AsciiGridFile;
+pub use self::dem_preparation::{DemBounds, DemManifest, DemManifestFile, DemPreparer};
+use self::dem_tile::DemTile;
+use self::geotiff_file::GeoTiffFile;
+use self::hgt_file::HgtFile;
+use self::tile_index::{grid_cell, SrtmTileIndex};
+
+const DEFAULT_SRTM_DATA_DIR: &str = "resources/srtm_data";
+
+/// struct to search coordinate from multiple DEM tiles
+///
+/// GeoTIFF(.tif, .tiff)，SRTMの.hgt，Esri ASCII grid(.asc)を混ぜて置ける
+///
+/// タイルは共有しているので，cloneしても読み込み直しは起きない
+#[derive(Clone)]
+pub struct SrtmReader {
+    index: Arc<SrtmTileIndex>,
+    interpolation: ElevationInterpolation,
+    dem_version: DemVersion,
+    /// 既に警告を出した，どのタイルにも含まれないグリッド
+    reported_holes: Arc<Mutex<HashSet<(i32, i32)>>>,
 }
 
-impl SrtmFile {
-    pub fn open(path: &Path) -> ApplicationResult<Self> {
-        let mut path_buf = PathBuf::new();
-        path_buf.push(path);
-
-        let mut file =
-            File::open(path).map_err(Self::cvt_err(format!("Failed to open {:?}", path)))?;
-
-        let (byte_order, ifd_offset) = Self::read_header(&mut file).map_err(Self::cvt_err(
-            format!("Failed to read header from {:?}", path),
-        ))?;
-
-        let ifd_entries = match byte_order {
-            SrtmByteOrder::LittleEndian => Self::read_ifd::<LittleEndian>(&mut file, ifd_offset),
-            SrtmByteOrder::BigEndian => Self::read_ifd::<BigEndian>(&mut file, ifd_offset),
-        }
-        .map_err(Self::cvt_err(format!(
-            "Failed to read IFD entries from {:?}",
-            path
-        )))?;
-
-        match byte_order {
-            SrtmByteOrder::LittleEndian => {
-                Self::from_ifd::<LittleEndian>(&ifd_entries, path_buf, file)
-            }
-            SrtmByteOrder::BigEndian => Self::from_ifd::<BigEndian>(&ifd_entries, path_buf, file),
+impl SrtmReader {
+    /// 環境変数`SRTM_DATA_DIR`(未設定なら`resources/srtm_data`)にある全てのタイルを読み込む
+    ///
+    /// 補間方法は環境変数`ELEVATION_INTERPOLATION`(nearest, bilinear, bicubic)で指定できる
+    /// DEMの版は環境変数`DEM_VERSION`で指定でき，
+    /// 未設定ならマニフェスト(`prepare_dem`で作ったもの)かタイルの構成から決める
+    pub fn new() -> ApplicationResult<Self> {
+        let dir =
+            std::env::var("SRTM_DATA_DIR").unwrap_or_else(|_| DEFAULT_SRTM_DATA_DIR.to_string());
+        let interpolation = match std::env::var("ELEVATION_INTERPOLATION") {
+            Ok(name) => name.parse().map_err(|_| {
+                ApplicationError::ExternalError(format!(
+                    "Invalid ELEVATION_INTERPOLATION {:?} (expected nearest, bilinear or bicubic)",
+                    name
+                ))
+            })?,
+            Err(_) => ElevationInterpolation::default(),
+        };
+        let mut reader = Self::open_dir(Path::new(&dir), interpolation)?;
+        if let Ok(version) = std::env::var("DEM_VERSION") {
+            reader.dem_version = DemVersion::from(version);
         }
+        log::info!("DEM version: {}", reader.dem_version);
+        Ok(reader)
     }
 
-    pub fn get(&self, coord: &Coordinate) -> ApplicationResult<Option<Elevation>> {
-        let mut file = File::open(&self.path)
-            .map_err(Self::cvt_err(format!("Failed to open {:?}", self.path)))?;
-
-        let (lon_scale, lat_scale): (f64, f64) = self.pixel_scale.clone().into();
-        let (lon, lat): (f64, f64) = coord.clone().into();
-
-        let lon_idx = ((lon - self.lon_range.start.value()) / lon_scale) as u32;
-        let lat_idx = ((self.lat_range.end.value() - lat) / lat_scale) as usize;
-
-        let offset = (self.strip_offsets[lat_idx] + lon_idx * 2) as u64;
-
-        file.seek(SeekFrom::Start(offset))
-            .map_err(Self::cvt_err(format!(
-                "failed to seek to offset {}",
-                offset
-            )))?;
+    /// `dir`のタイルを読み込む
+    ///
+    /// マニフェストがあれば，載っているタイルだけを内容が記録と同じか確かめてから読む
+    /// 無ければディレクトリにある全てのタイルを読み，読めないものは飛ばす
+    pub fn open_dir(dir: &Path, interpolation: ElevationInterpolation) -> ApplicationResult<Self> {
+        let manifest = DemManifest::read(dir)?;
+        let tiles = match &manifest {
+            Some(manifest) => manifest
+                .verify_tiles(dir)?
+                .iter()
+                .map(|path| {
+                    Self::open_tile(path).unwrap_or_else(|| {
+                        Err(ApplicationError::ExternalError(format!(
+                            "{:?} in the DEM manifest is not a DEM tile",
+                            path
+                        )))
+                    })
+                })
+                .collect::<ApplicationResult<Vec<_>>>()?,
+            None => Self::open_all_tiles(dir)?,
+        };
 
-        // TODO: 毎回読まずにLRU cacheを用意する
-        //     : 行ごと読んで取っておくのは有効かも
-        let data: i32 = match self.byte_order {
-            SrtmByteOrder::LittleEndian => file.read_i16::<LittleEndian>(),
-            SrtmByteOrder::BigEndian => file.read_i16::<BigEndian>(),
+        if tiles.is_empty() {
+            return Err(ApplicationError::ExternalError(format!(
+                "No DEM tiles were found in {:?}",
+                dir
+            )));
+        }
+        for tile in tiles.iter() {
+            let grid = tile.grid();
+            log::info!(
+                "Loaded DEM tile {:?} (lat: {}..{}, lon: {}..{}, {}x{} pixels)",
+                tile.path(),
+                grid.lat_range().start.value(),
+                grid.lat_range().end.value(),
+                grid.lon_range().start.value(),
+                grid.lon_range().end.value(),
+                grid.width(),
+                grid.height(),
+            );
         }
-        .map_err(Self::cvt_err(format!(
-            "Failed to read elevation of {:?} from {:?}",
-            coord, self.path
-        )))?
-        .into();
 
-        // TODO: then_someが実装されたら置き換える https://github.com/rust-lang/rust/issues/64260
-        Ok((data != self.no_data_value.value()).then(|| data.try_into().unwrap()))
-    }
+        let dem_version = match manifest {
+            Some(manifest) => DemVersion::from(format!("{}-{}", interpolation, manifest.version())),
+            None => Self::fingerprint(&tiles, interpolation)?,
+        };
 
-    pub fn contains(&self, coord: &Coordinate) -> bool {
-        self.lat_range.contains(coord.latitude()) && self.lon_range.contains(coord.longitude())
+        Ok(Self {
+            dem_version,
+            index: Arc::new(SrtmTileIndex::new(tiles)),
+            interpolation,
+            reported_holes: Arc::new(Mutex::new(HashSet::new())),
+        })
     }
 
-    fn read_header(f: &mut File) -> std::io::Result<(SrtmByteOrder, u32)> {
-        f.seek(SeekFrom::Start(0u64))?;
-
-        let byte_order =
-            SrtmByteOrder::from_u16(f.read_u16::<LittleEndian>()?).ok_or_else(|| {
-                std::io::Error::new(std::io::ErrorKind::Other, "invalid SRTM byte_order")
-            })?;
-
-        let version = f.read_u16::<LittleEndian>()?;
-
-        (0x2A == version).then(|| ()).ok_or_else(|| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("invalid SRTM version {:X}", version),
-            )
-        })?;
-
-        let ifd_offset = match byte_order {
-            SrtmByteOrder::LittleEndian => f.read_u32::<LittleEndian>(),
-            SrtmByteOrder::BigEndian => f.read_u32::<BigEndian>(),
-        }?;
-
-        Ok((byte_order, ifd_offset))
+    /// ディレクトリにある全てのタイルを開く．読めないタイルは警告して飛ばす
+    fn open_all_tiles(dir: &Path) -> ApplicationResult<Vec<Box<dyn DemTile>>> {
+        let paths = fs::read_dir(dir)
+            .map_err(|err| {
+                ApplicationError::ExternalError(format!("Failed to read {:?} ({})", dir, err))
+            })?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.is_file())
+            .sorted()
+            .collect_vec();
+
+        let tiles = paths
+            .iter()
+            .filter_map(|path| match Self::open_tile(path) {
+                Some(Ok(tile)) => Some(tile),
+                Some(Err(err)) => {
+                    log::warn!("Skipped an invalid DEM tile {:?}: {}", path, err);
+                    None
+                }
+                None => None,
+            })
+            .collect_vec();
+        Ok(tiles)
     }
 
-    fn read_ifd<Endian: ByteOrder>(
-        f: &mut File,
-        offset: u32,
-    ) -> std::io::Result<HashMap<IfdTag, IfdEntry>> {
-        f.seek(SeekFrom::Start(offset as u64))?;
-
-        let entry_count = f.read_u16::<Endian>()?;
-        let mut entries = HashMap::new();
-
-        for _ in 0..entry_count {
-            let tag_opt = IfdTag::from_u16(f.read_u16::<Endian>()?);
-            let entry = IfdEntry::read::<Endian>(f)?;
-            if let Some(tag) = tag_opt {
-                entries.insert(tag, entry);
+    /// 1点の標高を求める．タイルを読むのでブロッキングする
+    pub fn get_elevation(&self, coord: &Coordinate) -> ApplicationResult<Option<Elevation>> {
+        match self.index.find(coord) {
+            Some(tile) => tile.get(coord, self.interpolation),
+            None => {
+                self.report_hole(coord);
+                Ok(None)
             }
         }
-
-        let next_offset = f.read_u32::<Endian>()?;
-        if next_offset != 0 {
-            Self::read_ifd::<Endian>(f, next_offset)?
-                .into_iter()
-                .for_each(|(k, v)| {
-                    entries.insert(k, v);
-                });
-        }
-
-        Ok(entries)
     }
 
-    fn from_ifd<Endian: ByteOrder + GetSrtmByteOrder>(
-        entries: &HashMap<IfdTag, IfdEntry>,
-        path: PathBuf,
-        mut file: File,
-    ) -> ApplicationResult<Self> {
-        let read_tag_data = |tag: IfdTag| -> ApplicationResult<&IfdEntry> {
-            entries.get(&tag).ok_or_else(|| {
-                ApplicationError::ExternalError(format!("Failed to find tag {:?}", tag))
-            })
+    /// 拡張子から形式を判断してタイルを開く．DEMでないファイルならNone
+    fn open_tile(path: &Path) -> Option<ApplicationResult<Box<dyn DemTile>>> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        let tile: ApplicationResult<Box<dyn DemTile>> = match ext.as_str() {
+            "tif" | "tiff" => GeoTiffFile::open(path).map(|tile| Box::new(tile) as _),
+            "hgt" => HgtFile::open(path).map(|tile| Box::new(tile) as _),
+            "asc" => AsciiGridFile::open(path).map(|tile| Box::new(tile) as _),
+            _ => return None,
         };
-        let width = read_tag_data(IfdTag::ImageWidth)?.data;
-        let height = read_tag_data(IfdTag::ImageHeight)?.data;
-
-        let strip_offsets_entry = read_tag_data(IfdTag::StripOffsets)?;
-        let strip_offsets = Self::read_strip_offsets::<Endian>(strip_offsets_entry, &mut file)
-            .map_err(Self::cvt_err("Failed to read strip offsets".into()))?;
-
-        let pixel_scale_entry = read_tag_data(IfdTag::ModelPixelScale)?;
-        let [pixel_scale_lon, pixel_scale_lat] =
-            Self::read_pixel_scale::<Endian>(pixel_scale_entry, &mut file)
-                .map_err(Self::cvt_err("Failed to read strip offsets".into()))?;
-
-        let tiepoint_offset_entry = read_tag_data(IfdTag::ModelTiepoint)?;
-        let [left_lon, up_lat] = Self::read_tiepoint::<Endian>(tiepoint_offset_entry, &mut file)
-            .map_err(Self::cvt_err("Failed to read strip offsets".into()))?;
-        let bottom_lat = up_lat - pixel_scale_lat * height as f64;
-        let right_lon = left_lon + pixel_scale_lon * width as f64;
-
-        let no_data_entry = read_tag_data(IfdTag::NoDataValue)?;
-        let no_data_value = Self::read_no_data_value(no_data_entry, &mut file)
-            .map_err(Self::cvt_err("Failed to read NO_DATA value".into()))?
-            .try_into()?;
-
-        Ok(Self {
-            path,
-            byte_order: Endian::get_srtm_byte_order(),
-            lat_range: Latitude::try_from(bottom_lat)?..Latitude::try_from(up_lat)?,
-            lon_range: Longitude::try_from(left_lon)?..Longitude::try_from(right_lon)?,
-            pixel_scale: Coordinate::new(pixel_scale_lat, pixel_scale_lon)?,
-            strip_offsets,
-            no_data_value,
-        })
+        Some(tile)
     }
 
-    fn read_strip_offsets<Endian: ByteOrder>(
-        entry: &IfdEntry,
-        file: &mut File,
-    ) -> std::io::Result<Vec<u32>> {
-        let mut strip_offsets = vec![0u32; entry.count as usize];
-        file.seek(SeekFrom::Start(entry.data as u64))?;
-        file.read_u32_into::<Endian>(&mut strip_offsets[..])?;
-        Ok(strip_offsets)
+    /// 補間方法と，タイルのファイル名・内容のSHA-256から版を決める
+    ///
+    /// タイルを差し替えたり補間方法を変えたりすると版が変わり，保存済みの標高が求め直される
+    /// 版の決め方はマニフェストと同じなので，同じタイルならマニフェストの有無で版は変わらない
+    fn fingerprint(
+        tiles: &[Box<dyn DemTile>],
+        interpolation: ElevationInterpolation,
+    ) -> ApplicationResult<DemVersion> {
+        let files = tiles
+            .iter()
+            .map(|tile| DemManifestFile::read(tile.path()))
+            .collect::<ApplicationResult<Vec<_>>>()?;
+        Ok(DemVersion::from(format!(
+            "{}-{}",
+            interpolation,
+            DemManifest::version_of(&files)
+        )))
     }
 
-    fn read_pixel_scale<Endian: ByteOrder>(
-        entry: &IfdEntry,
-        file: &mut File,
-    ) -> std::io::Result<[f64; 2]> {
-        let mut pixel_scale = [0f64; 2];
-        file.seek(SeekFrom::Start(entry.data as u64))?;
-        file.read_f64_into::<Endian>(&mut pixel_scale)?;
-        Ok(pixel_scale)
+    /// タイルのない範囲の標高が求められたことを，グリッドごとに一度だけ警告する
+    fn report_hole(&self, coord: &Coordinate) {
+        let (lat, lon) = grid_cell(coord);
+        let is_new_hole = self
+            .reported_holes
+            .lock()
+            .map_or(true, |mut holes| holes.insert((lat, lon)));
+        if is_new_hole {
+            log::warn!(
+                "No DEM tile covers {:?}. Elevations in lat: {}..{}, lon: {}..{} will be missing \
+                 until a tile for the area is added to the data directory. ({} tiles loaded)",
+                coord,
+                lat,
+                lat + 1,
+                lon,
+                lon + 1,
+                self.index.tiles().len()
+            );
+        }
     }
+}
 
-    fn read_tiepoint<Endian: ByteOrder>(
-        entry: &IfdEntry,
-        file: &mut File,
-    ) -> std::io::Result<[f64; 2]> {
-        let mut tiepoint = [0f64; 6];
-        file.seek(SeekFrom::Start(entry.data as u64))?;
-        file.read_f64_into::<Endian>(&mut tiepoint)?;
-        Ok(tiepoint[3..5].try_into().unwrap())
+#[async_trait]
+impl ElevationApi for SrtmReader {
+    async fn get_elevations(
+        &self,
+        coords: &[Coordinate],
+    ) -> ApplicationResult<Vec<Option<Elevation>>> {
+        // タイルの読み込みでasyncのスレッドを止めないよう，blocking用のスレッドで引く
+        let reader = self.clone();
+        let coords = coords.to_vec();
+        tokio::task::spawn_blocking(move || {
+            coords
+                .iter()
+                .map(|coord| reader.get_elevation(coord))
+                .collect()
+        })
+        .await
+        .map_err(|err| {
+            ApplicationError::ExternalError(format!("Failed to look up elevations ({})", err))
+        })?
     }
 
-    fn read_no_data_value(entry: &IfdEntry, file: &mut File) -> std::io::Result<i32> {
-        let mut buf = vec![0u8; (entry.count - 1) as usize];
-        file.seek(SeekFrom::Start(entry.data as u64))?;
-        file.read_exact(&mut buf[..])?;
-        Ok(std::str::from_utf8(&buf).unwrap().parse::<i32>().unwrap())
+    fn dem_version(&self) -> DemVersion {
+        self.dem_version.clone()
     }
 
-    /// Returns a closure that converts io::Error to ApplicationError
-    fn cvt_err(msg: String) -> Box<dyn Fn(std::io::Error) -> ApplicationError> {
-        Box::new(move |err| ApplicationError::ExternalError(format!("{} ({})", msg, err)))
+    fn is_covered(&self, coord: &Coordinate) -> bool {
+        self.index.find(coord).is_some()
     }
 }
 
-/// struct to search coordinate from multiple SrtmFiles
-pub struct SrtmReader {
-    files: Vec<SrtmFile>,
-}
+#[cfg(test)]
+mod tests {
+    use super::dem_tile::tests::temp_dir;
+    use super::*;
 
-impl SrtmReader {
-    pub fn new() -> ApplicationResult<Self> {
-        // TODO: 複数ファイル対応
-        Ok(Self {
-            files: vec![SrtmFile::open(Path::new("resources/srtm_data/srtm.tif"))?],
-        })
+    fn version_of_tile(dir: &Path, value: u8) -> DemVersion {
+        fs::write(dir.join("N35E139.hgt"), vec![value; 2 * 3 * 3]).unwrap();
+        SrtmReader::open_dir(dir, ElevationInterpolation::Nearest)
+            .unwrap()
+            .dem_version
     }
-}
 
-impl ElevationApi for SrtmReader {
-    fn get_elevation(&self, coord: &Coordinate) -> ApplicationResult<Option<Elevation>> {
-        for area in self.files.iter() {
-            if area.contains(coord) {
-                return area.get(coord);
-            }
-        }
-        Ok(None)
+    #[test]
+    fn dem_version_follows_tile_contents() {
+        let dir = temp_dir("srtm_fingerprint");
+
+        let version = version_of_tile(&dir, 1);
+        assert_eq!(version_of_tile(&dir, 1), version);
+        // ファイル名と大きさが同じでも，内容が変われば版が変わる
+        assert_ne!(version_of_tile(&dir, 2), version);
+
+        fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+use super::dem_tile::{cvt_err, DemGrid, DemTile};
+
+/// struct to process Esri ASCII grid (.asc) files
+///
+/// ```text
+/// ncols        4
+/// nrows        3
+/// xllcorner    139.0
+/// yllcorner    35.0
+/// cellsize     0.0001
+/// NODATA_value -9999
+/// 12.3 12.5 ...
+/// ```
+///
+/// テキストなので，読み込み時に全ての値をメモリに展開しておく
+pub(super) struct AsciiGridFile {
+    path: PathBuf,
+    grid: DemGrid,
+    values: Vec<Option<f32>>,
+}
+
+impl AsciiGridFile {
+    pub fn open(path: &Path) -> ApplicationResult<Self> {
+        let text =
+            fs::read_to_string(path).map_err(cvt_err(format!("Failed to read {:?}", path)))?;
+        Self::parse(path, &text).map_err(|err| {
+            ApplicationError::ExternalError(format!("Failed to parse {:?}: {}", path, err))
+        })
+    }
+
+    fn parse(path: &Path, text: &str) -> ApplicationResult<Self> {
+        let mut tokens = text.split_whitespace().peekable();
+
+        // ヘッダは「キー 値」の行が続き，数値で始まる行から値になる
+        let mut header = HashMap::new();
+        while let Some(key) = tokens.next_if(|token| token.parse::<f64>().is_err()) {
+            let value = tokens
+                .next()
+                .and_then(|value| value.parse::<f64>().ok())
+                .ok_or_else(|| {
+                    ApplicationError::ExternalError(format!("Invalid header value of {}", key))
+                })?;
+            header.insert(key.to_ascii_lowercase(), value);
+        }
+        let get = |key: &str| {
+            header
+                .get(key)
+                .copied()
+                .ok_or_else(|| ApplicationError::ExternalError(format!("Missing header {}", key)))
+        };
+
+        let width = get("ncols")? as usize;
+        let height = get("nrows")? as usize;
+        let cellsize = get("cellsize")?;
+        let scale = (cellsize, cellsize);
+        let grid = match (get("xllcorner"), get("yllcorner")) {
+            (Ok(left), Ok(bottom)) => DemGrid::from_corner(
+                left,
+                bottom + cellsize * height as f64,
+                scale,
+                width,
+                height,
+            )?,
+            _ => DemGrid::from_center(
+                get("xllcenter")?,
+                get("yllcenter")? + cellsize * height.saturating_sub(1) as f64,
+                scale,
+                width,
+                height,
+            )?,
+        };
+        let no_data_value = header.get("nodata_value").copied();
+
+        let values = tokens
+            .map(|token| {
+                let value = token.parse::<f64>().map_err(|_| {
+                    ApplicationError::ExternalError(format!("Invalid value {}", token))
+                })?;
+                Ok(Some(value as f32).filter(|_| Some(value) != no_data_value))
+            })
+            .collect::<ApplicationResult<Vec<_>>>()?;
+        if values.len() != width * height {
+            return Err(ApplicationError::ExternalError(format!(
+                "Expected {}x{} values, but got {}",
+                width,
+                height,
+                values.len()
+            )));
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            grid,
+            values,
+        })
+    }
+}
+
+impl DemTile for AsciiGridFile {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn grid(&self) -> &DemGrid {
+        &self.grid
+    }
+
+    fn read_pixel(&self, col: usize, row: usize) -> ApplicationResult<Option<f64>> {
+        Ok(self.values[row * self.grid.width() + col].map(f64::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> ApplicationResult<AsciiGridFile> {
+        AsciiGridFile::parse(Path::new("test.asc"), text)
+    }
+
+    #[test]
+    fn can_parse_corner_header() {
+        let file = parse(
+            "ncols 2\nnrows 3\nxllcorner 139.0\nyllcorner 35.0\ncellsize 0.5\nNODATA_value -9999\n\
+             1 2\n3 -9999\n5.5 6\n",
+        )
+        .unwrap();
+        let grid = file.grid();
+        assert_eq!((grid.width(), grid.height()), (2, 3));
+        assert_eq!(grid.lat_range().end.value(), 36.5);
+        assert_eq!(grid.lon_range().end.value(), 140.);
+        assert_eq!(file.read_pixel(1, 0).unwrap(), Some(2.));
+        assert_eq!(file.read_pixel(1, 1).unwrap(), None);
+        assert_eq!(file.read_pixel(0, 2).unwrap(), Some(5.5));
+    }
+
+    #[test]
+    fn can_parse_center_header() {
+        let file = parse(
+            "NCOLS 3\nNROWS 2\nXLLCENTER 139.0\nYLLCENTER 35.0\nCELLSIZE 0.5\n1 2 3\n4 5 6\n",
+        )
+        .unwrap();
+        let grid = file.grid();
+        assert_eq!(grid.lat_range().start.value(), 35.);
+        assert_eq!(grid.lat_range().end.value(), 35.5);
+        assert_eq!(grid.lon_range().end.value(), 140.);
+        assert_eq!(file.read_pixel(2, 1).unwrap(), Some(6.));
+    }
+
+    #[test]
+    fn cannot_parse_invalid_grid() {
+        // 値の数が足りない
+        assert!(
+            parse("ncols 2\nnrows 2\nxllcorner 139\nyllcorner 35\ncellsize 0.5\n1 2 3\n").is_err()
+        );
+        // cellsizeが無い
+        assert!(parse("ncols 1\nnrows 1\nxllcorner 139\nyllcorner 35\n1\n").is_err());
+        // ヘッダの値が数値でない
+        assert!(parse("ncols two\nnrows 1\n").is_err());
+    }
+}
@@ -0,0 +1,547 @@
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use getset::Getters;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use route_bucket_domain::model::route::{Coordinate, ElevationInterpolation};
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+use super::dem_tile::cvt_err;
+use super::tile_index::SrtmTileIndex;
+use super::SrtmReader;
+
+/// 書き出したディレクトリに置くマニフェストのファイル名
+pub(super) const DEM_MANIFEST_FILE_NAME: &str = "manifest.json";
+
+const HGT_NO_DATA_VALUE: i16 = -32768;
+
+/// 元のファイルがこれより細かい(秒角)なら1秒角，そうでなければ3秒角のタイルにする
+const ONE_ARC_SECOND_THRESHOLD: f64 = 1.5;
+
+/// 切り出す範囲 [度]
+#[derive(Clone, Debug, PartialEq, Getters, Serialize, Deserialize)]
+#[get = "pub"]
+pub struct DemBounds {
+    min_latitude: f64,
+    min_longitude: f64,
+    max_latitude: f64,
+    max_longitude: f64,
+}
+
+impl DemBounds {
+    pub fn new(
+        min_latitude: f64,
+        min_longitude: f64,
+        max_latitude: f64,
+        max_longitude: f64,
+    ) -> ApplicationResult<Self> {
+        // 範囲外の値はCoordinateで弾く
+        Coordinate::new(min_latitude, min_longitude)?;
+        Coordinate::new(max_latitude, max_longitude)?;
+        if min_latitude >= max_latitude || min_longitude >= max_longitude {
+            return Err(ApplicationError::ValidationError(format!(
+                "Invalid bounds (lat: {}..{}, lon: {}..{})",
+                min_latitude, max_latitude, min_longitude, max_longitude
+            )));
+        }
+        Ok(Self {
+            min_latitude,
+            min_longitude,
+            max_latitude,
+            max_longitude,
+        })
+    }
+
+    fn contains(&self, lat: f64, lon: f64) -> bool {
+        (self.min_latitude..=self.max_latitude).contains(&lat)
+            && (self.min_longitude..=self.max_longitude).contains(&lon)
+    }
+
+    /// 範囲にかかる1度四方のグリッド(南西端の緯度, 経度)
+    fn grid_cells(&self) -> Vec<(i32, i32)> {
+        let cells = |min: f64, max: f64| min.floor() as i32..max.ceil() as i32;
+        cells(self.min_latitude, self.max_latitude)
+            .cartesian_product(cells(self.min_longitude, self.max_longitude))
+            .collect()
+    }
+}
+
+/// マニフェストに載せるファイル
+#[derive(Clone, Debug, Getters, Serialize, Deserialize)]
+#[get = "pub"]
+pub struct DemManifestFile {
+    file: String,
+    bytes: u64,
+    sha256: String,
+}
+
+impl DemManifestFile {
+    /// 実行したディレクトリや置き場所に依らないよう，ファイル名だけを記録する
+    pub(super) fn read(path: &Path) -> ApplicationResult<Self> {
+        let mut file = File::open(path).map_err(cvt_err(format!("Failed to open {:?}", path)))?;
+        let mut hasher = Sha256::new();
+        let bytes = io::copy(&mut file, &mut hasher)
+            .map_err(cvt_err(format!("Failed to read {:?}", path)))?;
+        Ok(Self {
+            file: path
+                .file_name()
+                .unwrap_or(path.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+            bytes,
+            sha256: format!("{:x}", hasher.finalize()),
+        })
+    }
+}
+
+/// `DemPreparer`で書き出したタイルの記録
+///
+/// 同じ入力からは同じマニフェストができるので，デプロイ先のタイルが揃っているか確かめられる
+#[derive(Clone, Debug, Getters, Serialize, Deserialize)]
+#[get = "pub"]
+pub struct DemManifest {
+    /// 書き出したタイルの内容から決まる版
+    version: String,
+    bounds: DemBounds,
+    /// タイルの解像度 [秒角]
+    resolution: u32,
+    sources: Vec<DemManifestFile>,
+    tiles: Vec<DemManifestFile>,
+}
+
+impl DemManifest {
+    /// `dir`のマニフェストを読む．無ければNone
+    pub fn read(dir: &Path) -> ApplicationResult<Option<Self>> {
+        let path = dir.join(DEM_MANIFEST_FILE_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let text =
+            fs::read_to_string(&path).map_err(cvt_err(format!("Failed to read {:?}", path)))?;
+        serde_json::from_str(&text).map(Some).map_err(|err| {
+            ApplicationError::ExternalError(format!("Invalid DEM manifest {:?} ({})", path, err))
+        })
+    }
+
+    /// マニフェストに載っているタイルが`dir`にあり，内容が記録と同じか確かめる
+    ///
+    /// 確かめたタイルのパスを，マニフェストの順に返す
+    pub(super) fn verify_tiles(&self, dir: &Path) -> ApplicationResult<Vec<PathBuf>> {
+        self.tiles
+            .iter()
+            .map(|tile| {
+                let path = dir.join(&tile.file);
+                let is_plain_name = Path::new(&tile.file).file_name() == Some(tile.file.as_ref());
+                if !is_plain_name || !path.is_file() {
+                    return Err(ApplicationError::ExternalError(format!(
+                        "DEM tile {:?} in the manifest was not found in {:?}",
+                        tile.file, dir
+                    )));
+                }
+                let actual = DemManifestFile::read(&path)?;
+                if actual.bytes != tile.bytes || actual.sha256 != tile.sha256 {
+                    return Err(ApplicationError::ExternalError(format!(
+                        "DEM tile {:?} differs from the manifest (sha256: {}, expected {}). \
+                         Run prepare_dem again to rebuild {:?}",
+                        path, actual.sha256, tile.sha256, dir
+                    )));
+                }
+                Ok(path)
+            })
+            .collect()
+    }
+
+    /// タイルのファイル名と内容から版を決める
+    pub(super) fn version_of(tiles: &[DemManifestFile]) -> String {
+        let mut hasher = Sha256::new();
+        tiles.iter().for_each(|tile| {
+            hasher.update(tile.file.as_bytes());
+            hasher.update(tile.sha256.as_bytes());
+        });
+        format!("{:x}", hasher.finalize())[..16].to_string()
+    }
+
+    fn write(&self, dir: &Path) -> ApplicationResult<()> {
+        let path = dir.join(DEM_MANIFEST_FILE_NAME);
+        let text = serde_json::to_string_pretty(self).map_err(|err| {
+            ApplicationError::ExternalError(format!("Failed to serialize DEM manifest ({})", err))
+        })?;
+        fs::write(&path, text).map_err(cvt_err(format!("Failed to write {:?}", path)))
+    }
+}
+
+/// 手元のDEMファイルから，`SrtmReader`が読むディレクトリを作る
+///
+/// 指定した範囲を1度四方の.hgtタイルに切り出し(範囲が複数のファイルにまたがる場合はつなぎ合わせ)，
+/// 使ったファイルと書き出したタイルをマニフェストに記録する
+pub struct DemPreparer {
+    index: SrtmTileIndex,
+    paths: Vec<PathBuf>,
+    sources: Vec<DemManifestFile>,
+}
+
+impl DemPreparer {
+    /// `SrtmReader`と同じパーサーでファイルを開く
+    ///
+    /// `SrtmReader`と違い，読めないファイルがあればエラーにする
+    pub fn open_files(paths: &[PathBuf]) -> ApplicationResult<Self> {
+        if paths.is_empty() {
+            return Err(ApplicationError::ValidationError(
+                "No DEM files were given".into(),
+            ));
+        }
+        let tiles = paths
+            .iter()
+            .map(|path| {
+                SrtmReader::open_tile(path).unwrap_or_else(|| {
+                    Err(ApplicationError::ValidationError(format!(
+                        "{:?} is not a DEM file (expected .tif, .tiff, .hgt or .asc)",
+                        path
+                    )))
+                })
+            })
+            .collect::<ApplicationResult<Vec<_>>>()?;
+        let sources = paths
+            .iter()
+            .map(|path| DemManifestFile::read(path))
+            .collect::<ApplicationResult<Vec<_>>>()?;
+
+        Ok(Self {
+            index: SrtmTileIndex::new(tiles),
+            paths: paths.to_vec(),
+            sources,
+        })
+    }
+
+    /// 元のファイルの解像度から，書き出すタイルの解像度[秒角]を決める
+    pub fn default_resolution(&self) -> u32 {
+        let finest = self
+            .index
+            .tiles()
+            .iter()
+            .map(|tile| tile.grid().pixel_area().sqrt() * 3600.)
+            .fold(f64::INFINITY, f64::min);
+        if finest < ONE_ARC_SECOND_THRESHOLD {
+            1
+        } else {
+            3
+        }
+    }
+
+    /// `bounds`を`resolution`秒角の.hgtタイルにして`out_dir`に書き出す
+    ///
+    /// 範囲外の点や，どのファイルにも含まれない点はno_dataにする
+    /// 1点も標高が無いタイルは書き出さない
+    /// 前回書き出したタイルが残らないよう，`out_dir`の.hgtファイルとマニフェストは先に消す
+    pub fn prepare(
+        &self,
+        bounds: &DemBounds,
+        resolution: u32,
+        out_dir: &Path,
+    ) -> ApplicationResult<DemManifest> {
+        if resolution != 1 && resolution != 3 {
+            return Err(ApplicationError::ValidationError(format!(
+                "Invalid resolution {} (expected 1 or 3 arc seconds)",
+                resolution
+            )));
+        }
+        fs::create_dir_all(out_dir).map_err(cvt_err(format!("Failed to create {:?}", out_dir)))?;
+        self.clear_out_dir(out_dir)?;
+
+        let size = 3600 / resolution as usize + 1;
+        let mut tiles = Vec::new();
+        for (lat, lon) in bounds.grid_cells() {
+            let path = out_dir.join(Self::hgt_file_name(lat, lon));
+            if self.write_hgt(&path, bounds, lat, lon, size)? {
+                tiles.push(DemManifestFile::read(&path)?);
+            } else {
+                log::warn!(
+                    "No DEM data in lat: {}..{}, lon: {}..{}. Skipped {:?}",
+                    lat,
+                    lat + 1,
+                    lon,
+                    lon + 1,
+                    path
+                );
+            }
+        }
+        if tiles.is_empty() {
+            return Err(ApplicationError::ValidationError(format!(
+                "The DEM files don't cover any part of {:?}",
+                bounds
+            )));
+        }
+
+        let manifest = DemManifest {
+            version: DemManifest::version_of(&tiles),
+            bounds: bounds.clone(),
+            resolution,
+            sources: self.sources.clone(),
+            tiles,
+        };
+        manifest.write(out_dir)?;
+        Ok(manifest)
+    }
+
+    /// 前回の.hgtファイルとマニフェストを消す
+    ///
+    /// 元のファイルを消したり上書きしたりしないよう，元のファイルがあるディレクトリには書き出さない
+    fn clear_out_dir(&self, out_dir: &Path) -> ApplicationResult<()> {
+        let canonical_out_dir = out_dir
+            .canonicalize()
+            .map_err(cvt_err(format!("Failed to resolve {:?}", out_dir)))?;
+        let source_in_out_dir = self.paths.iter().find(|path| {
+            path.canonicalize()
+                .ok()
+                .and_then(|path| path.parent().map(|dir| dir == canonical_out_dir))
+                .unwrap_or(false)
+        });
+        if let Some(path) = source_in_out_dir {
+            return Err(ApplicationError::ValidationError(format!(
+                "{:?} is in the output directory {:?}. Please write the tiles to another directory",
+                path, out_dir
+            )));
+        }
+
+        let entries =
+            fs::read_dir(out_dir).map_err(cvt_err(format!("Failed to read {:?}", out_dir)))?;
+        for entry in entries {
+            let path = entry
+                .map_err(cvt_err(format!("Failed to read {:?}", out_dir)))?
+                .path();
+            let is_hgt = matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some(ext) if ext.eq_ignore_ascii_case("hgt")
+            );
+            let is_manifest = path.file_name() == Some(DEM_MANIFEST_FILE_NAME.as_ref());
+            if path.is_file() && (is_hgt || is_manifest) {
+                fs::remove_file(&path).map_err(cvt_err(format!("Failed to remove {:?}", path)))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 1つのタイルを書き出す．1点も標高が無ければ書き出さずにfalseを返す
+    fn write_hgt(
+        &self,
+        path: &Path,
+        bounds: &DemBounds,
+        lat: i32,
+        lon: i32,
+        size: usize,
+    ) -> ApplicationResult<bool> {
+        let step = 1. / (size - 1) as f64;
+        let mut data = Vec::with_capacity(size * size * 2);
+        let mut has_data = false;
+        for row in 0..size {
+            let pixel_lat = f64::from(lat + 1) - row as f64 * step;
+            for col in 0..size {
+                let pixel_lon = f64::from(lon) + col as f64 * step;
+                let value = if bounds.contains(pixel_lat, pixel_lon) {
+                    self.sample(pixel_lat, pixel_lon)?
+                } else {
+                    None
+                };
+                has_data |= value.is_some();
+                data.extend_from_slice(&value.unwrap_or(HGT_NO_DATA_VALUE).to_be_bytes());
+            }
+        }
+        if !has_data {
+            return Ok(false);
+        }
+
+        let file = File::create(path).map_err(cvt_err(format!("Failed to create {:?}", path)))?;
+        let mut writer = BufWriter::new(file);
+        writer
+            .write_all(&data)
+            .and_then(|_| writer.flush())
+            .map_err(cvt_err(format!("Failed to write {:?}", path)))?;
+        Ok(true)
+    }
+
+    fn sample(&self, lat: f64, lon: f64) -> ApplicationResult<Option<i16>> {
+        let coord = Coordinate::new(lat, lon)?;
+        let elevation = match self.index.find(&coord) {
+            Some(tile) => tile.get(&coord, ElevationInterpolation::Bilinear)?,
+            None => None,
+        };
+        elevation
+            .map(|elevation| {
+                i16::try_from(elevation.value())
+                    .ok()
+                    .filter(|value| *value != HGT_NO_DATA_VALUE)
+                    .ok_or_else(|| {
+                        ApplicationError::ExternalError(format!(
+                            "Elevation {} at ({}, {}) cannot be stored in a .hgt tile",
+                            elevation.value(),
+                            lat,
+                            lon
+                        ))
+                    })
+            })
+            .transpose()
+    }
+
+    fn hgt_file_name(lat: i32, lon: i32) -> String {
+        format!(
+            "{}{:02}{}{:03}.hgt",
+            if lat >= 0 { 'N' } else { 'S' },
+            lat.abs(),
+            if lon >= 0 { 'E' } else { 'W' },
+            lon.abs()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use route_bucket_domain::external::ElevationApi;
+
+    use super::super::dem_tile::tests::temp_dir;
+    use super::*;
+
+    /// 標高が全て`value`の3x3の.hgtファイルを書く
+    fn write_source(dir: &Path, name: &str, value: i16) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, value.to_be_bytes().repeat(3 * 3)).unwrap();
+        path
+    }
+
+    fn file_names(files: &[DemManifestFile]) -> Vec<&str> {
+        files.iter().map(|file| file.file().as_str()).collect()
+    }
+
+    fn yokohama_bounds() -> DemBounds {
+        DemBounds::new(35.2, 139.2, 35.8, 139.8).unwrap()
+    }
+
+    #[test]
+    fn cannot_create_inverted_bounds() {
+        assert!(matches!(
+            DemBounds::new(36., 139., 35., 140.),
+            Err(ApplicationError::ValidationError(_))
+        ));
+        assert!(DemBounds::new(35., 139., 91., 140.).is_err());
+    }
+
+    #[test]
+    fn can_list_grid_cells() {
+        let bounds = DemBounds::new(35.5, 139.2, 36.5, 139.8).unwrap();
+        assert_eq!(bounds.grid_cells(), vec![(35, 139), (36, 139)]);
+    }
+
+    #[test]
+    fn can_name_hgt_files() {
+        assert_eq!(DemPreparer::hgt_file_name(35, 139), "N35E139.hgt");
+        assert_eq!(DemPreparer::hgt_file_name(-1, -75), "S01W075.hgt");
+    }
+
+    #[test]
+    fn can_prepare_tiles() {
+        let source_dir = temp_dir("prepare_source");
+        let out_dir = temp_dir("prepare_out");
+        let source = write_source(&source_dir, "N35E139.hgt", 100);
+        // 前回書き出したタイルは消える
+        write_source(&out_dir, "N10E010.hgt", 1);
+
+        let preparer = DemPreparer::open_files(&[source]).unwrap();
+        assert_eq!(preparer.default_resolution(), 3);
+        let manifest = preparer.prepare(&yokohama_bounds(), 3, &out_dir).unwrap();
+
+        assert_eq!(file_names(manifest.tiles()), vec!["N35E139.hgt"]);
+        assert_eq!(file_names(manifest.sources()), vec!["N35E139.hgt"]);
+        assert_eq!(manifest.tiles()[0].bytes(), &(1201 * 1201 * 2));
+        assert!(!out_dir.join("N10E010.hgt").exists());
+
+        let reader = SrtmReader::open_dir(&out_dir, ElevationInterpolation::Nearest).unwrap();
+        let coord = Coordinate::new(35.5, 139.5).unwrap();
+        assert_eq!(
+            reader.get_elevation(&coord).unwrap().map(|e| e.value()),
+            Some(100)
+        );
+        // 範囲外はno_data
+        let outside = Coordinate::new(35.1, 139.5).unwrap();
+        assert_eq!(reader.get_elevation(&outside).unwrap(), None);
+
+        // 同じ入力からは同じ版になる
+        let again = preparer.prepare(&yokohama_bounds(), 3, &out_dir).unwrap();
+        assert_eq!(again.version(), manifest.version());
+
+        fs::remove_dir_all(source_dir).unwrap();
+        fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[test]
+    fn cannot_prepare_outside_of_sources() {
+        let source_dir = temp_dir("prepare_outside_source");
+        let out_dir = temp_dir("prepare_outside_out");
+        let source = write_source(&source_dir, "N35E139.hgt", 100);
+
+        let preparer = DemPreparer::open_files(&[source]).unwrap();
+        let bounds = DemBounds::new(10.2, 10.2, 10.8, 10.8).unwrap();
+        assert!(matches!(
+            preparer.prepare(&bounds, 3, &out_dir),
+            Err(ApplicationError::ValidationError(_))
+        ));
+        assert!(matches!(
+            preparer.prepare(&yokohama_bounds(), 2, &out_dir),
+            Err(ApplicationError::ValidationError(_))
+        ));
+
+        fs::remove_dir_all(source_dir).unwrap();
+        fs::remove_dir_all(out_dir).unwrap();
+    }
+
+    #[test]
+    fn cannot_prepare_into_source_dir() {
+        let dir = temp_dir("prepare_into_source");
+        let source = write_source(&dir, "N35E139.hgt", 100);
+
+        let preparer = DemPreparer::open_files(std::slice::from_ref(&source)).unwrap();
+        assert!(matches!(
+            preparer.prepare(&yokohama_bounds(), 3, &dir),
+            Err(ApplicationError::ValidationError(_))
+        ));
+        assert!(source.is_file());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reader_loads_only_verified_tiles_in_manifest() {
+        let source_dir = temp_dir("manifest_source");
+        let out_dir = temp_dir("manifest_out");
+        let source = write_source(&source_dir, "N35E139.hgt", 100);
+        DemPreparer::open_files(&[source])
+            .unwrap()
+            .prepare(&yokohama_bounds(), 3, &out_dir)
+            .unwrap();
+
+        // マニフェストに載っていないタイルは読まない
+        write_source(&out_dir, "N00E000.hgt", 1);
+        let reader = SrtmReader::open_dir(&out_dir, ElevationInterpolation::Nearest).unwrap();
+        assert!(!reader.is_covered(&Coordinate::new(0.5, 0.5).unwrap()));
+
+        // 内容が変わったタイルがあれば読み込めない
+        let tile = out_dir.join("N35E139.hgt");
+        let mut data = fs::read(&tile).unwrap();
+        data[0] ^= 1;
+        fs::write(&tile, data).unwrap();
+        assert!(matches!(
+            SrtmReader::open_dir(&out_dir, ElevationInterpolation::Nearest),
+            Err(ApplicationError::ExternalError(_))
+        ));
+
+        // マニフェストに載っているタイルが無くても読み込めない
+        fs::remove_file(&tile).unwrap();
+        assert!(SrtmReader::open_dir(&out_dir, ElevationInterpolation::Nearest).is_err());
+
+        fs::remove_dir_all(source_dir).unwrap();
+        fs::remove_dir_all(out_dir).unwrap();
+    }
+}
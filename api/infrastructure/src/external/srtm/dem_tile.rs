@@ -0,0 +1,280 @@
+use std::convert::TryFrom;
+use std::ops::Range;
+use std::path::Path;
+
+use route_bucket_domain::model::route::{
+    Coordinate, Elevation, ElevationInterpolation, Latitude, Longitude,
+};
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+/// 1枚のDEMファイル(タイル)
+///
+/// 形式ごとの違いはピクセルの読み方だけで，座標との対応は`DemGrid`で共通化する
+pub(super) trait DemTile: Send + Sync {
+    fn path(&self) -> &Path;
+
+    fn grid(&self) -> &DemGrid;
+
+    /// (`col`, `row`)のピクセルの標高を読む．no_dataならNone
+    ///
+    /// `col`, `row`はグリッドの範囲内であることが保証される
+    fn read_pixel(&self, col: usize, row: usize) -> ApplicationResult<Option<f64>>;
+
+    fn get(
+        &self,
+        coord: &Coordinate,
+        interpolation: ElevationInterpolation,
+    ) -> ApplicationResult<Option<Elevation>> {
+        let grid = self.grid();
+        let (x, y) = grid.pixel_position(coord);
+        interpolation.interpolate(x, y, |col, row| {
+            let (col, row) = grid.clamp(col, row);
+            self.read_pixel(col, row)
+        })
+    }
+}
+
+/// タイルのピクセルの並びと座標の対応
+///
+/// ピクセルは北西端から東へ`width`個，南へ`height`個並ぶ
+pub(super) struct DemGrid {
+    width: usize,
+    height: usize,
+    /// 北西端のピクセルの中心の経度・緯度
+    origin: (f64, f64),
+    /// ピクセルの間隔[度] (経度方向, 緯度方向)
+    scale: (f64, f64),
+    lat_range: Range<Latitude>,
+    lon_range: Range<Longitude>,
+}
+
+impl DemGrid {
+    /// ピクセルが範囲を表す(GeoTIFFのPixelIsArea)グリッド
+    ///
+    /// `left`, `top`は北西端のピクセルの北西の角
+    pub fn from_corner(
+        left: f64,
+        top: f64,
+        scale: (f64, f64),
+        width: usize,
+        height: usize,
+    ) -> ApplicationResult<Self> {
+        Self::validate_size(scale, width, height)?;
+        let (lon_scale, lat_scale) = scale;
+        Ok(Self {
+            width,
+            height,
+            origin: (left + lon_scale / 2., top - lat_scale / 2.),
+            scale,
+            lat_range: Latitude::try_from(top - lat_scale * height as f64)?
+                ..Latitude::try_from(top)?,
+            lon_range: Longitude::try_from(left)?
+                ..Longitude::try_from(left + lon_scale * width as f64)?,
+        })
+    }
+
+    /// ピクセルが点を表す(SRTMの.hgtなど)グリッド
+    ///
+    /// `left`, `top`は北西端のピクセルの中心で，範囲は端のピクセルの中心までとなる
+    pub fn from_center(
+        left: f64,
+        top: f64,
+        scale: (f64, f64),
+        width: usize,
+        height: usize,
+    ) -> ApplicationResult<Self> {
+        Self::validate_size(scale, width, height)?;
+        let (lon_scale, lat_scale) = scale;
+        Ok(Self {
+            width,
+            height,
+            origin: (left, top),
+            scale,
+            lat_range: Latitude::try_from(top - lat_scale * (height - 1) as f64)?
+                ..Latitude::try_from(top)?,
+            lon_range: Longitude::try_from(left)?
+                ..Longitude::try_from(left + lon_scale * (width - 1) as f64)?,
+        })
+    }
+
+    fn validate_size(scale: (f64, f64), width: usize, height: usize) -> ApplicationResult<()> {
+        if width > 0 && height > 0 && scale.0 > 0. && scale.1 > 0. {
+            Ok(())
+        } else {
+            Err(ApplicationError::ExternalError(format!(
+                "Invalid DEM grid (size: {}x{}, pixel scale: {:?})",
+                width, height, scale
+            )))
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn lat_range(&self) -> &Range<Latitude> {
+        &self.lat_range
+    }
+
+    pub fn lon_range(&self) -> &Range<Longitude> {
+        &self.lon_range
+    }
+
+    /// 1ピクセルの面積[度^2] (小さいほど高解像度)
+    pub fn pixel_area(&self) -> f64 {
+        self.scale.0 * self.scale.1
+    }
+
+    /// 南端・西端を含み，北端・東端を含まない
+    pub fn contains(&self, coord: &Coordinate) -> bool {
+        self.lat_range.contains(coord.latitude()) && self.lon_range.contains(coord.longitude())
+    }
+
+    /// `contains`に加えて北端・東端の境界上の点も含む
+    pub fn covers(&self, coord: &Coordinate) -> bool {
+        self.lat_range.start <= *coord.latitude()
+            && *coord.latitude() <= self.lat_range.end
+            && self.lon_range.start <= *coord.longitude()
+            && *coord.longitude() <= self.lon_range.end
+    }
+
+    /// ピクセル単位の位置 (整数の位置にピクセルの中心が来る)
+    fn pixel_position(&self, coord: &Coordinate) -> (f64, f64) {
+        (
+            (coord.longitude().value() - self.origin.0) / self.scale.0,
+            (self.origin.1 - coord.latitude().value()) / self.scale.1,
+        )
+    }
+
+    /// グリッドの外側のピクセルは端のピクセルに丸める(タイルの境目の点もここで丸められる)
+    fn clamp(&self, col: i64, row: i64) -> (usize, usize) {
+        (
+            col.clamp(0, self.width as i64 - 1) as usize,
+            row.clamp(0, self.height as i64 - 1) as usize,
+        )
+    }
+}
+
+/// Returns a closure that converts io::Error to ApplicationError
+pub(super) fn cvt_err(msg: String) -> Box<dyn Fn(std::io::Error) -> ApplicationError> {
+    Box::new(move |err| ApplicationError::ExternalError(format!("{} ({})", msg, err)))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// メモリ上の値を返すタイル
+    pub(crate) struct GridTile {
+        path: PathBuf,
+        grid: DemGrid,
+        values: Vec<Option<f64>>,
+    }
+
+    impl GridTile {
+        /// 値が`row * 10 + col`になる，`from_center`のグリッドのタイル
+        pub(crate) fn new(left: f64, top: f64, scale: f64, size: usize) -> Self {
+            Self {
+                path: PathBuf::from(format!("grid_{}_{}_{}", left, top, scale)),
+                grid: DemGrid::from_center(left, top, (scale, scale), size, size).unwrap(),
+                values: (0..size * size)
+                    .map(|i| Some((i / size * 10 + i % size) as f64))
+                    .collect(),
+            }
+        }
+    }
+
+    impl DemTile for GridTile {
+        fn path(&self) -> &Path {
+            &self.path
+        }
+
+        fn grid(&self) -> &DemGrid {
+            &self.grid
+        }
+
+        fn read_pixel(&self, col: usize, row: usize) -> ApplicationResult<Option<f64>> {
+            Ok(self.values[row * self.grid.width() + col])
+        }
+    }
+
+    /// テストごとに別の一時ディレクトリ
+    pub(crate) fn temp_dir(test_name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "route-bucket-dem-test-{}-{}",
+            std::process::id(),
+            test_name
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn coord(lat: f64, lon: f64) -> Coordinate {
+        Coordinate::new(lat, lon).unwrap()
+    }
+
+    fn ranges(grid: &DemGrid) -> (f64, f64, f64, f64) {
+        (
+            grid.lat_range().start.value(),
+            grid.lat_range().end.value(),
+            grid.lon_range().start.value(),
+            grid.lon_range().end.value(),
+        )
+    }
+
+    #[test]
+    fn corner_grid_covers_whole_pixels() {
+        let grid = DemGrid::from_corner(139., 36., (0.5, 0.25), 2, 4).unwrap();
+        assert_eq!(ranges(&grid), (35., 36., 139., 140.));
+        assert_eq!(grid.pixel_position(&coord(35.875, 139.25)), (0., 0.));
+        assert_eq!(grid.pixel_area(), 0.125);
+    }
+
+    #[test]
+    fn center_grid_ends_at_edge_pixel_centers() {
+        let grid = DemGrid::from_center(139., 36., (0.5, 0.5), 3, 3).unwrap();
+        assert_eq!(ranges(&grid), (35., 36., 139., 140.));
+        assert_eq!(grid.pixel_position(&coord(35., 140.)), (2., 2.));
+    }
+
+    #[test]
+    fn cannot_create_empty_grid() {
+        assert!(DemGrid::from_corner(139., 36., (0.5, 0.5), 0, 3).is_err());
+        assert!(DemGrid::from_center(139., 36., (0., 0.5), 3, 3).is_err());
+    }
+
+    #[test]
+    fn contains_excludes_north_and_east_edges() {
+        let grid = DemGrid::from_center(139., 36., (0.5, 0.5), 3, 3).unwrap();
+        assert!(grid.contains(&coord(35., 139.)));
+        assert!(!grid.contains(&coord(36., 139.5)));
+        assert!(!grid.contains(&coord(35.5, 140.)));
+        assert!(grid.covers(&coord(36., 140.)));
+        assert!(!grid.covers(&coord(36.1, 139.5)));
+    }
+
+    #[test]
+    fn get_clamps_to_edge_pixels() {
+        let tile = GridTile::new(139., 36., 0.5, 3);
+        let get = |lat, lon| {
+            tile.get(&coord(lat, lon), ElevationInterpolation::Nearest)
+                .unwrap()
+                .map(|elevation| elevation.value())
+        };
+        assert_eq!(get(35.5, 139.5), Some(11));
+        assert_eq!(get(36., 140.), Some(2));
+        // 双線形補間でグリッドの外側を参照しても，端のピクセルに丸められる
+        assert_eq!(
+            tile.get(&coord(35., 140.), ElevationInterpolation::Bilinear)
+                .unwrap()
+                .map(|elevation| elevation.value()),
+            Some(22)
+        );
+    }
+}
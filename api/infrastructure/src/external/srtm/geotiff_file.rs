@@ -0,0 +1,992 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use flate2::read::ZlibDecoder;
+use lru::LruCache;
+use memmap2::Mmap;
+use num_traits::FromPrimitive;
+
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+use super::dem_tile::{cvt_err, DemGrid, DemTile};
+
+/// 圧縮されたファイルで，展開済みのストリップ・タイルを保持しておく数
+const DECODED_CHUNK_CACHE_SIZE: usize = 32;
+
+#[derive(Clone, Copy, num_derive::FromPrimitive)]
+enum TiffByteOrder {
+    LittleEndian = 0x4949,
+    BigEndian = 0x4D4D,
+}
+
+macro_rules! impl_read_write {
+    ($($read:ident, $write:ident: $t:ty),*) => {
+        impl TiffByteOrder {
+            $(
+                fn $read(self, buf: &[u8]) -> $t {
+                    match self {
+                        Self::LittleEndian => LittleEndian::$read(buf),
+                        Self::BigEndian => BigEndian::$read(buf),
+                    }
+                }
+
+                #[allow(dead_code)]
+                fn $write(self, buf: &mut [u8], value: $t) {
+                    match self {
+                        Self::LittleEndian => LittleEndian::$write(buf, value),
+                        Self::BigEndian => BigEndian::$write(buf, value),
+                    }
+                }
+            )*
+        }
+    };
+}
+
+impl_read_write!(
+    read_u16, write_u16: u16,
+    read_u32, write_u32: u32,
+    read_i16, write_i16: i16,
+    read_i32, write_i32: i32,
+    read_f32, write_f32: f32,
+    read_f64, write_f64: f64
+);
+
+#[derive(Clone, Debug, num_derive::FromPrimitive, Eq, PartialEq, Hash)]
+enum IfdTag {
+    ImageWidth = 0x0100,
+    ImageHeight = 0x0101,
+    BitsPerSample = 0x0102,
+    Compression = 0x0103,
+    StripOffsets = 0x0111,
+    SamplesPerPixel = 0x0115,
+    RowsPerStrip = 0x0116,
+    StripByteCounts = 0x0117,
+    Predictor = 0x013D,
+    TileWidth = 0x0142,
+    TileLength = 0x0143,
+    TileOffsets = 0x0144,
+    TileByteCounts = 0x0145,
+    SampleFormat = 0x0153,
+    ModelPixelScale = 0x830E,
+    ModelTiepoint = 0x8482,
+    GeoKeyDirectory = 0x87AF,
+    NoDataValue = 0xA481,
+}
+
+#[derive(Debug)]
+struct IfdEntry {
+    datatype: u16,
+    count: usize,
+    /// 値そのもの(4byte以下の場合)か，値へのオフセットが入っている位置
+    value_pos: usize,
+}
+
+impl IfdEntry {
+    fn type_size(&self) -> Option<usize> {
+        match self.datatype {
+            1 | 2 | 6 | 7 => Some(1),
+            3 | 8 => Some(2),
+            4 | 9 | 11 => Some(4),
+            5 | 10 | 12 => Some(8),
+            _ => None,
+        }
+    }
+}
+
+/// GeoTIFFの最初のIFD(本体の画像)
+struct Ifd<'a> {
+    bytes: &'a [u8],
+    byte_order: TiffByteOrder,
+    entries: HashMap<IfdTag, IfdEntry>,
+}
+
+impl<'a> Ifd<'a> {
+    fn read(bytes: &'a [u8]) -> ApplicationResult<Self> {
+        let byte_order = bytes
+            .get(0..2)
+            .and_then(|buf| TiffByteOrder::from_u16(LittleEndian::read_u16(buf)))
+            .ok_or_else(|| ApplicationError::ExternalError("invalid TIFF byte_order".into()))?;
+        let mut ifd = Self {
+            bytes,
+            byte_order,
+            entries: HashMap::new(),
+        };
+
+        let version = ifd.read_u16_at(2)?;
+        if version != 0x2A {
+            return Err(ApplicationError::ExternalError(format!(
+                "invalid TIFF version {:X} (BigTIFF is not supported)",
+                version
+            )));
+        }
+
+        let ifd_offset = ifd.read_u32_at(4)? as usize;
+        let entry_count = ifd.read_u16_at(ifd_offset)? as usize;
+        for i in 0..entry_count {
+            let pos = ifd_offset + 2 + i * 12;
+            if let Some(tag) = IfdTag::from_u16(ifd.read_u16_at(pos)?) {
+                let entry = IfdEntry {
+                    datatype: ifd.read_u16_at(pos + 2)?,
+                    count: ifd.read_u32_at(pos + 4)? as usize,
+                    value_pos: pos + 8,
+                };
+                ifd.entries.insert(tag, entry);
+            }
+        }
+        Ok(ifd)
+    }
+
+    fn slice(&self, pos: usize, len: usize) -> ApplicationResult<&'a [u8]> {
+        self.bytes.get(pos..pos + len).ok_or_else(|| {
+            ApplicationError::ExternalError(format!(
+                "Failed to read {} bytes at {} (out of the file)",
+                len, pos
+            ))
+        })
+    }
+
+    fn read_u16_at(&self, pos: usize) -> ApplicationResult<u16> {
+        Ok(self.byte_order.read_u16(self.slice(pos, 2)?))
+    }
+
+    fn read_u32_at(&self, pos: usize) -> ApplicationResult<u32> {
+        Ok(self.byte_order.read_u32(self.slice(pos, 4)?))
+    }
+
+    fn has(&self, tag: IfdTag) -> bool {
+        self.entries.contains_key(&tag)
+    }
+
+    /// タグの値のバイト列と，値1つあたりのバイト数
+    fn values(&self, tag: IfdTag) -> ApplicationResult<Option<(&'a [u8], &IfdEntry)>> {
+        let entry = match self.entries.get(&tag) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let size = entry.type_size().ok_or_else(|| {
+            ApplicationError::ExternalError(format!(
+                "Unknown TIFF field type {} of {:?}",
+                entry.datatype, tag
+            ))
+        })?;
+        let len = size * entry.count;
+        let pos = if len <= 4 {
+            entry.value_pos
+        } else {
+            self.read_u32_at(entry.value_pos)? as usize
+        };
+        Ok(Some((self.slice(pos, len)?, entry)))
+    }
+
+    fn uints(&self, tag: IfdTag) -> ApplicationResult<Option<Vec<usize>>> {
+        let (buf, entry) = match self.values(tag.clone())? {
+            Some(values) => values,
+            None => return Ok(None),
+        };
+        let values = match entry.datatype {
+            1 => buf.iter().map(|byte| *byte as usize).collect(),
+            3 => buf
+                .chunks_exact(2)
+                .map(|value| self.byte_order.read_u16(value) as usize)
+                .collect(),
+            4 => buf
+                .chunks_exact(4)
+                .map(|value| self.byte_order.read_u32(value) as usize)
+                .collect(),
+            datatype => {
+                return Err(ApplicationError::ExternalError(format!(
+                    "Expected unsigned integers for {:?}, but got field type {}",
+                    tag, datatype
+                )))
+            }
+        };
+        Ok(Some(values))
+    }
+
+    fn uint(&self, tag: IfdTag) -> ApplicationResult<Option<usize>> {
+        Ok(self.uints(tag)?.and_then(|values| values.first().copied()))
+    }
+
+    fn doubles(&self, tag: IfdTag) -> ApplicationResult<Option<Vec<f64>>> {
+        let (buf, entry) = match self.values(tag.clone())? {
+            Some(values) => values,
+            None => return Ok(None),
+        };
+        match entry.datatype {
+            12 => Ok(Some(
+                buf.chunks_exact(8)
+                    .map(|value| self.byte_order.read_f64(value))
+                    .collect(),
+            )),
+            datatype => Err(ApplicationError::ExternalError(format!(
+                "Expected doubles for {:?}, but got field type {}",
+                tag, datatype
+            ))),
+        }
+    }
+
+    fn ascii(&self, tag: IfdTag) -> ApplicationResult<Option<String>> {
+        Ok(self.values(tag)?.map(|(buf, _)| {
+            String::from_utf8_lossy(buf)
+                .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+                .to_string()
+        }))
+    }
+
+    fn require<T>(tag: IfdTag, value: Option<T>) -> ApplicationResult<T> {
+        value
+            .ok_or_else(|| ApplicationError::ExternalError(format!("Failed to find tag {:?}", tag)))
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Compression {
+    None,
+    Lzw,
+    Deflate,
+}
+
+impl Compression {
+    fn from_tag(value: usize) -> ApplicationResult<Self> {
+        match value {
+            1 => Ok(Self::None),
+            5 => Ok(Self::Lzw),
+            8 | 32946 => Ok(Self::Deflate),
+            _ => Err(ApplicationError::ExternalError(format!(
+                "Unsupported TIFF compression {}",
+                value
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum SampleType {
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl SampleType {
+    fn from_tags(sample_format: usize, bits_per_sample: usize) -> ApplicationResult<Self> {
+        match (sample_format, bits_per_sample) {
+            (2, 16) => Ok(Self::I16),
+            (1, 16) => Ok(Self::U16),
+            (2, 32) => Ok(Self::I32),
+            (1, 32) => Ok(Self::U32),
+            (3, 32) => Ok(Self::F32),
+            (3, 64) => Ok(Self::F64),
+            _ => Err(ApplicationError::ExternalError(format!(
+                "Unsupported TIFF sample (format: {}, bits: {})",
+                sample_format, bits_per_sample
+            ))),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+
+    fn is_integer(self) -> bool {
+        !matches!(self, Self::F32 | Self::F64)
+    }
+
+    fn read(self, byte_order: TiffByteOrder, buf: &[u8]) -> f64 {
+        match self {
+            Self::I16 => byte_order.read_i16(buf).into(),
+            Self::U16 => byte_order.read_u16(buf).into(),
+            Self::I32 => byte_order.read_i32(buf).into(),
+            Self::U32 => byte_order.read_u32(buf).into(),
+            Self::F32 => byte_order.read_f32(buf).into(),
+            Self::F64 => byte_order.read_f64(buf),
+        }
+    }
+
+    /// no_dataの値をこの型で表せる値に揃える
+    fn normalize(self, value: f64) -> f64 {
+        match self {
+            Self::F32 => f64::from(value as f32),
+            Self::F64 => value,
+            _ => value.round(),
+        }
+    }
+}
+
+/// ピクセルを格納する単位(ストリップまたはタイル)の大きさ
+struct ChunkLayout {
+    width: usize,
+    height: usize,
+    /// 東西方向に並ぶ数
+    across: usize,
+}
+
+/// struct to process single band DEM GeoTIFF files
+/// (e.g. srtm 30x30 GeoTIFF files from https://srtm.csi.cgiar.org/)
+///
+/// ストリップ・タイルのどちらの配置にも対応し，無圧縮・LZW・DEFLATEを読める
+///
+/// ファイル全体をメモリマップしておき，標高の読み出しではファイルを開き直さない
+/// (ページキャッシュがリクエスト間で共有されるキャッシュとして働く)
+/// 圧縮されたファイルでは，展開したストリップ・タイルをLRUキャッシュに持っておく
+pub(super) struct GeoTiffFile {
+    path: PathBuf,
+    header: GeoTiffHeader,
+    mmap: Mmap,
+    decoded_chunks: Mutex<LruCache<usize, Arc<Vec<Option<f32>>>>>,
+}
+
+/// IFDから読み取った，ピクセルの読み方
+struct GeoTiffHeader {
+    grid: DemGrid,
+    byte_order: TiffByteOrder,
+    sample_type: SampleType,
+    compression: Compression,
+    /// 水平差分(Predictor=2)がかかっているか
+    horizontal_predictor: bool,
+    layout: ChunkLayout,
+    chunk_offsets: Vec<usize>,
+    chunk_byte_counts: Vec<usize>,
+    no_data_value: Option<f64>,
+}
+
+impl GeoTiffHeader {
+    fn from_ifd(ifd: &Ifd) -> ApplicationResult<Self> {
+        let width = Ifd::require(IfdTag::ImageWidth, ifd.uint(IfdTag::ImageWidth)?)?;
+        let height = Ifd::require(IfdTag::ImageHeight, ifd.uint(IfdTag::ImageHeight)?)?;
+
+        let samples_per_pixel = ifd.uint(IfdTag::SamplesPerPixel)?.unwrap_or(1);
+        if samples_per_pixel != 1 {
+            return Err(ApplicationError::ExternalError(format!(
+                "Expected a single band DEM, but got {} samples per pixel",
+                samples_per_pixel
+            )));
+        }
+        // TIFFの既定値は1bitの符号なし整数だが，省略されていればSRTMと同じ16bitの符号付き整数とみなす
+        // (符号なしで読むと，海面下の値や-32768の欠測値が正の大きな値になる)
+        let sample_type = SampleType::from_tags(
+            ifd.uint(IfdTag::SampleFormat)?.unwrap_or(2),
+            ifd.uint(IfdTag::BitsPerSample)?.unwrap_or(16),
+        )?;
+        let compression = Compression::from_tag(ifd.uint(IfdTag::Compression)?.unwrap_or(1))?;
+        let horizontal_predictor = match ifd.uint(IfdTag::Predictor)?.unwrap_or(1) {
+            1 => false,
+            2 if sample_type.is_integer() => true,
+            predictor => {
+                return Err(ApplicationError::ExternalError(format!(
+                    "Unsupported TIFF predictor {} for {:?}",
+                    predictor, sample_type
+                )))
+            }
+        };
+
+        let (layout, chunk_offsets, chunk_byte_counts) = if ifd.has(IfdTag::TileOffsets) {
+            let tile_width = Ifd::require(IfdTag::TileWidth, ifd.uint(IfdTag::TileWidth)?)?;
+            let tile_height = Ifd::require(IfdTag::TileLength, ifd.uint(IfdTag::TileLength)?)?;
+            (
+                ChunkLayout {
+                    width: tile_width,
+                    height: tile_height,
+                    across: (width + tile_width - 1) / tile_width.max(1),
+                },
+                Ifd::require(IfdTag::TileOffsets, ifd.uints(IfdTag::TileOffsets)?)?,
+                Ifd::require(IfdTag::TileByteCounts, ifd.uints(IfdTag::TileByteCounts)?)?,
+            )
+        } else {
+            let strip_offsets =
+                Ifd::require(IfdTag::StripOffsets, ifd.uints(IfdTag::StripOffsets)?)?;
+            let rows_per_strip = ifd
+                .uint(IfdTag::RowsPerStrip)?
+                .unwrap_or_else(|| (height + strip_offsets.len() - 1) / strip_offsets.len().max(1))
+                .min(height);
+            // 無圧縮なら，StripByteCountsがなくても大きさは決まる
+            let strip_byte_counts = match ifd.uints(IfdTag::StripByteCounts)? {
+                Some(counts) => counts,
+                None => vec![width * rows_per_strip * sample_type.size(); strip_offsets.len()],
+            };
+            (
+                ChunkLayout {
+                    width,
+                    height: rows_per_strip,
+                    across: 1,
+                },
+                strip_offsets,
+                strip_byte_counts,
+            )
+        };
+        let chunk_count = layout.across * ((height + layout.height - 1) / layout.height.max(1));
+        if layout.width == 0
+            || layout.height == 0
+            || chunk_offsets.len() < chunk_count
+            || chunk_byte_counts.len() < chunk_count
+        {
+            return Err(ApplicationError::ExternalError(format!(
+                "Inconsistent strips/tiles ({} offsets and {} byte counts for {} chunks)",
+                chunk_offsets.len(),
+                chunk_byte_counts.len(),
+                chunk_count
+            )));
+        }
+
+        let pixel_scale = Ifd::require(
+            IfdTag::ModelPixelScale,
+            ifd.doubles(IfdTag::ModelPixelScale)?,
+        )?;
+        let tiepoint = Ifd::require(IfdTag::ModelTiepoint, ifd.doubles(IfdTag::ModelTiepoint)?)?;
+        if pixel_scale.len() < 2 || tiepoint.len() < 6 {
+            return Err(ApplicationError::ExternalError(
+                "Invalid ModelPixelScale or ModelTiepoint".into(),
+            ));
+        }
+        let scale = (pixel_scale[0], pixel_scale[1]);
+        // ラスタ上の(i, j)が(x, y)に対応する
+        let left = tiepoint[3] - tiepoint[0] * scale.0;
+        let top = tiepoint[4] + tiepoint[1] * scale.1;
+        let grid = if Self::is_pixel_is_point(ifd)? {
+            DemGrid::from_center(left, top, scale, width, height)?
+        } else {
+            DemGrid::from_corner(left, top, scale, width, height)?
+        };
+
+        let no_data_value = match ifd.ascii(IfdTag::NoDataValue)? {
+            Some(value) => Some(value.parse::<f64>().map_err(|_| {
+                ApplicationError::ExternalError(format!("Invalid NO_DATA value {:?}", value))
+            })?),
+            None => None,
+        }
+        .map(|value| sample_type.normalize(value));
+
+        Ok(Self {
+            grid,
+            byte_order: ifd.byte_order,
+            sample_type,
+            compression,
+            horizontal_predictor,
+            layout,
+            chunk_offsets,
+            chunk_byte_counts,
+            no_data_value,
+        })
+    }
+
+    /// GeoKeyDirectoryのGTRasterTypeGeoKey(1025)がRasterPixelIsPoint(2)かどうか
+    fn is_pixel_is_point(ifd: &Ifd) -> ApplicationResult<bool> {
+        let keys = match ifd.uints(IfdTag::GeoKeyDirectory)? {
+            Some(keys) => keys,
+            None => return Ok(false),
+        };
+        Ok(keys
+            .chunks_exact(4)
+            .skip(1)
+            .any(|key| key[0] == 1025 && key[1] == 0 && key[3] == 2))
+    }
+
+    fn decode_sample(&self, buf: &[u8]) -> Option<f64> {
+        let value = self.sample_type.read(self.byte_order, buf);
+        if value.is_nan() || Some(value) == self.no_data_value {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    /// 各行で左隣との差分になっている値を元に戻す
+    fn undo_horizontal_predictor(&self, bytes: &mut [u8]) {
+        let size = self.sample_type.size();
+        for row in bytes.chunks_mut(self.layout.width * size) {
+            for i in 1..row.len() / size {
+                let (prev, cur) = row[(i - 1) * size..(i + 1) * size].split_at_mut(size);
+                match size {
+                    2 => {
+                        let value = self
+                            .byte_order
+                            .read_u16(prev)
+                            .wrapping_add(self.byte_order.read_u16(cur));
+                        self.byte_order.write_u16(cur, value)
+                    }
+                    _ => {
+                        let value = self
+                            .byte_order
+                            .read_u32(prev)
+                            .wrapping_add(self.byte_order.read_u32(cur));
+                        self.byte_order.write_u32(cur, value)
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl GeoTiffFile {
+    pub fn open(path: &Path) -> ApplicationResult<Self> {
+        let file = File::open(path).map_err(cvt_err(format!("Failed to open {:?}", path)))?;
+        // SAFETY: タイルはサーバーの起動中に書き換えられない前提
+        let mmap =
+            unsafe { Mmap::map(&file) }.map_err(cvt_err(format!("Failed to mmap {:?}", path)))?;
+
+        let header = Ifd::read(&mmap)
+            .and_then(|ifd| GeoTiffHeader::from_ifd(&ifd))
+            .map_err(|err| {
+                ApplicationError::ExternalError(format!(
+                    "Failed to read GeoTIFF header from {:?}: {}",
+                    path, err
+                ))
+            })?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            header,
+            mmap,
+            decoded_chunks: Mutex::new(LruCache::new(DECODED_CHUNK_CACHE_SIZE)),
+        })
+    }
+
+    fn decoded_chunk(&self, chunk: usize) -> ApplicationResult<Arc<Vec<Option<f32>>>> {
+        if let Some(decoded) = self
+            .decoded_chunks
+            .lock()
+            .ok()
+            .and_then(|mut cache| cache.get(&chunk).cloned())
+        {
+            return Ok(decoded);
+        }
+
+        let decoded = Arc::new(self.decode_chunk(chunk)?);
+        if let Ok(mut cache) = self.decoded_chunks.lock() {
+            cache.put(chunk, decoded.clone());
+        }
+        Ok(decoded)
+    }
+
+    fn decode_chunk(&self, chunk: usize) -> ApplicationResult<Vec<Option<f32>>> {
+        let (offset, byte_count) = (
+            self.header.chunk_offsets[chunk],
+            self.header.chunk_byte_counts[chunk],
+        );
+        let raw = self.mmap.get(offset..offset + byte_count).ok_or_else(|| {
+            ApplicationError::ExternalError(format!(
+                "Chunk {} of {:?} is out of the file",
+                chunk, self.path
+            ))
+        })?;
+
+        let mut bytes = match self.header.compression {
+            Compression::None => raw.to_vec(),
+            Compression::Lzw => {
+                weezl::decode::Decoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+                    .decode(raw)
+                    .map_err(|err| {
+                        ApplicationError::ExternalError(format!(
+                            "Failed to decode LZW chunk {} of {:?} ({})",
+                            chunk, self.path, err
+                        ))
+                    })?
+            }
+            Compression::Deflate => {
+                let mut bytes = Vec::new();
+                ZlibDecoder::new(raw)
+                    .read_to_end(&mut bytes)
+                    .map_err(cvt_err(format!(
+                        "Failed to decode DEFLATE chunk {} of {:?}",
+                        chunk, self.path
+                    )))?;
+                bytes
+            }
+        };
+        let header = &self.header;
+        if header.horizontal_predictor {
+            header.undo_horizontal_predictor(&mut bytes);
+        }
+
+        Ok(bytes
+            .chunks_exact(header.sample_type.size())
+            .map(|buf| header.decode_sample(buf).map(|value| value as f32))
+            .collect())
+    }
+}
+
+impl DemTile for GeoTiffFile {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn grid(&self) -> &DemGrid {
+        &self.header.grid
+    }
+
+    fn read_pixel(&self, col: usize, row: usize) -> ApplicationResult<Option<f64>> {
+        let header = &self.header;
+        let layout = &header.layout;
+        let chunk = row / layout.height * layout.across + col / layout.width;
+        let index = row % layout.height * layout.width + col % layout.width;
+
+        if matches!(header.compression, Compression::None) && !header.horizontal_predictor {
+            let size = header.sample_type.size();
+            let offset = header.chunk_offsets[chunk] + index * size;
+            let buf = self.mmap.get(offset..offset + size).ok_or_else(|| {
+                ApplicationError::ExternalError(format!(
+                    "Failed to read pixel ({}, {}) from {:?} (offset {} is out of the file)",
+                    col, row, self.path, offset
+                ))
+            })?;
+            return Ok(header.decode_sample(buf));
+        }
+
+        self.decoded_chunk(chunk)?
+            .get(index)
+            .map(|value| value.map(f64::from))
+            .ok_or_else(|| {
+                ApplicationError::ExternalError(format!(
+                    "Failed to read pixel ({}, {}) from {:?} (chunk {} is too short)",
+                    col, row, self.path, chunk
+                ))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+
+    use super::super::dem_tile::tests::temp_dir;
+    use super::*;
+
+    const WIDTH: usize = 5;
+    const HEIGHT: usize = 3;
+    const ROWS_PER_STRIP: usize = 2;
+    const NO_DATA_VALUE: i16 = -32768;
+    const NO_DATA_PIXEL: (usize, usize) = (1, 1);
+    const BELOW_SEA_PIXEL: (usize, usize) = (4, 2);
+
+    /// (col, row)のピクセルの値
+    fn pixel(col: usize, row: usize) -> i16 {
+        if (col, row) == NO_DATA_PIXEL {
+            NO_DATA_VALUE
+        } else if (col, row) == BELOW_SEA_PIXEL {
+            -12
+        } else {
+            (row * 100 + col) as i16
+        }
+    }
+
+    /// テスト用のGeoTIFFを組み立てる
+    ///
+    /// 南北0.5度・東西0.25度のピクセルが，北西端(139, 36)から5x3個並ぶ
+    struct TestTiff {
+        byte_order: TiffByteOrder,
+        compression: u16,
+        predictor: bool,
+        /// タイル配置の場合のタイルの1辺 (Noneならストリップ配置)
+        tile_size: Option<usize>,
+        /// BitsPerSampleとSampleFormat(i16)を書くか
+        sample_tags: bool,
+        no_data: bool,
+    }
+
+    impl Default for TestTiff {
+        fn default() -> Self {
+            Self {
+                byte_order: TiffByteOrder::LittleEndian,
+                compression: 1,
+                predictor: false,
+                tile_size: None,
+                sample_tags: true,
+                no_data: true,
+            }
+        }
+    }
+
+    impl TestTiff {
+        /// (左端の列, 上端の行, 幅, 高さ)
+        fn chunks(&self) -> Vec<(usize, usize, usize, usize)> {
+            match self.tile_size {
+                Some(size) => (0..HEIGHT)
+                    .step_by(size)
+                    .flat_map(|top| {
+                        (0..WIDTH)
+                            .step_by(size)
+                            .map(move |left| (left, top, size, size))
+                    })
+                    .collect(),
+                None => (0..HEIGHT)
+                    .step_by(ROWS_PER_STRIP)
+                    .map(|row| (0, row, WIDTH, ROWS_PER_STRIP.min(HEIGHT - row)))
+                    .collect(),
+            }
+        }
+
+        fn encode_chunk(
+            &self,
+            (left, top, width, height): (usize, usize, usize, usize),
+        ) -> Vec<u8> {
+            let mut raw = vec![0; width * height * 2];
+            for row in 0..height {
+                let mut prev = 0u16;
+                for col in 0..width {
+                    // タイルの画像の外側は0で埋める
+                    let value = if left + col < WIDTH && top + row < HEIGHT {
+                        pixel(left + col, top + row) as u16
+                    } else {
+                        0
+                    };
+                    let stored = if self.predictor {
+                        value.wrapping_sub(prev)
+                    } else {
+                        value
+                    };
+                    prev = value;
+                    let pos = (row * width + col) * 2;
+                    self.byte_order.write_u16(&mut raw[pos..pos + 2], stored);
+                }
+            }
+            match self.compression {
+                5 => weezl::encode::Encoder::with_tiff_size_switch(weezl::BitOrder::Msb, 8)
+                    .encode(&raw)
+                    .unwrap(),
+                8 => {
+                    let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                    encoder.write_all(&raw).unwrap();
+                    encoder.finish().unwrap()
+                }
+                _ => raw,
+            }
+        }
+
+        fn shorts(&self, values: &[u16]) -> (u16, Vec<u8>) {
+            let mut buf = vec![0; values.len() * 2];
+            for (i, value) in values.iter().enumerate() {
+                self.byte_order.write_u16(&mut buf[i * 2..], *value);
+            }
+            (3, buf)
+        }
+
+        fn longs(&self, values: &[usize]) -> (u16, Vec<u8>) {
+            let mut buf = vec![0; values.len() * 4];
+            for (i, value) in values.iter().enumerate() {
+                self.byte_order.write_u32(&mut buf[i * 4..], *value as u32);
+            }
+            (4, buf)
+        }
+
+        fn doubles(&self, values: &[f64]) -> (u16, Vec<u8>) {
+            let mut buf = vec![0; values.len() * 8];
+            for (i, value) in values.iter().enumerate() {
+                self.byte_order.write_f64(&mut buf[i * 8..], *value);
+            }
+            (12, buf)
+        }
+
+        fn bytes(&self) -> Vec<u8> {
+            // ヘッダは最後に書く
+            let mut bytes = vec![0; 8];
+            let (mut offsets, mut byte_counts) = (Vec::new(), Vec::new());
+            for chunk in self.chunks() {
+                let encoded = self.encode_chunk(chunk);
+                offsets.push(bytes.len());
+                byte_counts.push(encoded.len());
+                bytes.extend(encoded);
+            }
+
+            let mut entries = vec![
+                (IfdTag::ImageWidth, self.longs(&[WIDTH])),
+                (IfdTag::ImageHeight, self.longs(&[HEIGHT])),
+                (IfdTag::Compression, self.shorts(&[self.compression])),
+                (IfdTag::SamplesPerPixel, self.shorts(&[1])),
+                (IfdTag::ModelPixelScale, self.doubles(&[0.25, 0.5, 0.])),
+                (
+                    IfdTag::ModelTiepoint,
+                    self.doubles(&[0., 0., 0., 139., 36., 0.]),
+                ),
+            ];
+            match self.tile_size {
+                Some(size) => entries.extend(vec![
+                    (IfdTag::TileWidth, self.longs(&[size])),
+                    (IfdTag::TileLength, self.longs(&[size])),
+                    (IfdTag::TileOffsets, self.longs(&offsets)),
+                    (IfdTag::TileByteCounts, self.longs(&byte_counts)),
+                ]),
+                None => entries.extend(vec![
+                    (IfdTag::StripOffsets, self.longs(&offsets)),
+                    (IfdTag::RowsPerStrip, self.longs(&[ROWS_PER_STRIP])),
+                    (IfdTag::StripByteCounts, self.longs(&byte_counts)),
+                ]),
+            }
+            if self.predictor {
+                entries.push((IfdTag::Predictor, self.shorts(&[2])));
+            }
+            if self.sample_tags {
+                entries.push((IfdTag::BitsPerSample, self.shorts(&[16])));
+                entries.push((IfdTag::SampleFormat, self.shorts(&[2])));
+            }
+            if self.no_data {
+                let value = format!("{}\0", NO_DATA_VALUE);
+                entries.push((IfdTag::NoDataValue, (2, value.into_bytes())));
+            }
+            entries.sort_by_key(|(tag, _)| tag.clone() as u16);
+
+            // 4byteを超える値はIFDの外に置く
+            let mut fields = Vec::new();
+            for (tag, (datatype, value)) in entries {
+                let size = IfdEntry {
+                    datatype,
+                    count: 0,
+                    value_pos: 0,
+                }
+                .type_size()
+                .unwrap();
+                let count = value.len() / size;
+                let mut field = vec![0; 4];
+                if value.len() <= 4 {
+                    field[..value.len()].copy_from_slice(&value);
+                } else {
+                    self.byte_order.write_u32(&mut field, bytes.len() as u32);
+                    bytes.extend(value);
+                }
+                fields.push((tag.clone() as u16, datatype, count, field));
+            }
+
+            let ifd_offset = bytes.len();
+            let mut ifd = vec![0; 2 + fields.len() * 12 + 4];
+            self.byte_order.write_u16(&mut ifd, fields.len() as u16);
+            for (i, (tag, datatype, count, field)) in fields.into_iter().enumerate() {
+                let pos = 2 + i * 12;
+                self.byte_order.write_u16(&mut ifd[pos..], tag);
+                self.byte_order.write_u16(&mut ifd[pos + 2..], datatype);
+                self.byte_order.write_u32(&mut ifd[pos + 4..], count as u32);
+                ifd[pos + 8..pos + 12].copy_from_slice(&field);
+            }
+            bytes.extend(ifd);
+
+            let byte_order_mark = self.byte_order as u16;
+            bytes[0..2].copy_from_slice(&byte_order_mark.to_le_bytes());
+            self.byte_order.write_u16(&mut bytes[2..4], 0x2A);
+            self.byte_order
+                .write_u32(&mut bytes[4..8], ifd_offset as u32);
+            bytes
+        }
+
+        fn open(&self, test_name: &str) -> ApplicationResult<GeoTiffFile> {
+            let dir = temp_dir(test_name);
+            let path = dir.join("N35E139.tif");
+            fs::write(&path, self.bytes()).unwrap();
+            let file = GeoTiffFile::open(&path);
+            // メモリマップはファイルを消しても読める
+            fs::remove_dir_all(dir).unwrap();
+            file
+        }
+    }
+
+    fn assert_reads_all_pixels(tiff: TestTiff, test_name: &str) {
+        let file = tiff.open(test_name).unwrap();
+
+        let grid = file.grid();
+        assert_eq!((grid.width(), grid.height()), (WIDTH, HEIGHT));
+        assert_eq!(grid.lat_range().start.value(), 34.5);
+        assert_eq!(grid.lon_range().end.value(), 140.25);
+        for row in 0..HEIGHT {
+            for col in 0..WIDTH {
+                let expected = Some(pixel(col, row) as f64).filter(|_| (col, row) != NO_DATA_PIXEL);
+                assert_eq!(
+                    file.read_pixel(col, row).unwrap(),
+                    expected,
+                    "pixel ({}, {})",
+                    col,
+                    row
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn can_read_uncompressed_strips() {
+        assert_reads_all_pixels(TestTiff::default(), "tiff_strips");
+    }
+
+    #[test]
+    fn can_read_big_endian() {
+        let tiff = TestTiff {
+            byte_order: TiffByteOrder::BigEndian,
+            ..Default::default()
+        };
+        assert_reads_all_pixels(tiff, "tiff_big_endian");
+    }
+
+    #[test]
+    fn can_read_lzw() {
+        let tiff = TestTiff {
+            compression: 5,
+            ..Default::default()
+        };
+        assert_reads_all_pixels(tiff, "tiff_lzw");
+    }
+
+    #[test]
+    fn can_read_deflate() {
+        let tiff = TestTiff {
+            compression: 8,
+            ..Default::default()
+        };
+        assert_reads_all_pixels(tiff, "tiff_deflate");
+    }
+
+    #[test]
+    fn can_read_horizontal_predictor() {
+        let tiff = TestTiff {
+            compression: 8,
+            predictor: true,
+            ..Default::default()
+        };
+        assert_reads_all_pixels(tiff, "tiff_predictor");
+    }
+
+    #[test]
+    fn can_read_tiles() {
+        let tiff = TestTiff {
+            tile_size: Some(2),
+            ..Default::default()
+        };
+        assert_reads_all_pixels(tiff, "tiff_tiles");
+    }
+
+    #[test]
+    fn can_read_compressed_tiles_with_predictor() {
+        let tiff = TestTiff {
+            compression: 5,
+            predictor: true,
+            tile_size: Some(2),
+            ..Default::default()
+        };
+        assert_reads_all_pixels(tiff, "tiff_lzw_tiles");
+    }
+
+    #[test]
+    fn reads_as_i16_without_sample_tags() {
+        let tiff = TestTiff {
+            sample_tags: false,
+            ..Default::default()
+        };
+        assert_reads_all_pixels(tiff, "tiff_default_sample");
+    }
+
+    #[test]
+    fn cannot_read_unsupported_compression() {
+        let tiff = TestTiff {
+            compression: 7,
+            ..Default::default()
+        };
+        assert!(tiff.open("tiff_jpeg").is_err());
+    }
+}
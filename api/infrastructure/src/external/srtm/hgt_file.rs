@@ -0,0 +1,151 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use byteorder::{BigEndian, ByteOrder};
+use memmap2::Mmap;
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+use super::dem_tile::{cvt_err, DemGrid, DemTile};
+
+const HGT_NO_DATA_VALUE: i16 = -32768;
+
+static HGT_FILE_NAME_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)^([NS])(\d{2})([EW])(\d{3})\.hgt$").unwrap());
+
+/// struct to process SRTM .hgt files (e.g. N35E139.hgt)
+///
+/// 範囲はファイル名の南西端から1度四方で，1秒角(3601x3601)か3秒角(1201x1201)の
+/// ビッグエンディアンのi16が北の行から並んでいる．端の行・列は隣のタイルと重複する
+pub(super) struct HgtFile {
+    path: PathBuf,
+    grid: DemGrid,
+    mmap: Mmap,
+}
+
+impl HgtFile {
+    pub fn open(path: &Path) -> ApplicationResult<Self> {
+        let (lat, lon) = Self::parse_file_name(path)?;
+
+        let file = File::open(path).map_err(cvt_err(format!("Failed to open {:?}", path)))?;
+        // SAFETY: タイルはサーバーの起動中に書き換えられない前提
+        let mmap =
+            unsafe { Mmap::map(&file) }.map_err(cvt_err(format!("Failed to mmap {:?}", path)))?;
+
+        let size = ((mmap.len() / 2) as f64).sqrt() as usize;
+        if size < 2 || size * size * 2 != mmap.len() {
+            return Err(ApplicationError::ExternalError(format!(
+                "{:?} is not a square grid of i16 ({} bytes)",
+                path,
+                mmap.len()
+            )));
+        }
+        let scale = 1. / (size - 1) as f64;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            grid: DemGrid::from_center(lon, lat + 1., (scale, scale), size, size)?,
+            mmap,
+        })
+    }
+
+    /// ファイル名から南西端の(緯度, 経度)を読む
+    fn parse_file_name(path: &Path) -> ApplicationResult<(f64, f64)> {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        let caps = HGT_FILE_NAME_REGEX.captures(name).ok_or_else(|| {
+            ApplicationError::ExternalError(format!(
+                "Failed to get the location of {:?} from its name (expected e.g. N35E139.hgt)",
+                path
+            ))
+        })?;
+        let sign = |hemisphere: &str, positive: &str| {
+            if hemisphere.eq_ignore_ascii_case(positive) {
+                1.
+            } else {
+                -1.
+            }
+        };
+        let lat = sign(&caps[1], "N") * caps[2].parse::<f64>().unwrap();
+        let lon = sign(&caps[3], "E") * caps[4].parse::<f64>().unwrap();
+        Ok((lat, lon))
+    }
+}
+
+impl DemTile for HgtFile {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn grid(&self) -> &DemGrid {
+        &self.grid
+    }
+
+    fn read_pixel(&self, col: usize, row: usize) -> ApplicationResult<Option<f64>> {
+        let offset = (row * self.grid.width() + col) * 2;
+        let data = BigEndian::read_i16(&self.mmap[offset..offset + 2]);
+        Ok(Some(data)
+            .filter(|data| *data != HGT_NO_DATA_VALUE)
+            .map(f64::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use byteorder::WriteBytesExt;
+
+    use super::super::dem_tile::tests::temp_dir;
+    use super::*;
+
+    #[test]
+    fn can_parse_file_name() {
+        assert_eq!(
+            HgtFile::parse_file_name(Path::new("dem/N35E139.hgt")).ok(),
+            Some((35., 139.))
+        );
+        assert_eq!(
+            HgtFile::parse_file_name(Path::new("s12w077.HGT")).ok(),
+            Some((-12., -77.))
+        );
+        assert!(HgtFile::parse_file_name(Path::new("N35E139.tif")).is_err());
+        assert!(HgtFile::parse_file_name(Path::new("N5E139.hgt")).is_err());
+    }
+
+    #[test]
+    fn can_open_hgt_file() {
+        let dir = temp_dir("hgt");
+        let path = dir.join("N35E139.hgt");
+        let mut bytes = Vec::new();
+        for value in [1, 2, 3, 4, HGT_NO_DATA_VALUE, 6, 7, 8, 9] {
+            bytes.write_i16::<BigEndian>(value).unwrap();
+        }
+        fs::write(&path, bytes).unwrap();
+
+        let file = HgtFile::open(&path).unwrap();
+        assert_eq!((file.grid().width(), file.grid().height()), (3, 3));
+        assert_eq!(file.grid().lat_range().start.value(), 35.);
+        assert_eq!(file.grid().lon_range().end.value(), 140.);
+        assert_eq!(file.read_pixel(2, 0).unwrap(), Some(3.));
+        assert_eq!(file.read_pixel(1, 1).unwrap(), None);
+        assert_eq!(file.read_pixel(0, 2).unwrap(), Some(7.));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cannot_open_non_square_file() {
+        let dir = temp_dir("hgt_non_square");
+        let path = dir.join("N35E139.hgt");
+        fs::write(&path, vec![0; 2 * 6]).unwrap();
+
+        assert!(HgtFile::open(&path).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use route_bucket_domain::model::route::Coordinate;
+
+use super::dem_tile::DemTile;
+
+/// 座標が属する1度四方のグリッド(南西端の緯度, 経度)
+pub(super) fn grid_cell(coord: &Coordinate) -> (i32, i32) {
+    (
+        coord.latitude().value().floor() as i32,
+        coord.longitude().value().floor() as i32,
+    )
+}
+
+/// DemTileの範囲を1度四方のグリッドに登録した空間インデックス
+pub(super) struct SrtmTileIndex {
+    tiles: Vec<Box<dyn DemTile>>,
+    grid: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl SrtmTileIndex {
+    pub fn new(tiles: Vec<Box<dyn DemTile>>) -> Self {
+        let mut grid: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (i, tile) in tiles.iter().enumerate() {
+            let tile_grid = tile.grid();
+            // 北端・東端の境界上の点も引けるように，端を含むグリッドまで登録する
+            let lat_cells = tile_grid.lat_range().start.value().floor() as i32
+                ..=tile_grid.lat_range().end.value().floor() as i32;
+            let lon_cells = tile_grid.lon_range().start.value().floor() as i32
+                ..=tile_grid.lon_range().end.value().floor() as i32;
+            for lat in lat_cells {
+                for lon in lon_cells.clone() {
+                    grid.entry((lat, lon)).or_default().push(i);
+                }
+            }
+        }
+        // 複数の形式・解像度のタイルが重なる場合は，高解像度のものを優先する
+        for indices in grid.values_mut() {
+            indices.sort_by(|&i, &j| {
+                tiles[i]
+                    .grid()
+                    .pixel_area()
+                    .partial_cmp(&tiles[j].grid().pixel_area())
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+        Self { tiles, grid }
+    }
+
+    pub fn tiles(&self) -> &[Box<dyn DemTile>] {
+        &self.tiles
+    }
+
+    /// `coord`の標高を持つタイルを探す
+    ///
+    /// タイルの境目の点は，その点を南端・西端に含むタイルを優先し，
+    /// なければ北端・東端に含むタイルを使う
+    pub fn find(&self, coord: &Coordinate) -> Option<&dyn DemTile> {
+        let candidates = self
+            .grid
+            .get(&grid_cell(coord))?
+            .iter()
+            .map(|&i| self.tiles[i].as_ref());
+        candidates
+            .clone()
+            .find(|tile| tile.grid().contains(coord))
+            .or_else(|| candidates.clone().find(|tile| tile.grid().covers(coord)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::dem_tile::tests::GridTile;
+    use super::*;
+
+    fn find_path(index: &SrtmTileIndex, lat: f64, lon: f64) -> Option<String> {
+        index
+            .find(&Coordinate::new(lat, lon).unwrap())
+            .map(|tile| tile.path().to_string_lossy().to_string())
+    }
+
+    #[test]
+    fn can_find_tile() {
+        let index = SrtmTileIndex::new(vec![
+            Box::new(GridTile::new(139., 36., 0.5, 3)),
+            Box::new(GridTile::new(140., 36., 0.5, 3)),
+        ]);
+        assert_eq!(
+            find_path(&index, 35.5, 139.5),
+            Some("grid_139_36_0.5".into())
+        );
+        assert_eq!(
+            find_path(&index, 35.5, 140.5),
+            Some("grid_140_36_0.5".into())
+        );
+        assert_eq!(find_path(&index, 34.5, 139.5), None);
+    }
+
+    #[test]
+    fn prefers_tile_containing_boundary_at_south_west() {
+        let index = SrtmTileIndex::new(vec![
+            Box::new(GridTile::new(139., 36., 0.5, 3)),
+            Box::new(GridTile::new(140., 36., 0.5, 3)),
+        ]);
+        assert_eq!(
+            find_path(&index, 35.5, 140.),
+            Some("grid_140_36_0.5".into())
+        );
+        // 東隣のタイルが無ければ，東端に含むタイルを使う
+        assert_eq!(
+            find_path(&index, 35.5, 141.),
+            Some("grid_140_36_0.5".into())
+        );
+        assert_eq!(
+            find_path(&index, 36., 139.5),
+            Some("grid_139_36_0.5".into())
+        );
+    }
+
+    #[test]
+    fn prefers_high_resolution_tile() {
+        let index = SrtmTileIndex::new(vec![
+            Box::new(GridTile::new(139., 36., 0.5, 3)),
+            Box::new(GridTile::new(139., 36., 0.25, 3)),
+        ]);
+        assert_eq!(
+            find_path(&index, 35.9, 139.1),
+            Some("grid_139_36_0.25".into())
+        );
+        // 高解像度のタイルの範囲外では，低解像度のタイルを使う
+        assert_eq!(
+            find_path(&index, 35.1, 139.9),
+            Some("grid_139_36_0.5".into())
+        );
+    }
+}
@@ -0,0 +1,46 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+use lru::LruCache;
+use once_cell::sync::Lazy;
+use route_bucket_domain::{
+    external::TileCacheApi,
+    model::tile::{TileKey, VectorTile},
+};
+
+/// 保持しておくタイルの数
+const CACHE_SIZE: usize = 4096;
+static TIME_TO_LIVE: Lazy<Duration> = Lazy::new(|| Duration::minutes(5));
+
+pub struct TileCache(Mutex<LruCache<TileKey, (DateTime<Utc>, VectorTile)>>);
+
+impl TileCache {
+    pub fn new() -> Self {
+        Self(Mutex::new(LruCache::new(CACHE_SIZE)))
+    }
+}
+
+impl Default for TileCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TileCacheApi for TileCache {
+    fn get(&self, key: &TileKey) -> Option<VectorTile> {
+        let mut cache = self.0.lock().unwrap();
+        match cache.get(key) {
+            Some((expires_at, tile)) if Utc::now() < *expires_at => Some(tile.clone()),
+            Some(_) => {
+                cache.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn put(&self, key: TileKey, tile: VectorTile) {
+        let expires_at = Utc::now() + *TIME_TO_LIVE;
+        self.0.lock().unwrap().put(key, (expires_at, tile));
+    }
+}
@@ -1,10 +1,11 @@
 pub use external::firebase::FirebaseAuthApi;
 pub use external::osrm::OsrmApi;
 pub use external::reserved_uids_reader::ReservedUidsReader;
-pub use external::srtm::SrtmReader;
+pub use external::srtm::{DemBounds, DemManifest, DemManifestFile, DemPreparer, SrtmReader};
+pub use external::tile_cache::TileCache;
 pub use repository::{
-    init_repositories, permission::PermissionRepositoryMySql, route::RouteRepositoryMySql,
-    user::UserRepositoryMySql,
+    collection::CollectionRepositoryMySql, init_repositories,
+    permission::PermissionRepositoryMySql, route::RouteRepositoryMySql, user::UserRepositoryMySql,
 };
 
 mod dto;
@@ -9,10 +9,12 @@ use sqlx::pool::PoolConnection;
 use sqlx::{MySql, TransactionManager};
 use tokio::sync::Mutex;
 
+use self::collection::CollectionRepositoryMySql;
 use self::permission::PermissionRepositoryMySql;
 use self::route::RouteRepositoryMySql;
 use self::user::UserRepositoryMySql;
 
+pub mod collection;
 pub mod permission;
 pub mod route;
 pub mod user;
@@ -21,6 +23,7 @@ pub async fn init_repositories() -> (
     RouteRepositoryMySql,
     UserRepositoryMySql,
     PermissionRepositoryMySql,
+    CollectionRepositoryMySql,
 ) {
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL NOT FOUND");
     let pool = Arc::new(
@@ -33,7 +36,8 @@ pub async fn init_repositories() -> (
     (
         RouteRepositoryMySql(pool.clone()),
         UserRepositoryMySql(pool.clone()),
-        PermissionRepositoryMySql(pool),
+        PermissionRepositoryMySql(pool.clone()),
+        CollectionRepositoryMySql(pool),
     )
 }
 
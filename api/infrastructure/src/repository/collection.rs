@@ -0,0 +1,330 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::FutureExt;
+use itertools::Itertools;
+use sqlx::MySqlPool;
+use tokio::sync::Mutex;
+
+use route_bucket_domain::model::{
+    collection::{Collection, CollectionId},
+    permission::PermissionType,
+    user::UserId,
+};
+use route_bucket_domain::repository::{CollectionRepository, Connection, Repository};
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+use crate::dto::collection::CollectionDto;
+use crate::repository::{gen_err_mapper, RepositoryConnectionMySql};
+
+pub struct CollectionRepositoryMySql(pub(super) Arc<MySqlPool>);
+
+impl CollectionRepositoryMySql {
+    async fn find_route_ids(
+        id: &CollectionId,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<Vec<String>> {
+        let mut conn = conn.lock().await;
+
+        sqlx::query_as::<_, (String,)>(
+            r"
+            SELECT `route_id` FROM collection_routes
+            WHERE `collection_id` = ?
+            ORDER BY `position`
+            ",
+        )
+        .bind(id.to_string())
+        .fetch_all(&mut *conn)
+        .await
+        .map(|rows| rows.into_iter().map(|(route_id,)| route_id).collect())
+        .map_err(gen_err_mapper("failed to find route ids in collection"))
+    }
+
+    async fn update_route_ids(
+        collection: &Collection,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<()> {
+        Self::delete_by_collection_id(collection.id(), "collection_routes", conn).await?;
+        if collection.route_ids().is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = conn.lock().await;
+        let query = format!(
+            "INSERT INTO collection_routes VALUES {}",
+            collection
+                .route_ids()
+                .iter()
+                .map(|_| "(?, ?, ?)")
+                .join(", ")
+        );
+        collection
+            .route_ids()
+            .iter()
+            .enumerate()
+            .fold(sqlx::query(&query), |query, (position, route_id)| {
+                query
+                    .bind(collection.id().to_string())
+                    .bind(route_id.to_string())
+                    .bind(position as u32)
+            })
+            .execute(&mut *conn)
+            .await
+            .map_err(gen_err_mapper("failed to insert collection routes"))?;
+
+        Ok(())
+    }
+
+    async fn delete_by_collection_id(
+        id: &CollectionId,
+        table_name: &str,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<()> {
+        let mut conn = conn.lock().await;
+
+        let id_name = match table_name {
+            "collections" => Ok("id"),
+            "collection_routes" | "collection_permissions" => Ok("collection_id"),
+            _ => Err(ApplicationError::DataBaseError(format!(
+                "Invalid table_name {} for delete_by_collection_id",
+                table_name
+            ))),
+        }?;
+        let query = format!("DELETE FROM {} WHERE `{}` = ?", table_name, id_name);
+
+        sqlx::query(&query)
+            .bind(id.to_string())
+            .execute(&mut *conn)
+            .await
+            .map_err(gen_err_mapper("failed to delete collection"))?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Repository for CollectionRepositoryMySql {
+    type Connection = RepositoryConnectionMySql;
+
+    async fn get_connection(&self) -> ApplicationResult<Self::Connection> {
+        self.0
+            .acquire()
+            .await
+            .map(Mutex::new)
+            .map(RepositoryConnectionMySql)
+            .map_err(gen_err_mapper("failed to get connection"))
+    }
+}
+
+#[async_trait]
+impl CollectionRepository for CollectionRepositoryMySql {
+    async fn find(
+        &self,
+        id: &CollectionId,
+        conn: &Self::Connection,
+    ) -> ApplicationResult<Collection> {
+        let dto = {
+            let mut conn = conn.lock().await;
+            sqlx::query_as::<_, CollectionDto>(
+                r"
+                SELECT * FROM collections WHERE `id` = ? FOR UPDATE
+                ",
+            )
+            .bind(id.to_string())
+            .fetch_one(&mut *conn)
+            .await
+            .map_err(gen_err_mapper("failed to find collection"))?
+        };
+        let route_ids = Self::find_route_ids(id, conn).await?;
+
+        dto.into_model(route_ids)
+    }
+
+    async fn find_by_owner(
+        &self,
+        owner_id: &UserId,
+        conn: &Self::Connection,
+    ) -> ApplicationResult<Vec<Collection>> {
+        let dtos = {
+            let mut conn = conn.lock().await;
+            sqlx::query_as::<_, CollectionDto>(
+                r"
+                SELECT * FROM collections WHERE `owner_id` = ?
+                ORDER BY `updated_at` DESC
+                ",
+            )
+            .bind(owner_id.to_string())
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(gen_err_mapper("failed to find collections"))?
+        };
+
+        let mut collections = Vec::new();
+        for dto in dtos {
+            let id = CollectionId::from_string(dto.id().clone());
+            let route_ids = Self::find_route_ids(&id, conn).await?;
+            collections.push(dto.into_model(route_ids)?);
+        }
+        Ok(collections)
+    }
+
+    async fn find_permission_type(
+        &self,
+        id: &CollectionId,
+        user_id: &UserId,
+        conn: &Self::Connection,
+    ) -> ApplicationResult<PermissionType> {
+        let mut conn = conn.lock().await;
+
+        let sqlx_result = sqlx::query_as::<_, (String,)>(
+            r"
+            SELECT `permission_type` FROM collection_permissions
+            WHERE `collection_id` = ? AND `user_id` = ?
+            ",
+        )
+        .bind(id.to_string())
+        .bind(user_id.to_string())
+        .fetch_one(&mut *conn)
+        .await;
+
+        match sqlx_result {
+            Ok((permission_type,)) => permission_type.parse().map_err(|e| {
+                ApplicationError::DataBaseError(format!(
+                    "Failed to parse collection_permissions.permission_type ({:?})",
+                    e
+                ))
+            }),
+            Err(sqlx::Error::RowNotFound) => Ok(PermissionType::None),
+            Err(other_sqlx_err) => Err(gen_err_mapper("failed to find collection permission")(
+                other_sqlx_err,
+            )),
+        }
+    }
+
+    async fn insert(
+        &self,
+        collection: &Collection,
+        conn: &Self::Connection,
+    ) -> ApplicationResult<()> {
+        conn.transaction(|conn| {
+            async move {
+                {
+                    let mut conn = conn.lock().await;
+                    let dto = CollectionDto::from_model(collection);
+                    sqlx::query(
+                        r"
+                        INSERT INTO collections (`id`, `name`, `owner_id`, `visibility`)
+                        VALUES (?, ?, ?, ?)
+                        ",
+                    )
+                    .bind(dto.id())
+                    .bind(dto.name())
+                    .bind(dto.owner_id())
+                    .bind(dto.visibility())
+                    .execute(&mut *conn)
+                    .await
+                    .map_err(gen_err_mapper("failed to insert Collection"))?;
+                }
+                Self::update_route_ids(collection, conn).await
+            }
+            .boxed()
+        })
+        .await
+    }
+
+    async fn update(
+        &self,
+        collection: &Collection,
+        conn: &Self::Connection,
+    ) -> ApplicationResult<()> {
+        conn.transaction(|conn| {
+            async move {
+                {
+                    let mut conn = conn.lock().await;
+                    let dto = CollectionDto::from_model(collection);
+                    // ルートの並べ替えだけでもupdated_atを進める
+                    sqlx::query(
+                        r"
+                        UPDATE collections
+                        SET `name` = ?, `visibility` = ?, `updated_at` = CURRENT_TIMESTAMP
+                        WHERE `id` = ?
+                        ",
+                    )
+                    .bind(dto.name())
+                    .bind(dto.visibility())
+                    .bind(dto.id())
+                    .execute(&mut *conn)
+                    .await
+                    .map_err(gen_err_mapper("failed to update Collection"))?;
+                }
+                Self::update_route_ids(collection, conn).await
+            }
+            .boxed()
+        })
+        .await
+    }
+
+    async fn insert_or_update_permission(
+        &self,
+        id: &CollectionId,
+        user_id: &UserId,
+        permission_type: PermissionType,
+        conn: &Self::Connection,
+    ) -> ApplicationResult<()> {
+        let mut conn = conn.lock().await;
+
+        sqlx::query(
+            r"
+            INSERT INTO collection_permissions VALUES (?, ?, ?)
+            ON DUPLICATE KEY UPDATE `permission_type` = ?
+            ",
+        )
+        .bind(id.to_string())
+        .bind(user_id.to_string())
+        .bind(permission_type.to_string())
+        .bind(permission_type.to_string())
+        .execute(&mut *conn)
+        .await
+        .map_err(gen_err_mapper(
+            "failed to insert or update collection permission",
+        ))?;
+
+        Ok(())
+    }
+
+    async fn delete_permission(
+        &self,
+        id: &CollectionId,
+        user_id: &UserId,
+        conn: &Self::Connection,
+    ) -> ApplicationResult<()> {
+        let mut conn = conn.lock().await;
+
+        sqlx::query(
+            r"
+            DELETE FROM collection_permissions WHERE `collection_id` = ? AND `user_id` = ?
+            ",
+        )
+        .bind(id.to_string())
+        .bind(user_id.to_string())
+        .execute(&mut *conn)
+        .await
+        .map_err(gen_err_mapper("failed to delete collection permission"))?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, id: &CollectionId, conn: &Self::Connection) -> ApplicationResult<()> {
+        conn.transaction(|conn| {
+            async move {
+                Self::delete_by_collection_id(id, "collections", conn).await?;
+                Self::delete_by_collection_id(id, "collection_routes", conn).await?;
+                Self::delete_by_collection_id(id, "collection_permissions", conn).await?;
+
+                Ok(())
+            }
+            .boxed()
+        })
+        .await
+    }
+}
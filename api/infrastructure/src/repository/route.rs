@@ -6,15 +6,20 @@ use itertools::{enumerate, Itertools};
 use sqlx::MySqlPool;
 use tokio::sync::Mutex;
 
+use std::convert::TryFrom;
+
+use route_bucket_domain::external::ElevationApi;
 use route_bucket_domain::model::route::{
-    Operation, Route, RouteId, RouteInfo, RouteSearchQuery, Segment, SegmentList,
+    Operation, ProximityQuery, Route, RouteId, RouteInfo, RouteSearchQuery, Segment, SegmentList,
+    SimilarExtentQuery, Tag, TagCount, MAX_NEARBY_CANDIDATES, MAX_SIMILAR_CANDIDATES,
 };
 use route_bucket_domain::repository::{Connection, Repository, RouteRepository};
 use route_bucket_utils::{ApplicationError, ApplicationResult};
 
 use crate::dto::operation::OperationDto;
-use crate::dto::route::RouteDto;
-use crate::dto::search_query::SearchQuery;
+use crate::dto::route::{RouteDto, ROUTE_COLUMNS};
+use crate::dto::route_cell::RouteCellDto;
+use crate::dto::search_query::{bind_values, SearchQuery};
 use crate::dto::segment::SegmentDto;
 use crate::repository::{gen_err_mapper, RepositoryConnectionMySql};
 
@@ -106,8 +111,9 @@ impl RouteRepositoryMySql {
 
         sqlx::query(
             r"
-            INSERT INTO segments VALUES (?, ?, ?, ?, ?)
-            ON DUPLICATE KEY UPDATE `index` = ?
+            INSERT INTO segments VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON DUPLICATE KEY UPDATE
+                `index` = ?, elevations = ?, dem_version = ?, elevation_override = ?
             ",
         )
         .bind(dto.id())
@@ -115,7 +121,14 @@ impl RouteRepositoryMySql {
         .bind(dto.index())
         .bind(dto.mode())
         .bind(dto.polyline())
+        .bind(dto.elevations())
+        .bind(dto.dem_version())
+        .bind(dto.elevation_override())
+        .bind(dto.source_elevations())
         .bind(dto.index())
+        .bind(dto.elevations())
+        .bind(dto.dem_version())
+        .bind(dto.elevation_override())
         .execute(&mut *conn)
         .await
         .map_err(gen_err_mapper("failed to insert Segment"))?;
@@ -136,6 +149,62 @@ impl RouteRepositoryMySql {
         Ok(())
     }
 
+    /// 近くのルートを探すための格子を`seg_list`に合わせる
+    ///
+    /// 形の変わらない更新では何も書き込まないよう，保存してあるセルとの差分だけを消したり加えたりする
+    async fn update_cells(
+        id: &RouteId,
+        seg_list: &SegmentList,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<()> {
+        let mut conn = conn.lock().await;
+        let old = sqlx::query_as::<_, RouteCellDto>("SELECT * FROM route_cells WHERE route_id = ?")
+            .bind(id.to_string())
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(gen_err_mapper("failed to find route cells"))?;
+        let new = RouteCellDto::from_model(id, seg_list);
+        let (removed, added) = RouteCellDto::diff(&old, &new);
+
+        // 1つのクエリにまとめるが，プレースホルダの数の上限を超えないように分ける
+        for dtos in removed.chunks(1000) {
+            let query = format!(
+                "DELETE FROM route_cells WHERE route_id = ? \
+                 AND (`target`, `latitude_index`, `longitude_index`) IN ({})",
+                dtos.iter().map(|_| "(?, ?, ?)").join(", ")
+            );
+            dtos.iter()
+                .fold(sqlx::query(&query).bind(id.to_string()), |query, dto| {
+                    query
+                        .bind(dto.target())
+                        .bind(dto.latitude_index())
+                        .bind(dto.longitude_index())
+                })
+                .execute(&mut *conn)
+                .await
+                .map_err(gen_err_mapper("failed to delete route cells"))?;
+        }
+        for dtos in added.chunks(1000) {
+            let query = format!(
+                "INSERT INTO route_cells VALUES {}",
+                dtos.iter().map(|_| "(?, ?, ?, ?)").join(", ")
+            );
+            dtos.iter()
+                .fold(sqlx::query(&query), |query, dto| {
+                    query
+                        .bind(dto.route_id())
+                        .bind(dto.target())
+                        .bind(dto.latitude_index())
+                        .bind(dto.longitude_index())
+                })
+                .execute(&mut *conn)
+                .await
+                .map_err(gen_err_mapper("failed to insert route cells"))?;
+        }
+
+        Ok(())
+    }
+
     async fn update_operations(
         id: &RouteId,
         operations: &[Operation],
@@ -218,7 +287,9 @@ impl RouteRepositoryMySql {
 
         let id_name = match table_name {
             "routes" => Ok("id"),
-            "operations" | "segments" => Ok("route_id"),
+            "operations" | "segments" | "route_cells" | "route_tags" | "collection_routes" => {
+                Ok("route_id")
+            }
             _ => Err(ApplicationError::DataBaseError(format!(
                 "Invalid table_name {} for delete_by_route_id",
                 table_name
@@ -236,6 +307,254 @@ impl RouteRepositoryMySql {
     }
 }
 
+impl RouteRepositoryMySql {
+    /// 範囲の列を追加する前に保存したルートに，セグメントから求めた範囲を書き込む
+    ///
+    /// `updated_at`は変えない．点の無いルートは範囲を持たないので書き込まない
+    /// 書き込んだルートの数を返す
+    pub async fn backfill_bounding_boxes(&self) -> ApplicationResult<usize> {
+        let conn = self.get_connection().await?;
+        let ids = {
+            let mut conn = conn.lock().await;
+            sqlx::query_as::<_, (String,)>("SELECT id FROM routes WHERE min_latitude IS NULL")
+                .fetch_all(&mut *conn)
+                .await
+                .map_err(gen_err_mapper(
+                    "failed to find routes without bounding boxes",
+                ))?
+        };
+
+        let mut count = 0;
+        for (id,) in ids {
+            let id = RouteId::from_string(id);
+            let is_filled = conn
+                .transaction(|conn| {
+                    async move {
+                        let bbox = match Self::find_seg_list(&id, conn).await?.calc_bounding_box() {
+                            Ok(bbox) => bbox,
+                            Err(_) => return Ok(false),
+                        };
+                        let (min, max) = (bbox.min_coord(), bbox.max_coord());
+                        let mut conn = conn.lock().await;
+                        sqlx::query(
+                            r"
+                            UPDATE routes
+                            SET
+                                min_latitude = ?, min_longitude = ?, max_latitude = ?,
+                                max_longitude = ?, updated_at = updated_at
+                            WHERE id = ?
+                            ",
+                        )
+                        .bind(min.latitude().value())
+                        .bind(min.longitude().value())
+                        .bind(max.latitude().value())
+                        .bind(max.longitude().value())
+                        .bind(id.to_string())
+                        .execute(&mut *conn)
+                        .await
+                        .map_err(gen_err_mapper("failed to backfill bounding box"))?;
+                        Ok(true)
+                    }
+                    .boxed()
+                })
+                .await?;
+            if is_filled {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// 格子の表を追加する前に保存したルートに，格子を作る
+    ///
+    /// 点の無いルートは格子を持たないので作らない．作ったルートの数を返す
+    pub async fn backfill_cells(&self) -> ApplicationResult<usize> {
+        let conn = self.get_connection().await?;
+        let ids = {
+            let mut conn = conn.lock().await;
+            sqlx::query_as::<_, (String,)>(
+                r"
+                SELECT id FROM routes
+                WHERE NOT EXISTS (SELECT 1 FROM route_cells WHERE route_id = routes.id)
+                ",
+            )
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(gen_err_mapper("failed to find routes without cells"))?
+        };
+
+        let mut count = 0;
+        for (id,) in ids {
+            let id = RouteId::from_string(id);
+            let is_filled = conn
+                .transaction(|conn| {
+                    async move {
+                        let seg_list = Self::find_seg_list(&id, conn).await?;
+                        if seg_list.iter().next().is_none() {
+                            return Ok(false);
+                        }
+                        Self::update_cells(&id, &seg_list, conn).await?;
+                        Ok(true)
+                    }
+                    .boxed()
+                })
+                .await?;
+            if is_filled {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// 難易度の列を追加する前に保存したルートに，難易度を付ける
+    ///
+    /// 初期値(スコア0)のままのルートだけを対象に，DEMの標高を付けて求め直す
+    /// 求め直しても0のルートは数えない．付けたルートの数を返す
+    pub async fn backfill_difficulties<E: ElevationApi>(
+        &self,
+        elevation_api: &E,
+    ) -> ApplicationResult<usize> {
+        let conn = self.get_connection().await?;
+        let ids = {
+            let mut conn = conn.lock().await;
+            sqlx::query_as::<_, (String,)>(
+                "SELECT id FROM routes WHERE difficulty_score = 0 AND difficulty_rating = 'easy'",
+            )
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(gen_err_mapper("failed to find routes without difficulty"))?
+        };
+
+        let mut count = 0;
+        for (id,) in ids {
+            let id = RouteId::from_string(id);
+            let is_filled = conn
+                .transaction(|conn| {
+                    async move {
+                        let mut route = self.find_with_elevations(&id, elevation_api, conn).await?;
+                        route.calc_route_features_from_seg_list()?;
+                        if *route.info().difficulty().score() == 0. {
+                            return Ok(false);
+                        }
+                        Self::update_elevation_features(route.info(), conn).await?;
+                        Ok(true)
+                    }
+                    .boxed()
+                })
+                .await?;
+            if is_filled {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// 標高を保存する前に保存したルートや，DEMを更新する前の標高のルートに，現在のDEMの標高を保存する
+    ///
+    /// 読むたびにDEMを引かないようにするためのもので，DEMを更新したら実行する
+    /// 元の標高だけで足りるセグメントは求め直さないので数えない．保存したルートの数を返す
+    pub async fn backfill_elevations<E: ElevationApi>(
+        &self,
+        elevation_api: &E,
+    ) -> ApplicationResult<usize> {
+        let conn = self.get_connection().await?;
+        let ids = {
+            let mut conn = conn.lock().await;
+            sqlx::query_as::<_, (String,)>(
+                r"
+                SELECT DISTINCT route_id FROM segments
+                WHERE dem_version IS NULL OR dem_version <> ?
+                ",
+            )
+            .bind(elevation_api.dem_version().to_string())
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(gen_err_mapper(
+                "failed to find routes with outdated elevations",
+            ))?
+        };
+
+        let mut count = 0;
+        for (id,) in ids {
+            let id = RouteId::from_string(id);
+            let is_filled = conn
+                .transaction(|conn| {
+                    async move {
+                        let info = self.find_info(&id, conn).await?;
+                        let seg_list = Self::find_seg_list(&id, conn).await?;
+                        let dem_versions = seg_list
+                            .iter()
+                            .map(|seg| seg.dem_version().clone())
+                            .collect::<Vec<_>>();
+                        let mut route = Route::new(info, Vec::new(), seg_list);
+                        elevation_api.attach_elevations(&mut route).await?;
+                        if route
+                            .seg_list()
+                            .iter()
+                            .zip(dem_versions)
+                            .all(|(seg, dem_version)| *seg.dem_version() == dem_version)
+                        {
+                            return Ok(false);
+                        }
+                        route.calc_route_features_from_seg_list()?;
+                        Self::update_segment_list(&id, route.seg_list(), conn).await?;
+                        Self::update_elevation_features(route.info(), conn).await?;
+                        Ok(true)
+                    }
+                    .boxed()
+                })
+                .await?;
+            if is_filled {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// 操作の履歴を除いてルートを読み，標高を付ける
+    async fn find_with_elevations<E: ElevationApi>(
+        &self,
+        id: &RouteId,
+        elevation_api: &E,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<Route> {
+        let info = self.find_info(id, conn).await?;
+        let seg_list = Self::find_seg_list(id, conn).await?;
+        let mut route = Route::new(info, Vec::new(), seg_list);
+        elevation_api.attach_elevations(&mut route).await?;
+        Ok(route)
+    }
+
+    /// 標高から求める列(獲得標高と難易度)だけを，更新日時を変えずに書き込む
+    async fn update_elevation_features(
+        info: &RouteInfo,
+        conn: &<Self as Repository>::Connection,
+    ) -> ApplicationResult<()> {
+        let mut conn = conn.lock().await;
+        let dto = RouteDto::from_model(info)?;
+
+        sqlx::query(
+            r"
+            UPDATE routes
+            SET
+                ascent_elevation_gain = ?, descent_elevation_gain = ?, difficulty_score = ?,
+                difficulty_rating = ?, updated_at = updated_at
+            WHERE id = ?
+            ",
+        )
+        .bind(dto.ascent_elevation_gain())
+        .bind(dto.descent_elevation_gain())
+        .bind(dto.difficulty_score())
+        .bind(dto.difficulty_rating())
+        .bind(dto.id())
+        .execute(&mut *conn)
+        .await
+        .map_err(gen_err_mapper("failed to update elevation features"))?;
+
+        Ok(())
+    }
+}
+
 #[async_trait]
 impl Repository for RouteRepositoryMySql {
     type Connection = RepositoryConnectionMySql;
@@ -273,11 +592,10 @@ impl RouteRepository for RouteRepositoryMySql {
     ) -> ApplicationResult<RouteInfo> {
         let mut conn = conn.lock().await;
 
-        sqlx::query_as::<_, RouteDto>(
-            r"
-            SELECT * FROM routes WHERE id = ? FOR UPDATE
-            ",
-        )
+        sqlx::query_as::<_, RouteDto>(&format!(
+            "SELECT {} FROM routes WHERE id = ? FOR UPDATE",
+            ROUTE_COLUMNS
+        ))
         .bind(id.to_string())
         .fetch_one(&mut *conn)
         .await
@@ -291,8 +609,9 @@ impl RouteRepository for RouteRepositoryMySql {
         conn: &Self::Connection,
     ) -> ApplicationResult<Vec<RouteInfo>> {
         let mut conn = conn.lock().await;
+        let (sql, values) = SearchQuery::from(query).to_sql(false);
 
-        sqlx::query_as::<_, RouteDto>(&SearchQuery::from(query).to_sql(false))
+        bind_values(sqlx::query_as::<_, RouteDto>(&sql), values)
             .fetch_all(&mut *conn)
             .await
             .map_err(gen_err_mapper("failed to find infos"))?
@@ -307,14 +626,162 @@ impl RouteRepository for RouteRepositoryMySql {
         conn: &<Self as Repository>::Connection,
     ) -> ApplicationResult<usize> {
         let mut conn = conn.lock().await;
+        let (sql, values) = SearchQuery::from(query).to_sql(true);
 
-        sqlx::query_as::<_, (i64,)>(&SearchQuery::from(query).to_sql(true))
+        bind_values(sqlx::query_as::<_, (i64,)>(&sql), values)
             .fetch_one(&mut *conn)
             .await
             .map(|(count,)| count as usize)
             .map_err(gen_err_mapper("failed to count infos"))
     }
 
+    async fn find_ids_near(
+        &self,
+        query: &ProximityQuery,
+        conn: &Self::Connection,
+    ) -> ApplicationResult<Vec<RouteId>> {
+        let (lat_range, lon_range) = RouteCellDto::search_range(query)?;
+        let (center_lat, center_lon, lon_scale) = RouteCellDto::search_center(query)?;
+        let mut conn = conn.lock().await;
+
+        // 中心に最も近いセルまでの(格子の上の)距離が近い順に，候補の数を絞る
+        sqlx::query_as::<_, (String,)>(
+            r"
+            SELECT route_id FROM route_cells
+            WHERE
+                `target` = ?
+                AND `latitude_index` BETWEEN ? AND ?
+                AND `longitude_index` BETWEEN ? AND ?
+            GROUP BY route_id
+            ORDER BY
+                MIN(POW(`latitude_index` + 0.5 - ?, 2)
+                    + POW((`longitude_index` + 0.5 - ?) * ?, 2)),
+                route_id
+            LIMIT ?
+            ",
+        )
+        .bind(query.target.to_string())
+        .bind(lat_range.start())
+        .bind(lat_range.end())
+        .bind(lon_range.start())
+        .bind(lon_range.end())
+        .bind(center_lat)
+        .bind(center_lon)
+        .bind(lon_scale)
+        .bind(MAX_NEARBY_CANDIDATES as u32)
+        .fetch_all(&mut *conn)
+        .await
+        .map(|ids| {
+            ids.into_iter()
+                .map(|(id,)| RouteId::from_string(id))
+                .collect()
+        })
+        .map_err(gen_err_mapper("failed to find routes nearby"))
+    }
+
+    async fn find_ids_with_similar_extent(
+        &self,
+        query: &SimilarExtentQuery,
+        conn: &Self::Connection,
+    ) -> ApplicationResult<Vec<RouteId>> {
+        let (lat_margin, lon_margin) = query.margin_degrees();
+        let (min, max) = (query.bbox().min_coord(), query.bbox().max_coord());
+        let edges = [
+            ("min_latitude", min.latitude().value(), lat_margin),
+            ("max_latitude", max.latitude().value(), lat_margin),
+            ("min_longitude", min.longitude().value(), lon_margin),
+            ("max_longitude", max.longitude().value(), lon_margin),
+        ];
+        let mut conn = conn.lock().await;
+
+        // 各辺のずれ(経度は緯度と同じ尺度にする)の和が小さい順に，候補の数を絞る
+        // NOTE: 辺ごとに比べるので，日付変更線の向こうにずれたルートは拾えない
+        let sql = format!(
+            r"
+            SELECT id FROM routes
+            WHERE {} {}
+            ORDER BY {}, id
+            LIMIT ?
+            ",
+            edges
+                .iter()
+                .map(|(column, _, _)| format!("`{}` BETWEEN ? AND ?", column))
+                .join(" AND "),
+            if query.owner_id().is_some() {
+                "AND owner_id = ?"
+            } else {
+                ""
+            },
+            edges
+                .iter()
+                .map(|(column, _, _)| format!("ABS(`{}` - ?) * ?", column))
+                .join(" + ")
+        );
+        let sql_query = edges.iter().fold(
+            sqlx::query_as::<_, (String,)>(&sql),
+            |sql_query, (_, value, margin)| sql_query.bind(value - margin).bind(value + margin),
+        );
+        let sql_query = match query.owner_id() {
+            Some(owner_id) => sql_query.bind(owner_id.to_string()),
+            None => sql_query,
+        };
+        edges
+            .iter()
+            .fold(sql_query, |sql_query, (_, value, margin)| {
+                sql_query.bind(value).bind(lat_margin / margin)
+            })
+            .bind(MAX_SIMILAR_CANDIDATES as u32)
+            .fetch_all(&mut *conn)
+            .await
+            .map(|ids| {
+                ids.into_iter()
+                    .map(|(id,)| RouteId::from_string(id))
+                    .collect()
+            })
+            .map_err(gen_err_mapper("failed to find routes with similar extents"))
+    }
+
+    async fn find_seg_lists(
+        &self,
+        ids: &[RouteId],
+        conn: &Self::Connection,
+    ) -> ApplicationResult<Vec<(RouteId, SegmentList)>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = conn.lock().await;
+
+        // 形に要らない標高の列は読まない
+        let query = format!(
+            r"
+            SELECT
+                id, route_id, `index`, mode, polyline, NULL AS elevations, NULL AS dem_version,
+                NULL AS elevation_override, NULL AS source_elevations
+            FROM segments
+            WHERE route_id IN ({})
+            ORDER BY route_id, `index`
+            ",
+            ids.iter().map(|_| "?").join(", ")
+        );
+        ids.iter()
+            .fold(sqlx::query_as::<_, SegmentDto>(&query), |query, id| {
+                query.bind(id.to_string())
+            })
+            .fetch_all(&mut *conn)
+            .await
+            .map_err(gen_err_mapper("failed to find segments"))?
+            .into_iter()
+            .group_by(|dto| dto.route_id().clone())
+            .into_iter()
+            .map(|(route_id, dtos)| {
+                let seg_list = dtos
+                    .map(SegmentDto::into_model)
+                    .collect::<ApplicationResult<Vec<_>>>()?;
+                Ok((RouteId::from_string(route_id), SegmentList::from(seg_list)))
+            })
+            .collect()
+    }
+
     async fn insert_info(
         &self,
         info: &RouteInfo,
@@ -327,9 +794,12 @@ impl RouteRepository for RouteRepositoryMySql {
             r"
             INSERT INTO routes (
                 `id`, `name`, `owner_id`, `operation_pos`, `ascent_elevation_gain`, 
-                `descent_elevation_gain`, `total_distance`       
+                `descent_elevation_gain`, `total_distance`, `difficulty_score`,
+                `difficulty_rating`, `elevation_source`, `min_latitude`, `min_longitude`,
+                `max_latitude`, `max_longitude`, `description`, `surface_type`, `bike_type`,
+                `start_label`, `finish_label`, `external_link`
             )
-            VALUES (?, ?, ?, ?, ?, ?, ?)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             ",
         )
         .bind(dto.id())
@@ -339,6 +809,19 @@ impl RouteRepository for RouteRepositoryMySql {
         .bind(dto.ascent_elevation_gain())
         .bind(dto.descent_elevation_gain())
         .bind(dto.total_distance())
+        .bind(dto.difficulty_score())
+        .bind(dto.difficulty_rating())
+        .bind(dto.elevation_source())
+        .bind(dto.min_latitude())
+        .bind(dto.min_longitude())
+        .bind(dto.max_latitude())
+        .bind(dto.max_longitude())
+        .bind(dto.description())
+        .bind(dto.surface_type())
+        .bind(dto.bike_type())
+        .bind(dto.start_label())
+        .bind(dto.finish_label())
+        .bind(dto.external_link())
         .execute(&mut *conn)
         .await
         .map_err(gen_err_mapper("failed to insert RouteInfo"))?;
@@ -358,7 +841,11 @@ impl RouteRepository for RouteRepositoryMySql {
             UPDATE routes
             SET 
                 name = ?, owner_id = ?, operation_pos = ?, ascent_elevation_gain = ?,
-                descent_elevation_gain = ?, total_distance = ?
+                descent_elevation_gain = ?, total_distance = ?, difficulty_score = ?,
+                difficulty_rating = ?, elevation_source = ?, min_latitude = ?,
+                min_longitude = ?, max_latitude = ?, max_longitude = ?, description = ?,
+                surface_type = ?, bike_type = ?, start_label = ?, finish_label = ?,
+                external_link = ?
             WHERE id = ?
             ",
         )
@@ -368,6 +855,19 @@ impl RouteRepository for RouteRepositoryMySql {
         .bind(dto.ascent_elevation_gain())
         .bind(dto.descent_elevation_gain())
         .bind(dto.total_distance())
+        .bind(dto.difficulty_score())
+        .bind(dto.difficulty_rating())
+        .bind(dto.elevation_source())
+        .bind(dto.min_latitude())
+        .bind(dto.min_longitude())
+        .bind(dto.max_latitude())
+        .bind(dto.max_longitude())
+        .bind(dto.description())
+        .bind(dto.surface_type())
+        .bind(dto.bike_type())
+        .bind(dto.start_label())
+        .bind(dto.finish_label())
+        .bind(dto.external_link())
         .bind(dto.id())
         .execute(&mut *conn)
         .await
@@ -381,6 +881,7 @@ impl RouteRepository for RouteRepositoryMySql {
             async move {
                 self.update_info(route.info(), conn).await?;
                 Self::update_segment_list(route.info().id(), route.seg_list(), conn).await?;
+                Self::update_cells(route.info().id(), route.seg_list(), conn).await?;
 
                 if *route.info().op_num() == route.op_list().len() {
                     Self::update_operations(route.info().id(), route.op_list(), conn).await?;
@@ -392,12 +893,72 @@ impl RouteRepository for RouteRepositoryMySql {
         .await
     }
 
+    async fn update_tags(
+        &self,
+        info: &RouteInfo,
+        conn: &Self::Connection,
+    ) -> ApplicationResult<()> {
+        conn.transaction(|conn| {
+            async move {
+                Self::delete_by_route_id(info.id(), "route_tags", conn).await?;
+                if info.tags().is_empty() {
+                    return Ok(());
+                }
+
+                let mut conn = conn.lock().await;
+                let query = format!(
+                    "INSERT INTO route_tags VALUES {}",
+                    info.tags().iter().map(|_| "(?, ?)").join(", ")
+                );
+                info.tags()
+                    .iter()
+                    .fold(sqlx::query(&query), |query, tag| {
+                        query.bind(info.id().to_string()).bind(tag.to_string())
+                    })
+                    .execute(&mut *conn)
+                    .await
+                    .map_err(gen_err_mapper("failed to insert route tags"))?;
+
+                Ok(())
+            }
+            .boxed()
+        })
+        .await
+    }
+
+    async fn count_tags(
+        &self,
+        limit: usize,
+        conn: &Self::Connection,
+    ) -> ApplicationResult<Vec<TagCount>> {
+        let mut conn = conn.lock().await;
+
+        sqlx::query_as::<_, (String, i64)>(
+            r"
+            SELECT `tag`, COUNT(*) AS `count` FROM route_tags
+            GROUP BY `tag`
+            ORDER BY `count` DESC, `tag` ASC
+            LIMIT ?
+            ",
+        )
+        .bind(limit as i64)
+        .fetch_all(&mut *conn)
+        .await
+        .map_err(gen_err_mapper("failed to count tags"))?
+        .into_iter()
+        .map(|(tag, count)| Ok(TagCount::from((Tag::try_from(tag)?, count as usize))))
+        .collect()
+    }
+
     async fn delete(&self, id: &RouteId, conn: &Self::Connection) -> ApplicationResult<()> {
         conn.transaction(|conn| {
             async move {
                 Self::delete_by_route_id(id, "routes", conn).await?;
                 Self::delete_by_route_id(id, "operations", conn).await?;
                 Self::delete_by_route_id(id, "segments", conn).await?;
+                Self::delete_by_route_id(id, "route_cells", conn).await?;
+                Self::delete_by_route_id(id, "route_tags", conn).await?;
+                Self::delete_by_route_id(id, "collection_routes", conn).await?;
 
                 Ok(())
             }
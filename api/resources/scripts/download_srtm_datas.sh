@@ -1,7 +1,10 @@
+# usage: download_srtm_datas.sh [TILE ...] (e.g. N30E120 N30E135)
 mkdir -p resources/srtm_data
-cd resources/srtm_data \
-&& wget https://srtm.csi.cgiar.org/wp-content/uploads/files/srtm_30x30/TIFF/N30E120.zip \
-    -O srtm.zip \
-&& unzip srtm.zip \
-&& mv "$(unzip -Z1 srtm.zip)" srtm.tif \
-&& rm srtm.zip
\ No newline at end of file
+cd resources/srtm_data || exit 1
+for tile in "${@:-N30E120}"; do
+  wget "https://srtm.csi.cgiar.org/wp-content/uploads/files/srtm_30x30/TIFF/${tile}.zip" \
+      -O "${tile}.zip" \
+  && unzip -o "${tile}.zip" \
+  && mv "$(unzip -Z1 "${tile}.zip")" "${tile}.tif" \
+  && rm "${tile}.zip"
+done
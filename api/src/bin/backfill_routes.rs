@@ -0,0 +1,38 @@
+//! 検索用の列を追加する前に保存したルートに，その列を埋める
+//!
+//! ```sh
+//! cargo run --bin backfill_routes
+//! ```
+//!
+//! 埋まっていないルートだけを対象にするので，何度実行してもよい
+//! 標高と難易度は`SRTM_DATA_DIR`のDEMから求める．DEMを更新したときも，標高を保存し直すために実行する
+
+use std::process::exit;
+
+use route_bucket_infrastructure::{init_repositories, SrtmReader};
+use route_bucket_utils::ApplicationResult;
+
+async fn run() -> ApplicationResult<()> {
+    let (route_repository, ..) = init_repositories().await;
+    let srtm_reader = SrtmReader::new()?;
+
+    let count = route_repository.backfill_bounding_boxes().await?;
+    log::info!("Filled bounding boxes of {} routes", count);
+    let count = route_repository.backfill_cells().await?;
+    log::info!("Filled cells of {} routes", count);
+    let count = route_repository.backfill_elevations(&srtm_reader).await?;
+    log::info!("Saved elevations of {} routes", count);
+    let count = route_repository.backfill_difficulties(&srtm_reader).await?;
+    log::info!("Filled difficulties of {} routes", count);
+    Ok(())
+}
+
+#[actix_web::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    if let Err(err) = run().await {
+        eprintln!("{:?}", err);
+        exit(1);
+    }
+}
@@ -0,0 +1,113 @@
+//! 手元のDEMファイルから，SrtmReaderが読むディレクトリ(SRTM_DATA_DIR)を作る
+//!
+//! ```sh
+//! cargo run --bin prepare_dem -- \
+//!     --bbox 139,35,141,36 --out resources/srtm_data [--resolution 1|3] <DEM files>...
+//! ```
+//!
+//! `--bbox`はルート検索の`bbox`と同じく`minLon,minLat,maxLon,maxLat`の順に書く
+//!
+//! 指定した範囲を1度四方の.hgtタイルに切り出し，使ったファイルと書き出したタイルを
+//! `manifest.json`に記録する
+
+use std::path::PathBuf;
+use std::process::exit;
+
+use route_bucket_infrastructure::{DemBounds, DemPreparer};
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+const USAGE: &str = "Usage: prepare_dem --bbox <min_lon>,<min_lat>,<max_lon>,<max_lat> \
+                     --out <dir> [--resolution 1|3] <DEM files (.tif, .tiff, .hgt, .asc)>...";
+
+struct Args {
+    bounds: DemBounds,
+    out_dir: PathBuf,
+    resolution: Option<u32>,
+    files: Vec<PathBuf>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> ApplicationResult<Self> {
+        let invalid = |msg: String| ApplicationError::ValidationError(msg);
+        let mut bounds = None;
+        let mut out_dir = None;
+        let mut resolution = None;
+        let mut files = Vec::new();
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| invalid(format!("{} needs a value", name)))
+            };
+            match arg.as_str() {
+                "--bbox" => bounds = Some(Self::parse_bounds(&value("--bbox")?)?),
+                "--out" => out_dir = Some(PathBuf::from(value("--out")?)),
+                "--resolution" => {
+                    let value = value("--resolution")?;
+                    resolution = Some(
+                        value
+                            .parse()
+                            .map_err(|_| invalid(format!("Invalid --resolution {:?}", value)))?,
+                    );
+                }
+                flag if flag.starts_with("--") => {
+                    return Err(invalid(format!("Unknown option {}", flag)))
+                }
+                _ => files.push(PathBuf::from(arg)),
+            }
+        }
+
+        Ok(Self {
+            bounds: bounds.ok_or_else(|| invalid("--bbox is required".into()))?,
+            out_dir: out_dir.ok_or_else(|| invalid("--out is required".into()))?,
+            resolution,
+            files,
+        })
+    }
+
+    fn parse_bounds(value: &str) -> ApplicationResult<DemBounds> {
+        let values = value
+            .split(',')
+            .map(|v| v.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .ok()
+            .filter(|values| values.len() == 4)
+            .ok_or_else(|| {
+                ApplicationError::ValidationError(format!(
+                    "Invalid --bbox {:?} (expected <min_lon>,<min_lat>,<max_lon>,<max_lat>)",
+                    value
+                ))
+            })?;
+        DemBounds::new(values[1], values[0], values[3], values[2])
+    }
+}
+
+fn run() -> ApplicationResult<()> {
+    let args = Args::parse(std::env::args().skip(1))?;
+
+    let preparer = DemPreparer::open_files(&args.files)?;
+    let resolution = args
+        .resolution
+        .unwrap_or_else(|| preparer.default_resolution());
+    let manifest = preparer.prepare(&args.bounds, resolution, &args.out_dir)?;
+
+    for tile in manifest.tiles() {
+        log::info!("Wrote {} ({})", tile.file(), tile.sha256());
+    }
+    log::info!(
+        "Prepared {} tiles in {:?} (DEM version: {})",
+        manifest.tiles().len(),
+        args.out_dir,
+        manifest.version()
+    );
+    Ok(())
+}
+
+fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    if let Err(err) = run() {
+        eprintln!("{:?}\n{}", err, USAGE);
+        exit(1);
+    }
+}
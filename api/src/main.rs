@@ -3,7 +3,10 @@ use actix_web::middleware::Logger;
 use actix_web::{web, App, Error, HttpServer, Result};
 
 use route_bucket_backend::server::Server;
-use route_bucket_controller::{BuildRouteService, BuildUserService};
+use route_bucket_controller::{
+    BuildCollectionService, BuildElevationService, BuildRouteService, BuildTileService,
+    BuildUserService,
+};
 
 #[actix_web::main]
 async fn main() -> Result<(), Error> {
@@ -19,6 +22,9 @@ async fn main() -> Result<(), Error> {
             .app_data(server.clone())
             .build_route_service::<Server>()
             .build_user_service::<Server>()
+            .build_elevation_service::<Server>()
+            .build_collection_service::<Server>()
+            .build_tile_service::<Server>()
     })
     .bind("0.0.0.0:8080")?
     .run()
@@ -1,35 +1,42 @@
 use route_bucket_domain::external::{
-    CallElevationApi, CallReservedUserIdCheckerApi, CallRouteInterpolationApi, CallUserAuthApi,
+    CallElevationApi, CallReservedUserIdCheckerApi, CallRouteInterpolationApi, CallTileCacheApi,
+    CallUserAuthApi,
 };
 use route_bucket_domain::repository::{
-    CallPermissionRepository, CallRouteRepository, CallUserRepository,
+    CallCollectionRepository, CallPermissionRepository, CallRouteRepository, CallUserRepository,
 };
 use route_bucket_infrastructure::{
-    init_repositories, FirebaseAuthApi, OsrmApi, PermissionRepositoryMySql, ReservedUidsReader,
-    RouteRepositoryMySql, SrtmReader, UserRepositoryMySql,
+    init_repositories, CollectionRepositoryMySql, FirebaseAuthApi, OsrmApi,
+    PermissionRepositoryMySql, ReservedUidsReader, RouteRepositoryMySql, SrtmReader, TileCache,
+    UserRepositoryMySql,
 };
 
 pub struct Server {
     route_repository: RouteRepositoryMySql,
     user_repository: UserRepositoryMySql,
     permission_repository: PermissionRepositoryMySql,
+    collection_repository: CollectionRepositoryMySql,
     srtm_reader: SrtmReader,
     osrm_api: OsrmApi,
     firebase_auth_api: FirebaseAuthApi,
     reserved_uids_reader: ReservedUidsReader,
+    tile_cache: TileCache,
 }
 
 impl Server {
     pub async fn new() -> Self {
-        let (route_repository, user_repository, permission_repository) = init_repositories().await;
+        let (route_repository, user_repository, permission_repository, collection_repository) =
+            init_repositories().await;
         Self {
             route_repository,
             user_repository,
             permission_repository,
+            collection_repository,
             srtm_reader: SrtmReader::new().unwrap(),
             osrm_api: OsrmApi::new(),
             firebase_auth_api: FirebaseAuthApi::new().await.unwrap(),
             reserved_uids_reader: ReservedUidsReader::new().unwrap(),
+            tile_cache: TileCache::new(),
         }
     }
 }
@@ -59,6 +66,14 @@ impl CallPermissionRepository for Server {
     }
 }
 
+impl CallCollectionRepository for Server {
+    type CollectionRepository = CollectionRepositoryMySql;
+
+    fn collection_repository(&self) -> &Self::CollectionRepository {
+        &self.collection_repository
+    }
+}
+
 impl CallElevationApi for Server {
     type ElevationApi = SrtmReader;
 
@@ -90,3 +105,11 @@ impl CallReservedUserIdCheckerApi for Server {
         &self.reserved_uids_reader
     }
 }
+
+impl CallTileCacheApi for Server {
+    type TileCacheApi = TileCache;
+
+    fn tile_cache_api(&self) -> &Self::TileCacheApi {
+        &self.tile_cache
+    }
+}
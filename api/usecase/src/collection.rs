@@ -0,0 +1,1154 @@
+use std::convert::TryFrom;
+
+use async_trait::async_trait;
+use futures::FutureExt;
+use validator::Validate;
+
+pub use requests::*;
+pub use responses::*;
+use route_bucket_domain::external::{CallElevationApi, CallUserAuthApi, ElevationApi, UserAuthApi};
+use route_bucket_domain::model::collection::{
+    Collection, CollectionArchive, CollectionId, CollectionStats,
+};
+use route_bucket_domain::model::permission::PermissionType;
+use route_bucket_domain::model::route::{Route, RouteGpx, RouteId};
+use route_bucket_domain::repository::{
+    CallCollectionRepository, CallRouteRepository, CollectionRepository, Connection, Repository,
+    RouteRepository,
+};
+use route_bucket_utils::ApplicationResult;
+
+mod requests;
+mod responses;
+
+#[async_trait]
+pub trait CollectionUseCase {
+    /// ログインしていなければ`user_access_token`はNone
+    async fn find(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: Option<&str>,
+    ) -> ApplicationResult<CollectionGetResponse>;
+
+    /// `req.owner_id`のコレクションのうち，見られるものだけを返す
+    async fn find_by_owner(
+        &self,
+        req: &CollectionListRequest,
+        user_access_token: Option<&str>,
+    ) -> ApplicationResult<CollectionListResponse>;
+
+    /// 各ルートを1つのtrkとして持つGPXを返す
+    async fn find_gpx(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: Option<&str>,
+    ) -> ApplicationResult<CollectionGetGpxResponse>;
+
+    /// 各ルートのGPXファイルをまとめたzipを返す
+    async fn find_archive(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: Option<&str>,
+    ) -> ApplicationResult<CollectionGetArchiveResponse>;
+
+    async fn create(
+        &self,
+        user_access_token: &str,
+        req: &CollectionCreateRequest,
+    ) -> ApplicationResult<CollectionCreateResponse>;
+
+    /// 名前や公開範囲を，指定された項目だけ書き換える (公開範囲は所有者だけが変えられる)
+    async fn update(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: &str,
+        req: &CollectionUpdateRequest,
+    ) -> ApplicationResult<Collection>;
+
+    async fn delete(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: &str,
+    ) -> ApplicationResult<()>;
+
+    async fn add_route(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: &str,
+        req: &CollectionRouteRequest,
+    ) -> ApplicationResult<Collection>;
+
+    async fn remove_route(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: &str,
+        route_id: &RouteId,
+    ) -> ApplicationResult<Collection>;
+
+    async fn reorder_routes(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: &str,
+        req: &CollectionReorderRequest,
+    ) -> ApplicationResult<Collection>;
+
+    async fn update_permission(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: &str,
+        req: &CollectionPermissionRequest,
+    ) -> ApplicationResult<()>;
+
+    async fn delete_permission(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: &str,
+        req: &CollectionPermissionDeleteRequest,
+    ) -> ApplicationResult<()>;
+}
+
+/// `user_access_token`のユーザーが`collection`に`target_type`以上の権限を持つか確かめる
+async fn authorize<T>(
+    usecase: &T,
+    collection: &Collection,
+    user_access_token: Option<&str>,
+    target_type: PermissionType,
+    conn: &<T::CollectionRepository as Repository>::Connection,
+) -> ApplicationResult<()>
+where
+    T: CallCollectionRepository + CallUserAuthApi + Sync,
+{
+    let user_id = match user_access_token {
+        Some(token) => Some(usecase.user_auth_api().authenticate(token).await?),
+        None => None,
+    };
+    let granted = match &user_id {
+        Some(user_id) if user_id != collection.owner_id() => {
+            usecase
+                .collection_repository()
+                .find_permission_type(collection.id(), user_id, conn)
+                .await?
+        }
+        _ => PermissionType::None,
+    };
+    collection.authorize(user_id.as_ref(), granted, target_type)
+}
+
+/// 標高を付け直した，GPXに書き出すためのルートを並び順どおりに返す
+async fn find_routes_for_export<T>(
+    usecase: &T,
+    collection: &Collection,
+) -> ApplicationResult<Vec<Route>>
+where
+    T: CallRouteRepository + CallElevationApi + Sync,
+{
+    let conn = usecase.route_repository().get_connection().await?;
+    let mut routes = Vec::new();
+    for route_id in collection.route_ids() {
+        let mut route = usecase.route_repository().find(route_id, &conn).await?;
+        usecase
+            .elevation_api()
+            .attach_elevations(&mut route)
+            .await?;
+        route.calc_route_features_from_seg_list()?;
+        routes.push(route);
+    }
+    Ok(routes)
+}
+
+#[async_trait]
+impl<T> CollectionUseCase for T
+where
+    T: CallCollectionRepository + CallRouteRepository + CallElevationApi + CallUserAuthApi + Sync,
+{
+    async fn find(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: Option<&str>,
+    ) -> ApplicationResult<CollectionGetResponse> {
+        let conn = self.collection_repository().get_connection().await?;
+        let collection = self
+            .collection_repository()
+            .find(collection_id, &conn)
+            .await?;
+        authorize(
+            self,
+            &collection,
+            user_access_token,
+            PermissionType::Viewer,
+            &conn,
+        )
+        .await?;
+
+        let route_conn = self.route_repository().get_connection().await?;
+        let mut routes = Vec::new();
+        for route_id in collection.route_ids() {
+            routes.push(
+                self.route_repository()
+                    .find_info(route_id, &route_conn)
+                    .await?,
+            );
+        }
+        let stats = CollectionStats::from(routes.as_slice());
+
+        Ok(CollectionGetResponse {
+            collection,
+            routes,
+            stats,
+        })
+    }
+
+    async fn find_by_owner(
+        &self,
+        req: &CollectionListRequest,
+        user_access_token: Option<&str>,
+    ) -> ApplicationResult<CollectionListResponse> {
+        let owner_id = &req.owner_id;
+        let conn = self.collection_repository().get_connection().await?;
+        let user_id = match user_access_token {
+            Some(token) => Some(self.user_auth_api().authenticate(token).await?),
+            None => None,
+        };
+
+        let mut collections = Vec::new();
+        for collection in self
+            .collection_repository()
+            .find_by_owner(owner_id, &conn)
+            .await?
+        {
+            let granted = match &user_id {
+                Some(user_id) if user_id != owner_id => {
+                    self.collection_repository()
+                        .find_permission_type(collection.id(), user_id, &conn)
+                        .await?
+                }
+                _ => PermissionType::None,
+            };
+            if collection.permission_type(user_id.as_ref(), granted) >= PermissionType::Viewer {
+                collections.push(collection);
+            }
+        }
+
+        Ok(CollectionListResponse { collections })
+    }
+
+    async fn find_gpx(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: Option<&str>,
+    ) -> ApplicationResult<CollectionGetGpxResponse> {
+        let conn = self.collection_repository().get_connection().await?;
+        let collection = self
+            .collection_repository()
+            .find(collection_id, &conn)
+            .await?;
+        authorize(
+            self,
+            &collection,
+            user_access_token,
+            PermissionType::Viewer,
+            &conn,
+        )
+        .await?;
+
+        let routes = find_routes_for_export(self, &collection).await?;
+        RouteGpx::from_routes(collection.name(), routes)
+    }
+
+    async fn find_archive(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: Option<&str>,
+    ) -> ApplicationResult<CollectionGetArchiveResponse> {
+        let conn = self.collection_repository().get_connection().await?;
+        let collection = self
+            .collection_repository()
+            .find(collection_id, &conn)
+            .await?;
+        authorize(
+            self,
+            &collection,
+            user_access_token,
+            PermissionType::Viewer,
+            &conn,
+        )
+        .await?;
+
+        let gpxs = find_routes_for_export(self, &collection)
+            .await?
+            .into_iter()
+            .map(RouteGpx::try_from)
+            .collect::<ApplicationResult<Vec<_>>>()?;
+        CollectionArchive::from_gpxs(collection.name(), gpxs, *collection.updated_at())
+    }
+
+    async fn create(
+        &self,
+        user_access_token: &str,
+        req: &CollectionCreateRequest,
+    ) -> ApplicationResult<CollectionCreateResponse> {
+        let conn = self.collection_repository().get_connection().await?;
+        req.validate()?;
+        let owner_id = self.user_auth_api().authenticate(user_access_token).await?;
+        let collection = Collection::new(&req.name, owner_id, req.visibility);
+
+        self.collection_repository()
+            .insert(&collection, &conn)
+            .await?;
+
+        Ok(CollectionCreateResponse {
+            id: collection.id().clone(),
+        })
+    }
+
+    async fn update(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: &str,
+        req: &CollectionUpdateRequest,
+    ) -> ApplicationResult<Collection> {
+        let conn = self.collection_repository().get_connection().await?;
+        conn.transaction(|conn| {
+            async move {
+                let mut collection = self
+                    .collection_repository()
+                    .find(collection_id, conn)
+                    .await?;
+                let target_type = if req.visibility.is_some() {
+                    PermissionType::Owner
+                } else {
+                    PermissionType::Editor
+                };
+                authorize(
+                    self,
+                    &collection,
+                    Some(user_access_token),
+                    target_type,
+                    conn,
+                )
+                .await?;
+
+                req.apply(&mut collection)?;
+                self.collection_repository()
+                    .update(&collection, conn)
+                    .await?;
+
+                Ok(collection)
+            }
+            .boxed()
+        })
+        .await
+    }
+
+    async fn delete(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: &str,
+    ) -> ApplicationResult<()> {
+        let conn = self.collection_repository().get_connection().await?;
+        conn.transaction(|conn| {
+            async move {
+                let collection = self
+                    .collection_repository()
+                    .find(collection_id, conn)
+                    .await?;
+                authorize(
+                    self,
+                    &collection,
+                    Some(user_access_token),
+                    PermissionType::Owner,
+                    conn,
+                )
+                .await?;
+
+                self.collection_repository()
+                    .delete(collection_id, conn)
+                    .await
+            }
+            .boxed()
+        })
+        .await
+    }
+
+    async fn add_route(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: &str,
+        req: &CollectionRouteRequest,
+    ) -> ApplicationResult<Collection> {
+        let conn = self.collection_repository().get_connection().await?;
+        conn.transaction(|conn| {
+            async move {
+                let mut collection = self
+                    .collection_repository()
+                    .find(collection_id, conn)
+                    .await?;
+                authorize(
+                    self,
+                    &collection,
+                    Some(user_access_token),
+                    PermissionType::Editor,
+                    conn,
+                )
+                .await?;
+
+                // ルートが存在することを確かめる
+                let route_conn = self.route_repository().get_connection().await?;
+                self.route_repository()
+                    .find_info(&req.route_id, &route_conn)
+                    .await?;
+
+                collection.add_route(req.route_id.clone(), req.pos)?;
+                self.collection_repository()
+                    .update(&collection, conn)
+                    .await?;
+
+                Ok(collection)
+            }
+            .boxed()
+        })
+        .await
+    }
+
+    async fn remove_route(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: &str,
+        route_id: &RouteId,
+    ) -> ApplicationResult<Collection> {
+        let conn = self.collection_repository().get_connection().await?;
+        conn.transaction(|conn| {
+            async move {
+                let mut collection = self
+                    .collection_repository()
+                    .find(collection_id, conn)
+                    .await?;
+                authorize(
+                    self,
+                    &collection,
+                    Some(user_access_token),
+                    PermissionType::Editor,
+                    conn,
+                )
+                .await?;
+
+                collection.remove_route(route_id)?;
+                self.collection_repository()
+                    .update(&collection, conn)
+                    .await?;
+
+                Ok(collection)
+            }
+            .boxed()
+        })
+        .await
+    }
+
+    async fn reorder_routes(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: &str,
+        req: &CollectionReorderRequest,
+    ) -> ApplicationResult<Collection> {
+        let conn = self.collection_repository().get_connection().await?;
+        conn.transaction(|conn| {
+            async move {
+                let mut collection = self
+                    .collection_repository()
+                    .find(collection_id, conn)
+                    .await?;
+                authorize(
+                    self,
+                    &collection,
+                    Some(user_access_token),
+                    PermissionType::Editor,
+                    conn,
+                )
+                .await?;
+
+                collection.reorder(req.route_ids.clone())?;
+                self.collection_repository()
+                    .update(&collection, conn)
+                    .await?;
+
+                Ok(collection)
+            }
+            .boxed()
+        })
+        .await
+    }
+
+    async fn update_permission(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: &str,
+        req: &CollectionPermissionRequest,
+    ) -> ApplicationResult<()> {
+        let conn = self.collection_repository().get_connection().await?;
+        let permission_type = req.permission_type()?;
+        let collection = self
+            .collection_repository()
+            .find(collection_id, &conn)
+            .await?;
+        authorize(
+            self,
+            &collection,
+            Some(user_access_token),
+            PermissionType::Editor,
+            &conn,
+        )
+        .await?;
+
+        self.collection_repository()
+            .insert_or_update_permission(collection.id(), &req.user_id, permission_type, &conn)
+            .await
+    }
+
+    async fn delete_permission(
+        &self,
+        collection_id: &CollectionId,
+        user_access_token: &str,
+        req: &CollectionPermissionDeleteRequest,
+    ) -> ApplicationResult<()> {
+        let conn = self.collection_repository().get_connection().await?;
+        let collection = self
+            .collection_repository()
+            .find(collection_id, &conn)
+            .await?;
+        authorize(
+            self,
+            &collection,
+            Some(user_access_token),
+            PermissionType::Editor,
+            &conn,
+        )
+        .await?;
+
+        self.collection_repository()
+            .delete_permission(collection.id(), &req.user_id, &conn)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use crate::{expect_at_repository, expect_once};
+    use route_bucket_domain::{
+        external::{MockElevationApi, MockUserAuthApi},
+        model::{
+            collection::CollectionVisibility,
+            fixtures::{
+                collection::CollectionFixtures,
+                route::{RouteFixtures, RouteInfoFixtures},
+                user::UserIdFixtures,
+            },
+            route::RouteInfo,
+            user::UserId,
+        },
+        repository::{MockCollectionRepository, MockConnection, MockRouteRepository},
+    };
+    use route_bucket_utils::ApplicationError;
+    use rstest::rstest;
+
+    use super::*;
+
+    fn collection_id() -> CollectionId {
+        CollectionId::from_string("collection0".into())
+    }
+
+    fn doncic_token() -> String {
+        String::from("token.for.doncic")
+    }
+
+    fn porzingis_token() -> String {
+        String::from("token.for.porzingis")
+    }
+
+    fn route_id(id: &str) -> RouteId {
+        RouteId::from_string(id.into())
+    }
+
+    fn collection_with_visibility(visibility: CollectionVisibility) -> Collection {
+        let mut collection = Collection::rides_in_kanto();
+        collection.set_visibility(visibility);
+        collection
+    }
+
+    /// `Collection::rides_in_kanto`の各ルート
+    fn route_infos() -> Vec<(RouteId, RouteInfo)> {
+        vec![
+            (route_id("a"), RouteInfo::yokohama_to_chiba()),
+            (route_id("b"), RouteInfo::yokohama_to_tokyo()),
+            (
+                route_id("c"),
+                RouteInfo::filled_route0(100, 120, 1000., 0., 0),
+            ),
+        ]
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_find_public_collection_anonymously() {
+        let collection = collection_with_visibility(CollectionVisibility::Public);
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_find_at_collection_repository(collection_id(), collection.clone());
+        usecase.expect_find_infos_at_route_repository(route_infos());
+
+        let infos = route_infos()
+            .into_iter()
+            .map(|(_, info)| info)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            usecase.find(&collection_id(), None).await,
+            Ok(CollectionGetResponse {
+                collection,
+                stats: CollectionStats::from(infos.as_slice()),
+                routes: infos,
+            })
+        );
+    }
+
+    #[rstest]
+    #[case::private_anonymous(CollectionVisibility::Private, None)]
+    #[case::shared_anonymous(CollectionVisibility::Shared, None)]
+    #[case::shared_without_permission(CollectionVisibility::Shared, Some(PermissionType::None))]
+    #[tokio::test]
+    async fn cannot_find_collection_without_permission(
+        #[case] visibility: CollectionVisibility,
+        #[case] granted: Option<PermissionType>,
+    ) {
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_find_at_collection_repository(
+            collection_id(),
+            collection_with_visibility(visibility),
+        );
+        if let Some(granted) = granted {
+            usecase.expect_authenticate_at_auth_api(porzingis_token(), UserId::porzingis());
+            usecase
+                .expect_find_permission_type_at_collection_repository(UserId::porzingis(), granted);
+        }
+
+        let token = granted.map(|_| porzingis_token());
+        assert!(matches!(
+            usecase.find(&collection_id(), token.as_deref()).await,
+            Err(ApplicationError::AuthorizationError(_))
+        ));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_find_shared_collection_with_permission() {
+        let collection = collection_with_visibility(CollectionVisibility::Shared);
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_find_at_collection_repository(collection_id(), collection.clone());
+        usecase.expect_authenticate_at_auth_api(porzingis_token(), UserId::porzingis());
+        usecase.expect_find_permission_type_at_collection_repository(
+            UserId::porzingis(),
+            PermissionType::Viewer,
+        );
+        usecase.expect_find_infos_at_route_repository(route_infos());
+
+        let resp = usecase
+            .find(&collection_id(), Some(&porzingis_token()))
+            .await
+            .unwrap();
+        assert_eq!(resp.collection, collection);
+        assert_eq!(*resp.stats.route_count(), 3);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_find_only_visible_collections_by_owner() {
+        let collections = vec![
+            collection_with_visibility(CollectionVisibility::Private),
+            collection_with_visibility(CollectionVisibility::Shared),
+            collection_with_visibility(CollectionVisibility::Public),
+        ];
+        let mut usecase = TestCollectionUseCase::new();
+        expect_at_repository!(
+            usecase.collection_repository,
+            find_by_owner,
+            UserId::doncic(),
+            collections
+        );
+
+        assert_eq!(
+            usecase
+                .find_by_owner(
+                    &CollectionListRequest {
+                        owner_id: UserId::doncic()
+                    },
+                    None
+                )
+                .await,
+            Ok(CollectionListResponse {
+                collections: vec![collection_with_visibility(CollectionVisibility::Public)],
+            })
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_create() {
+        let req = CollectionCreateRequest {
+            name: "Rides in Kanto".into(),
+            visibility: CollectionVisibility::Public,
+        };
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+        expect_at_repository!(
+            usecase.collection_repository,
+            insert,
+            Collection::new(
+                "Rides in Kanto",
+                UserId::doncic(),
+                CollectionVisibility::Public
+            ),
+            ()
+        );
+
+        assert!(usecase.create(&doncic_token(), &req).await.is_ok());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn cannot_create_without_name() {
+        let req = CollectionCreateRequest {
+            name: "".into(),
+            visibility: CollectionVisibility::Private,
+        };
+        let usecase = TestCollectionUseCase::new();
+
+        assert!(matches!(
+            usecase.create(&doncic_token(), &req).await,
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_rename_as_editor() {
+        let req = CollectionUpdateRequest {
+            name: Some("Renamed".into()),
+            ..Default::default()
+        };
+        let mut expected = collection_with_visibility(CollectionVisibility::Shared);
+        expected.rename("Renamed");
+
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_find_at_collection_repository(
+            collection_id(),
+            collection_with_visibility(CollectionVisibility::Shared),
+        );
+        usecase.expect_authenticate_at_auth_api(porzingis_token(), UserId::porzingis());
+        usecase.expect_find_permission_type_at_collection_repository(
+            UserId::porzingis(),
+            PermissionType::Editor,
+        );
+        usecase.expect_update_at_collection_repository(expected.clone());
+
+        assert_eq!(
+            usecase
+                .update(&collection_id(), &porzingis_token(), &req)
+                .await,
+            Ok(expected)
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn cannot_change_visibility_as_editor() {
+        let req = CollectionUpdateRequest {
+            visibility: Some(CollectionVisibility::Public),
+            ..Default::default()
+        };
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_find_at_collection_repository(
+            collection_id(),
+            collection_with_visibility(CollectionVisibility::Shared),
+        );
+        usecase.expect_authenticate_at_auth_api(porzingis_token(), UserId::porzingis());
+        usecase.expect_find_permission_type_at_collection_repository(
+            UserId::porzingis(),
+            PermissionType::Editor,
+        );
+
+        assert!(matches!(
+            usecase
+                .update(&collection_id(), &porzingis_token(), &req)
+                .await,
+            Err(ApplicationError::AuthorizationError(_))
+        ));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_delete_as_owner() {
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_find_at_collection_repository(collection_id(), Collection::rides_in_kanto());
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+        expect_at_repository!(usecase.collection_repository, delete, collection_id(), ());
+
+        assert_eq!(
+            usecase.delete(&collection_id(), &doncic_token()).await,
+            Ok(())
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_add_route() {
+        let req = CollectionRouteRequest {
+            route_id: route_id("new"),
+            pos: Some(1),
+        };
+        let mut expected = Collection::rides_in_kanto();
+        expected.add_route(route_id("new"), Some(1)).unwrap();
+
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_find_at_collection_repository(collection_id(), Collection::rides_in_kanto());
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+        usecase.expect_get_connection_at_route_repository();
+        expect_at_repository!(
+            usecase.route_repository,
+            find_info,
+            route_id("new"),
+            RouteInfo::empty_route0(0)
+        );
+        usecase.expect_update_at_collection_repository(expected.clone());
+
+        assert_eq!(
+            usecase
+                .add_route(&collection_id(), &doncic_token(), &req)
+                .await,
+            Ok(expected)
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_remove_route() {
+        let mut expected = Collection::rides_in_kanto();
+        expected.remove_route(&route_id("b")).unwrap();
+
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_find_at_collection_repository(collection_id(), Collection::rides_in_kanto());
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+        usecase.expect_update_at_collection_repository(expected.clone());
+
+        assert_eq!(
+            usecase
+                .remove_route(&collection_id(), &doncic_token(), &route_id("b"))
+                .await,
+            Ok(expected)
+        );
+    }
+
+    #[rstest]
+    #[case::reversed(&["c", "b", "a"], true)]
+    #[case::missing(&["c", "b"], false)]
+    #[tokio::test]
+    async fn can_reorder_routes_only_with_permutation(#[case] ids: &[&str], #[case] is_ok: bool) {
+        let route_ids = ids.iter().map(|id| route_id(id)).collect::<Vec<_>>();
+        let req = CollectionReorderRequest {
+            route_ids: route_ids.clone(),
+        };
+        let mut expected = Collection::rides_in_kanto();
+
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_find_at_collection_repository(collection_id(), Collection::rides_in_kanto());
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+        if is_ok {
+            expected.reorder(route_ids).unwrap();
+            usecase.expect_update_at_collection_repository(expected.clone());
+        }
+
+        let result = usecase
+            .reorder_routes(&collection_id(), &doncic_token(), &req)
+            .await;
+        if is_ok {
+            assert_eq!(result, Ok(expected));
+        } else {
+            assert!(matches!(result, Err(ApplicationError::ValidationError(_))));
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_update_permission() {
+        let req = CollectionPermissionRequest {
+            user_id: UserId::porzingis(),
+            permission_type: PermissionType::Editor,
+        };
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_find_at_collection_repository(collection_id(), Collection::rides_in_kanto());
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+        expect_at_repository!(
+            usecase.collection_repository,
+            insert_or_update_permission,
+            collection_id(),
+            UserId::porzingis(),
+            PermissionType::Editor,
+            ()
+        );
+
+        assert_eq!(
+            usecase
+                .update_permission(&collection_id(), &doncic_token(), &req)
+                .await,
+            Ok(())
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn cannot_grant_owner_permission() {
+        let req = CollectionPermissionRequest {
+            user_id: UserId::porzingis(),
+            permission_type: PermissionType::Owner,
+        };
+        let usecase = TestCollectionUseCase::new();
+
+        assert!(matches!(
+            usecase
+                .update_permission(&collection_id(), &doncic_token(), &req)
+                .await,
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_delete_permission() {
+        let req = CollectionPermissionDeleteRequest {
+            user_id: UserId::porzingis(),
+        };
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_find_at_collection_repository(collection_id(), Collection::rides_in_kanto());
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+        expect_at_repository!(
+            usecase.collection_repository,
+            delete_permission,
+            collection_id(),
+            UserId::porzingis(),
+            ()
+        );
+
+        assert_eq!(
+            usecase
+                .delete_permission(&collection_id(), &doncic_token(), &req)
+                .await,
+            Ok(())
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_find_gpx() {
+        let mut collection = collection_with_visibility(CollectionVisibility::Public);
+        collection
+            .reorder(vec![route_id("b"), route_id("a"), route_id("c")])
+            .unwrap();
+        let routes = vec![
+            (route_id("a"), Route::yokohama_to_chiba_filled(true, false)),
+            (route_id("b"), Route::yokohama_to_tokyo_filled(true, false)),
+            (
+                route_id("c"),
+                Route::yokohama_to_chiba_via_tokyo_filled(true, false),
+            ),
+        ];
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_find_at_collection_repository(collection_id(), collection);
+        usecase.expect_export_routes(routes);
+
+        let mut expected = vec![
+            Route::yokohama_to_tokyo_filled(true, false),
+            Route::yokohama_to_chiba_filled(true, false),
+            Route::yokohama_to_chiba_via_tokyo_filled(true, false),
+        ];
+        for route in expected.iter_mut() {
+            route.calc_route_features_from_seg_list().unwrap();
+        }
+        assert_eq!(
+            usecase.find_gpx(&collection_id(), None).await,
+            RouteGpx::from_routes("Rides in Kanto", expected)
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_find_archive() {
+        let mut collection = collection_with_visibility(CollectionVisibility::Public);
+        collection.remove_route(&route_id("c")).unwrap();
+        let routes = vec![
+            (route_id("a"), Route::yokohama_to_chiba_filled(true, false)),
+            (route_id("b"), Route::yokohama_to_tokyo_filled(true, false)),
+        ];
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_find_at_collection_repository(collection_id(), collection.clone());
+        usecase.expect_export_routes(routes);
+
+        let gpxs = vec![
+            Route::yokohama_to_chiba_filled(true, false),
+            Route::yokohama_to_tokyo_filled(true, false),
+        ]
+        .into_iter()
+        .map(|mut route| {
+            route.calc_route_features_from_seg_list().unwrap();
+            RouteGpx::try_from(route).unwrap()
+        })
+        .collect();
+        assert_eq!(
+            usecase.find_archive(&collection_id(), None).await,
+            CollectionArchive::from_gpxs("Rides in Kanto", gpxs, *collection.updated_at())
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn cannot_export_private_collection_anonymously() {
+        let mut usecase = TestCollectionUseCase::new();
+        usecase.expect_find_at_collection_repository(collection_id(), Collection::rides_in_kanto());
+
+        assert!(matches!(
+            usecase.find_archive(&collection_id(), None).await,
+            Err(ApplicationError::AuthorizationError(_))
+        ));
+    }
+
+    struct TestCollectionUseCase {
+        collection_repository: MockCollectionRepository,
+        route_repository: MockRouteRepository,
+        elevation_api: MockElevationApi,
+        auth_api: MockUserAuthApi,
+    }
+
+    // setup methods for mocking
+    impl TestCollectionUseCase {
+        fn new() -> Self {
+            let mut usecase = TestCollectionUseCase {
+                collection_repository: MockCollectionRepository::new(),
+                route_repository: MockRouteRepository::new(),
+                elevation_api: MockElevationApi::new(),
+                auth_api: MockUserAuthApi::new(),
+            };
+            expect_at_repository!(
+                usecase.collection_repository,
+                get_connection,
+                MockConnection {}
+            );
+
+            usecase
+        }
+
+        fn expect_find_at_collection_repository(
+            &mut self,
+            param_id: CollectionId,
+            return_collection: Collection,
+        ) {
+            expect_at_repository!(
+                self.collection_repository,
+                find,
+                param_id,
+                return_collection
+            );
+        }
+
+        fn expect_find_permission_type_at_collection_repository(
+            &mut self,
+            param_user_id: UserId,
+            return_permission_type: PermissionType,
+        ) {
+            expect_at_repository!(
+                self.collection_repository,
+                find_permission_type,
+                collection_id(),
+                param_user_id,
+                return_permission_type
+            );
+        }
+
+        fn expect_update_at_collection_repository(&mut self, param_collection: Collection) {
+            expect_at_repository!(self.collection_repository, update, param_collection, ());
+        }
+
+        fn expect_get_connection_at_route_repository(&mut self) {
+            expect_at_repository!(self.route_repository, get_connection, MockConnection {});
+        }
+
+        /// `infos`のどのidでも呼ばれうる
+        fn expect_find_infos_at_route_repository(&mut self, infos: Vec<(RouteId, RouteInfo)>) {
+            self.expect_get_connection_at_route_repository();
+            self.route_repository
+                .expect_find_info()
+                .times(infos.len())
+                .returning(move |id, _| {
+                    Ok(infos
+                        .iter()
+                        .find(|(route_id, _)| route_id == id)
+                        .unwrap()
+                        .1
+                        .clone())
+                });
+        }
+
+        /// 書き出す各ルートを読み出し，標高を(そのまま)付け直す
+        fn expect_export_routes(&mut self, routes: Vec<(RouteId, Route)>) {
+            self.expect_get_connection_at_route_repository();
+            self.elevation_api
+                .expect_attach_elevations()
+                .times(routes.len())
+                .returning(|_| Ok(()));
+            self.route_repository
+                .expect_find()
+                .times(routes.len())
+                .returning(move |id, _| {
+                    Ok(routes
+                        .iter()
+                        .find(|(route_id, _)| route_id == id)
+                        .unwrap()
+                        .1
+                        .clone())
+                });
+        }
+
+        fn expect_authenticate_at_auth_api(&mut self, param_token: String, return_id: UserId) {
+            expect_once!(self.auth_api, authenticate, param_token, return_id);
+        }
+    }
+
+    // impls to enable trait CollectionUseCase
+    impl CallCollectionRepository for TestCollectionUseCase {
+        type CollectionRepository = MockCollectionRepository;
+
+        fn collection_repository(&self) -> &Self::CollectionRepository {
+            &self.collection_repository
+        }
+    }
+
+    impl CallRouteRepository for TestCollectionUseCase {
+        type RouteRepository = MockRouteRepository;
+
+        fn route_repository(&self) -> &Self::RouteRepository {
+            &self.route_repository
+        }
+    }
+
+    impl CallElevationApi for TestCollectionUseCase {
+        type ElevationApi = MockElevationApi;
+
+        fn elevation_api(&self) -> &Self::ElevationApi {
+            &self.elevation_api
+        }
+    }
+
+    impl CallUserAuthApi for TestCollectionUseCase {
+        type UserAuthApi = MockUserAuthApi;
+
+        fn user_auth_api(&self) -> &Self::UserAuthApi {
+            &self.auth_api
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use derive_more::From;
+use serde::Deserialize;
+use validator::Validate;
+
+use route_bucket_domain::model::{
+    collection::{Collection, CollectionVisibility},
+    permission::PermissionType,
+    route::RouteId,
+    user::UserId,
+};
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+#[derive(From, Deserialize)]
+pub struct CollectionListRequest {
+    pub(super) owner_id: UserId,
+}
+
+#[derive(From, Deserialize, Validate)]
+pub struct CollectionCreateRequest {
+    #[validate(length(min = 1, max = 50))]
+    pub(super) name: String,
+    #[serde(default)]
+    pub(super) visibility: CollectionVisibility,
+}
+
+/// 指定した項目だけを書き換える
+#[derive(Default, From, Deserialize, Validate)]
+pub struct CollectionUpdateRequest {
+    #[validate(length(min = 1, max = 50))]
+    pub(super) name: Option<String>,
+    pub(super) visibility: Option<CollectionVisibility>,
+}
+
+impl CollectionUpdateRequest {
+    pub(super) fn apply(&self, collection: &mut Collection) -> ApplicationResult<()> {
+        self.validate()?;
+        if let Some(name) = &self.name {
+            collection.rename(name);
+        }
+        if let Some(visibility) = self.visibility {
+            collection.set_visibility(visibility);
+        }
+        Ok(())
+    }
+}
+
+#[derive(From, Deserialize)]
+pub struct CollectionRouteRequest {
+    pub(super) route_id: RouteId,
+    /// 入れる位置 (省略すると末尾)
+    pub(super) pos: Option<usize>,
+}
+
+#[derive(From, Deserialize)]
+pub struct CollectionReorderRequest {
+    /// 並べ替えた後の順番
+    pub(super) route_ids: Vec<RouteId>,
+}
+
+#[derive(From, Deserialize)]
+pub struct CollectionPermissionRequest {
+    pub(super) user_id: UserId,
+    #[serde(alias = "type")]
+    pub(super) permission_type: PermissionType,
+}
+
+impl CollectionPermissionRequest {
+    /// 所有者は移せないので，与えられるのはEditorまで
+    pub(super) fn permission_type(&self) -> ApplicationResult<PermissionType> {
+        if self.permission_type == PermissionType::Owner {
+            return Err(ApplicationError::ValidationError(
+                "Owner permission cannot be granted on a collection".into(),
+            ));
+        }
+        Ok(self.permission_type)
+    }
+}
+
+#[derive(From, Deserialize)]
+pub struct CollectionPermissionDeleteRequest {
+    pub(super) user_id: UserId,
+}
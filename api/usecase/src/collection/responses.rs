@@ -0,0 +1,33 @@
+use serde::Serialize;
+
+use route_bucket_domain::model::{
+    collection::{Collection, CollectionArchive, CollectionId, CollectionStats},
+    route::{RouteGpx, RouteInfo},
+};
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct CollectionGetResponse {
+    #[serde(flatten)]
+    pub collection: Collection,
+    /// コレクションの並び順どおり
+    pub routes: Vec<RouteInfo>,
+    pub stats: CollectionStats,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct CollectionListResponse {
+    /// 更新の新しい順
+    pub collections: Vec<Collection>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct CollectionCreateResponse {
+    pub id: CollectionId,
+}
+
+pub type CollectionGetGpxResponse = RouteGpx;
+
+pub type CollectionGetArchiveResponse = CollectionArchive;
@@ -0,0 +1,142 @@
+use std::convert::TryInto;
+
+use async_trait::async_trait;
+use route_bucket_domain::{
+    external::{CallElevationApi, ElevationApi},
+    model::route::{Coordinate, ElevationProfile},
+};
+use route_bucket_utils::ApplicationResult;
+
+pub use requests::*;
+
+mod requests;
+
+#[async_trait]
+pub trait ElevationUseCase {
+    async fn lookup(&self, req: ElevationLookupRequest) -> ApplicationResult<ElevationProfile>;
+}
+
+#[async_trait]
+impl<T> ElevationUseCase for T
+where
+    T: CallElevationApi + Sync,
+{
+    async fn lookup(&self, req: ElevationLookupRequest) -> ApplicationResult<ElevationProfile> {
+        let coords: Vec<Coordinate> = req.try_into()?;
+
+        let elevations = self.elevation_api().get_elevations(&coords).await?;
+
+        Ok(ElevationProfile::new(elevations))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use rstest::rstest;
+
+    use route_bucket_domain::{
+        external::MockElevationApi,
+        model::{
+            fixtures::route::CoordinateFixtures,
+            route::{Elevation, Polyline},
+        },
+    };
+    use route_bucket_utils::ApplicationError;
+
+    use super::*;
+
+    fn elevation(value: i32) -> Elevation {
+        Elevation::try_from(value).unwrap()
+    }
+
+    /// yokohamaでは1m，tokyoでは標高なし，それ以外では11mを返すモック
+    fn elevation_api() -> MockElevationApi {
+        let mut api = MockElevationApi::new();
+        api.expect_get_elevations().once().returning(|coords| {
+            Ok(coords
+                .iter()
+                .map(|coord| {
+                    if *coord == Coordinate::yokohama(false, None) {
+                        Some(elevation(1))
+                    } else if *coord == Coordinate::tokyo(false, None) {
+                        None
+                    } else {
+                        Some(elevation(11))
+                    }
+                })
+                .collect())
+        });
+        api
+    }
+
+    #[rstest]
+    #[case::coordinates(
+        ElevationLookupRequest::Coordinates {
+            coordinates: Coordinate::yokohama_to_chiba_via_tokyo_coords(false, None)
+        },
+        vec![Some(elevation(1)), None, Some(elevation(11))],
+    )]
+    #[case::polyline(
+        ElevationLookupRequest::Polyline {
+            polyline: Coordinate::yokohama_to_chiba_polyline()
+        },
+        vec![Some(elevation(1)), Some(elevation(11))],
+    )]
+    #[tokio::test]
+    async fn can_lookup(
+        #[case] req: ElevationLookupRequest,
+        #[case] expected_elevations: Vec<Option<Elevation>>,
+    ) {
+        let usecase = TestElevationUseCase {
+            elevation_api: elevation_api(),
+        };
+        assert_eq!(
+            usecase.lookup(req).await,
+            Ok(ElevationProfile::new(expected_elevations))
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn cannot_lookup_invalid_polyline() {
+        let usecase = TestElevationUseCase {
+            elevation_api: MockElevationApi::new(),
+        };
+        let req = ElevationLookupRequest::Polyline {
+            polyline: Polyline::from(String::from("invalid polyline")),
+        };
+        assert!(matches!(
+            usecase.lookup(req).await,
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn cannot_lookup_too_many_points() {
+        let usecase = TestElevationUseCase {
+            elevation_api: MockElevationApi::new(),
+        };
+        let req = ElevationLookupRequest::Coordinates {
+            coordinates: vec![Coordinate::tokyo(false, None); MAX_LOOKUP_POINTS + 1],
+        };
+        assert!(matches!(
+            usecase.lookup(req).await,
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
+    struct TestElevationUseCase {
+        elevation_api: MockElevationApi,
+    }
+
+    impl CallElevationApi for TestElevationUseCase {
+        type ElevationApi = MockElevationApi;
+
+        fn elevation_api(&self) -> &Self::ElevationApi {
+            &self.elevation_api
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::convert::{TryFrom, TryInto};
+
+use serde::Deserialize;
+
+use route_bucket_domain::model::route::{Coordinate, Polyline};
+use route_bucket_utils::ApplicationError;
+
+/// 一度に標高を引ける点の数の上限
+pub const MAX_LOOKUP_POINTS: usize = 10000;
+
+/// 座標のリストか，エンコードされたPolylineのどちらかを受け付ける
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum ElevationLookupRequest {
+    Coordinates { coordinates: Vec<Coordinate> },
+    Polyline { polyline: Polyline },
+}
+
+impl TryFrom<ElevationLookupRequest> for Vec<Coordinate> {
+    type Error = ApplicationError;
+
+    fn try_from(req: ElevationLookupRequest) -> Result<Self, Self::Error> {
+        let coords: Vec<Coordinate> = match req {
+            ElevationLookupRequest::Coordinates { coordinates } => coordinates,
+            ElevationLookupRequest::Polyline { polyline } => {
+                polyline.try_into().map_err(|err: ApplicationError| {
+                    ApplicationError::ValidationError(format!("Invalid polyline. ({})", err))
+                })?
+            }
+        };
+        if coords.len() > MAX_LOOKUP_POINTS {
+            return Err(ApplicationError::ValidationError(format!(
+                "Too many points ({} > {}).",
+                coords.len(),
+                MAX_LOOKUP_POINTS
+            )));
+        }
+        Ok(coords)
+    }
+}
@@ -1,4 +1,7 @@
+pub mod collection;
+pub mod elevation;
 pub mod route;
+pub mod tile;
 pub mod user;
 
 #[cfg(test)]
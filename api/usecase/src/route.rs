@@ -10,25 +10,84 @@ use route_bucket_domain::external::{
     RouteInterpolationApi, UserAuthApi,
 };
 use route_bucket_domain::model::permission::{Permission, PermissionType};
-use route_bucket_domain::model::route::{Operation, Route, RouteId, RouteInfo, RouteSearchQuery};
+use route_bucket_domain::model::route::{
+    DaylightChecker, ElevationSource, EnergyModel, Operation, ProximityQuery, Route, RouteGpx,
+    RouteId, RouteInfo, RouteSearchCursor, RouteSearchQuery, RouteSimilarity, Segment, SegmentList,
+    SimilarExtentQuery, StagePlan,
+};
 use route_bucket_domain::repository::{
     CallPermissionRepository, CallRouteRepository, Connection, PermissionRepository, Repository,
     RouteRepository,
 };
-use route_bucket_utils::ApplicationResult;
+use route_bucket_utils::{ApplicationError, ApplicationResult};
 
 mod requests;
 mod responses;
 
+/// 重複の警告に添えるルートの数の上限
+const MAX_NEAR_DUPLICATES: usize = 5;
+
 #[async_trait]
 pub trait RouteUseCase {
-    async fn find(&self, route_id: &RouteId) -> ApplicationResult<RouteGetResponse>;
+    async fn find(
+        &self,
+        route_id: &RouteId,
+        req: &RouteGetRequest,
+    ) -> ApplicationResult<RouteGetResponse>;
 
     async fn find_all(&self) -> ApplicationResult<RouteSearchResponse>;
 
     async fn search(&self, query: RouteSearchQuery) -> ApplicationResult<RouteSearchResponse>;
 
-    async fn find_gpx(&self, route_id: &RouteId) -> ApplicationResult<RouteGetGpxResponse>;
+    /// 地点の近くを通る(または出発する)ルートを近い順に返す
+    async fn search_nearby(&self, query: ProximityQuery) -> ApplicationResult<RouteNearbyResponse>;
+
+    /// 形の近いルートを返す
+    ///
+    /// `near_duplicate`のルートがあれば，作り直しではないかをユーザーに確かめる
+    async fn find_similar(
+        &self,
+        route_id: &RouteId,
+        req: &RouteSimilarRequest,
+    ) -> ApplicationResult<RouteSimilarResponse>;
+
+    /// よく使われているタグを返す
+    async fn find_popular_tags(
+        &self,
+        req: &PopularTagsRequest,
+    ) -> ApplicationResult<PopularTagsResponse>;
+
+    async fn find_gpx(
+        &self,
+        route_id: &RouteId,
+        req: &RouteGetRequest,
+    ) -> ApplicationResult<RouteGetGpxResponse>;
+
+    async fn estimate_energy(
+        &self,
+        route_id: &RouteId,
+        req: &RouteEnergyRequest,
+    ) -> ApplicationResult<RouteEnergyResponse>;
+
+    async fn check_daylight(
+        &self,
+        route_id: &RouteId,
+        req: &RouteDaylightRequest,
+    ) -> ApplicationResult<RouteDaylightResponse>;
+
+    async fn split_into_stages(
+        &self,
+        route_id: &RouteId,
+        req: &RouteStagesRequest,
+    ) -> ApplicationResult<RouteStagesResponse>;
+
+    /// `day`が指定されていればその日のステージのみ，なければ全ステージを別々のtrkとして持つGPXを返す
+    async fn find_stages_gpx(
+        &self,
+        route_id: &RouteId,
+        req: &RouteStagesRequest,
+        day: Option<usize>,
+    ) -> ApplicationResult<RouteGetGpxResponse>;
 
     async fn create(
         &self,
@@ -36,6 +95,13 @@ pub trait RouteUseCase {
         req: &RouteCreateRequest,
     ) -> ApplicationResult<RouteCreateResponse>;
 
+    /// GPXのトラックをルートとして取り込む (点が`MAX_TRACK_POINTS`を超えるトラックは取り込まない)
+    async fn import_gpx(
+        &self,
+        user_access_token: &str,
+        req: &RouteImportRequest,
+    ) -> ApplicationResult<RouteCreateResponse>;
+
     async fn rename(
         &self,
         route_id: &RouteId,
@@ -43,6 +109,14 @@ pub trait RouteUseCase {
         req: &RouteRenameRequest,
     ) -> ApplicationResult<RouteInfo>;
 
+    /// 名前や説明などを，指定された項目だけ書き換える
+    async fn update_metadata(
+        &self,
+        route_id: &RouteId,
+        user_access_token: &str,
+        req: &RouteMetadataRequest,
+    ) -> ApplicationResult<RouteInfo>;
+
     async fn add_point(
         &self,
         route_id: &RouteId,
@@ -67,6 +141,29 @@ pub trait RouteUseCase {
         req: &NewPointRequest,
     ) -> ApplicationResult<RouteOperationResponse>;
 
+    async fn set_waypoint_elevation(
+        &self,
+        route_id: &RouteId,
+        user_access_token: &str,
+        pos: usize,
+        req: &WaypointElevationRequest,
+    ) -> ApplicationResult<RouteOperationResponse>;
+
+    async fn set_segment_interpolation(
+        &self,
+        route_id: &RouteId,
+        user_access_token: &str,
+        pos: usize,
+        req: &SegmentInterpolationRequest,
+    ) -> ApplicationResult<RouteOperationResponse>;
+
+    async fn set_elevation_source(
+        &self,
+        route_id: &RouteId,
+        user_access_token: &str,
+        req: &ElevationSourceRequest,
+    ) -> ApplicationResult<RouteOperationResponse>;
+
     async fn clear_route(
         &self,
         route_id: &RouteId,
@@ -100,6 +197,105 @@ pub trait RouteUseCase {
         user_access_token: &str,
         req: &DeletePermissionRequest,
     ) -> ApplicationResult<()>;
+
+    async fn add_tag(
+        &self,
+        route_id: &RouteId,
+        user_access_token: &str,
+        req: &RouteTagRequest,
+    ) -> ApplicationResult<RouteInfo>;
+
+    async fn remove_tag(
+        &self,
+        route_id: &RouteId,
+        user_access_token: &str,
+        req: &RouteTagRequest,
+    ) -> ApplicationResult<RouteInfo>;
+}
+
+/// `query`で探した候補と`route`の形を比べ，近い順に`limit`個返す
+///
+/// 候補は形だけで比べ，返すルートだけ情報を読む
+async fn compare_shapes<T>(
+    usecase: &T,
+    route: &Route,
+    query: &SimilarExtentQuery,
+    limit: usize,
+    conn: &<T::RouteRepository as Repository>::Connection,
+) -> ApplicationResult<Vec<SimilarRoute>>
+where
+    T: CallRouteRepository + Sync,
+{
+    let ids = usecase
+        .route_repository()
+        .find_ids_with_similar_extent(query, conn)
+        .await?
+        .into_iter()
+        .filter(|id| id != route.info().id())
+        .collect::<Vec<_>>();
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut matches = Vec::new();
+    for (id, seg_list) in usecase
+        .route_repository()
+        .find_seg_lists(&ids, conn)
+        .await?
+    {
+        if let Some(similarity) = RouteSimilarity::between(route.seg_list(), &seg_list)? {
+            matches.push((id, similarity));
+        }
+    }
+    matches.sort_by_key(|(_, similarity)| *similarity.frechet_distance());
+    matches.truncate(limit);
+
+    let mut routes = Vec::with_capacity(matches.len());
+    for (id, similarity) in matches {
+        routes.push(SimilarRoute {
+            route_info: usecase.route_repository().find_info(&id, conn).await?,
+            similarity,
+        });
+    }
+    Ok(routes)
+}
+
+/// `route`と同じ所有者の，ほぼ同じ形のルート．点が無ければ探さない
+///
+/// 他のルートを読むので，編集のトランザクションを終えてから呼ぶ
+async fn find_near_duplicates<T>(
+    usecase: &T,
+    route: &Route,
+    conn: &<T::RouteRepository as Repository>::Connection,
+) -> ApplicationResult<Vec<SimilarRoute>>
+where
+    T: CallRouteRepository + Sync,
+{
+    let bbox = match route.seg_list().calc_bounding_box() {
+        Ok(bbox) => bbox,
+        Err(_) => return Ok(Vec::new()),
+    };
+    let query = SimilarExtentQuery::near_duplicates_of(bbox, route.info().owner_id().clone())?;
+    let routes = compare_shapes(usecase, route, &query, MAX_NEAR_DUPLICATES, conn).await?;
+    Ok(routes
+        .into_iter()
+        .filter(|similar| *similar.similarity.near_duplicate())
+        .collect())
+}
+
+/// 編集したルートを返す．ほぼ同じ形のルートがあれば警告として添える
+async fn operation_response<T>(
+    usecase: &T,
+    route: Route,
+    conn: &<T::RouteRepository as Repository>::Connection,
+) -> ApplicationResult<RouteOperationResponse>
+where
+    T: CallRouteRepository + Sync,
+{
+    let near_duplicates = find_near_duplicates(usecase, &route, conn).await?;
+    let mut resp: RouteOperationResponse = route.try_into()?;
+    resp.near_duplicates = near_duplicates;
+    Ok(resp)
 }
 
 #[async_trait]
@@ -112,14 +308,28 @@ where
         + CallUserAuthApi
         + Sync,
 {
-    async fn find(&self, route_id: &RouteId) -> ApplicationResult<RouteGetResponse> {
+    async fn find(
+        &self,
+        route_id: &RouteId,
+        req: &RouteGetRequest,
+    ) -> ApplicationResult<RouteGetResponse> {
+        let marker_interval = req.marker_interval()?;
         let conn = self.route_repository().get_connection().await?;
 
         let mut route = self.route_repository().find(route_id, &conn).await?;
-        self.elevation_api().attach_elevations(&mut route)?;
+        self.elevation_api().attach_elevations(&mut route).await?;
         route.calc_route_features_from_seg_list()?;
 
-        route.try_into()
+        let distance_markers = match marker_interval {
+            Some(interval) => route.seg_list().calc_distance_markers(interval)?,
+            None => Vec::new(),
+        };
+        let uncovered_segments = self
+            .elevation_api()
+            .find_uncovered_segments(route.seg_list());
+        let mut resp: RouteGetResponse = (route, distance_markers).try_into()?;
+        resp.uncovered_segments = uncovered_segments;
+        Ok(resp)
     }
 
     async fn find_all(&self) -> ApplicationResult<RouteSearchResponse> {
@@ -134,29 +344,194 @@ where
         Ok(RouteSearchResponse {
             route_infos,
             result_num,
+            next_cursor: None,
         })
     }
 
     async fn search(&self, query: RouteSearchQuery) -> ApplicationResult<RouteSearchResponse> {
         let conn = self.route_repository().get_connection().await?;
+        query.validate_cursor()?;
+
+        let route_infos = self
+            .route_repository()
+            .search_infos(query.clone(), &conn)
+            .await?;
+        // ページが埋まっていれば続きがあるかもしれない
+        let next_cursor = query
+            .page_size
+            .filter(|page_size| *page_size > 0 && route_infos.len() >= *page_size)
+            .and_then(|_| route_infos.last())
+            .map(|info| RouteSearchCursor::after(info, query.sort_by, query.order));
 
         Ok(RouteSearchResponse {
-            route_infos: self
-                .route_repository()
-                .search_infos(query.clone(), &conn)
-                .await?,
+            route_infos,
             result_num: self.route_repository().count_infos(query, &conn).await?,
+            next_cursor,
+        })
+    }
+
+    async fn search_nearby(&self, query: ProximityQuery) -> ApplicationResult<RouteNearbyResponse> {
+        let conn = self.route_repository().get_connection().await?;
+        query.center()?;
+        query.radius()?;
+
+        // 候補は形だけで距離を確かめ，返すルートだけ情報を読む
+        let ids = self.route_repository().find_ids_near(&query, &conn).await?;
+        let mut matches = Vec::new();
+        for (id, seg_list) in self.route_repository().find_seg_lists(&ids, &conn).await? {
+            if let Some(distance) = query.match_distance(&seg_list)? {
+                matches.push((id, distance));
+            }
+        }
+        matches.sort_by_key(|(_, distance)| *distance);
+        if let Some(limit) = query.limit {
+            matches.truncate(limit);
+        }
+
+        let mut routes = Vec::with_capacity(matches.len());
+        for (id, distance) in matches {
+            routes.push(NearbyRoute {
+                route_info: self.route_repository().find_info(&id, &conn).await?,
+                distance,
+            });
+        }
+        Ok(RouteNearbyResponse { routes })
+    }
+
+    async fn find_similar(
+        &self,
+        route_id: &RouteId,
+        req: &RouteSimilarRequest,
+    ) -> ApplicationResult<RouteSimilarResponse> {
+        let limit = req.limit()?;
+        let conn = self.route_repository().get_connection().await?;
+
+        let route = self.route_repository().find(route_id, &conn).await?;
+        let bbox = match route.seg_list().calc_bounding_box() {
+            Ok(bbox) => bbox,
+            Err(_) => return Ok(RouteSimilarResponse { routes: Vec::new() }),
+        };
+
+        // 範囲が大きくずれたルートは似ていないので，範囲で候補を絞ってから形を比べる
+        let query = SimilarExtentQuery::similar_to(
+            bbox,
+            req.same_owner.then(|| route.info().owner_id().clone()),
+        )?;
+        let routes = compare_shapes(self, &route, &query, limit, &conn).await?;
+
+        Ok(RouteSimilarResponse { routes })
+    }
+
+    async fn find_popular_tags(
+        &self,
+        req: &PopularTagsRequest,
+    ) -> ApplicationResult<PopularTagsResponse> {
+        let conn = self.route_repository().get_connection().await?;
+        let limit = req.limit()?;
+
+        let tags = self.route_repository().count_tags(limit, &conn).await?;
+        Ok(PopularTagsResponse { tags })
+    }
+
+    async fn find_gpx(
+        &self,
+        route_id: &RouteId,
+        req: &RouteGetRequest,
+    ) -> ApplicationResult<RouteGetGpxResponse> {
+        let marker_interval = req.marker_interval()?;
+        let conn = self.route_repository().get_connection().await?;
+
+        let mut route = self.route_repository().find(route_id, &conn).await?;
+        self.elevation_api().attach_elevations(&mut route).await?;
+        route.calc_route_features_from_seg_list()?;
+
+        let distance_markers = match marker_interval {
+            Some(interval) => route.seg_list().calc_distance_markers(interval)?,
+            None => Vec::new(),
+        };
+        (route, distance_markers).try_into()
+    }
+
+    async fn estimate_energy(
+        &self,
+        route_id: &RouteId,
+        req: &RouteEnergyRequest,
+    ) -> ApplicationResult<RouteEnergyResponse> {
+        let model: EnergyModel = req.try_into()?;
+        let conn = self.route_repository().get_connection().await?;
+
+        let mut route = self.route_repository().find(route_id, &conn).await?;
+        self.elevation_api().attach_elevations(&mut route).await?;
+
+        Ok(RouteEnergyResponse {
+            total: model.estimate(route.seg_list()),
+            segments: model.estimate_each_segment(route.seg_list()),
         })
     }
 
-    async fn find_gpx(&self, route_id: &RouteId) -> ApplicationResult<RouteGetGpxResponse> {
+    async fn check_daylight(
+        &self,
+        route_id: &RouteId,
+        req: &RouteDaylightRequest,
+    ) -> ApplicationResult<RouteDaylightResponse> {
+        let checker: DaylightChecker = req.try_into()?;
         let conn = self.route_repository().get_connection().await?;
 
         let mut route = self.route_repository().find(route_id, &conn).await?;
-        self.elevation_api().attach_elevations(&mut route)?;
+        self.elevation_api().attach_elevations(&mut route).await?;
         route.calc_route_features_from_seg_list()?;
 
-        route.try_into()
+        checker.check(route.seg_list())
+    }
+
+    async fn split_into_stages(
+        &self,
+        route_id: &RouteId,
+        req: &RouteStagesRequest,
+    ) -> ApplicationResult<RouteStagesResponse> {
+        let plan: StagePlan = req.try_into()?;
+        let conn = self.route_repository().get_connection().await?;
+
+        let mut route = self.route_repository().find(route_id, &conn).await?;
+        self.elevation_api().attach_elevations(&mut route).await?;
+        route.calc_route_features_from_seg_list()?;
+
+        Ok(RouteStagesResponse {
+            stages: plan.split(route.seg_list())?,
+        })
+    }
+
+    async fn find_stages_gpx(
+        &self,
+        route_id: &RouteId,
+        req: &RouteStagesRequest,
+        day: Option<usize>,
+    ) -> ApplicationResult<RouteGetGpxResponse> {
+        let plan: StagePlan = req.try_into()?;
+        let conn = self.route_repository().get_connection().await?;
+
+        let mut route = self.route_repository().find(route_id, &conn).await?;
+        self.elevation_api().attach_elevations(&mut route).await?;
+        route.calc_route_features_from_seg_list()?;
+
+        let mut stages = plan.split(route.seg_list())?;
+        let (mut info, _, _) = route.into();
+        if let Some(day) = day {
+            let stage = stages
+                .into_iter()
+                .find(|stage| *stage.day() == day)
+                .ok_or_else(|| {
+                    ApplicationError::ResourceNotFound(format!(
+                        "Route {} has no stage for day {}.",
+                        route_id, day
+                    ))
+                })?;
+            let name = format!("{}_day{}", info.name(), day);
+            info.rename(&name);
+            stages = vec![stage];
+        }
+
+        RouteGpx::from_stages(info, stages)
     }
 
     async fn create(
@@ -176,6 +551,7 @@ where
 
                 Ok(RouteCreateResponse {
                     id: route_info.id().clone(),
+                    near_duplicates: Vec::new(),
                 })
             }
             .boxed()
@@ -183,6 +559,55 @@ where
         .await
     }
 
+    async fn import_gpx(
+        &self,
+        user_access_token: &str,
+        req: &RouteImportRequest,
+    ) -> ApplicationResult<RouteCreateResponse> {
+        let conn = self.route_repository().get_connection().await?;
+        let route = conn
+            .transaction(|conn| {
+                async move {
+                    let owner_id = self.user_auth_api().authenticate(user_access_token).await?;
+
+                    let (gpx_name, points) = RouteGpx::read_track(req.gpx.as_bytes())?;
+                    let name = req.name.clone().or(gpx_name).ok_or_else(|| {
+                        ApplicationError::ValidationError(
+                            "The GPX has no name. Please specify the name of the route.".into(),
+                        )
+                    })?;
+                    let seg_list = SegmentList::from_track(points)?;
+                    let elevation_source = req.elevation_source.unwrap_or_else(|| {
+                        if seg_list.iter().any(Segment::has_source_elevations) {
+                            ElevationSource::Source
+                        } else {
+                            ElevationSource::Dem
+                        }
+                    });
+
+                    let mut route =
+                        Route::new(RouteInfo::new(&name, owner_id), Vec::new(), seg_list);
+                    route.set_elevation_source(elevation_source);
+                    self.route_repository()
+                        .insert_info(route.info(), conn)
+                        .await?;
+
+                    self.elevation_api().attach_elevations(&mut route).await?;
+                    route.calc_route_features_from_seg_list()?;
+                    self.route_repository().update(&route, conn).await?;
+
+                    Ok(route)
+                }
+                .boxed()
+            })
+            .await?;
+
+        Ok(RouteCreateResponse {
+            id: route.info().id().clone(),
+            near_duplicates: find_near_duplicates(self, &route, &conn).await?,
+        })
+    }
+
     async fn rename(
         &self,
         route_id: &RouteId,
@@ -212,49 +637,80 @@ where
         .await
     }
 
-    async fn add_point(
+    async fn update_metadata(
         &self,
         route_id: &RouteId,
         user_access_token: &str,
-        pos: usize,
-        req: &NewPointRequest,
-    ) -> ApplicationResult<RouteOperationResponse> {
+        req: &RouteMetadataRequest,
+    ) -> ApplicationResult<RouteInfo> {
         let conn = self.route_repository().get_connection().await?;
         conn.transaction(|conn| {
             async move {
-                let mut route = self.route_repository().find(route_id, conn).await?;
+                let mut route_info = self.route_repository().find_info(route_id, conn).await?;
                 let user_id = self.user_auth_api().authenticate(user_access_token).await?;
 
                 let perm_conn = self.permission_repository().get_connection().await?;
                 self.permission_repository()
-                    .authorize_user(route.info(), &user_id, PermissionType::Editor, &perm_conn)
+                    .authorize_user(&route_info, &user_id, PermissionType::Editor, &perm_conn)
                     .await?;
 
-                let op = Operation::new_add(
-                    pos,
-                    self.route_interpolation_api()
-                        .correct_coordinate(&req.coord, req.mode)
-                        .await?,
-                    route.seg_list(),
-                    req.mode,
-                )?;
-                route.push_operation(op)?;
-
-                self.route_interpolation_api()
-                    .interpolate_empty_segments(&mut route)
+                req.apply(&mut route_info)?;
+                self.route_repository()
+                    .update_info(&route_info, conn)
                     .await?;
-                self.elevation_api().attach_elevations(&mut route)?;
-                route.calc_route_features_from_seg_list()?;
 
-                self.route_repository().update(&route, conn).await?;
-
-                route.try_into()
+                Ok(route_info)
             }
             .boxed()
         })
         .await
     }
 
+    async fn add_point(
+        &self,
+        route_id: &RouteId,
+        user_access_token: &str,
+        pos: usize,
+        req: &NewPointRequest,
+    ) -> ApplicationResult<RouteOperationResponse> {
+        let conn = self.route_repository().get_connection().await?;
+        let route = conn
+            .transaction(|conn| {
+                async move {
+                    let mut route = self.route_repository().find(route_id, conn).await?;
+                    let user_id = self.user_auth_api().authenticate(user_access_token).await?;
+
+                    let perm_conn = self.permission_repository().get_connection().await?;
+                    self.permission_repository()
+                        .authorize_user(route.info(), &user_id, PermissionType::Editor, &perm_conn)
+                        .await?;
+
+                    let op = Operation::new_add(
+                        pos,
+                        self.route_interpolation_api()
+                            .correct_coordinate(&req.coord, req.mode)
+                            .await?,
+                        route.seg_list(),
+                        req.mode,
+                    )?;
+                    route.push_operation(op)?;
+
+                    self.route_interpolation_api()
+                        .interpolate_empty_segments(&mut route)
+                        .await?;
+                    self.elevation_api().attach_elevations(&mut route).await?;
+                    route.calc_route_features_from_seg_list()?;
+
+                    self.route_repository().update(&route, conn).await?;
+
+                    Ok(route)
+                }
+                .boxed()
+            })
+            .await?;
+        operation_response(self, route, &conn).await
+    }
+
     async fn remove_point(
         &self,
         route_id: &RouteId,
@@ -263,32 +719,34 @@ where
         req: &RemovePointRequest,
     ) -> ApplicationResult<RouteOperationResponse> {
         let conn = self.route_repository().get_connection().await?;
-        conn.transaction(|conn| {
-            async move {
-                let mut route = self.route_repository().find(route_id, conn).await?;
-                let user_id = self.user_auth_api().authenticate(user_access_token).await?;
+        let route = conn
+            .transaction(|conn| {
+                async move {
+                    let mut route = self.route_repository().find(route_id, conn).await?;
+                    let user_id = self.user_auth_api().authenticate(user_access_token).await?;
 
-                let perm_conn = self.permission_repository().get_connection().await?;
-                self.permission_repository()
-                    .authorize_user(route.info(), &user_id, PermissionType::Editor, &perm_conn)
-                    .await?;
+                    let perm_conn = self.permission_repository().get_connection().await?;
+                    self.permission_repository()
+                        .authorize_user(route.info(), &user_id, PermissionType::Editor, &perm_conn)
+                        .await?;
 
-                let op = Operation::new_remove(pos, route.seg_list(), req.mode)?;
-                route.push_operation(op)?;
+                    let op = Operation::new_remove(pos, route.seg_list(), req.mode)?;
+                    route.push_operation(op)?;
 
-                self.route_interpolation_api()
-                    .interpolate_empty_segments(&mut route)
-                    .await?;
-                self.elevation_api().attach_elevations(&mut route)?;
-                route.calc_route_features_from_seg_list()?;
+                    self.route_interpolation_api()
+                        .interpolate_empty_segments(&mut route)
+                        .await?;
+                    self.elevation_api().attach_elevations(&mut route).await?;
+                    route.calc_route_features_from_seg_list()?;
 
-                self.route_repository().update(&route, conn).await?;
+                    self.route_repository().update(&route, conn).await?;
 
-                route.try_into()
-            }
-            .boxed()
-        })
-        .await
+                    Ok(route)
+                }
+                .boxed()
+            })
+            .await?;
+        operation_response(self, route, &conn).await
     }
 
     async fn move_point(
@@ -299,67 +757,178 @@ where
         req: &NewPointRequest,
     ) -> ApplicationResult<RouteOperationResponse> {
         let conn = self.route_repository().get_connection().await?;
-        conn.transaction(|conn| {
-            async move {
-                let mut route = self.route_repository().find(route_id, conn).await?;
-                let user_id = self.user_auth_api().authenticate(user_access_token).await?;
+        let route = conn
+            .transaction(|conn| {
+                async move {
+                    let mut route = self.route_repository().find(route_id, conn).await?;
+                    let user_id = self.user_auth_api().authenticate(user_access_token).await?;
+
+                    let perm_conn = self.permission_repository().get_connection().await?;
+                    self.permission_repository()
+                        .authorize_user(route.info(), &user_id, PermissionType::Editor, &perm_conn)
+                        .await?;
+
+                    let op = Operation::new_move(
+                        pos,
+                        self.route_interpolation_api()
+                            .correct_coordinate(&req.coord, req.mode)
+                            .await?,
+                        route.seg_list(),
+                        req.mode,
+                    )?;
+                    route.push_operation(op)?;
 
-                let perm_conn = self.permission_repository().get_connection().await?;
-                self.permission_repository()
-                    .authorize_user(route.info(), &user_id, PermissionType::Editor, &perm_conn)
-                    .await?;
-
-                let op = Operation::new_move(
-                    pos,
                     self.route_interpolation_api()
-                        .correct_coordinate(&req.coord, req.mode)
-                        .await?,
-                    route.seg_list(),
-                    req.mode,
-                )?;
-                route.push_operation(op)?;
-
-                self.route_interpolation_api()
-                    .interpolate_empty_segments(&mut route)
-                    .await?;
-                self.elevation_api().attach_elevations(&mut route)?;
-                route.calc_route_features_from_seg_list()?;
+                        .interpolate_empty_segments(&mut route)
+                        .await?;
+                    self.elevation_api().attach_elevations(&mut route).await?;
+                    route.calc_route_features_from_seg_list()?;
 
-                self.route_repository().update(&route, conn).await?;
+                    self.route_repository().update(&route, conn).await?;
 
-                route.try_into()
-            }
-            .boxed()
-        })
-        .await
+                    Ok(route)
+                }
+                .boxed()
+            })
+            .await?;
+        operation_response(self, route, &conn).await
     }
 
-    async fn clear_route(
+    async fn set_waypoint_elevation(
         &self,
         route_id: &RouteId,
         user_access_token: &str,
+        pos: usize,
+        req: &WaypointElevationRequest,
     ) -> ApplicationResult<RouteOperationResponse> {
+        let elevation = req.elevation()?;
         let conn = self.route_repository().get_connection().await?;
-        conn.transaction(|conn| {
-            async move {
-                let mut info = self.route_repository().find_info(route_id, conn).await?;
-                let user_id = self.user_auth_api().authenticate(user_access_token).await?;
+        let route = conn
+            .transaction(|conn| {
+                async move {
+                    let mut route = self.route_repository().find(route_id, conn).await?;
+                    let user_id = self.user_auth_api().authenticate(user_access_token).await?;
+
+                    let perm_conn = self.permission_repository().get_connection().await?;
+                    self.permission_repository()
+                        .authorize_user(route.info(), &user_id, PermissionType::Editor, &perm_conn)
+                        .await?;
+
+                    let op =
+                        Operation::new_set_waypoint_elevation(pos, elevation, route.seg_list())?;
+                    route.push_operation(op)?;
+
+                    // 点列は変わらないので，標高だけを求め直す
+                    self.elevation_api().attach_elevations(&mut route).await?;
+                    route.calc_route_features_from_seg_list()?;
+
+                    self.route_repository().update(&route, conn).await?;
+
+                    Ok(route)
+                }
+                .boxed()
+            })
+            .await?;
+        operation_response(self, route, &conn).await
+    }
 
-                let perm_conn = self.permission_repository().get_connection().await?;
-                self.permission_repository()
-                    .authorize_user(&info, &user_id, PermissionType::Editor, &perm_conn)
-                    .await?;
+    async fn set_segment_interpolation(
+        &self,
+        route_id: &RouteId,
+        user_access_token: &str,
+        pos: usize,
+        req: &SegmentInterpolationRequest,
+    ) -> ApplicationResult<RouteOperationResponse> {
+        let conn = self.route_repository().get_connection().await?;
+        let route = conn
+            .transaction(|conn| {
+                async move {
+                    let mut route = self.route_repository().find(route_id, conn).await?;
+                    let user_id = self.user_auth_api().authenticate(user_access_token).await?;
+
+                    let perm_conn = self.permission_repository().get_connection().await?;
+                    self.permission_repository()
+                        .authorize_user(route.info(), &user_id, PermissionType::Editor, &perm_conn)
+                        .await?;
+
+                    let op =
+                        Operation::new_set_interpolation(pos, req.interpolate, route.seg_list())?;
+                    route.push_operation(op)?;
+
+                    // 点列は変わらないので，標高だけを求め直す
+                    self.elevation_api().attach_elevations(&mut route).await?;
+                    route.calc_route_features_from_seg_list()?;
+
+                    self.route_repository().update(&route, conn).await?;
+
+                    Ok(route)
+                }
+                .boxed()
+            })
+            .await?;
+        operation_response(self, route, &conn).await
+    }
 
-                info.clear_route();
-                let cleared_route = Route::new(info, vec![], vec![].into());
-                self.route_repository().update(&cleared_route, conn).await?;
+    async fn set_elevation_source(
+        &self,
+        route_id: &RouteId,
+        user_access_token: &str,
+        req: &ElevationSourceRequest,
+    ) -> ApplicationResult<RouteOperationResponse> {
+        let conn = self.route_repository().get_connection().await?;
+        let route = conn
+            .transaction(|conn| {
+                async move {
+                    let mut route = self.route_repository().find(route_id, conn).await?;
+                    let user_id = self.user_auth_api().authenticate(user_access_token).await?;
+
+                    let perm_conn = self.permission_repository().get_connection().await?;
+                    self.permission_repository()
+                        .authorize_user(route.info(), &user_id, PermissionType::Editor, &perm_conn)
+                        .await?;
+
+                    route.set_elevation_source(req.elevation_source);
+                    self.elevation_api().attach_elevations(&mut route).await?;
+                    route.calc_route_features_from_seg_list()?;
+
+                    self.route_repository().update(&route, conn).await?;
+
+                    Ok(route)
+                }
+                .boxed()
+            })
+            .await?;
+        operation_response(self, route, &conn).await
+    }
 
-                // TODO: ここは正直無駄なので、APIを変更するべき？
-                cleared_route.try_into()
-            }
-            .boxed()
-        })
-        .await
+    async fn clear_route(
+        &self,
+        route_id: &RouteId,
+        user_access_token: &str,
+    ) -> ApplicationResult<RouteOperationResponse> {
+        let conn = self.route_repository().get_connection().await?;
+        let route = conn
+            .transaction(|conn| {
+                async move {
+                    let mut info = self.route_repository().find_info(route_id, conn).await?;
+                    let user_id = self.user_auth_api().authenticate(user_access_token).await?;
+
+                    let perm_conn = self.permission_repository().get_connection().await?;
+                    self.permission_repository()
+                        .authorize_user(&info, &user_id, PermissionType::Editor, &perm_conn)
+                        .await?;
+
+                    info.clear_route();
+                    let cleared_route = Route::new(info, vec![], vec![].into());
+                    self.route_repository().update(&cleared_route, conn).await?;
+
+                    // TODO: ここは正直無駄なので、APIを変更するべき？
+                    Ok(cleared_route)
+                }
+                .boxed()
+            })
+            .await?;
+        operation_response(self, route, &conn).await
     }
 
     async fn redo_operation(
@@ -368,31 +937,33 @@ where
         user_access_token: &str,
     ) -> ApplicationResult<RouteOperationResponse> {
         let conn = self.route_repository().get_connection().await?;
-        conn.transaction(|conn| {
-            async move {
-                let mut route = self.route_repository().find(route_id, conn).await?;
-                let user_id = self.user_auth_api().authenticate(user_access_token).await?;
+        let route = conn
+            .transaction(|conn| {
+                async move {
+                    let mut route = self.route_repository().find(route_id, conn).await?;
+                    let user_id = self.user_auth_api().authenticate(user_access_token).await?;
 
-                let perm_conn = self.permission_repository().get_connection().await?;
-                self.permission_repository()
-                    .authorize_user(route.info(), &user_id, PermissionType::Editor, &perm_conn)
-                    .await?;
+                    let perm_conn = self.permission_repository().get_connection().await?;
+                    self.permission_repository()
+                        .authorize_user(route.info(), &user_id, PermissionType::Editor, &perm_conn)
+                        .await?;
 
-                route.redo_operation()?;
+                    route.redo_operation()?;
 
-                self.route_interpolation_api()
-                    .interpolate_empty_segments(&mut route)
-                    .await?;
-                self.elevation_api().attach_elevations(&mut route)?;
-                route.calc_route_features_from_seg_list()?;
+                    self.route_interpolation_api()
+                        .interpolate_empty_segments(&mut route)
+                        .await?;
+                    self.elevation_api().attach_elevations(&mut route).await?;
+                    route.calc_route_features_from_seg_list()?;
 
-                self.route_repository().update(&route, conn).await?;
+                    self.route_repository().update(&route, conn).await?;
 
-                route.try_into()
-            }
-            .boxed()
-        })
-        .await
+                    Ok(route)
+                }
+                .boxed()
+            })
+            .await?;
+        operation_response(self, route, &conn).await
     }
 
     async fn undo_operation(
@@ -401,31 +972,33 @@ where
         user_access_token: &str,
     ) -> ApplicationResult<RouteOperationResponse> {
         let conn = self.route_repository().get_connection().await?;
-        conn.transaction(|conn| {
-            async move {
-                let mut route = self.route_repository().find(route_id, conn).await?;
-                let user_id = self.user_auth_api().authenticate(user_access_token).await?;
+        let route = conn
+            .transaction(|conn| {
+                async move {
+                    let mut route = self.route_repository().find(route_id, conn).await?;
+                    let user_id = self.user_auth_api().authenticate(user_access_token).await?;
 
-                let perm_conn = self.permission_repository().get_connection().await?;
-                self.permission_repository()
-                    .authorize_user(route.info(), &user_id, PermissionType::Editor, &perm_conn)
-                    .await?;
+                    let perm_conn = self.permission_repository().get_connection().await?;
+                    self.permission_repository()
+                        .authorize_user(route.info(), &user_id, PermissionType::Editor, &perm_conn)
+                        .await?;
 
-                route.undo_operation()?;
+                    route.undo_operation()?;
 
-                self.route_interpolation_api()
-                    .interpolate_empty_segments(&mut route)
-                    .await?;
-                self.elevation_api().attach_elevations(&mut route)?;
-                route.calc_route_features_from_seg_list()?;
+                    self.route_interpolation_api()
+                        .interpolate_empty_segments(&mut route)
+                        .await?;
+                    self.elevation_api().attach_elevations(&mut route).await?;
+                    route.calc_route_features_from_seg_list()?;
 
-                self.route_repository().update(&route, conn).await?;
+                    self.route_repository().update(&route, conn).await?;
 
-                route.try_into()
-            }
-            .boxed()
-        })
-        .await
+                    Ok(route)
+                }
+                .boxed()
+            })
+            .await?;
+        operation_response(self, route, &conn).await
     }
 
     async fn delete(&self, route_id: &RouteId, user_access_token: &str) -> ApplicationResult<()> {
@@ -491,23 +1064,90 @@ where
             .delete(route_info.id(), &req.user_id, &perm_conn)
             .await
     }
+
+    async fn add_tag(
+        &self,
+        route_id: &RouteId,
+        user_access_token: &str,
+        req: &RouteTagRequest,
+    ) -> ApplicationResult<RouteInfo> {
+        let conn = self.route_repository().get_connection().await?;
+        conn.transaction(|conn| {
+            async move {
+                let mut route_info = self.route_repository().find_info(route_id, conn).await?;
+                let user_id = self.user_auth_api().authenticate(user_access_token).await?;
+
+                let perm_conn = self.permission_repository().get_connection().await?;
+                self.permission_repository()
+                    .authorize_user(&route_info, &user_id, PermissionType::Editor, &perm_conn)
+                    .await?;
+
+                route_info.add_tag(req.tag.clone())?;
+                self.route_repository()
+                    .update_tags(&route_info, conn)
+                    .await?;
+
+                Ok(route_info)
+            }
+            .boxed()
+        })
+        .await
+    }
+
+    async fn remove_tag(
+        &self,
+        route_id: &RouteId,
+        user_access_token: &str,
+        req: &RouteTagRequest,
+    ) -> ApplicationResult<RouteInfo> {
+        let conn = self.route_repository().get_connection().await?;
+        conn.transaction(|conn| {
+            async move {
+                let mut route_info = self.route_repository().find_info(route_id, conn).await?;
+                let user_id = self.user_auth_api().authenticate(user_access_token).await?;
+
+                let perm_conn = self.permission_repository().get_connection().await?;
+                self.permission_repository()
+                    .authorize_user(&route_info, &user_id, PermissionType::Editor, &perm_conn)
+                    .await?;
+
+                route_info.remove_tag(&req.tag);
+                self.route_repository()
+                    .update_tags(&route_info, conn)
+                    .await?;
+
+                Ok(route_info)
+            }
+            .boxed()
+        })
+        .await
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::convert::TryFrom;
+
     use crate::{expect_at_repository, expect_once};
+    use chrono::{TimeZone, Utc};
     use route_bucket_domain::{
         external::{MockElevationApi, MockRouteInterpolationApi, MockUserAuthApi},
         model::{
             fixtures::{
                 route::{
                     CoordinateFixtures, OperationFixtures, PermissionFixtures, RouteFixtures,
-                    RouteGpxFixtures, RouteInfoFixtures, RouteSearchQueryFixtures, SegmentFixtures,
+                    RouteGpxFixtures, RouteInfoFixtures, RouteMetadataFixtures,
+                    RouteSearchQueryFixtures, SegmentFixtures, StagePlanFixtures, TagFixtures,
                 },
                 user::UserIdFixtures,
             },
             permission::Permission,
-            route::{Coordinate, DrawingMode, RouteGpx, Segment},
+            route::{
+                BikeType, Coordinate, DrawingMode, ElevationSource, ProximityTarget, RouteGpx,
+                RouteMetadata, RouteSortKey, Segment, SegmentList, SortOrder, SurfaceType, Tag,
+                TagCount,
+            },
+            types::Url,
             user::UserId,
         },
         repository::{MockConnection, MockPermissionRepository, MockRouteRepository},
@@ -561,6 +1201,12 @@ mod tests {
         String::from("token.for.doncic")
     }
 
+    /// `find`で距離などを計算した後のセグメント
+    fn calculated_seg_list(mut route: Route) -> SegmentList {
+        route.calc_route_features_from_seg_list().unwrap();
+        route.seg_list().clone()
+    }
+
     #[rstest]
     #[tokio::test]
     async fn can_find() {
@@ -573,13 +1219,76 @@ mod tests {
             Route::yokohama_to_chiba_filled(false, false),
             Route::yokohama_to_chiba_filled(true, false),
         );
+        usecase.expect_find_uncovered_segments_at_elevation_api(
+            calculated_seg_list(Route::yokohama_to_chiba_filled(true, false)),
+            Vec::new(),
+        );
 
         assert_eq!(
-            usecase.find(&route_id()).await,
+            usecase.find(&route_id(), &RouteGetRequest::default()).await,
             Route::yokohama_to_chiba_filled(true, true).try_into()
         );
     }
 
+    #[rstest]
+    #[tokio::test]
+    async fn can_find_with_uncovered_segments() {
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_at_route_repository(
+            route_id(),
+            Route::yokohama_to_chiba_filled(false, false),
+        );
+        usecase.expect_attach_elevations_at_elevation_api(
+            Route::yokohama_to_chiba_filled(false, false),
+            Route::yokohama_to_chiba_filled(true, false),
+        );
+        usecase.expect_find_uncovered_segments_at_elevation_api(
+            calculated_seg_list(Route::yokohama_to_chiba_filled(true, false)),
+            vec![1],
+        );
+
+        let mut expected: RouteGetResponse = Route::yokohama_to_chiba_filled(true, true)
+            .try_into()
+            .unwrap();
+        expected.uncovered_segments = vec![1];
+        assert_eq!(
+            usecase.find(&route_id(), &RouteGetRequest::default()).await,
+            Ok(expected)
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_find_with_distance_markers() {
+        let req = RouteGetRequest {
+            marker_interval: Some(20.),
+        };
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_at_route_repository(
+            route_id(),
+            Route::yokohama_to_chiba_filled(false, false),
+        );
+        usecase.expect_attach_elevations_at_elevation_api(
+            Route::yokohama_to_chiba_filled(false, false),
+            Route::yokohama_to_chiba_filled(true, false),
+        );
+        usecase.expect_find_uncovered_segments_at_elevation_api(
+            calculated_seg_list(Route::yokohama_to_chiba_filled(true, false)),
+            Vec::new(),
+        );
+
+        let route = Route::yokohama_to_chiba_filled(true, true);
+        let markers = route
+            .seg_list()
+            .calc_distance_markers(20000.0.try_into().unwrap())
+            .unwrap();
+        assert_eq!(markers.len(), 2);
+        assert_eq!(
+            usecase.find(&route_id(), &req).await,
+            (route, markers).try_into()
+        );
+    }
+
     #[rstest]
     #[tokio::test]
     async fn can_find_all() {
@@ -590,36 +1299,229 @@ mod tests {
         );
 
         assert_eq!(
-            usecase.find_all().await,
-            Ok(RouteSearchResponse {
-                route_infos: vec![RouteInfo::empty_route0(0)],
-                result_num: 1
-            })
+            usecase.find_all().await,
+            Ok(RouteSearchResponse {
+                route_infos: vec![RouteInfo::empty_route0(0)],
+                result_num: 1,
+                next_cursor: None,
+            })
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_search() {
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_search_infos_at_route_repository(
+            RouteSearchQuery::search_guest(),
+            vec![RouteInfo::empty_route0(0)],
+        );
+        usecase.expect_count_infos_at_route_repository(RouteSearchQuery::search_guest(), 1);
+
+        assert_eq!(
+            usecase.search(RouteSearchQuery::search_guest()).await,
+            Ok(RouteSearchResponse {
+                route_infos: vec![RouteInfo::empty_route0(0)],
+                result_num: 1,
+                next_cursor: None,
+            })
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_search_with_cursor() {
+        let info = RouteInfo::empty_route0(0);
+        let first_page = RouteSearchQuery {
+            page_size: Some(1),
+            ..RouteSearchQuery::search_guest()
+        };
+        let next_page = RouteSearchQuery {
+            cursor: Some(RouteSearchCursor::after(
+                &info,
+                RouteSortKey::UpdatedAt,
+                SortOrder::Desc,
+            )),
+            ..first_page.clone()
+        };
+
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_search_infos_at_route_repository(next_page.clone(), vec![info.clone()]);
+        usecase.expect_count_infos_at_route_repository(next_page.clone(), 3);
+
+        assert_eq!(
+            usecase.search(next_page.clone()).await,
+            Ok(RouteSearchResponse {
+                route_infos: vec![info.clone()],
+                result_num: 3,
+                next_cursor: Some(RouteSearchCursor::after(
+                    &info,
+                    RouteSortKey::UpdatedAt,
+                    SortOrder::Desc,
+                )),
+            })
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn cannot_search_with_cursor_for_another_order() {
+        let query = RouteSearchQuery {
+            sort_by: RouteSortKey::Name,
+            cursor: Some(RouteSearchCursor::after(
+                &RouteInfo::empty_route0(0),
+                RouteSortKey::UpdatedAt,
+                SortOrder::Desc,
+            )),
+            ..RouteSearchQuery::search_guest()
+        };
+
+        let usecase = TestRouteUseCase::new();
+        assert!(matches!(
+            usecase.search(query).await,
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_find_gpx() {
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_at_route_repository(
+            route_id(),
+            Route::yokohama_to_chiba_via_tokyo_filled(false, false),
+        );
+        usecase.expect_attach_elevations_at_elevation_api(
+            Route::yokohama_to_chiba_via_tokyo_filled(false, false),
+            Route::yokohama_to_chiba_via_tokyo_filled(true, false),
+        );
+
+        assert_eq!(
+            usecase
+                .find_gpx(&route_id(), &RouteGetRequest::default())
+                .await,
+            Ok(RouteGpx::route0())
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_find_gpx_with_distance_markers() {
+        let req = RouteGetRequest {
+            marker_interval: Some(20.),
+        };
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_at_route_repository(
+            route_id(),
+            Route::yokohama_to_chiba_via_tokyo_filled(false, false),
+        );
+        usecase.expect_attach_elevations_at_elevation_api(
+            Route::yokohama_to_chiba_via_tokyo_filled(false, false),
+            Route::yokohama_to_chiba_via_tokyo_filled(true, false),
+        );
+
+        assert_eq!(
+            usecase.find_gpx(&route_id(), &req).await,
+            Ok(RouteGpx::route0_with_markers())
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_estimate_energy() {
+        let req = RouteEnergyRequest {
+            rider_mass: 65.,
+            bike_mass: 8.,
+            cda: 0.32,
+            crr: 0.005,
+            speed: 25.,
+        };
+        let route = Route::yokohama_to_chiba_via_tokyo_filled(true, false);
+
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_at_route_repository(
+            route_id(),
+            Route::yokohama_to_chiba_via_tokyo_filled(false, false),
+        );
+        usecase.expect_attach_elevations_at_elevation_api(
+            Route::yokohama_to_chiba_via_tokyo_filled(false, false),
+            route.clone(),
+        );
+
+        let resp = usecase.estimate_energy(&route_id(), &req).await.unwrap();
+        assert_eq!(*resp.total.kilojoules(), 772.894619482579);
+        assert_eq!(*resp.total.kilocalories(), 769.6926978594835);
+        assert_eq!(
+            resp.segments
+                .iter()
+                .map(|segment| *segment.kilojoules())
+                .collect::<Vec<_>>(),
+            vec![353.17206123701453, 419.72255824556436]
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_check_daylight() {
+        let departure = Utc.ymd(2021, 6, 21).and_hms(8, 0, 0);
+        let req = RouteDaylightRequest {
+            departure,
+            speed: 20.,
+        };
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_at_route_repository(
+            route_id(),
+            Route::yokohama_to_chiba_via_tokyo_filled(false, false),
+        );
+        usecase.expect_attach_elevations_at_elevation_api(
+            Route::yokohama_to_chiba_via_tokyo_filled(false, false),
+            Route::yokohama_to_chiba_via_tokyo_filled(true, false),
+        );
+
+        assert_eq!(
+            usecase.check_daylight(&route_id(), &req).await,
+            DaylightChecker::new(departure, 20.)
+                .unwrap()
+                .check(Route::yokohama_to_chiba_via_tokyo_filled(true, true).seg_list())
         );
     }
 
     #[rstest]
     #[tokio::test]
-    async fn can_search() {
+    async fn can_split_into_stages() {
+        let req = RouteStagesRequest {
+            distance: Some(30000.),
+            ascent: None,
+        };
         let mut usecase = TestRouteUseCase::new();
-        usecase.expect_search_infos_at_route_repository(
-            RouteSearchQuery::search_guest(),
-            vec![RouteInfo::empty_route0(0)],
+        usecase.expect_find_at_route_repository(
+            route_id(),
+            Route::yokohama_to_chiba_via_tokyo_filled(false, false),
+        );
+        usecase.expect_attach_elevations_at_elevation_api(
+            Route::yokohama_to_chiba_via_tokyo_filled(false, false),
+            Route::yokohama_to_chiba_via_tokyo_filled(true, false),
         );
-        usecase.expect_count_infos_at_route_repository(RouteSearchQuery::search_guest(), 1);
 
         assert_eq!(
-            usecase.search(RouteSearchQuery::search_guest()).await,
-            Ok(RouteSearchResponse {
-                route_infos: vec![RouteInfo::empty_route0(0)],
-                result_num: 1
+            usecase.split_into_stages(&route_id(), &req).await,
+            Ok(RouteStagesResponse {
+                stages: StagePlan::thirty_kilometers()
+                    .split(Route::yokohama_to_chiba_via_tokyo_filled(true, true).seg_list())
+                    .unwrap()
             })
         );
     }
 
     #[rstest]
+    #[case::all_stages(None, RouteGpx::route0_stages())]
+    #[case::single_stage(Some(2), RouteGpx::route0_day2())]
     #[tokio::test]
-    async fn can_find_gpx() {
+    async fn can_find_stages_gpx(#[case] day: Option<usize>, #[case] expected: RouteGpx) {
+        let req = RouteStagesRequest {
+            distance: Some(30000.),
+            ascent: None,
+        };
         let mut usecase = TestRouteUseCase::new();
         usecase.expect_find_at_route_repository(
             route_id(),
@@ -630,7 +1532,10 @@ mod tests {
             Route::yokohama_to_chiba_via_tokyo_filled(true, false),
         );
 
-        assert_eq!(usecase.find_gpx(&route_id()).await, Ok(RouteGpx::route0()));
+        assert_eq!(
+            usecase.find_stages_gpx(&route_id(), &req, day).await,
+            Ok(expected)
+        );
     }
 
     #[rstest]
@@ -648,6 +1553,80 @@ mod tests {
         assert!(matches!(usecase.create(&doncic_token(), &req).await, Ok(_)));
     }
 
+    #[rstest]
+    #[tokio::test]
+    async fn can_import_gpx() {
+        let req = RouteImportRequest {
+            gpx: String::from_utf8(RouteGpx::route0().as_slice().to_vec()).unwrap(),
+            name: None,
+            elevation_source: None,
+        };
+
+        let (_, points) = RouteGpx::read_track(RouteGpx::route0().as_slice()).unwrap();
+        let mut info = RouteInfo::empty_route0(0);
+        info.set_elevation_source(ElevationSource::Source);
+        let imported = Route::new(
+            info.clone(),
+            Vec::new(),
+            SegmentList::from_track(points).unwrap(),
+        );
+        let mut attached = imported.clone();
+        attached
+            .iter_seg_mut()
+            .for_each(Segment::apply_source_elevations);
+        let mut calculated = attached.clone();
+        calculated.calc_route_features_from_seg_list().unwrap();
+
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+        usecase.expect_insert_info_at_route_repository(info);
+        usecase.expect_attach_elevations_at_elevation_api(imported, attached);
+        usecase.expect_update_at_route_repository(calculated.clone());
+
+        // 同じ所有者の同じ形のルートがあれば警告する
+        let duplicate = RouteInfo::empty_route0(0);
+        usecase.expect_find_ids_with_similar_extent_at_route_repository(
+            SimilarExtentQuery::near_duplicates_of(
+                calculated.seg_list().calc_bounding_box().unwrap(),
+                UserId::doncic(),
+            )
+            .unwrap(),
+            vec![duplicate.id().clone()],
+        );
+        usecase.expect_find_seg_lists_at_route_repository(
+            vec![duplicate.id().clone()],
+            vec![(duplicate.id().clone(), calculated.seg_list().clone())],
+        );
+        usecase.expect_find_info_at_route_repository(duplicate.id().clone(), duplicate.clone());
+
+        let resp = usecase.import_gpx(&doncic_token(), &req).await.unwrap();
+        assert_eq!(
+            resp.near_duplicates
+                .iter()
+                .map(|similar| (&similar.route_info, *similar.similarity.near_duplicate()))
+                .collect::<Vec<_>>(),
+            vec![(&duplicate, true)]
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn cannot_import_gpx_without_points() {
+        let req = RouteImportRequest {
+            gpx: r#"<gpx version="1.1" creator="test"><trk><trkseg></trkseg></trk></gpx>"#.into(),
+            name: Some("route0".into()),
+            elevation_source: None,
+        };
+
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+
+        assert!(matches!(
+            usecase.import_gpx(&doncic_token(), &req).await,
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
     #[rstest]
     #[tokio::test]
     async fn can_rename() {
@@ -671,6 +1650,104 @@ mod tests {
         );
     }
 
+    #[rstest]
+    #[tokio::test]
+    async fn can_update_metadata() {
+        let metadata = RouteMetadata::gravel_ride();
+        let req = RouteMetadataRequest {
+            name: Some("route1".into()),
+            description: metadata.description().clone(),
+            surface_type: Some(Some(SurfaceType::Gravel)),
+            bike_type: Some(Some(BikeType::Gravel)),
+            start_label: Some(" Yokohama ".into()),
+            finish_label: metadata.finish_label().clone(),
+            external_link: Some(metadata.external_link().clone()),
+        };
+        let mut expected = RouteInfo::empty_route1(0);
+        expected.set_metadata(metadata);
+
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_info_at_route_repository(route_id(), RouteInfo::empty_route0(0));
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+        usecase.expect_authorize_user_at_permission_repository(
+            RouteInfo::empty_route0(0),
+            UserId::doncic(),
+            PermissionType::Editor,
+        );
+        usecase.expect_update_info_at_route_repository(expected.clone());
+
+        assert_eq!(
+            usecase
+                .update_metadata(&route_id(), &doncic_token(), &req)
+                .await,
+            Ok(expected)
+        );
+    }
+
+    #[rstest]
+    #[case::by_null(serde_json::from_str(
+        r#"{"description": "", "start_label": "  ", "surface_type": null, "bike_type": null, "external_link": null}"#
+    ).unwrap())]
+    #[case::by_empty_string(serde_json::from_str(
+        r#"{"description": "", "start_label": "  ", "surface_type": "", "bike_type": " ", "external_link": ""}"#
+    ).unwrap())]
+    #[tokio::test]
+    async fn can_clear_metadata(#[case] req: RouteMetadataRequest) {
+        let mut info = RouteInfo::empty_route0(0);
+        info.set_metadata(RouteMetadata::gravel_ride());
+
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_info_at_route_repository(route_id(), info.clone());
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+        usecase.expect_authorize_user_at_permission_repository(
+            info.clone(),
+            UserId::doncic(),
+            PermissionType::Editor,
+        );
+        let mut metadata = RouteMetadata::gravel_ride();
+        metadata
+            .set_description(None)
+            .set_start_label(None)
+            .set_surface_type(None)
+            .set_bike_type(None)
+            .set_external_link(None);
+        info.set_metadata(metadata);
+        usecase.expect_update_info_at_route_repository(info.clone());
+
+        assert_eq!(
+            usecase
+                .update_metadata(&route_id(), &doncic_token(), &req)
+                .await,
+            Ok(info)
+        );
+    }
+
+    #[rstest]
+    #[case::too_long_name(RouteMetadataRequest { name: Some("a".repeat(51)), ..Default::default() })]
+    #[case::too_long_label(RouteMetadataRequest { finish_label: Some("a".repeat(51)), ..Default::default() })]
+    #[case::not_http_link(RouteMetadataRequest {
+        external_link: Some(Url::try_from(String::from("ftp://example.com/route")).ok()),
+        ..Default::default()
+    })]
+    #[tokio::test]
+    async fn cannot_update_invalid_metadata(#[case] req: RouteMetadataRequest) {
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_info_at_route_repository(route_id(), RouteInfo::empty_route0(0));
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+        usecase.expect_authorize_user_at_permission_repository(
+            RouteInfo::empty_route0(0),
+            UserId::doncic(),
+            PermissionType::Editor,
+        );
+
+        assert!(matches!(
+            usecase
+                .update_metadata(&route_id(), &doncic_token(), &req)
+                .await,
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
     #[rstest]
     #[tokio::test]
     async fn can_add_point() {
@@ -706,6 +1783,7 @@ mod tests {
         usecase.expect_update_at_route_repository(Route::yokohama_to_chiba_via_tokyo_filled(
             true, true,
         ));
+        usecase.expect_no_near_duplicates(&Route::yokohama_to_chiba_via_tokyo_filled(true, true));
 
         assert_eq!(
             usecase
@@ -742,6 +1820,7 @@ mod tests {
             Route::yokohama_to_chiba_filled(true, false),
         );
         usecase.expect_update_at_route_repository(Route::yokohama_to_chiba_filled(true, true));
+        usecase.expect_no_near_duplicates(&Route::yokohama_to_chiba_filled(true, true));
 
         assert_eq!(
             usecase
@@ -784,6 +1863,7 @@ mod tests {
             Route::yokohama_to_tokyo_filled(true, false),
         );
         usecase.expect_update_at_route_repository(Route::yokohama_to_tokyo_filled(true, true));
+        usecase.expect_no_near_duplicates(&Route::yokohama_to_tokyo_filled(true, true));
 
         assert_eq!(
             usecase
@@ -812,6 +1892,44 @@ mod tests {
         );
     }
 
+    #[rstest]
+    #[tokio::test]
+    async fn can_set_elevation_source() {
+        let req = ElevationSourceRequest {
+            elevation_source: ElevationSource::Source,
+        };
+        let with_source = |mut route: Route| {
+            route.set_elevation_source(ElevationSource::Source);
+            route
+        };
+        let route = with_source(Route::yokohama_to_chiba_via_tokyo_filled(true, true));
+
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_at_route_repository(
+            route_id(),
+            Route::yokohama_to_chiba_via_tokyo_filled(true, true),
+        );
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+        usecase.expect_authorize_user_at_permission_repository(
+            RouteInfo::yokohama_to_chiba_via_tokyo(),
+            UserId::doncic(),
+            PermissionType::Editor,
+        );
+        usecase.expect_attach_elevations_at_elevation_api(
+            route.clone(),
+            with_source(Route::yokohama_to_chiba_via_tokyo_filled(true, false)),
+        );
+        usecase.expect_update_at_route_repository(route.clone());
+        usecase.expect_no_near_duplicates(&route);
+
+        assert_eq!(
+            usecase
+                .set_elevation_source(&route_id(), &doncic_token(), &req)
+                .await,
+            route.try_into()
+        );
+    }
+
     #[rstest]
     #[tokio::test]
     async fn can_redo_operation() {
@@ -837,6 +1955,7 @@ mod tests {
         usecase.expect_update_at_route_repository(Route::yokohama_to_chiba_via_tokyo_filled(
             true, true,
         ));
+        usecase.expect_no_near_duplicates(&Route::yokohama_to_chiba_via_tokyo_filled(true, true));
 
         assert_eq!(
             usecase.redo_operation(&route_id(), &doncic_token()).await,
@@ -867,6 +1986,7 @@ mod tests {
             Route::yokohama_to_chiba_filled(true, false),
         );
         usecase.expect_update_at_route_repository(Route::yokohama_to_chiba_filled(true, true));
+        usecase.expect_no_near_duplicates(&Route::yokohama_to_chiba_filled(true, true));
 
         assert_eq!(
             usecase.undo_operation(&route_id(), &doncic_token()).await,
@@ -940,6 +2060,252 @@ mod tests {
         );
     }
 
+    #[rstest]
+    #[tokio::test]
+    async fn can_add_tag() {
+        let req = RouteTagRequest { tag: Tag::gravel() };
+        let info = RouteInfo::empty_route0(0);
+        let mut tagged = info.clone();
+        tagged.add_tag(Tag::gravel()).unwrap();
+
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_info_at_route_repository(route_id(), info.clone());
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+        usecase.expect_authorize_user_at_permission_repository(
+            info,
+            UserId::doncic(),
+            PermissionType::Editor,
+        );
+        usecase.expect_update_tags_at_route_repository(tagged.clone());
+
+        let result = usecase.add_tag(&route_id(), &doncic_token(), &req).await;
+        assert_eq!(result, Ok(tagged));
+        assert_eq!(result.unwrap().tags(), &vec![Tag::gravel()]);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_remove_tag() {
+        let req = RouteTagRequest { tag: Tag::gravel() };
+        let mut info = RouteInfo::empty_route0(0);
+        info.add_tag(Tag::gravel()).unwrap();
+        info.add_tag(Tag::commute()).unwrap();
+
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_info_at_route_repository(route_id(), info.clone());
+        usecase.expect_authenticate_at_auth_api(doncic_token(), UserId::doncic());
+        usecase.expect_authorize_user_at_permission_repository(
+            info.clone(),
+            UserId::doncic(),
+            PermissionType::Editor,
+        );
+        let mut untagged = info;
+        untagged.remove_tag(&Tag::gravel());
+        usecase.expect_update_tags_at_route_repository(untagged.clone());
+
+        let result = usecase
+            .remove_tag(&route_id(), &doncic_token(), &req)
+            .await
+            .unwrap();
+        assert_eq!(result.tags(), &vec![Tag::commute()]);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_find_popular_tags() {
+        let tags = vec![
+            TagCount::from((Tag::commute(), 5)),
+            TagCount::from((Tag::gravel(), 2)),
+        ];
+
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_count_tags_at_route_repository(20, tags.clone());
+
+        assert_eq!(
+            usecase
+                .find_popular_tags(&PopularTagsRequest::default())
+                .await,
+            Ok(PopularTagsResponse { tags })
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn cannot_find_too_many_popular_tags() {
+        let usecase = TestRouteUseCase::new();
+        let req = PopularTagsRequest { limit: Some(1000) };
+        assert!(matches!(
+            usecase.find_popular_tags(&req).await,
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
+    #[rstest]
+    #[case::passing(ProximityTarget::Path, 100., Some(0.))]
+    #[case::not_starting(ProximityTarget::Start, 100., None)]
+    #[tokio::test]
+    async fn can_search_nearby(
+        #[case] target: ProximityTarget,
+        #[case] radius: f64,
+        #[case] expected_distance: Option<f64>,
+    ) {
+        let route = Route::yokohama_to_chiba_via_tokyo_filled(false, true);
+        let query = ProximityQuery {
+            latitude: 35.68048,
+            longitude: 139.76906,
+            radius,
+            target,
+            limit: None,
+        };
+
+        let id = route.info().id().clone();
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_ids_near_at_route_repository(query.clone(), vec![id.clone()]);
+        usecase.expect_find_seg_lists_at_route_repository(
+            vec![id.clone()],
+            vec![(id.clone(), route.seg_list().clone())],
+        );
+        if expected_distance.is_some() {
+            usecase.expect_find_info_at_route_repository(id, route.info().clone());
+        }
+
+        let expected = expected_distance
+            .map(|distance| NearbyRoute {
+                route_info: route.info().clone(),
+                distance: distance.try_into().unwrap(),
+            })
+            .into_iter()
+            .collect();
+        assert_eq!(
+            usecase.search_nearby(query).await,
+            Ok(RouteNearbyResponse { routes: expected })
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn search_nearby_reads_info_only_within_limit() {
+        let near = Route::yokohama_to_chiba_via_tokyo_filled(false, true);
+        let far = Route::yokohama_to_chiba_filled(false, true);
+        let query = ProximityQuery {
+            latitude: 35.68048,
+            longitude: 139.76906,
+            radius: 100_000.,
+            target: ProximityTarget::Path,
+            limit: Some(1),
+        };
+        let ids = vec![far.info().id().clone(), near.info().id().clone()];
+
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_ids_near_at_route_repository(query.clone(), ids.clone());
+        usecase.expect_find_seg_lists_at_route_repository(
+            ids.clone(),
+            vec![
+                (ids[0].clone(), far.seg_list().clone()),
+                (ids[1].clone(), near.seg_list().clone()),
+            ],
+        );
+        usecase.expect_find_info_at_route_repository(ids[1].clone(), near.info().clone());
+
+        assert_eq!(
+            usecase.search_nearby(query).await,
+            Ok(RouteNearbyResponse {
+                routes: vec![NearbyRoute {
+                    route_info: near.info().clone(),
+                    distance: 0.0.try_into().unwrap(),
+                }]
+            })
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn cannot_search_nearby_with_invalid_radius() {
+        let query = ProximityQuery {
+            latitude: 35.68048,
+            longitude: 139.76906,
+            radius: -1.,
+            target: ProximityTarget::Path,
+            limit: None,
+        };
+        let usecase = TestRouteUseCase::new();
+        assert!(matches!(
+            usecase.search_nearby(query).await,
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn can_find_similar() {
+        let route = Route::yokohama_to_chiba_via_tokyo_filled(false, true);
+        let duplicate = Route::yokohama_to_chiba_via_tokyo_filled(false, true);
+        let other = Route::yokohama_to_chiba_filled(false, true);
+        let req = RouteSimilarRequest {
+            limit: Some(5),
+            same_owner: true,
+        };
+
+        let ids = vec![
+            other.info().id().clone(),
+            route.info().id().clone(),
+            duplicate.info().id().clone(),
+        ];
+
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_at_route_repository(route.info().id().clone(), route.clone());
+        usecase.expect_find_ids_with_similar_extent_at_route_repository(
+            SimilarExtentQuery::similar_to(
+                route.seg_list().calc_bounding_box().unwrap(),
+                Some(UserId::doncic()),
+            )
+            .unwrap(),
+            ids.clone(),
+        );
+        // 自分自身とは比べない
+        usecase.expect_find_seg_lists_at_route_repository(
+            vec![ids[0].clone(), ids[2].clone()],
+            vec![
+                (ids[0].clone(), other.seg_list().clone()),
+                (ids[2].clone(), duplicate.seg_list().clone()),
+            ],
+        );
+        let infos = [other.info().clone(), duplicate.info().clone()];
+        usecase
+            .route_repository
+            .expect_find_info()
+            .times(2)
+            .returning(move |id, _| Ok(infos.iter().find(|info| info.id() == id).unwrap().clone()));
+
+        let resp = usecase.find_similar(route.info().id(), &req).await.unwrap();
+        assert_eq!(
+            resp.routes
+                .iter()
+                .map(|similar| (
+                    similar.route_info.id(),
+                    *similar.similarity.near_duplicate()
+                ))
+                .collect::<Vec<_>>(),
+            vec![(duplicate.info().id(), true), (other.info().id(), false)]
+        );
+        assert_eq!(resp.routes[0].similarity.frechet_distance().value(), 0.);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn empty_route_has_no_similar_routes() {
+        let route = Route::empty();
+        let mut usecase = TestRouteUseCase::new();
+        usecase.expect_find_at_route_repository(route.info().id().clone(), route.clone());
+
+        assert_eq!(
+            usecase
+                .find_similar(route.info().id(), &RouteSimilarRequest::default())
+                .await,
+            Ok(RouteSimilarResponse { routes: Vec::new() })
+        );
+    }
+
     struct TestRouteUseCase {
         route_repository: MockRouteRepository,
         permission_repository: MockPermissionRepository,
@@ -991,6 +2357,52 @@ mod tests {
             expect_at_repository!(self.route_repository, count_infos, query, return_count);
         }
 
+        fn expect_find_ids_near_at_route_repository(
+            &mut self,
+            query: ProximityQuery,
+            return_ids: Vec<RouteId>,
+        ) {
+            expect_at_repository!(self.route_repository, find_ids_near, query, return_ids);
+        }
+
+        fn expect_find_ids_with_similar_extent_at_route_repository(
+            &mut self,
+            query: SimilarExtentQuery,
+            return_ids: Vec<RouteId>,
+        ) {
+            expect_at_repository!(
+                self.route_repository,
+                find_ids_with_similar_extent,
+                query,
+                return_ids
+            );
+        }
+
+        /// 編集した`route`に，ほぼ同じ形のルートが無い
+        fn expect_no_near_duplicates(&mut self, route: &Route) {
+            self.expect_find_ids_with_similar_extent_at_route_repository(
+                SimilarExtentQuery::near_duplicates_of(
+                    route.seg_list().calc_bounding_box().unwrap(),
+                    route.info().owner_id().clone(),
+                )
+                .unwrap(),
+                Vec::new(),
+            );
+        }
+
+        fn expect_find_seg_lists_at_route_repository(
+            &mut self,
+            param_ids: Vec<RouteId>,
+            return_seg_lists: Vec<(RouteId, SegmentList)>,
+        ) {
+            expect_at_repository!(
+                self.route_repository,
+                find_seg_lists,
+                param_ids,
+                return_seg_lists
+            );
+        }
+
         fn expect_insert_info_at_route_repository(&mut self, param_info: RouteInfo) {
             expect_at_repository!(self.route_repository, insert_info, param_info, ());
         }
@@ -1003,6 +2415,18 @@ mod tests {
             expect_at_repository!(self.route_repository, update_info, param_info, ());
         }
 
+        fn expect_update_tags_at_route_repository(&mut self, param_info: RouteInfo) {
+            expect_at_repository!(self.route_repository, update_tags, param_info, ());
+        }
+
+        fn expect_count_tags_at_route_repository(
+            &mut self,
+            param_limit: usize,
+            return_tags: Vec<TagCount>,
+        ) {
+            expect_at_repository!(self.route_repository, count_tags, param_limit, return_tags);
+        }
+
         fn expect_delete_at_route_repository(&mut self, param_id: RouteId) {
             expect_at_repository!(self.route_repository, delete, param_id, ());
         }
@@ -1115,6 +2539,19 @@ mod tests {
             );
         }
 
+        fn expect_find_uncovered_segments_at_elevation_api(
+            &mut self,
+            param_seg_list: SegmentList,
+            return_indices: Vec<usize>,
+        ) {
+            expect_once!(
+                private self.elevation_api,
+                find_uncovered_segments,
+                param_seg_list
+            )
+            .return_const(return_indices);
+        }
+
         fn expect_authenticate_at_auth_api(&mut self, param_token: String, return_id: UserId) {
             expect_once!(self.auth_api, authenticate, param_token, return_id);
         }
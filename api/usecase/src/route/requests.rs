@@ -1,17 +1,86 @@
+use std::convert::TryFrom;
+
+use chrono::{DateTime, Utc};
 use derive_more::From;
-use serde::Deserialize;
+use serde::de::IntoDeserializer;
+use serde::{Deserialize, Deserializer};
+use validator::Validate;
 
 use route_bucket_domain::model::{
     permission::PermissionType,
-    route::{Coordinate, DrawingMode},
+    route::{
+        BikeType, Coordinate, DaylightChecker, Distance, DrawingMode, Elevation, ElevationSource,
+        EnergyModel, RouteInfo, StagePlan, SurfaceType, Tag,
+    },
+    types::Url,
     user::UserId,
 };
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+#[derive(Default, From, Deserialize, Validate)]
+pub struct RouteGetRequest {
+    /// interval of distance markers [km]
+    #[validate(range(min = 0.1))]
+    pub(super) marker_interval: Option<f64>,
+}
+
+impl RouteGetRequest {
+    pub(super) fn marker_interval(&self) -> ApplicationResult<Option<Distance>> {
+        self.validate()?;
+        self.marker_interval
+            .map(|interval| Distance::try_from(interval * 1000.))
+            .transpose()
+    }
+}
+
+#[derive(Default, From, Deserialize, Validate)]
+pub struct RouteSimilarRequest {
+    /// 返すルートの数
+    #[validate(range(min = 1, max = 50))]
+    pub(super) limit: Option<usize>,
+    /// 同じ所有者のルートだけと比べるか (重複の確認用)
+    #[serde(default)]
+    pub(super) same_owner: bool,
+}
+
+impl RouteSimilarRequest {
+    pub(super) fn limit(&self) -> ApplicationResult<usize> {
+        self.validate()?;
+        Ok(self.limit.unwrap_or(10))
+    }
+}
+
+#[derive(Default, From, Deserialize, Validate)]
+pub struct PopularTagsRequest {
+    /// 返すタグの数
+    #[validate(range(min = 1, max = 100))]
+    pub(super) limit: Option<usize>,
+}
+
+impl PopularTagsRequest {
+    pub(super) fn limit(&self) -> ApplicationResult<usize> {
+        self.validate()?;
+        Ok(self.limit.unwrap_or(20))
+    }
+}
 
 #[derive(From, Deserialize)]
 pub struct RouteCreateRequest {
     pub(super) name: String,
 }
 
+#[derive(From, Deserialize)]
+pub struct RouteImportRequest {
+    /// GPXファイルの中身
+    pub(super) gpx: String,
+    /// 省略するとGPXに書かれた名前にする
+    #[serde(default)]
+    pub(super) name: Option<String>,
+    /// 省略すると，GPXに`<ele>`があれば元の標高を使う
+    #[serde(default)]
+    pub(super) elevation_source: Option<ElevationSource>,
+}
+
 #[derive(From, Deserialize)]
 pub struct NewPointRequest {
     pub(super) mode: DrawingMode,
@@ -23,11 +92,123 @@ pub struct RemovePointRequest {
     pub(super) mode: DrawingMode,
 }
 
+#[derive(From, Deserialize)]
+pub struct WaypointElevationRequest {
+    /// 指定する標高[m] (nullなら指定を解除する)
+    pub(super) elevation: Option<i32>,
+}
+
+impl WaypointElevationRequest {
+    pub(super) fn elevation(&self) -> ApplicationResult<Option<Elevation>> {
+        self.elevation.map(Elevation::try_from).transpose()
+    }
+}
+
+#[derive(From, Deserialize)]
+pub struct SegmentInterpolationRequest {
+    /// 橋・トンネルとして始点と終点の間の標高を線形補間するか
+    pub(super) interpolate: bool,
+}
+
+#[derive(From, Deserialize)]
+pub struct ElevationSourceRequest {
+    /// 取り込んだファイルの標高を使うか，DEMの標高を使うか
+    pub(super) elevation_source: ElevationSource,
+}
+
 #[derive(From, Deserialize)]
 pub struct RouteRenameRequest {
     pub(super) name: String,
 }
 
+/// 指定した項目だけを書き換える
+///
+/// 文字列の項目は空文字列で消す．路面・自転車の種類とリンクは，nullか空文字列で消す
+#[derive(Default, From, Deserialize, Validate)]
+pub struct RouteMetadataRequest {
+    #[validate(length(min = 1, max = 50))]
+    pub(super) name: Option<String>,
+    /// markdown
+    #[validate(length(max = 5000))]
+    pub(super) description: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_clearable")]
+    pub(super) surface_type: Option<Option<SurfaceType>>,
+    #[serde(default, deserialize_with = "deserialize_clearable")]
+    pub(super) bike_type: Option<Option<BikeType>>,
+    #[validate(length(max = 50))]
+    pub(super) start_label: Option<String>,
+    #[validate(length(max = 50))]
+    pub(super) finish_label: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_clearable")]
+    pub(super) external_link: Option<Option<Url>>,
+}
+
+impl RouteMetadataRequest {
+    pub(super) fn apply(&self, route_info: &mut RouteInfo) -> ApplicationResult<()> {
+        self.validate()?;
+
+        let non_empty = |value: &String| Some(value.trim().to_string()).filter(|v| !v.is_empty());
+        let mut metadata = route_info.metadata().clone();
+        if let Some(description) = &self.description {
+            metadata.set_description(non_empty(description));
+        }
+        if let Some(surface_type) = self.surface_type {
+            metadata.set_surface_type(surface_type);
+        }
+        if let Some(bike_type) = self.bike_type {
+            metadata.set_bike_type(bike_type);
+        }
+        if let Some(start_label) = &self.start_label {
+            metadata.set_start_label(non_empty(start_label));
+        }
+        if let Some(finish_label) = &self.finish_label {
+            metadata.set_finish_label(non_empty(finish_label));
+        }
+        if let Some(external_link) = &self.external_link {
+            if let Some(url) = external_link
+                .as_ref()
+                .filter(|url| !Self::is_valid_link(url))
+            {
+                return Err(ApplicationError::ValidationError(format!(
+                    "Invalid external_link {} (expected an http or https URL)",
+                    url
+                )));
+            }
+            metadata.set_external_link(external_link.clone());
+        }
+
+        if let Some(name) = &self.name {
+            route_info.rename(name);
+        }
+        route_info.set_metadata(metadata);
+        Ok(())
+    }
+
+    /// 画面に表示するリンクなので，httpかhttpsのURLに限る
+    fn is_valid_link(url: &Url) -> bool {
+        let url = url.to_string();
+        (url.starts_with("http://") || url.starts_with("https://"))
+            && url.len() <= 2048
+            && validator::validate_url(&url)
+    }
+}
+
+/// 項目を消す指定(nullか空白だけの文字列)を`Some(None)`として読む
+///
+/// 項目が無いときは`#[serde(default)]`でNone(書き換えない)になる
+fn deserialize_clearable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    match Option::<String>::deserialize(deserializer)? {
+        Some(value) if !value.trim().is_empty() => {
+            T::deserialize(value.into_deserializer()).map(|value| Some(Some(value)))
+        }
+        _ => Ok(Some(None)),
+    }
+}
+
 #[derive(From, Deserialize)]
 pub struct UpdatePermissionRequest {
     pub(super) user_id: UserId,
@@ -39,3 +220,104 @@ pub struct UpdatePermissionRequest {
 pub struct DeletePermissionRequest {
     pub(super) user_id: UserId,
 }
+
+#[derive(From, Deserialize)]
+pub struct RouteTagRequest {
+    pub(super) tag: Tag,
+}
+
+#[derive(From, Deserialize, Validate)]
+pub struct RouteEnergyRequest {
+    /// [kg]
+    #[validate(range(min = 20., max = 300.))]
+    pub(super) rider_mass: f64,
+    /// [kg]
+    #[validate(range(min = 1., max = 100.))]
+    pub(super) bike_mass: f64,
+    /// [m^2]
+    #[serde(default = "RouteEnergyRequest::default_cda")]
+    #[validate(range(min = 0.1, max = 1.5))]
+    pub(super) cda: f64,
+    #[serde(default = "RouteEnergyRequest::default_crr")]
+    #[validate(range(min = 0.001, max = 0.05))]
+    pub(super) crr: f64,
+    /// average speed [km/h]
+    #[serde(default = "RouteEnergyRequest::default_speed")]
+    #[validate(range(min = 1., max = 80.))]
+    pub(super) speed: f64,
+}
+
+impl RouteEnergyRequest {
+    fn default_cda() -> f64 {
+        0.32
+    }
+
+    fn default_crr() -> f64 {
+        0.005
+    }
+
+    fn default_speed() -> f64 {
+        25.
+    }
+}
+
+impl TryFrom<&RouteEnergyRequest> for EnergyModel {
+    type Error = ApplicationError;
+
+    fn try_from(req: &RouteEnergyRequest) -> Result<Self, Self::Error> {
+        req.validate()?;
+        Ok(EnergyModel::new(
+            req.rider_mass,
+            req.bike_mass,
+            req.cda,
+            req.crr,
+            req.speed,
+        ))
+    }
+}
+
+#[derive(From, Deserialize, Validate)]
+pub struct RouteStagesRequest {
+    /// target distance per day [m]
+    #[validate(range(min = 0.))]
+    pub(super) distance: Option<f64>,
+    /// target ascent elevation gain per day [m]
+    #[validate(range(min = 0))]
+    pub(super) ascent: Option<i32>,
+}
+
+impl TryFrom<&RouteStagesRequest> for StagePlan {
+    type Error = ApplicationError;
+
+    fn try_from(req: &RouteStagesRequest) -> Result<Self, Self::Error> {
+        req.validate()?;
+        StagePlan::new(
+            req.distance.map(Distance::try_from).transpose()?,
+            req.ascent.map(Elevation::try_from).transpose()?,
+        )
+    }
+}
+
+#[derive(From, Deserialize, Validate)]
+pub struct RouteDaylightRequest {
+    pub(super) departure: DateTime<Utc>,
+    /// average speed [km/h]
+    #[serde(default = "RouteDaylightRequest::default_speed")]
+    #[validate(range(min = 1., max = 80.))]
+    pub(super) speed: f64,
+}
+
+impl RouteDaylightRequest {
+    fn default_speed() -> f64 {
+        20.
+    }
+}
+
+impl TryFrom<&RouteDaylightRequest> for DaylightChecker {
+    type Error = ApplicationError;
+
+    fn try_from(req: &RouteDaylightRequest) -> Result<Self, Self::Error> {
+        req.validate()?;
+        DaylightChecker::new(req.departure, req.speed)
+    }
+}
@@ -3,7 +3,8 @@ use std::convert::TryFrom;
 use serde::Serialize;
 
 use route_bucket_domain::model::route::{
-    BoundingBox, Coordinate, Distance, Elevation, Route, RouteGpx, RouteId, RouteInfo, Segment,
+    BoundingBox, Coordinate, DaylightReport, Distance, Elevation, EnergyExpenditure, Route,
+    RouteGpx, RouteId, RouteInfo, RouteSearchCursor, RouteSimilarity, Segment, Stage, TagCount,
 };
 use route_bucket_utils::ApplicationError;
 
@@ -15,6 +16,9 @@ pub struct RouteGetResponse {
     pub waypoints: Vec<Coordinate>,
     pub segments: Vec<Segment>,
     pub bounding_box: Option<BoundingBox>,
+    pub distance_markers: Vec<Coordinate>,
+    /// DEMのタイルが無く，標高を求められない点を含むセグメントの番号
+    pub uncovered_segments: Vec<usize>,
 }
 
 #[derive(Debug, Serialize)]
@@ -23,14 +27,58 @@ pub struct RouteSearchResponse {
     #[serde(rename = "routes")]
     pub route_infos: Vec<RouteInfo>,
     pub result_num: usize,
+    /// 続きを取得するときに`cursor`に渡す (続きが無ければNone)
+    pub next_cursor: Option<RouteSearchCursor>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct NearbyRoute {
+    #[serde(flatten)]
+    pub route_info: RouteInfo,
+    /// 検索した地点からの距離 [m]
+    pub distance: Distance,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct RouteNearbyResponse {
+    pub routes: Vec<NearbyRoute>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct SimilarRoute {
+    #[serde(flatten)]
+    pub route_info: RouteInfo,
+    #[serde(flatten)]
+    pub similarity: RouteSimilarity,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct RouteSimilarResponse {
+    /// 形の近い順
+    pub routes: Vec<SimilarRoute>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct PopularTagsResponse {
+    /// 付いているルートの多い順
+    pub tags: Vec<TagCount>,
 }
 
 pub type RouteGetGpxResponse = RouteGpx;
 
+pub type RouteDaylightResponse = DaylightReport;
+
 #[derive(Debug, Serialize)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct RouteCreateResponse {
     pub id: RouteId,
+    /// 同じ所有者の，ほぼ同じ形のルート (重複の警告)
+    pub near_duplicates: Vec<SimilarRoute>,
 }
 
 #[derive(Debug, Serialize)]
@@ -41,12 +89,36 @@ pub struct RouteOperationResponse {
     pub ascent_elevation_gain: Elevation,
     pub descent_elevation_gain: Elevation,
     pub total_distance: Distance,
+    /// 同じ所有者の，ほぼ同じ形のルート (重複の警告)
+    pub near_duplicates: Vec<SimilarRoute>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct RouteEnergyResponse {
+    pub total: EnergyExpenditure,
+    /// aligned with `RouteGetResponse::segments`
+    pub segments: Vec<EnergyExpenditure>,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(test, derive(PartialEq))]
+pub struct RouteStagesResponse {
+    pub stages: Vec<Stage>,
 }
 
 impl TryFrom<Route> for RouteGetResponse {
     type Error = ApplicationError;
 
     fn try_from(route: Route) -> Result<Self, Self::Error> {
+        Self::try_from((route, Vec::new()))
+    }
+}
+
+impl TryFrom<(Route, Vec<Coordinate>)> for RouteGetResponse {
+    type Error = ApplicationError;
+
+    fn try_from((route, distance_markers): (Route, Vec<Coordinate>)) -> Result<Self, Self::Error> {
         let (info, _, seg_list) = route.into();
         Ok(RouteGetResponse {
             route_info: info,
@@ -55,6 +127,8 @@ impl TryFrom<Route> for RouteGetResponse {
                 .then(|| seg_list.calc_bounding_box())
                 .transpose()?,
             segments: seg_list.into_segments_in_between(),
+            distance_markers,
+            uncovered_segments: Vec::new(),
         })
     }
 }
@@ -70,6 +144,7 @@ impl TryFrom<Route> for RouteOperationResponse {
             descent_elevation_gain: *info.descent_elevation_gain(),
             total_distance: *info.total_distance(),
             segments: seg_list.into_segments_in_between(),
+            near_duplicates: Vec::new(),
         })
     }
 }
@@ -91,19 +166,23 @@ mod tests {
             waypoints: Vec::new(),
             segments: Vec::new(),
             bounding_box: None,
+            distance_markers: Vec::new(),
+            uncovered_segments: Vec::new(),
         }
     }
 
     fn full_route_get_resp() -> RouteGetResponse {
         let dist = 26936.42633640023;
         RouteGetResponse {
-            route_info: RouteInfo::filled_route0(10, 0, 58759.973932514884, 3),
+            route_info: RouteInfo::filled_route0(10, 0, 58759.973932514884, 29.77998696625744, 3),
             waypoints: Coordinate::yokohama_to_chiba_via_tokyo_coords(false, None),
             segments: vec![
                 Segment::yokohama_to_tokyo(true, Some(0.), false, DrawingMode::Freehand),
                 Segment::tokyo_to_chiba(true, Some(dist), false, DrawingMode::Freehand),
             ],
             bounding_box: Some(BoundingBox::yokohama_to_chiba_via_tokyo()),
+            distance_markers: Vec::new(),
+            uncovered_segments: Vec::new(),
         }
     }
 
@@ -114,6 +193,7 @@ mod tests {
             descent_elevation_gain: Elevation::zero(),
             total_distance: Distance::zero(),
             segments: Vec::new(),
+            near_duplicates: Vec::new(),
         }
     }
 
@@ -128,6 +208,7 @@ mod tests {
                 Segment::yokohama_to_tokyo(true, Some(0.), false, DrawingMode::Freehand),
                 Segment::tokyo_to_chiba(true, Some(dist), false, DrawingMode::Freehand),
             ],
+            near_duplicates: Vec::new(),
         }
     }
 
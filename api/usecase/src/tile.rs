@@ -0,0 +1,328 @@
+use async_trait::async_trait;
+
+pub use requests::*;
+use route_bucket_domain::external::{CallTileCacheApi, CallUserAuthApi, TileCacheApi, UserAuthApi};
+use route_bucket_domain::model::route::{Route, RouteSearchQuery};
+use route_bucket_domain::model::tile::{TileCoord, TileKey, VectorTile};
+use route_bucket_domain::repository::{CallRouteRepository, Repository, RouteRepository};
+use route_bucket_utils::{ApplicationError, ApplicationResult};
+
+mod requests;
+
+/// 1枚のタイルに描くルートの数の上限 (新しく更新されたものから描く)
+///
+/// 超えた分は描かず，`VectorTile::is_truncated`で分かるようにする
+const MAX_ROUTES_PER_TILE: usize = 200;
+
+#[async_trait]
+pub trait TileUseCase {
+    /// タイルに掛かるルートを描いたベクタータイルを返す
+    ///
+    /// ルートは誰でも見られるので，`req.mine`でなければ全員のルートを描く
+    ///
+    /// 掛かるルートが`MAX_ROUTES_PER_TILE`を超えると，新しいものだけを描いて切り詰めたことを記録する
+    async fn find_route_tile(
+        &self,
+        z: u8,
+        x: u32,
+        y: u32,
+        req: &TileRequest,
+        user_access_token: Option<&str>,
+    ) -> ApplicationResult<VectorTile>;
+}
+
+#[async_trait]
+impl<T> TileUseCase for T
+where
+    T: CallRouteRepository + CallUserAuthApi + CallTileCacheApi + Sync,
+{
+    async fn find_route_tile(
+        &self,
+        z: u8,
+        x: u32,
+        y: u32,
+        req: &TileRequest,
+        user_access_token: Option<&str>,
+    ) -> ApplicationResult<VectorTile> {
+        let coord = TileCoord::new(z, x, y)?;
+        let owner_id = if req.mine {
+            let token = user_access_token.ok_or_else(|| {
+                ApplicationError::AuthenticationError(
+                    "Login is required to draw only your own routes".into(),
+                )
+            })?;
+            Some(self.user_auth_api().authenticate(token).await?)
+        } else {
+            None
+        };
+
+        let key = TileKey::new(coord, owner_id.clone());
+        if let Some(tile) = self.tile_cache_api().get(&key) {
+            return Ok(tile);
+        }
+
+        let conn = self.route_repository().get_connection().await?;
+        // 1件多く読んで，上限を超えたかを確かめる
+        let query = RouteSearchQuery {
+            owner_id,
+            bbox: Some(coord.bounding_box()?),
+            page_size: Some(MAX_ROUTES_PER_TILE + 1),
+            ..Default::default()
+        };
+        let mut infos = self.route_repository().search_infos(query, &conn).await?;
+        let truncated = infos.len() > MAX_ROUTES_PER_TILE;
+        infos.truncate(MAX_ROUTES_PER_TILE);
+
+        // 線は1回でまとめて読む (セグメントのないルートは返らないが，描くものもない)
+        let ids = infos
+            .iter()
+            .map(|info| info.id().clone())
+            .collect::<Vec<_>>();
+        let mut seg_lists = self.route_repository().find_seg_lists(&ids, &conn).await?;
+        let routes = infos
+            .into_iter()
+            .filter_map(|info| {
+                let pos = seg_lists.iter().position(|(id, _)| id == info.id())?;
+                let (_, seg_list) = seg_lists.swap_remove(pos);
+                Some(Route::new(info, Vec::new(), seg_list))
+            })
+            .collect::<Vec<_>>();
+
+        let tile = VectorTile::from_routes(&coord, &routes);
+        let tile = if truncated {
+            tile.mark_truncated()
+        } else {
+            tile
+        };
+        self.tile_cache_api().put(key, tile.clone());
+        Ok(tile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{expect_at_repository, expect_once};
+    use route_bucket_domain::{
+        external::{MockTileCacheApi, MockUserAuthApi},
+        model::{
+            fixtures::{route::RouteFixtures, user::UserIdFixtures},
+            route::Route,
+            user::UserId,
+        },
+        repository::{MockConnection, MockRouteRepository},
+    };
+    use rstest::rstest;
+
+    use super::*;
+
+    fn doncic_token() -> String {
+        String::from("token.for.doncic")
+    }
+
+    /// 横浜から千葉までのルートが入るタイル
+    fn yokohama_tile() -> TileCoord {
+        TileCoord::new(8, 227, 100).unwrap()
+    }
+
+    fn search_query(owner_id: Option<UserId>) -> RouteSearchQuery {
+        RouteSearchQuery {
+            owner_id,
+            bbox: Some(yokohama_tile().bounding_box().unwrap()),
+            page_size: Some(MAX_ROUTES_PER_TILE + 1),
+            ..Default::default()
+        }
+    }
+
+    #[rstest]
+    #[case::everyone(false, None, None)]
+    #[case::mine(true, Some(doncic_token()), Some(UserId::doncic()))]
+    #[tokio::test]
+    async fn can_find_route_tile(
+        #[case] mine: bool,
+        #[case] token: Option<String>,
+        #[case] owner_id: Option<UserId>,
+    ) {
+        let route = Route::yokohama_to_chiba_filled(false, true);
+        let key = TileKey::new(yokohama_tile(), owner_id.clone());
+        let query = search_query(owner_id.clone());
+        let expected = VectorTile::from_routes(&yokohama_tile(), std::slice::from_ref(&route));
+
+        let mut usecase = TestTileUseCase::new();
+        if let Some(token) = token.clone() {
+            usecase.expect_authenticate_at_auth_api(token, UserId::doncic());
+        }
+        usecase.expect_get_at_tile_cache_api(key.clone(), None);
+        expect_at_repository!(usecase.route_repository, get_connection, MockConnection {});
+        expect_at_repository!(
+            usecase.route_repository,
+            search_infos,
+            query,
+            vec![route.info().clone()]
+        );
+        expect_at_repository!(
+            usecase.route_repository,
+            find_seg_lists,
+            vec![route.info().id().clone()],
+            vec![(route.info().id().clone(), route.seg_list().clone())]
+        );
+        usecase.expect_put_at_tile_cache_api(key, expected.clone());
+
+        let tile = usecase
+            .find_route_tile(8, 227, 100, &TileRequest { mine }, token.as_deref())
+            .await
+            .unwrap();
+        assert!(!tile.is_truncated());
+        assert_eq!(tile, expected);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn marks_tile_truncated_over_limit() {
+        let route = Route::yokohama_to_chiba_filled(false, false);
+        let key = TileKey::new(yokohama_tile(), None);
+        let expected = VectorTile::from_routes(&yokohama_tile(), std::slice::from_ref(&route))
+            .mark_truncated();
+
+        let mut usecase = TestTileUseCase::new();
+        usecase.expect_get_at_tile_cache_api(key.clone(), None);
+        expect_at_repository!(usecase.route_repository, get_connection, MockConnection {});
+        expect_at_repository!(
+            usecase.route_repository,
+            search_infos,
+            search_query(None),
+            vec![route.info().clone(); MAX_ROUTES_PER_TILE + 1]
+        );
+        // 上限を超えた分の線は読まない
+        expect_at_repository!(
+            usecase.route_repository,
+            find_seg_lists,
+            vec![route.info().id().clone(); MAX_ROUTES_PER_TILE],
+            vec![(route.info().id().clone(), route.seg_list().clone())]
+        );
+        usecase.expect_put_at_tile_cache_api(key, expected.clone());
+
+        let tile = usecase
+            .find_route_tile(8, 227, 100, &TileRequest::default(), None)
+            .await
+            .unwrap();
+        assert!(tile.is_truncated());
+        assert_eq!(tile, expected);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn returns_cached_tile() {
+        let cached = VectorTile::from_routes(
+            &yokohama_tile(),
+            &[Route::yokohama_to_chiba_filled(false, false)],
+        );
+
+        let mut usecase = TestTileUseCase::new();
+        usecase.expect_get_at_tile_cache_api(
+            TileKey::new(yokohama_tile(), None),
+            Some(cached.clone()),
+        );
+
+        assert_eq!(
+            usecase
+                .find_route_tile(8, 227, 100, &TileRequest::default(), None)
+                .await,
+            Ok(cached)
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn cannot_find_own_route_tile_without_login() {
+        let usecase = TestTileUseCase::new();
+        assert!(matches!(
+            usecase
+                .find_route_tile(8, 227, 100, &TileRequest { mine: true }, None)
+                .await,
+            Err(ApplicationError::AuthenticationError(_))
+        ));
+    }
+
+    #[rstest]
+    #[case::too_deep(23, 0, 0)]
+    #[case::out_of_range(8, 256, 100)]
+    #[tokio::test]
+    async fn cannot_find_invalid_tile(#[case] z: u8, #[case] x: u32, #[case] y: u32) {
+        let usecase = TestTileUseCase::new();
+        assert!(matches!(
+            usecase
+                .find_route_tile(z, x, y, &TileRequest::default(), None)
+                .await,
+            Err(ApplicationError::ValidationError(_))
+        ));
+    }
+
+    struct TestTileUseCase {
+        route_repository: MockRouteRepository,
+        auth_api: MockUserAuthApi,
+        tile_cache_api: MockTileCacheApi,
+    }
+
+    // setup methods for mocking
+    impl TestTileUseCase {
+        fn new() -> Self {
+            TestTileUseCase {
+                route_repository: MockRouteRepository::new(),
+                auth_api: MockUserAuthApi::new(),
+                tile_cache_api: MockTileCacheApi::new(),
+            }
+        }
+
+        fn expect_authenticate_at_auth_api(&mut self, param_token: String, return_id: UserId) {
+            expect_once!(self.auth_api, authenticate, param_token, return_id);
+        }
+
+        fn expect_get_at_tile_cache_api(
+            &mut self,
+            param_key: TileKey,
+            return_tile: Option<VectorTile>,
+        ) {
+            expect_once!(self.tile_cache_api, get)
+                .withf(move |key| {
+                    assert_eq!(*key, param_key);
+                    true
+                })
+                .return_const(return_tile);
+        }
+
+        fn expect_put_at_tile_cache_api(&mut self, param_key: TileKey, param_tile: VectorTile) {
+            expect_once!(self.tile_cache_api, put)
+                .withf(move |key, tile| {
+                    assert_eq!(*key, param_key);
+                    assert_eq!(*tile, param_tile);
+                    true
+                })
+                .return_const(());
+        }
+    }
+
+    // impls to enable trait TileUseCase
+    impl CallRouteRepository for TestTileUseCase {
+        type RouteRepository = MockRouteRepository;
+
+        fn route_repository(&self) -> &Self::RouteRepository {
+            &self.route_repository
+        }
+    }
+
+    impl CallUserAuthApi for TestTileUseCase {
+        type UserAuthApi = MockUserAuthApi;
+
+        fn user_auth_api(&self) -> &Self::UserAuthApi {
+            &self.auth_api
+        }
+    }
+
+    impl CallTileCacheApi for TestTileUseCase {
+        type TileCacheApi = MockTileCacheApi;
+
+        fn tile_cache_api(&self) -> &Self::TileCacheApi {
+            &self.tile_cache_api
+        }
+    }
+}
@@ -0,0 +1,9 @@
+use derive_more::From;
+use serde::Deserialize;
+
+#[derive(Default, From, Deserialize)]
+pub struct TileRequest {
+    /// ログインしているユーザーのルートだけを描く
+    #[serde(default)]
+    pub(super) mine: bool,
+}
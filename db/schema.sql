@@ -7,12 +7,45 @@ CREATE TABLE routes
     `ascent_elevation_gain`  INTEGER UNSIGNED NOT NULL,
     `descent_elevation_gain` INTEGER UNSIGNED NOT NULL,
     `total_distance`         DOUBLE           NOT NULL,
+    `difficulty_score`       DOUBLE           NOT NULL DEFAULT 0,
+    `difficulty_rating`      VARCHAR(10)      CHARACTER SET ascii NOT NULL DEFAULT 'easy',
+    `elevation_source`       VARCHAR(10)      CHARACTER SET ascii NOT NULL DEFAULT 'dem',
+    `min_latitude`           DOUBLE,
+    `min_longitude`          DOUBLE,
+    `max_latitude`           DOUBLE,
+    `max_longitude`          DOUBLE,
+    `description`            TEXT,
+    `surface_type`           VARCHAR(10)      CHARACTER SET ascii,
+    `bike_type`              VARCHAR(10)      CHARACTER SET ascii,
+    `start_label`            VARCHAR(50),
+    `finish_label`           VARCHAR(50),
+    `external_link`          VARCHAR(2048),
     `created_at`    TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
     `updated_at`    TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
     INDEX updated_idx (`updated_at`),
+    INDEX difficulty_idx (`difficulty_rating`),
+    INDEX bbox_idx (`min_latitude`, `max_latitude`, `min_longitude`, `max_longitude`),
     PRIMARY KEY (`id`)
 );
 
+CREATE TABLE route_cells
+(
+    `route_id`        VARCHAR(11) NOT NULL,
+    `target`          VARCHAR(5)  CHARACTER SET ascii NOT NULL,
+    `latitude_index`  INTEGER     NOT NULL,
+    `longitude_index` INTEGER     NOT NULL,
+    INDEX route_idx (`route_id`),
+    PRIMARY KEY (`target`, `latitude_index`, `longitude_index`, `route_id`)
+);
+
+CREATE TABLE route_tags
+(
+    `route_id` VARCHAR(11) NOT NULL,
+    `tag`      VARCHAR(30) NOT NULL,
+    INDEX tag_idx (`tag`),
+    PRIMARY KEY (`route_id`, `tag`)
+);
+
 CREATE TABLE segments
 (
     `id`       VARCHAR(21)                        NOT NULL,
@@ -20,6 +53,10 @@ CREATE TABLE segments
     `index`    INTEGER UNSIGNED                   NOT NULL,
     `mode`     VARCHAR(15)    CHARACTER SET ascii NOT NULL,
     `polyline` VARCHAR(65000) CHARACTER SET ascii NOT NULL,
+    `elevations`  JSON                                    ,
+    `dem_version` VARCHAR(64)     CHARACTER SET ascii     ,
+    `elevation_override` JSON                             ,
+    `source_elevations`  JSON                             ,
     INDEX segment_idx (`route_id`, `index`),
     PRIMARY KEY (`id`)
 );
@@ -54,3 +91,32 @@ CREATE TABLE permissions
     `permission_type` VARCHAR(6)  NOT NULL,
     PRIMARY KEY (`user_id`, `route_id`)
 );
+
+CREATE TABLE collections
+(
+    `id`         VARCHAR(11) NOT NULL,
+    `name`       VARCHAR(50) NOT NULL,
+    `owner_id`   VARCHAR(40) NOT NULL,
+    `visibility` VARCHAR(10) CHARACTER SET ascii NOT NULL DEFAULT 'private',
+    `created_at` TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    `updated_at` TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    INDEX owner_idx (`owner_id`, `updated_at`),
+    PRIMARY KEY (`id`)
+);
+
+CREATE TABLE collection_routes
+(
+    `collection_id` VARCHAR(11)      NOT NULL,
+    `route_id`      VARCHAR(11)      NOT NULL,
+    `position`      INTEGER UNSIGNED NOT NULL,
+    INDEX route_idx (`route_id`),
+    PRIMARY KEY (`collection_id`, `route_id`)
+);
+
+CREATE TABLE collection_permissions
+(
+    `collection_id`   VARCHAR(11) NOT NULL,
+    `user_id`         VARCHAR(40) NOT NULL,
+    `permission_type` VARCHAR(6)  NOT NULL,
+    PRIMARY KEY (`user_id`, `collection_id`)
+);